@@ -0,0 +1,113 @@
+//! Baking of ambient occlusion for the surface voxels.
+
+use std::collections::HashMap;
+
+use crate::{Index, Neighborhood, OctreeBitmap};
+
+/// How far from the center of a voxel rays start, along its normal: just
+/// past the face it is exposed through.
+const SURFACE_OFFSET: f32 = 0.501;
+
+/// `n` directions spread evenly over the unit sphere, along a Fibonacci
+/// spiral.
+fn sphere_directions(n: u32) -> Vec<[f32; 3]> {
+    let golden_angle = std::f32::consts::PI * (3.0 - 5f32.sqrt());
+    (0..n)
+        .map(|i| {
+            let z = 1.0 - (2 * i + 1) as f32 / n as f32;
+            let r = (1.0 - z * z).sqrt();
+            let theta = golden_angle * i as f32;
+            [r * theta.cos(), r * theta.sin(), z]
+        })
+        .collect()
+}
+
+impl OctreeBitmap {
+    /// The ambient occlusion of each voxel of the [`surface`](Self::surface),
+    /// from `0.0` for a voxel that nothing shadows to `1.0` for one that is
+    /// enclosed, so that voxel renderers can shade surfaces softly.
+    ///
+    /// `samples` rays are cast from just outside of each voxel, over the
+    /// hemisphere facing away from its set face neighbors, and the occlusion
+    /// is the fraction of them that hit another set voxel within `radius`
+    /// voxels. Voxels with unset neighbors on opposite sides sample
+    /// the hemisphere of their exposed faces combined, or the whole sphere if
+    /// those cancel out. Rays skip empty nodes of the tree whole, as for
+    /// [`raycast`](Self::raycast). The outside of the bitmap occludes
+    /// nothing.
+    pub fn bake_ao(&self, samples: u32, radius: f32) -> HashMap<Index, f32> {
+        let directions = sphere_directions(samples);
+        let offsets: Vec<[i32; 3]> = Neighborhood::Six.offsets().collect();
+        let mut occlusion = HashMap::new();
+        for idx in self.surface().iter() {
+            let neighbors = self.neighbors(&idx, Neighborhood::Six);
+            // The sum of the outward normals of the exposed faces.
+            let mut normal = [0.0f32; 3];
+            for (offset, &set) in offsets.iter().zip(&neighbors) {
+                if !set {
+                    for i in 0..3 {
+                        normal[i] += offset[i] as f32;
+                    }
+                }
+            }
+            let length = normal.iter().map(|c| c * c).sum::<f32>().sqrt();
+            if length > 0.0 {
+                normal = normal.map(|c| c / length);
+            }
+            // Start just outside of the exposed faces, so that rays grazing
+            // the surface do not hit the voxels beside this one.
+            let origin = [0, 1, 2]
+                .map(|i| [idx.x, idx.y, idx.z][i] as f32 + 0.5 + normal[i] * SURFACE_OFFSET);
+            let hits = directions
+                .iter()
+                .filter(|dir| {
+                    // Reflect the directions behind the surface in front of it.
+                    let dot: f32 = (0..3).map(|i| dir[i] * normal[i]).sum();
+                    let dir = match dot < 0.0 {
+                        true => [0, 1, 2].map(|i| dir[i] - 2.0 * dot * normal[i]),
+                        false => **dir,
+                    };
+                    self.ray_iter(origin, dir)
+                        .take_while(|hit| hit.enter < radius)
+                        .any(|hit| hit.index != idx)
+                })
+                .count();
+            let value = if samples == 0 {
+                0.0
+            } else {
+                hits as f32 / samples as f32
+            };
+            occlusion.insert(idx, value);
+        }
+        occlusion
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn floor_and_pillar() {
+        let mut bitmap = OctreeBitmap::new(16);
+        bitmap.fill_box([0, 0, 0], [32, 32, 2], true);
+        bitmap.fill_box([14, 14, 2], [18, 18, 12], true);
+        let ao = bitmap.bake_ao(32, 6.0);
+
+        let mut keys: Vec<Index> = ao.keys().copied().collect();
+        keys.sort_by_key(Index::morton);
+        assert_eq!(keys, bitmap.surface().iter().collect::<Vec<_>>());
+        assert!(ao.values().all(|value| (0.0..=1.0).contains(value)));
+
+        // Open floor, the floor beside the pillar, and the corner where they
+        // meet.
+        assert_eq!(ao[&Index::new(4, 4, 1)], 0.0);
+        let beside = ao[&Index::new(12, 15, 1)];
+        let corner = ao[&Index::new(13, 15, 1)];
+        assert!(beside > 0.1, "{}", beside);
+        assert!(corner > beside, "{} {}", corner, beside);
+        assert!(corner < 0.6, "{}", corner);
+        // The top of the pillar sees nothing above it.
+        assert_eq!(ao[&Index::new(15, 15, 11)], 0.0);
+    }
+}
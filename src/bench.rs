@@ -0,0 +1,283 @@
+//! Reproducible synthetic workloads and timing, for comparing configurations
+//! of the bitmap on real hardware.
+//!
+//! Every generator is deterministic for a given seed, so runs on different
+//! machines or with different configurations measure the same work.
+
+use std::fmt;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use crate::{Index, OctreeBitmap};
+
+/// A small xorshift generator, so that workloads are identical everywhere.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Mix the seed so that small seeds still give well-spread sequences,
+        // and avoid the all-zero state.
+        Self(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u32) -> u32 {
+        (self.next_u64() % n as u64) as u32
+    }
+
+    /// A uniformly distributed value in `0.0..1.0`.
+    fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// A sequence of `count` single-voxel edits, like those of a brush being
+/// dragged around an editor.
+///
+/// The edits follow a random walk through the volume, setting three
+/// quarters of the voxels they touch and clearing the rest.
+pub fn edit_storm(width: u32, count: usize, seed: u64) -> Vec<(Index, bool)> {
+    let mut rng = Rng::new(seed);
+    let mut position = [width / 2; 3];
+    (0..count)
+        .map(|_| {
+            for x in &mut position {
+                *x = (*x + width + rng.below(5) - 2) % width;
+            }
+            (Index::from(position), rng.below(4) != 0)
+        })
+        .collect()
+}
+
+/// The shape filled by [`fill_pattern`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FillPattern {
+    /// Every voxel.
+    Solid,
+    /// A ball touching every side of the volume.
+    Sphere,
+    /// Alternating voxels, the worst case for compression.
+    Checkerboard,
+    /// Each voxel independently, with the given probability.
+    Noise { density: f64 },
+    /// Rolling hills: every voxel below a smooth height field along `z`.
+    Terrain,
+}
+
+/// The voxels of a pattern filling a volume of the given width.
+///
+/// The seed only affects [`Noise`](FillPattern::Noise) and
+/// [`Terrain`](FillPattern::Terrain).
+pub fn fill_pattern(width: u32, pattern: FillPattern, seed: u64) -> Vec<Index> {
+    let mut rng = Rng::new(seed);
+    let half = width as f64 / 2.0;
+    let phases = [rng.unit(), rng.unit(), rng.unit()].map(|p| p * std::f64::consts::TAU);
+    let mut indices = Vec::new();
+    for z in 0..width {
+        for y in 0..width {
+            for x in 0..width {
+                let set = match pattern {
+                    FillPattern::Solid => true,
+                    FillPattern::Sphere => {
+                        let [dx, dy, dz] = [x, y, z].map(|c| c as f64 + 0.5 - half);
+                        dx * dx + dy * dy + dz * dz <= half * half
+                    }
+                    FillPattern::Checkerboard => (x + y + z) % 2 == 0,
+                    FillPattern::Noise { density } => rng.unit() < density,
+                    FillPattern::Terrain => {
+                        let (u, v) = (x as f64 / width as f64, y as f64 / width as f64);
+                        let height = 0.5
+                            + 0.2 * (u * 7.0 + phases[0]).sin() * (v * 5.0 + phases[1]).cos()
+                            + 0.1 * ((u + v) * 17.0 + phases[2]).sin();
+                        (z as f64) < height * width as f64
+                    }
+                };
+                if set {
+                    indices.push(Index::new(x, y, z));
+                }
+            }
+        }
+    }
+    indices
+}
+
+/// `count` rays, as `(origin, direction)` pairs with unit directions, that
+/// start outside a volume of the given width and pass through a random point
+/// inside it.
+pub fn ray_batch(width: u32, count: usize, seed: u64) -> Vec<([f32; 3], [f32; 3])> {
+    let mut rng = Rng::new(seed);
+    let half = width as f64 / 2.0;
+    (0..count)
+        .map(|_| {
+            // A uniformly random direction, from a point on a sphere
+            // enclosing the volume.
+            let z = 2.0 * rng.unit() - 1.0;
+            let angle = rng.unit() * std::f64::consts::TAU;
+            let r = (1.0 - z * z).sqrt();
+            let outward = [r * angle.cos(), r * angle.sin(), z];
+            let origin = outward.map(|c| half + c * half * 2.0);
+            let target = [rng.unit(), rng.unit(), rng.unit()].map(|c| c * width as f64);
+            let direction = [0, 1, 2].map(|i| target[i] - origin[i]);
+            let length = direction.iter().map(|c| c * c).sum::<f64>().sqrt();
+            (
+                origin.map(|c| c as f32),
+                direction.map(|c| (c / length) as f32),
+            )
+        })
+        .collect()
+}
+
+/// The time taken by repeated runs of a workload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Measurement {
+    pub name: String,
+    pub iterations: u32,
+    pub total: Duration,
+}
+
+impl Measurement {
+    /// The mean time of a single iteration.
+    pub fn mean(&self) -> Duration {
+        self.total / self.iterations.max(1)
+    }
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {:?} per iteration ({} iterations)",
+            self.name,
+            self.mean(),
+            self.iterations
+        )
+    }
+}
+
+/// Times `iterations` calls of `f`, after one untimed warm-up call.
+///
+/// `f` is given a fresh copy of `setup`'s result on every call, and the time
+/// spent in `setup` is not measured.
+pub fn measure<S, T, F, R>(name: &str, iterations: u32, mut setup: S, mut f: F) -> Measurement
+where
+    S: FnMut() -> T,
+    F: FnMut(T) -> R,
+{
+    black_box(f(setup()));
+    let mut total = Duration::ZERO;
+    for _ in 0..iterations {
+        let input = setup();
+        let start = Instant::now();
+        black_box(f(input));
+        total += start.elapsed();
+    }
+    Measurement {
+        name: name.to_owned(),
+        iterations,
+        total,
+    }
+}
+
+/// Runs the standard set of workloads against bitmaps of the given width,
+/// each for the given number of iterations.
+pub fn run_standard(width: u32, iterations: u32, seed: u64) -> Vec<Measurement> {
+    let edits = edit_storm(width, 100_000, seed);
+    let terrain = fill_pattern(width, FillPattern::Terrain, seed);
+    let noise = fill_pattern(width, FillPattern::Noise { density: 0.1 }, seed);
+    let queries = edit_storm(width, 100_000, seed ^ 1);
+    let rays = ray_batch(width, 10_000, seed);
+    let filled = || {
+        let mut bitmap = OctreeBitmap::new(width);
+        bitmap.set_many(terrain.iter().copied(), true);
+        bitmap
+    };
+    let mut noisy = filled();
+    noisy.set_many(noise.iter().copied(), true);
+
+    vec![
+        measure(
+            "edit storm (set)",
+            iterations,
+            || OctreeBitmap::new(width),
+            |mut bitmap| {
+                for (idx, value) in &edits {
+                    bitmap.set(idx, *value);
+                }
+                bitmap
+            },
+        ),
+        measure(
+            "terrain fill (set_many)",
+            iterations,
+            || OctreeBitmap::new(width),
+            |mut bitmap| {
+                bitmap.set_many(terrain.iter().copied(), true);
+                bitmap
+            },
+        ),
+        measure(
+            "noise fill (set_many)",
+            iterations,
+            || OctreeBitmap::new(width),
+            |mut bitmap| {
+                bitmap.set_many(noise.iter().copied(), true);
+                bitmap
+            },
+        ),
+        measure(
+            "random reads (get)",
+            iterations,
+            || (),
+            |()| queries.iter().filter(|(idx, _)| noisy.get(idx)).count(),
+        ),
+        measure(
+            "iterate (iter)",
+            iterations,
+            || (),
+            |()| noisy.iter().count(),
+        ),
+        measure(
+            "ray casts (raycast)",
+            iterations,
+            || (),
+            |()| {
+                rays.iter()
+                    .filter(|(origin, direction)| noisy.raycast(*origin, *direction).is_some())
+                    .count()
+            },
+        ),
+        measure("diff", iterations, filled, |bitmap| {
+            bitmap.diff(&noisy).regions().len()
+        }),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn workloads_are_reproducible() {
+        assert_eq!(edit_storm(64, 100, 7), edit_storm(64, 100, 7));
+        assert_ne!(edit_storm(64, 100, 7), edit_storm(64, 100, 8));
+        assert!(edit_storm(64, 1000, 7)
+            .iter()
+            .all(|(idx, _)| idx.x < 64 && idx.y < 64 && idx.z < 64));
+        assert_eq!(fill_pattern(8, FillPattern::Solid, 0).len(), 512);
+        assert_eq!(fill_pattern(8, FillPattern::Checkerboard, 0).len(), 256);
+        for (origin, direction) in ray_batch(16, 100, 3) {
+            assert!(origin.iter().any(|&c| !(0.0..16.0).contains(&c)));
+            let length: f32 = direction.iter().map(|c| c * c).sum();
+            assert!((length - 1.0).abs() < 1e-5);
+        }
+
+        let measurements = run_standard(16, 1, 0);
+        assert!(measurements.iter().all(|m| m.iterations == 1));
+    }
+}
@@ -0,0 +1,280 @@
+//! A compact binary encoding of the tree.
+//!
+//! The encoding starts with the magic bytes `OCTB`, a format version byte
+//! and the height of the root. Version 1 is for bitmaps with the default
+//! [`LeafSize`](crate::LeafSize), and version 2 for the others, with the
+//! height of the leaves (`0` for single voxels or `1` for 2x2x2 bricks)
+//! after the height of the root. It is followed by the children of the root
+//! in depth-first order, with the children of each branch in Morton order.
+//! Each node is a tag byte: `0` for empty, `1` for full, `2` for a branch,
+//! whose children follow it, and `3` for a brick, whose bits follow it in
+//! little-endian order, as one byte for bricks of height 1 and eight for
+//! bricks of height 2.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::{brick_mask, Branch, BranchIndex, OctreeBitmap, RawNode, BRICK_HEIGHT};
+
+const MAGIC: &[u8; 4] = b"OCTB";
+const VERSION: u8 = 1;
+/// The version for bitmaps whose leaves are not of the default size.
+pub(crate) const LEAF_VERSION: u8 = 2;
+
+pub(crate) const TAG_EMPTY: u8 = 0;
+pub(crate) const TAG_FULL: u8 = 1;
+pub(crate) const TAG_BRANCH: u8 = 2;
+pub(crate) const TAG_BRICK: u8 = 3;
+
+/// An error encountered while reading a bitmap in the binary format.
+#[derive(Debug)]
+pub enum BinaryError {
+    Io(io::Error),
+    /// The data is not a bitmap in the binary format, or is corrupted.
+    Invalid(&'static str),
+    /// The data was written by a newer version of the format.
+    UnsupportedVersion(u8),
+}
+
+impl fmt::Display for BinaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "failed to access bitmap data: {}", err),
+            Self::Invalid(reason) => write!(f, "invalid bitmap data: {}", reason),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported bitmap format version {}", version)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BinaryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Invalid(_) | Self::UnsupportedVersion(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for BinaryError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// The number of bytes the bits of a brick of the given height take up.
+pub(crate) fn brick_bytes(height: u32) -> usize {
+    (1 << (3 * height)) / 8
+}
+
+/// Reads the height of the leaves that follows the header of a version
+/// 2 encoding.
+pub(crate) fn read_leaf_height<R: Read>(reader: &mut R) -> Result<u32, BinaryError> {
+    let mut leaf_height = [0];
+    reader.read_exact(&mut leaf_height)?;
+    match u32::from(leaf_height[0]) {
+        height if height < BRICK_HEIGHT => Ok(height),
+        _ => Err(BinaryError::Invalid("leaf height")),
+    }
+}
+
+impl OctreeBitmap {
+    /// Writes the bitmap in the binary format.
+    ///
+    /// The undo history is not written.
+    pub fn write_binary<W: Write>(&self, writer: W) -> io::Result<()> {
+        trace_span!("write_binary", branches = self.branches.len());
+        let mut writer = io::BufWriter::new(writer);
+        writer.write_all(MAGIC)?;
+        if self.leaf_height == BRICK_HEIGHT {
+            writer.write_all(&[VERSION, self.height as u8])?;
+        } else {
+            writer.write_all(&[LEAF_VERSION, self.height as u8, self.leaf_height as u8])?;
+        }
+        self.write_branch(&mut writer, BranchIndex::root(self.height))?;
+        writer.flush()
+    }
+
+    pub(crate) fn write_branch<W: Write>(
+        &self,
+        writer: &mut W,
+        index: BranchIndex,
+    ) -> io::Result<()> {
+        let branch = &self.branches[&index];
+        for i in 0..8 {
+            let (x, y, z) = (i & 1, (i >> 1) & 1, i >> 2);
+            match branch.children[z][y][x] {
+                RawNode::False => writer.write_all(&[TAG_EMPTY])?,
+                RawNode::True => writer.write_all(&[TAG_FULL])?,
+                RawNode::Branch => {
+                    writer.write_all(&[TAG_BRANCH])?;
+                    self.write_branch(writer, index.child(x, y, z))?;
+                }
+                RawNode::Brick(bits) => {
+                    writer.write_all(&[TAG_BRICK])?;
+                    writer.write_all(&bits.to_le_bytes()[..brick_bytes(index.height - 1)])?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The bitmap encoded in the binary format.
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.write_binary(&mut bytes)
+            .expect("writing to a vector cannot fail");
+        bytes
+    }
+
+    /// Reads a bitmap in the binary format, as written by
+    /// [`write_binary`](Self::write_binary).
+    ///
+    /// The tree is checked as it is read, and branches that are uniformly
+    /// empty or full are merged, so the result is always valid. Nothing past
+    /// the end of the bitmap is read, and the data is read a few bytes at a
+    /// time, so readers such as files should be buffered.
+    pub fn from_binary<R: Read>(mut reader: R) -> Result<Self, BinaryError> {
+        let mut header = [0; 6];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(BinaryError::Invalid("missing magic bytes"));
+        }
+        let leaf_height = match header[4] {
+            VERSION => BRICK_HEIGHT,
+            LEAF_VERSION => read_leaf_height(&mut reader)?,
+            version => return Err(BinaryError::UnsupportedVersion(version)),
+        };
+        let height = u32::from(header[5]);
+        if height == 0 || height >= u32::BITS {
+            return Err(BinaryError::Invalid("root height"));
+        }
+        trace_span!("read_binary", height);
+        let mut bitmap = Self::with_height(height);
+        bitmap.leaf_height = leaf_height;
+        let root = BranchIndex::root(height);
+        let children = bitmap.read_children(&mut reader, root)?;
+        bitmap.branches.insert(root, Branch { children });
+        trace_event!(branches = bitmap.branches.len(), "read bitmap");
+        Ok(bitmap)
+    }
+
+    /// Reads the children of the branch at the given index, inserting the
+    /// branches below it.
+    fn read_children<R: Read>(
+        &mut self,
+        reader: &mut R,
+        index: BranchIndex,
+    ) -> Result<[[[RawNode; 2]; 2]; 2], BinaryError> {
+        let mut children = [[[RawNode::False; 2]; 2]; 2];
+        for i in 0..8 {
+            let (x, y, z) = (i & 1, (i >> 1) & 1, i >> 2);
+            children[z][y][x] = self.read_node(reader, index.child(x, y, z))?;
+        }
+        Ok(children)
+    }
+
+    /// Reads the node at the given index, inserting the branches below it.
+    pub(crate) fn read_node<R: Read>(
+        &mut self,
+        reader: &mut R,
+        index: BranchIndex,
+    ) -> Result<RawNode, BinaryError> {
+        let brick_height = self.brick_height();
+        let mut tag = [0];
+        reader.read_exact(&mut tag)?;
+        Ok(match tag[0] {
+            TAG_EMPTY => RawNode::False,
+            TAG_FULL => RawNode::True,
+            TAG_BRANCH if index.height > brick_height => {
+                let children = self.read_children(reader, index)?;
+                self.insert_branch(index, children)
+            }
+            TAG_BRICK if index.height == brick_height && brick_height > 0 => {
+                let mut bytes = [0; 8];
+                reader.read_exact(&mut bytes[..brick_bytes(index.height)])?;
+                let bits = u64::from_le_bytes(bytes);
+                if bits & !brick_mask(index.height) != 0 {
+                    return Err(BinaryError::Invalid("brick bits out of range"));
+                }
+                RawNode::from_brick(bits, index.height)
+            }
+            TAG_BRANCH | TAG_BRICK => return Err(BinaryError::Invalid("node at wrong height")),
+            _ => return Err(BinaryError::Invalid("unknown node tag")),
+        })
+    }
+
+    /// The node for a branch with the given children, which is inserted
+    /// unless they are all equally uniform.
+    pub(crate) fn insert_branch(
+        &mut self,
+        index: BranchIndex,
+        children: [[[RawNode; 2]; 2]; 2],
+    ) -> RawNode {
+        let first = children[0][0][0];
+        if matches!(first, RawNode::False | RawNode::True)
+            && children.iter().flatten().flatten().all(|&c| c == first)
+        {
+            first
+        } else {
+            self.branches.insert(index, Branch { children });
+            RawNode::Branch
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::pseudo_random_indexes;
+    use crate::LeafSize;
+
+    #[test]
+    fn binary_round_trip() {
+        for width in [1, 2, 4, 64] {
+            let mut bitmap = OctreeBitmap::new(width);
+            let width = bitmap.width();
+            bitmap.fill_sphere([width as f32 / 2.0; 3], width as f32 / 3.0, true);
+            bitmap.set_many(pseudo_random_indexes(width, 300), true);
+            for leaf_size in [LeafSize::Four, LeafSize::Two, LeafSize::One] {
+                let bitmap = bitmap.clone().with_leaf_size(leaf_size);
+                let bytes = bitmap.to_binary();
+                assert_eq!(bytes[4] == VERSION, leaf_size == LeafSize::Four);
+                let read = OctreeBitmap::from_binary(&bytes[..]).unwrap();
+                assert_eq!(read.validate(), Ok(()));
+                assert_eq!(read, bitmap);
+                assert_eq!(read.leaf_size(), leaf_size);
+                assert_eq!(read.branches.len(), bitmap.branches.len());
+            }
+        }
+    }
+
+    #[test]
+    fn reject_invalid_data() {
+        let bitmap = OctreeBitmap::new(16);
+        let mut bytes = bitmap.to_binary();
+        assert!(matches!(
+            OctreeBitmap::from_binary(&bytes[..bytes.len() - 1]),
+            Err(BinaryError::Io(_))
+        ));
+        bytes[6] = 9;
+        assert!(matches!(
+            OctreeBitmap::from_binary(&bytes[..]),
+            Err(BinaryError::Invalid(_))
+        ));
+        bytes[4] = LEAF_VERSION + 1;
+        assert!(matches!(
+            OctreeBitmap::from_binary(&bytes[..]),
+            Err(BinaryError::UnsupportedVersion(_))
+        ));
+
+        // A uniform branch is merged into its parent.
+        let mut bytes = bitmap.to_binary();
+        bytes.splice(6..7, [TAG_BRANCH].into_iter().chain([TAG_FULL; 8]));
+        let read = OctreeBitmap::from_binary(&bytes[..]).unwrap();
+        assert_eq!(read.validate(), Ok(()));
+        let side = bitmap.width() as usize / 2;
+        assert_eq!(read.iter().count(), side * side * side);
+    }
+}
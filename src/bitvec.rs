@@ -0,0 +1,179 @@
+//! Conversions between planes and lines of a bitmap and [`BitVec`]s.
+
+use ::bitvec::prelude::{BitSlice, BitVec, Lsb0};
+use ::bitvec::view::BitView;
+
+use crate::{Axis, BranchIndex, Index, Layer, OctreeBitmap, View};
+
+impl Layer {
+    /// The bits of the layer one row after another, with bit `u + v * width`
+    /// for position `(u, v)`.
+    pub fn to_bitvec(&self) -> BitVec<u64, Lsb0> {
+        let width = self.width() as usize;
+        let mut bits = BitVec::with_capacity(width * width);
+        for v in 0..self.width() {
+            bits.extend_from_bitslice(&self.row(v).view_bits::<Lsb0>()[..width]);
+        }
+        bits
+    }
+
+    /// A layer with the bits laid out as by [`to_bitvec`](Self::to_bitvec).
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are not `width * width` bits.
+    pub fn from_bitslice(width: u32, bits: &BitSlice<u64, Lsb0>) -> Self {
+        let len = width as usize;
+        assert_eq!(bits.len(), len * len, "wrong number of bits for width");
+        let mut layer = Self::new(width);
+        for (v, row) in bits.chunks(len.max(1)).enumerate() {
+            layer.row_mut(v as u32).view_bits_mut::<Lsb0>()[..len].copy_from_bitslice(row);
+        }
+        layer
+    }
+}
+
+impl OctreeBitmap {
+    /// The voxels of the line along an axis through the position `(u, v)` on
+    /// the other two axes, in cyclic order after it as for
+    /// [`slice`](Self::slice), with bit `i` for coordinate `i` along the
+    /// axis.
+    ///
+    /// Only the nodes that the line passes through are descended into, and
+    /// uniform nodes are copied as whole ranges.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is outside the bitmap.
+    pub fn column(&self, axis: Axis, u: u32, v: u32) -> BitVec<u64, Lsb0> {
+        assert!(
+            u < self.width() && v < self.width(),
+            "column is out of bounds"
+        );
+        let axis = axis.index();
+        let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+        let mut column = BitVec::repeat(false, self.width() as usize);
+        let mut stack = vec![(BranchIndex::root(self.height), View::root(self))];
+        while let Some((node, view)) = stack.pop() {
+            let base = [node.base.x, node.base.y, node.base.z][axis] as usize;
+            match view {
+                View::Uniform(false) => {}
+                View::Uniform(true) => column[base..base + (1 << node.height)].fill(true),
+                _ => {
+                    let mut bits = [0; 3];
+                    bits[u_axis] = (u as usize >> (node.height - 1)) & 1;
+                    bits[v_axis] = (v as usize >> (node.height - 1)) & 1;
+                    for da in 0..2 {
+                        bits[axis] = da;
+                        let i = bits[0] | bits[1] << 1 | bits[2] << 2;
+                        stack.push((
+                            node.child(bits[0], bits[1], bits[2]),
+                            view.child(self, node, i),
+                        ));
+                    }
+                }
+            }
+        }
+        column
+    }
+
+    /// Overwrites the line along an axis through the position `(u, v)` with
+    /// bits laid out as by [`column`](Self::column).
+    ///
+    /// In recording mode, this is recorded as a single edit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is outside the bitmap, or if there are not as
+    /// many bits as the width.
+    pub fn set_column(&mut self, axis: Axis, u: u32, v: u32, bits: &BitSlice<u64, Lsb0>) {
+        assert!(
+            u < self.width() && v < self.width(),
+            "column is out of bounds"
+        );
+        assert_eq!(
+            bits.len(),
+            self.width() as usize,
+            "column has the wrong length"
+        );
+        let axis = axis.index();
+        let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+        let mut coords = [0; 3];
+        coords[u_axis] = u;
+        coords[v_axis] = v;
+        let mut low = coords.map(i64::from);
+        let mut high = low.map(|c| c + 1);
+        low[axis] = 0;
+        high[axis] = i64::from(self.width());
+        let voxels: Vec<Index> = bits
+            .iter_ones()
+            .map(|i| {
+                coords[axis] = i as u32;
+                Index::from(coords)
+            })
+            .collect();
+        self.record_group(|bitmap| {
+            bitmap.fill_box(low, high, false);
+            bitmap.set_many(voxels, true);
+        });
+    }
+
+    /// The cross section at coordinate `layer` along an axis, laid out as by
+    /// [`Layer::to_bitvec`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `layer` is outside the bitmap.
+    pub fn slice_bits(&self, axis: Axis, layer: u32) -> BitVec<u64, Lsb0> {
+        self.slice(axis, layer).to_bitvec()
+    }
+
+    /// Overwrites the cross section at coordinate `layer` along an axis with
+    /// bits laid out as by [`Layer::to_bitvec`], as for
+    /// [`set_slice`](Self::set_slice).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `layer` is outside the bitmap, or if there are not
+    /// `width * width` bits.
+    pub fn set_slice_bits(&mut self, axis: Axis, layer: u32, bits: &BitSlice<u64, Lsb0>) {
+        self.set_slice(axis, layer, &Layer::from_bitslice(self.width(), bits));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bits_round_trip() {
+        let mut bitmap = OctreeBitmap::new(32);
+        bitmap.fill_sphere([20.0, 30.0, 40.0], 15.0, true);
+        bitmap.set(&Index::new(63, 1, 2), true);
+        let width = bitmap.width();
+
+        let slice = bitmap.slice(Axis::Z, 40);
+        let bits = slice.to_bitvec();
+        assert_eq!(bits.len(), (width * width) as usize);
+        assert!(bits[20 + 30 * width as usize]);
+        assert!(Layer::from_bitslice(width, &bits) == slice);
+
+        let column = bitmap.column(Axis::X, 1, 2);
+        assert_eq!(column.iter_ones().collect::<Vec<_>>(), [63]);
+        let column = bitmap.column(Axis::Y, 40, 20);
+        for y in 0..width {
+            assert_eq!(column[y as usize], bitmap.get(&Index::new(20, y, 40)));
+        }
+
+        let inverted = !column.clone();
+        bitmap.set_column(Axis::Y, 40, 20, &inverted);
+        assert_eq!(bitmap.column(Axis::Y, 40, 20), inverted);
+        assert!(!bitmap.get(&Index::new(20, 30, 40)));
+
+        let mut bits = bitmap.slice_bits(Axis::X, 5);
+        bits.fill(true);
+        bitmap.set_slice_bits(Axis::X, 5, &bits);
+        assert_eq!(bitmap.slice(Axis::X, 5).count(), u64::from(width * width));
+        assert_eq!(bitmap.validate(), Ok(()));
+    }
+}
@@ -0,0 +1,242 @@
+//! Stamping one bitmap into another.
+
+use crate::{Index, Occupancy, OctreeBitmap};
+
+/// How the voxels of a source bitmap are combined with the voxels they land
+/// on in [`OctreeBitmap::blit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlitMode {
+    /// Every voxel covered by the source takes the source's value.
+    Overwrite,
+    /// Voxels set in the source are set.
+    Union,
+    /// Voxels set in the source are cleared.
+    Subtract,
+}
+
+impl OctreeBitmap {
+    /// Combines the voxels of `src` into this bitmap, with the origin of
+    /// `src` placed at `offset`. Parts of `src` that land outside this
+    /// bitmap are ignored.
+    ///
+    /// Uniform octants of `src` are applied as boxes. Mixed octants whose
+    /// position is aligned to their size are copied as whole subtrees where
+    /// they replace or land on empty space, if both bitmaps have the same
+    /// [leaf size](Self::leaf_size), so stamping a prefab does not visit its
+    /// voxels one at a time. In recording mode, this is recorded as a single
+    /// edit.
+    pub fn blit(&mut self, src: &OctreeBitmap, offset: Index, mode: BlitMode) {
+        trace_span!("blit", ?offset, ?mode, src_branches = src.branches.len());
+        let width = i64::from(self.width());
+        let offset = [offset.x, offset.y, offset.z].map(i64::from);
+        let brick_height = self.brick_height().max(src.brick_height());
+        let mut set = Vec::new();
+        let mut cleared = Vec::new();
+        self.record_group(|bitmap| {
+            src.visit_nodes(|base, height, occupancy| {
+                let side = 1 << height;
+                let low = [base.x, base.y, base.z].map(i64::from);
+                let low = [0, 1, 2].map(|i| low[i] + offset[i]);
+                let high = low.map(|c| c + side);
+                if low.iter().any(|&c| c >= width) {
+                    return false;
+                }
+                let value = match (occupancy, mode) {
+                    (Occupancy::Mixed, _) => {
+                        let aligned = (0..3).all(|i| low[i] % side == 0 && high[i] <= width);
+                        if !aligned
+                            || height <= brick_height
+                            || height >= bitmap.height
+                            || bitmap.leaf_height != src.leaf_height
+                            || mode == BlitMode::Subtract
+                        {
+                            return true;
+                        }
+                        let target = Index::new(low[0] as u32, low[1] as u32, low[2] as u32)
+                            .branch_at(height);
+                        match (mode, bitmap.node_occupancy(target)) {
+                            (BlitMode::Overwrite, _) => bitmap.fill_node(target, false),
+                            (_, Occupancy::Empty) => {}
+                            _ => return true,
+                        }
+                        bitmap.record_subtree(target);
+                        bitmap.graft(src, base.branch_at(height), target);
+                        return false;
+                    }
+                    (Occupancy::Full, BlitMode::Subtract) => false,
+                    (Occupancy::Full, _) => true,
+                    (Occupancy::Empty, BlitMode::Overwrite) => false,
+                    (Occupancy::Empty, _) => return false,
+                };
+                if height == 0 {
+                    let voxel = Index::new(low[0] as u32, low[1] as u32, low[2] as u32);
+                    if value { &mut set } else { &mut cleared }.push(voxel);
+                } else {
+                    bitmap.fill_box(low, high, value);
+                }
+                false
+            });
+            trace_event!(set = set.len(), cleared = cleared.len(), "blit voxels");
+            bitmap.set_many(set, true);
+            bitmap.set_many(cleared, false);
+            trace_event!(branches = bitmap.branches.len(), "blit");
+        });
+    }
+
+    /// Combines the voxels from `src_min` to `src_max` (exclusive) on each
+    /// axis into the box of the same size at `dst_min` in this bitmap, as for
+    /// [`blit`](Self::blit), such as to duplicate or move a selection in an
+    /// editor.
+    ///
+    /// The source box may overlap the destination: it is copied out with
+    /// [`crop`](Self::crop) first, so every voxel is combined with its value
+    /// from before the copy. Parts of either box outside the bitmap are
+    /// ignored. With [`BlitMode::Overwrite`], only the destination box is
+    /// replaced. Octants aligned to their size in both boxes are copied as
+    /// whole subtrees. In recording mode, this is recorded as a single edit.
+    pub fn copy_region(&mut self, src_min: Index, src_max: Index, dst_min: Index, mode: BlitMode) {
+        let width = i64::from(self.width());
+        let low = [src_min.x, src_min.y, src_min.z].map(|c| i64::from(c).min(width));
+        let high = [src_max.x, src_max.y, src_max.z].map(|c| i64::from(c).min(width));
+        if (0..3).any(|i| low[i] >= high[i]) {
+            return;
+        }
+        let region = self.crop(src_min, src_max);
+        self.record_group(|bitmap| match mode {
+            BlitMode::Overwrite => {
+                // The cropped bitmap may be wider than the box, so clear the
+                // box and add the region rather than overwriting with it.
+                let dst = [dst_min.x, dst_min.y, dst_min.z].map(i64::from);
+                bitmap.fill_box(dst, [0, 1, 2].map(|i| dst[i] + high[i] - low[i]), false);
+                bitmap.blit(&region, dst_min, BlitMode::Union);
+            }
+            _ => bitmap.blit(&region, dst_min, mode),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::pseudo_random_indexes;
+
+    #[test]
+    fn blit_modes() {
+        let mut src = OctreeBitmap::new(8);
+        src.fill_sphere([8.0, 8.0, 8.0], 5.0, true);
+        src.set_many([Index::new(0, 0, 0), Index::new(15, 1, 14)], true);
+        src.set(&Index::new(8, 8, 8), false);
+
+        let make_dest = || {
+            let mut dest = OctreeBitmap::new(32);
+            dest.fill_sphere([40.0, 50.0, 8.0], 10.0, true);
+            dest.set_many(pseudo_random_indexes(64, 300), true);
+            dest
+        };
+        for offset in [Index::new(32, 48, 0), Index::new(7, 50, 57)] {
+            for mode in [BlitMode::Overwrite, BlitMode::Union, BlitMode::Subtract] {
+                let mut dest = make_dest();
+                let before = make_dest();
+                dest.start_recording();
+                dest.blit(&src, offset, mode);
+                let src_width = src.width();
+                let inside = |idx: &Index| {
+                    [(idx.x, offset.x), (idx.y, offset.y), (idx.z, offset.z)]
+                        .iter()
+                        .all(|&(c, o)| (o..o + src_width).contains(&c))
+                };
+                let outside = |bitmap: &OctreeBitmap| {
+                    bitmap.iter().filter(|idx| !inside(idx)).collect::<Vec<_>>()
+                };
+                assert_eq!(outside(&dest), outside(&before));
+                let range = |o: u32| o..(o + src_width).min(dest.width());
+                for z in range(offset.z) {
+                    for y in range(offset.y) {
+                        for x in range(offset.x) {
+                            let idx = Index::new(x, y, z);
+                            let source =
+                                src.get(&Index::new(x - offset.x, y - offset.y, z - offset.z));
+                            let old = before.get(&idx);
+                            let expected = match mode {
+                                BlitMode::Overwrite => source,
+                                BlitMode::Union => old || source,
+                                BlitMode::Subtract => old && !source,
+                            };
+                            assert_eq!(dest.get(&idx), expected, "{:?} {:?}", idx, mode);
+                        }
+                    }
+                }
+                // The blit is undone as a single edit.
+                dest.undo();
+                assert_eq!(
+                    dest.iter().collect::<Vec<_>>(),
+                    before.iter().collect::<Vec<_>>()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn copy_overlapping_regions() {
+        let mut before = OctreeBitmap::new(16);
+        before.fill_sphere([10.0, 12.0, 9.0], 7.0, true);
+        before.fill_box([16, 16, 16], [24, 24, 24], true);
+        before.set_many(pseudo_random_indexes(32, 300), true);
+        let width = before.width();
+        let boxes = [
+            (
+                Index::new(3, 5, 0),
+                Index::new(20, 22, 13),
+                Index::new(10, 8, 4),
+            ),
+            (
+                Index::new(8, 8, 8),
+                Index::new(24, 24, 24),
+                Index::new(16, 0, 16),
+            ),
+            (
+                Index::new(16, 16, 16),
+                Index::new(40, 40, 40),
+                Index::new(0, 2, 1),
+            ),
+        ];
+        for (min, max, dst) in boxes {
+            for mode in [BlitMode::Overwrite, BlitMode::Union, BlitMode::Subtract] {
+                let mut bitmap = before.clone();
+                bitmap.start_recording();
+                bitmap.copy_region(min, max, dst, mode);
+                for z in 0..width {
+                    for y in 0..width {
+                        for x in 0..width {
+                            let idx = Index::new(x, y, z);
+                            let old = before.get(&idx);
+                            let src = [
+                                (x, dst.x, min.x, max.x),
+                                (y, dst.y, min.y, max.y),
+                                (z, dst.z, min.z, max.z),
+                            ]
+                            .map(|(c, d, low, high)| {
+                                let c = (c + low).checked_sub(d)?;
+                                (c >= low && c < high.min(width)).then_some(c)
+                            });
+                            let expected = match src {
+                                [Some(x), Some(y), Some(z)] => {
+                                    let source = before.get(&Index::new(x, y, z));
+                                    match mode {
+                                        BlitMode::Overwrite => source,
+                                        BlitMode::Union => old || source,
+                                        BlitMode::Subtract => old && !source,
+                                    }
+                                }
+                                _ => old,
+                            };
+                            assert_eq!(bitmap.get(&idx), expected, "{:?} {:?}", idx, mode);
+                        }
+                    }
+                }
+                bitmap.undo();
+                assert!(bitmap == before);
+            }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Bulk construction of bitmaps from sorted keys.
+
+use crate::{Branch, BranchIndex, Index, OctreeBitmap, RawNode};
+
+impl OctreeBitmap {
+    /// A bitmap of the given width with the voxels at the Morton codes in
+    /// `keys` set, as returned by [`Index::morton`], which must be sorted in
+    /// ascending order. Repeated keys are allowed.
+    ///
+    /// The tree is built bottom up, a level at a time: the keys are packed
+    /// into bricks, and each level is built by grouping the runs of eight
+    /// siblings of the one below, which are adjacent in Morton order. This
+    /// takes time linear in the number of keys, with no lookups in the tree,
+    /// so it is much faster than calling [`set`](Self::set) for each key when
+    /// importing large point sets.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `width` is zero or greater than [`MAX_WIDTH`](Self::MAX_WIDTH),
+    /// if the keys are not sorted, or if a key is outside the bitmap.
+    pub fn from_sorted_morton(keys: &[u128], width: u32) -> OctreeBitmap {
+        trace_span!("from_sorted_morton", keys = keys.len(), width);
+        let mut bitmap = OctreeBitmap::new(width);
+        let height = bitmap.height;
+        if let Some(&last) = keys.last() {
+            assert!(
+                last >> (3 * height) == 0,
+                "key {} is outside the bitmap of width {}",
+                last,
+                bitmap.width()
+            );
+        }
+
+        // The nodes of the current level that are not empty, by the Morton
+        // code of their position among the nodes of that level.
+        let brick_height = bitmap.brick_height();
+        let mut level: Vec<(u128, RawNode)> = Vec::new();
+        let mut previous = None;
+        for &key in keys {
+            assert!(previous <= Some(key), "keys are not sorted");
+            previous = Some(key);
+            let code = key >> (3 * brick_height);
+            let bit = 1u64 << (key & ((1 << (3 * brick_height)) - 1));
+            match level.last_mut() {
+                Some((last, RawNode::Brick(bits))) if *last == code => *bits |= bit,
+                _ => level.push((code, RawNode::Brick(bit))),
+            }
+        }
+        for (_, node) in &mut level {
+            *node = RawNode::from_brick(node.to_brick(brick_height), brick_height);
+        }
+
+        for parent_height in brick_height + 1..=height {
+            let mut parents: Vec<(u128, RawNode)> = Vec::new();
+            let mut start = 0;
+            while start < level.len() {
+                let code = level[start].0 >> 3;
+                let mut children = [[[RawNode::False; 2]; 2]; 2];
+                let mut end = start;
+                while end < level.len() && level[end].0 >> 3 == code {
+                    let i = (level[end].0 & 7) as usize;
+                    children[i >> 2][(i >> 1) & 1][i & 1] = level[end].1;
+                    end += 1;
+                }
+                start = end;
+                let index = BranchIndex {
+                    base: Index::from_morton(code << (3 * parent_height)),
+                    height: parent_height,
+                };
+                let full = children == [[[RawNode::True; 2]; 2]; 2];
+                if full && parent_height < height {
+                    parents.push((code, RawNode::True));
+                } else {
+                    bitmap.branches.insert(index, Branch { children });
+                    parents.push((code, RawNode::Branch));
+                }
+            }
+            level = parents;
+        }
+        trace_event!(branches = bitmap.branches.len(), "built bitmap");
+        bitmap
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::pseudo_random_indexes;
+
+    #[test]
+    fn matches_set_many() {
+        let mut expected = OctreeBitmap::new(16);
+        expected.fill_sphere([10.0, 12.0, 9.0], 6.0, true);
+        expected.fill_with(Index::new(16, 16, 16), Index::new(32, 32, 32), |_| true);
+        expected.set_many(pseudo_random_indexes(32, 300), true);
+        let mut keys: Vec<u128> = expected.iter().map(|idx| idx.morton()).collect();
+        keys.push(keys[10]);
+        keys.sort_unstable();
+
+        let bitmap = OctreeBitmap::from_sorted_morton(&keys, 16);
+        assert_eq!(bitmap, expected);
+        assert_eq!(bitmap.branches.len(), expected.branches.len());
+        assert!(OctreeBitmap::from_sorted_morton(&[], 16).is_empty());
+        assert!(OctreeBitmap::from_sorted_morton(&[0, 1, 2, 3, 4, 5, 6, 7], 1).is_full());
+    }
+}
@@ -0,0 +1,421 @@
+//! Comparison of bitmaps by their contents.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use crate::{BranchIndex, LeafSize, OctreeBitmap, View};
+
+/// The digest of an octant, as computed by `OctreeBitmap::digest`, when it
+/// is uniformly empty or full.
+const EMPTY: u64 = 0;
+const FULL: u64 = 1;
+
+impl OctreeBitmap {
+    /// Whether the octants at the node in the two bitmaps, which have the
+    /// same height, contain the same voxels.
+    fn node_eq(&self, other: &Self, node: BranchIndex, a: View, b: View) -> bool {
+        match (a, b) {
+            (View::Uniform(a), View::Uniform(b)) => a == b,
+            (View::Brick(a), View::Brick(b)) => a == b,
+            _ => (0..8).all(|i| {
+                let child = node.child(i & 1, (i >> 1) & 1, i >> 2);
+                self.node_eq(
+                    other,
+                    child,
+                    a.child(self, node, i),
+                    b.child(other, node, i),
+                )
+            }),
+        }
+    }
+
+    /// Whether the octants at the node in the two bitmaps have a set voxel in
+    /// common.
+    fn node_intersects(&self, other: &Self, node: BranchIndex, a: View, b: View) -> bool {
+        match (a, b) {
+            (View::Uniform(false), _) | (_, View::Uniform(false)) => false,
+            // The other octant is not empty.
+            (View::Uniform(true), _) | (_, View::Uniform(true)) => true,
+            (View::Brick(a), View::Brick(b)) => a & b != 0,
+            _ => (0..8).any(|i| {
+                let child = node.child(i & 1, (i >> 1) & 1, i >> 2);
+                self.node_intersects(
+                    other,
+                    child,
+                    a.child(self, node, i),
+                    b.child(other, node, i),
+                )
+            }),
+        }
+    }
+
+    /// Whether any voxel is set in both bitmaps, such as for broad-phase
+    /// collision between two voxelized objects.
+    ///
+    /// Both trees are walked together, stopping at the first voxel they
+    /// share. Octants that are empty in either bitmap are skipped whole, as
+    /// are octants that are full in one and not empty in the other.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bitmaps have different widths.
+    pub fn intersects(&self, other: &Self) -> bool {
+        assert_eq!(
+            self.height, other.height,
+            "cannot intersect bitmaps of different widths"
+        );
+        self.node_intersects(
+            other,
+            BranchIndex::root(self.height),
+            View::root(self),
+            View::root(other),
+        )
+    }
+
+    /// The number of voxels set in the octant at the node.
+    fn node_count_set(&self, node: BranchIndex, view: View) -> u128 {
+        match view {
+            View::Uniform(false) => 0,
+            View::Uniform(true) => 1 << (3 * node.height),
+            View::Brick(bits) => u128::from(bits.count_ones()),
+            View::Branch(_) => (0..8)
+                .map(|i| {
+                    let child = node.child(i & 1, (i >> 1) & 1, i >> 2);
+                    self.node_count_set(child, view.child(self, node, i))
+                })
+                .sum(),
+        }
+    }
+
+    /// The number of voxels set in the octants at the node in both bitmaps.
+    fn node_intersection_count(&self, other: &Self, node: BranchIndex, a: View, b: View) -> u128 {
+        match (a, b) {
+            (View::Uniform(false), _) | (_, View::Uniform(false)) => 0,
+            (View::Uniform(true), b) => other.node_count_set(node, b),
+            (a, View::Uniform(true)) => self.node_count_set(node, a),
+            (View::Brick(a), View::Brick(b)) => u128::from((a & b).count_ones()),
+            _ => (0..8)
+                .map(|i| {
+                    let child = node.child(i & 1, (i >> 1) & 1, i >> 2);
+                    self.node_intersection_count(
+                        other,
+                        child,
+                        a.child(self, node, i),
+                        b.child(other, node, i),
+                    )
+                })
+                .sum(),
+        }
+    }
+
+    /// The number of voxels set in both bitmaps, such as for the
+    /// intersection over union of two voxelizations, without building their
+    /// intersection.
+    ///
+    /// Both trees are walked together. Octants that are empty in either
+    /// bitmap are skipped whole, and where one is full the count is that of
+    /// the other's octant, so only octants mixed in both are descended
+    /// together.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bitmaps have different widths.
+    pub fn intersection_count(&self, other: &Self) -> u128 {
+        assert_eq!(
+            self.height, other.height,
+            "cannot intersect bitmaps of different widths"
+        );
+        self.node_intersection_count(
+            other,
+            BranchIndex::root(self.height),
+            View::root(self),
+            View::root(other),
+        )
+    }
+
+    /// Whether every voxel set in the octant at the node in this bitmap is
+    /// also set in the other.
+    fn node_subset(&self, other: &Self, node: BranchIndex, a: View, b: View) -> bool {
+        match (a, b) {
+            (View::Uniform(false), _) | (_, View::Uniform(true)) => true,
+            // The other octant is not full, or this one is not empty.
+            (View::Uniform(true), _) | (_, View::Uniform(false)) => false,
+            (View::Brick(a), View::Brick(b)) => a & !b == 0,
+            _ => (0..8).all(|i| {
+                let child = node.child(i & 1, (i >> 1) & 1, i >> 2);
+                self.node_subset(
+                    other,
+                    child,
+                    a.child(self, node, i),
+                    b.child(other, node, i),
+                )
+            }),
+        }
+    }
+
+    /// Whether every voxel set in this bitmap is also set in `other`, such as
+    /// to check that a carved region stays within an allowed mask.
+    ///
+    /// As with [`intersects`](Self::intersects), both trees are walked
+    /// together, stopping at the first voxel that is only set in this one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bitmaps have different widths.
+    pub fn is_subset(&self, other: &Self) -> bool {
+        assert_eq!(
+            self.height, other.height,
+            "cannot compare bitmaps of different widths"
+        );
+        self.node_subset(
+            other,
+            BranchIndex::root(self.height),
+            View::root(self),
+            View::root(other),
+        )
+    }
+
+    /// Whether every voxel set in `other` is also set in this bitmap. This is
+    /// the same as `other.is_subset(self)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bitmaps have different widths.
+    pub fn contains_all(&self, other: &Self) -> bool {
+        other.is_subset(self)
+    }
+
+    /// A hash of the contents of the octant at the node, that is the same
+    /// however the octant is split into branches. The digest of each branch
+    /// below the node is passed to `record` along the way.
+    fn digest(
+        &self,
+        node: BranchIndex,
+        view: View,
+        record: &mut impl FnMut(BranchIndex, u64),
+    ) -> u64 {
+        match view {
+            View::Uniform(false) => EMPTY,
+            View::Uniform(true) => FULL,
+            View::Brick(bits) => brick_digest(bits),
+            View::Branch(_) => {
+                let children: [u64; 8] = std::array::from_fn(|i| {
+                    let child = node.child(i & 1, (i >> 1) & 1, i >> 2);
+                    self.digest(child, view.child(self, node, i), record)
+                });
+                let digest = if children == [EMPTY; 8] {
+                    EMPTY
+                } else if children == [FULL; 8] {
+                    FULL
+                } else {
+                    let mut hasher = DefaultHasher::new();
+                    children.hash(&mut hasher);
+                    hasher.finish()
+                };
+                record(node, digest);
+                digest
+            }
+        }
+    }
+
+    /// Computes the hash of the contents of every branch, for skipping
+    /// identical subtrees in [`diff_hashed`](Self::diff_hashed).
+    ///
+    /// The hashes describe the bitmap as it is now, and are not updated
+    /// when it changes. Keep them alongside a snapshot of the bitmap to
+    /// find the changes since the snapshot quickly.
+    pub fn branch_hashes(&self) -> BranchHashes {
+        let root = BranchIndex::root(self.height);
+        let mut hashes = HashMap::new();
+        let digest = self.digest(root, View::root(self), &mut |node, digest| {
+            hashes.insert(node, digest);
+        });
+        BranchHashes {
+            hashes,
+            root: digest,
+            height: self.height,
+        }
+    }
+}
+
+fn brick_digest(bits: u64) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bits.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The hashes of the contents of every branch of a bitmap, as computed by
+/// [`OctreeBitmap::branch_hashes`].
+///
+/// Octants with the same contents have the same hash, however they are split
+/// into branches, so comparing hashes detects unchanged subtrees in constant
+/// time. Different contents have the same hash only with negligible
+/// probability.
+#[derive(Debug, Clone)]
+pub struct BranchHashes {
+    hashes: HashMap<BranchIndex, u64>,
+    root: u64,
+    height: u32,
+}
+
+impl BranchHashes {
+    /// The hash of the whole bitmap. Bitmaps of the same width with the same
+    /// voxels set have the same root hash.
+    pub fn root(&self) -> u64 {
+        self.root
+    }
+
+    /// The hash of the contents of the octant at the node.
+    pub(crate) fn node(&self, node: BranchIndex, view: View) -> u64 {
+        match view {
+            View::Uniform(false) => EMPTY,
+            View::Uniform(true) => FULL,
+            View::Brick(bits) => brick_digest(bits),
+            View::Branch(_) => self.hashes[&node],
+        }
+    }
+
+    pub(crate) fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+/// Bitmaps are equal when they have the same width and the same voxels are
+/// set, regardless of how the trees are laid out. The undo history is not
+/// compared.
+impl PartialEq for OctreeBitmap {
+    fn eq(&self, other: &Self) -> bool {
+        self.height == other.height
+            && self.node_eq(
+                other,
+                BranchIndex::root(self.height),
+                View::root(self),
+                View::root(other),
+            )
+    }
+}
+
+impl Eq for OctreeBitmap {}
+
+impl Hash for OctreeBitmap {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // The digests depend on where the bricks are, so bitmaps with other
+        // leaf sizes are hashed as they would be with the default one.
+        let bitmap = self.leaves_of(LeafSize::default());
+        bitmap.height.hash(state);
+        bitmap
+            .digest(
+                BranchIndex::root(bitmap.height),
+                View::root(&bitmap),
+                &mut |_, _| {},
+            )
+            .hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::pseudo_random_indexes;
+    use crate::{Branch, Index, RawNode};
+
+    fn hash(bitmap: &OctreeBitmap) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        bitmap.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn equal_contents() {
+        let indices = pseudo_random_indexes(64, 300);
+        let mut a = OctreeBitmap::new(64);
+        a.set_many(indices.iter().copied(), true);
+        a.fill_sphere([20.0, 20.0, 20.0], 10.0, true);
+        let mut b = OctreeBitmap::new(64);
+        b.fill_sphere([20.0, 20.0, 20.0], 10.0, true);
+        for idx in indices.iter().rev() {
+            b.set(idx, true);
+        }
+        b.set(&Index::new(63, 63, 63), !b.get(&Index::new(63, 63, 63)));
+        assert!(a != b);
+        b.set(&Index::new(63, 63, 63), a.get(&Index::new(63, 63, 63)));
+        assert!(a == b);
+        assert_eq!(hash(&a), hash(&b));
+        assert!(a != OctreeBitmap::new(32));
+        assert!(OctreeBitmap::new(8) == OctreeBitmap::new(8));
+
+        // An uncompressed full octant is the same as a compressed one.
+        let mut full = OctreeBitmap::new(64);
+        let root = BranchIndex::root(full.height);
+        full.fill_node(root.child(0, 0, 0), true);
+        let mut split = OctreeBitmap::new(64);
+        split.branches.get_mut(&root).unwrap().children[0][0][0] = RawNode::Branch;
+        split.branches.insert(
+            root.child(0, 0, 0),
+            Branch {
+                children: [[[RawNode::True; 2]; 2]; 2],
+            },
+        );
+        assert!(full == split);
+        assert_eq!(hash(&full), hash(&split));
+    }
+
+    #[test]
+    fn overlap() {
+        let mut a = OctreeBitmap::new(64);
+        a.fill_sphere([20.0, 20.0, 20.0], 10.0, true);
+        let mut b = OctreeBitmap::new(64);
+        assert!(!a.intersects(&b));
+        b.fill_sphere([50.0, 50.0, 50.0], 10.0, true);
+        b.set(&Index::new(20, 20, 9), true);
+        assert!(!a.intersects(&b));
+        b.set(&Index::new(20, 20, 11), true);
+        assert!(a.intersects(&b));
+        assert!(b.intersects(&a));
+
+        let mut full = OctreeBitmap::new(64);
+        full.fill_node(BranchIndex::root(full.height), true);
+        assert!(full.intersects(&a));
+        assert!(!full.intersects(&OctreeBitmap::new(64)));
+    }
+
+    #[test]
+    fn intersection_count() {
+        let mut a = OctreeBitmap::new(32);
+        a.fill_sphere([20.0, 20.0, 20.0], 12.0, true);
+        let mut b = OctreeBitmap::new(32);
+        b.fill_box([8, 0, 0], [64, 64, 24], true);
+        b.set_many(pseudo_random_indexes(64, 300), true);
+        let expected = a.iter().filter(|idx| b.get(idx)).count() as u128;
+        assert!(expected > 1000, "{}", expected);
+        assert_eq!(a.intersection_count(&b), expected);
+        assert_eq!(b.intersection_count(&a), expected);
+
+        let mut full = OctreeBitmap::new(32);
+        full.fill_node(BranchIndex::root(full.height), true);
+        assert_eq!(full.intersection_count(&a), a.count_set());
+        assert_eq!(full.intersection_count(&full), 1 << (3 * full.height));
+        assert_eq!(a.intersection_count(&OctreeBitmap::new(32)), 0);
+    }
+
+    #[test]
+    fn subset() {
+        let mut mask = OctreeBitmap::new(64);
+        mask.fill_sphere([40.0, 40.0, 40.0], 20.0, true);
+        let mut carved = OctreeBitmap::new(64);
+        assert!(carved.is_subset(&mask));
+        carved.fill_sphere([40.0, 40.0, 42.0], 15.0, true);
+        assert!(carved.is_subset(&mask));
+        assert!(mask.contains_all(&carved));
+        assert!(!mask.is_subset(&carved));
+        assert!(mask.is_subset(&mask));
+
+        carved.set(&Index::new(40, 40, 61), true);
+        assert!(!carved.is_subset(&mask));
+        let mut full = OctreeBitmap::new(64);
+        full.fill_node(BranchIndex::root(full.height), true);
+        assert!(full.contains_all(&carved));
+        assert!(!full.is_subset(&carved));
+    }
+}
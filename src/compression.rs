@@ -0,0 +1,323 @@
+//! Compressed containers for the binary encoding.
+//!
+//! A container starts with the magic bytes `OCTZ`, a format version byte and
+//! a compression method byte, followed by the compressed binary encoding.
+//! The encoding is always run-length encoded first: uniform nodes that are
+//! next to each other in Morton order are runs of equal tag bytes, which
+//! take a few bytes however long they are. Runs of bytes are written as the
+//! byte twice, followed by the number of further repeats as a LEB128 varint.
+//! LZ4 and zstd, with the `lz4` and `zstd` features, compress the result
+//! further.
+
+use std::io::{self, BufReader, Read, Write};
+
+use crate::{BinaryError, OctreeBitmap};
+
+const MAGIC: &[u8; 4] = b"OCTZ";
+const VERSION: u8 = 1;
+
+const METHOD_RLE: u8 = 0;
+const METHOD_LZ4: u8 = 1;
+const METHOD_ZSTD: u8 = 2;
+
+/// How to compress a bitmap with [`OctreeBitmap::write_compressed`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Only run-length encoding, which is fast and already shrinks large
+    /// uniform areas to almost nothing.
+    Rle,
+    /// LZ4 on top of run-length encoding, which is fast to decompress.
+    #[cfg(feature = "lz4")]
+    Lz4,
+    /// zstd at the given level on top of run-length encoding, for the
+    /// smallest files.
+    #[cfg(feature = "zstd")]
+    Zstd { level: i32 },
+}
+
+/// A writer that run-length encodes the bytes written to it.
+struct RleWriter<W: Write> {
+    writer: W,
+    /// The byte being repeated, and how many times it has been so far.
+    run: Option<(u8, u64)>,
+}
+
+impl<W: Write> RleWriter<W> {
+    fn new(writer: W) -> Self {
+        Self { writer, run: None }
+    }
+
+    fn write_run(&mut self) -> io::Result<()> {
+        match self.run.take() {
+            None => Ok(()),
+            Some((byte, 1)) => self.writer.write_all(&[byte]),
+            Some((byte, count)) => {
+                self.writer.write_all(&[byte, byte])?;
+                let mut extra = count - 2;
+                loop {
+                    let low = (extra & 0x7f) as u8;
+                    extra >>= 7;
+                    if extra == 0 {
+                        return self.writer.write_all(&[low]);
+                    }
+                    self.writer.write_all(&[low | 0x80])?;
+                }
+            }
+        }
+    }
+
+    /// Writes the last run, returning the inner writer.
+    fn finish(mut self) -> io::Result<W> {
+        self.write_run()?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for RleWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            match &mut self.run {
+                Some((current, count)) if *current == byte => *count += 1,
+                _ => {
+                    self.write_run()?;
+                    self.run = Some((byte, 1));
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+
+    /// Flushes the inner writer. The current run is only written by
+    /// `finish`, as it may continue.
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// A reader that decodes run-length encoded bytes as they are read.
+struct RleReader<R: Read> {
+    reader: R,
+    /// A byte read ahead that is not part of the current run.
+    next: Option<u8>,
+    /// The byte being repeated, and how many more times it is.
+    run: Option<(u8, u64)>,
+}
+
+impl<R: Read> RleReader<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            next: None,
+            run: None,
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        loop {
+            match self.reader.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn read_varint(&mut self) -> io::Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..u64::BITS).step_by(7) {
+            let byte = self
+                .read_byte()?
+                .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+            value |= u64::from(byte & 0x7f)
+                .checked_shl(shift)
+                .filter(|bits| bits >> shift == u64::from(byte & 0x7f))
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "run too long"))?;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::InvalidData, "run too long"))
+    }
+}
+
+impl<R: Read> Read for RleReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut n = 0;
+        while n < buf.len() {
+            if let Some((byte, count)) = &mut self.run {
+                let len = (*count).min((buf.len() - n) as u64) as usize;
+                buf[n..n + len].fill(*byte);
+                n += len;
+                *count -= len as u64;
+                if *count == 0 {
+                    self.run = None;
+                }
+                continue;
+            }
+            let byte = match self.next.take() {
+                Some(byte) => byte,
+                None => match self.read_byte()? {
+                    Some(byte) => byte,
+                    None => break,
+                },
+            };
+            match self.read_byte()? {
+                Some(next) if next == byte => {
+                    let count = self.read_varint()?.checked_add(2).ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidData, "run too long")
+                    })?;
+                    self.run = Some((byte, count));
+                }
+                next => {
+                    self.next = next;
+                    buf[n] = byte;
+                    n += 1;
+                }
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl OctreeBitmap {
+    /// Writes the bitmap in the binary format, run-length encoded into a
+    /// compressed container.
+    pub fn write_compressed<W: Write>(
+        &self,
+        mut writer: W,
+        compression: Compression,
+    ) -> io::Result<()> {
+        let method = match compression {
+            Compression::Rle => METHOD_RLE,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => METHOD_LZ4,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { .. } => METHOD_ZSTD,
+        };
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION, method])?;
+        match compression {
+            Compression::Rle => {
+                self.write_rle(writer)?;
+            }
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => {
+                let encoder = lz4_flex::frame::FrameEncoder::new(writer);
+                self.write_rle(encoder)?.finish()?;
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { level } => {
+                let encoder = zstd::Encoder::new(writer, level)?;
+                self.write_rle(encoder)?.finish()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_rle<W: Write>(&self, writer: W) -> io::Result<W> {
+        let mut rle = RleWriter::new(writer);
+        self.write_binary(&mut rle)?;
+        rle.finish()
+    }
+
+    /// Reads a bitmap written by [`write_compressed`](Self::write_compressed),
+    /// with any of the compression methods enabled by the crate's features.
+    ///
+    /// Unlike [`from_binary`](Self::from_binary), the reader is buffered,
+    /// and may be read past the end of the container.
+    pub fn read_compressed<R: Read>(mut reader: R) -> Result<Self, BinaryError> {
+        let mut header = [0; 6];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(BinaryError::Invalid("missing magic bytes"));
+        }
+        if header[4] != VERSION {
+            return Err(BinaryError::UnsupportedVersion(header[4]));
+        }
+        match header[5] {
+            METHOD_RLE => Self::from_binary(RleReader::new(BufReader::new(reader))),
+            #[cfg(feature = "lz4")]
+            METHOD_LZ4 => {
+                let decoder = lz4_flex::frame::FrameDecoder::new(reader);
+                Self::from_binary(RleReader::new(BufReader::new(decoder)))
+            }
+            #[cfg(feature = "zstd")]
+            METHOD_ZSTD => {
+                let decoder = zstd::Decoder::new(reader)?;
+                Self::from_binary(RleReader::new(BufReader::new(decoder)))
+            }
+            method if method == METHOD_LZ4 || method == METHOD_ZSTD => Err(BinaryError::Invalid(
+                "compression method not enabled in this build",
+            )),
+            _ => Err(BinaryError::Invalid("unknown compression method")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Index;
+
+    #[test]
+    fn rle_round_trip() {
+        let data: Vec<u8> = [&[0u8; 1000][..], &[1, 2, 2, 3], &[7; 200], &[0x80; 3], &[9]].concat();
+        let mut rle = RleWriter::new(Vec::new());
+        // In pieces, so runs continue across writes.
+        for chunk in data.chunks(7) {
+            rle.write_all(chunk).unwrap();
+        }
+        let encoded = rle.finish().unwrap();
+        assert!(encoded.len() < 20);
+        let mut decoded = Vec::new();
+        RleReader::new(&encoded[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn compressed_round_trip() {
+        let mut bitmap = OctreeBitmap::new(64);
+        bitmap.fill_sphere([40.0, 50.0, 60.0], 30.0, true);
+        bitmap.set_many((0..128).map(|x| Index::new(x, x / 2, 3)), true);
+        let binary = bitmap.to_binary();
+        let methods = [
+            Compression::Rle,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { level: 3 },
+        ];
+        for compression in methods {
+            let mut bytes = Vec::new();
+            bitmap.write_compressed(&mut bytes, compression).unwrap();
+            assert!(bytes.len() < binary.len(), "{:?}", compression);
+            let read = OctreeBitmap::read_compressed(&bytes[..]).unwrap();
+            assert_eq!(read.validate(), Ok(()));
+            assert!(read == bitmap);
+        }
+        let mut bytes = Vec::new();
+        bitmap
+            .write_compressed(&mut bytes, Compression::Rle)
+            .unwrap();
+        bytes[5] = 9;
+        assert!(matches!(
+            OctreeBitmap::read_compressed(&bytes[..]),
+            Err(BinaryError::Invalid(_))
+        ));
+
+        // A run of the largest length the varint can hold, plus the two bytes
+        // before it.
+        let mut bytes = b"OCTZ\x01\x00OO".to_vec();
+        bytes.extend([0xff; 9]);
+        bytes.push(0x01);
+        assert!(matches!(
+            OctreeBitmap::read_compressed(&bytes[..]),
+            Err(BinaryError::Io(err)) if err.kind() == io::ErrorKind::InvalidData
+        ));
+    }
+}
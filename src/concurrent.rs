@@ -0,0 +1,177 @@
+//! A bitmap that can be shared between threads.
+
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{Index, OctreeBitmap};
+
+/// A three-dimensional bitmap that supports reads and writes from multiple
+/// threads at once.
+///
+/// The tree is sharded by top-level octant: each of the eight octants is an
+/// independent [`OctreeBitmap`] of half the width, behind its own lock.
+/// Accesses to different octants never contend with each other, so threads
+/// that work on spatially separate regions (such as a parallel voxelization
+/// split by region) can run without waiting on each other.
+pub struct ConcurrentOctreeBitmap {
+    shards: [RwLock<OctreeBitmap>; 8],
+    height: u32,
+}
+
+impl ConcurrentOctreeBitmap {
+    /// Creates a new, empty bitmap.
+    ///
+    /// Like [`OctreeBitmap::new`], the values of indexes on each dimension
+    /// must be within the range `0..width`. The width is always at least 4 so
+    /// that there are eight octants to shard by.
+    pub fn new(width: u32) -> Self {
+        let height = OctreeBitmap::new(width).height.max(2);
+        Self {
+            shards: std::array::from_fn(|_| RwLock::new(OctreeBitmap::with_height(height - 1))),
+            height,
+        }
+    }
+
+    /// The width of the map. Index values in each dimension must be within
+    /// the range `0..map.width()`.
+    pub fn width(&self) -> u32 {
+        1 << self.height
+    }
+
+    /// The shard containing the index, and the index relative to that shard.
+    fn locate(&self, idx: &Index) -> (&RwLock<OctreeBitmap>, Index) {
+        let half = self.height - 1;
+        let mask = (1 << half) - 1;
+        let (x, y, z) = idx.bit(half);
+        assert!(
+            idx.x >> self.height == 0 && idx.y >> self.height == 0 && idx.z >> self.height == 0,
+            "index {:?} out of bounds for width {}",
+            idx,
+            self.width()
+        );
+        (
+            &self.shards[x | y << 1 | z << 2],
+            Index::new(idx.x & mask, idx.y & mask, idx.z & mask),
+        )
+    }
+
+    fn read(shard: &RwLock<OctreeBitmap>) -> RwLockReadGuard<'_, OctreeBitmap> {
+        shard.read().expect("shard lock poisoned")
+    }
+
+    fn write(shard: &RwLock<OctreeBitmap>) -> RwLockWriteGuard<'_, OctreeBitmap> {
+        shard.write().expect("shard lock poisoned")
+    }
+
+    /// Get the current value of the bit at the given index.
+    pub fn get(&self, idx: &Index) -> bool {
+        let (shard, idx) = self.locate(idx);
+        Self::read(shard).get(&idx)
+    }
+
+    /// Set the value at the given index.
+    pub fn set(&self, idx: &Index, value: bool) {
+        let (shard, idx) = self.locate(idx);
+        Self::write(shard).set(&idx, value);
+    }
+
+    /// Set the value at all of the given indexes, as with
+    /// [`OctreeBitmap::set_many`].
+    ///
+    /// Each shard is locked once, for all of the indexes within it.
+    pub fn set_many<I>(&self, indices: I, value: bool)
+    where
+        I: IntoIterator<Item = Index>,
+    {
+        let mut groups: [Vec<Index>; 8] = Default::default();
+        for idx in indices {
+            let (shard, idx) = self.locate(&idx);
+            let octant = self
+                .shards
+                .iter()
+                .position(|s| std::ptr::eq(s, shard))
+                .unwrap();
+            groups[octant].push(idx);
+        }
+        for (shard, group) in self.shards.iter().zip(groups) {
+            if !group.is_empty() {
+                Self::write(shard).set_many(group, value);
+            }
+        }
+    }
+
+    /// Clears the map.
+    pub fn clear(&self) {
+        for shard in &self.shards {
+            Self::write(shard).clear();
+        }
+    }
+
+    /// Converts this into a single-threaded bitmap of the same width, reusing
+    /// the branches of every shard.
+    pub fn into_bitmap(self) -> OctreeBitmap {
+        OctreeBitmap::from_octants(
+            self.shards
+                .map(|shard| shard.into_inner().expect("shard lock poisoned")),
+        )
+    }
+}
+
+impl From<OctreeBitmap> for ConcurrentOctreeBitmap {
+    /// Shards a bitmap, reusing its branches.
+    ///
+    /// Bitmaps narrower than 4 are first widened to 4.
+    fn from(mut bitmap: OctreeBitmap) -> Self {
+        if bitmap.height < 2 {
+            let mut wider = bitmap.with_height_like(2);
+            wider.set_many(bitmap.iter(), true);
+            bitmap = wider;
+        }
+        let height = bitmap.height;
+        Self {
+            shards: bitmap.split().map(RwLock::new),
+            height,
+        }
+    }
+}
+
+impl From<ConcurrentOctreeBitmap> for OctreeBitmap {
+    fn from(bitmap: ConcurrentOctreeBitmap) -> Self {
+        bitmap.into_bitmap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parallel_writes() {
+        let bitmap = ConcurrentOctreeBitmap::new(32);
+        std::thread::scope(|scope| {
+            for t in 0..4 {
+                let bitmap = &bitmap;
+                scope.spawn(move || {
+                    for x in (t..32).step_by(4) {
+                        for y in 0..32 {
+                            bitmap.set(&Index::new(x, y, (x * y) % 32), true);
+                        }
+                    }
+                });
+            }
+        });
+        assert!(bitmap.get(&Index::new(3, 5, 15)));
+        assert!(!bitmap.get(&Index::new(3, 5, 16)));
+
+        let bitmap = bitmap.into_bitmap();
+        assert_eq!(bitmap.iter().count(), 32 * 32);
+        assert!(bitmap.get(&Index::new(31, 31, 1)));
+
+        let bitmap = ConcurrentOctreeBitmap::from(bitmap);
+        bitmap.set_many([Index::new(31, 31, 1), Index::new(0, 0, 0)], false);
+        assert!(!bitmap.get(&Index::new(31, 31, 1)));
+        assert!(bitmap.get(&Index::new(1, 1, 1)));
+
+        let widest = ConcurrentOctreeBitmap::new(OctreeBitmap::MAX_WIDTH);
+        assert_eq!(widest.into_bitmap().width(), 1 << 31);
+    }
+}
@@ -0,0 +1,178 @@
+//! Extracting a box of voxels into a new bitmap.
+
+use crate::{Axis, Index, Occupancy, OctreeBitmap};
+
+impl OctreeBitmap {
+    /// A new bitmap containing the voxels from `min` to `max` (exclusive) on
+    /// each axis, moved so that `min` is at the origin.
+    ///
+    /// The new bitmap is just wide enough to fit the box. Mixed octants
+    /// inside the box whose position relative to `min` is aligned to their
+    /// size have their subtrees copied whole, and uniform octants are filled
+    /// as boxes, so only the voxels of mixed octants crossing the edges of
+    /// the box are copied one at a time.
+    pub fn crop(&self, min: Index, max: Index) -> OctreeBitmap {
+        let width = i64::from(self.width());
+        let low = [min.x, min.y, min.z].map(|c| i64::from(c).min(width));
+        let high = [max.x, max.y, max.z].map(|c| i64::from(c).min(width));
+        let extent = (0..3).map(|i| (high[i] - low[i]).max(0)).max().unwrap();
+        let extent = (extent as u32).max(1);
+        self.crop_to_height(min, max, OctreeBitmap::new(extent).height)
+    }
+
+    /// The voxels from `min` to `max` (exclusive), moved as for
+    /// [`crop`](Self::crop), in a new bitmap of the given height, which must
+    /// be tall enough to fit the box.
+    fn crop_to_height(&self, min: Index, max: Index, height: u32) -> OctreeBitmap {
+        let width = i64::from(self.width());
+        let low = [min.x, min.y, min.z].map(|c| i64::from(c).min(width));
+        let high = [max.x, max.y, max.z].map(|c| i64::from(c).min(width));
+        let mut cropped = self.with_height_like(height);
+        let brick_height = self.brick_height().max(cropped.brick_height());
+
+        let mut voxels = Vec::new();
+        self.visit_nodes(|base, height, occupancy| {
+            let side = 1 << height;
+            let node_low = [base.x, base.y, base.z].map(i64::from);
+            // The part of the node inside the box, relative to the box.
+            let part_low = [0, 1, 2].map(|i| node_low[i].max(low[i]) - low[i]);
+            let part_high = [0, 1, 2].map(|i| (node_low[i] + side).min(high[i]) - low[i]);
+            if (0..3).any(|i| part_low[i] >= part_high[i]) {
+                return false;
+            }
+            match occupancy {
+                Occupancy::Empty => false,
+                Occupancy::Full if height == 0 => {
+                    voxels.push(Index::new(
+                        part_low[0] as u32,
+                        part_low[1] as u32,
+                        part_low[2] as u32,
+                    ));
+                    false
+                }
+                Occupancy::Full => {
+                    cropped.fill_box(part_low, part_high, true);
+                    false
+                }
+                Occupancy::Mixed => {
+                    let aligned = (0..3)
+                        .all(|i| part_high[i] - part_low[i] == side && part_low[i] % side == 0);
+                    if aligned && height > brick_height && height < cropped.height {
+                        let target =
+                            Index::new(part_low[0] as u32, part_low[1] as u32, part_low[2] as u32);
+                        cropped.graft(self, base.branch_at(height), target.branch_at(height));
+                        false
+                    } else {
+                        true
+                    }
+                }
+            }
+        });
+        cropped.set_many(voxels, true);
+        cropped
+    }
+
+    /// Shrinks the bitmap to the smallest width that still holds every set
+    /// voxel, reclaiming the address space left empty after cropping or
+    /// eroding it. The width stays a power of two of at least 2.
+    ///
+    /// If `rebase` is true, the set voxels are first moved so that their
+    /// bounding box starts at the origin, which may shrink the bitmap
+    /// further. Returns the offset that was subtracted from every index,
+    /// which is zero if `rebase` is false. The contents are copied as for
+    /// [`crop`](Self::crop).
+    ///
+    /// Since the indexes of the old width do not apply to the new one, the
+    /// undo history is discarded and changed regions are no longer tracked.
+    pub fn shrink_to_fit_bounds(&mut self, rebase: bool) -> Index {
+        let ranges = [Axis::X, Axis::Y, Axis::Z].map(|axis| self.extent(axis).unwrap_or(0..0));
+        let low = match rebase {
+            true => Index::new(ranges[0].start, ranges[1].start, ranges[2].start),
+            false => Index::new(0, 0, 0),
+        };
+        let high = Index::new(ranges[0].end, ranges[1].end, ranges[2].end);
+        let extent = (high.x - low.x).max(high.y - low.y).max(high.z - low.z);
+        // The smallest height whose width is at least the extent.
+        let height = (u32::BITS - extent.saturating_sub(1).leading_zeros()).max(1);
+        if height == self.height && low == Index::new(0, 0, 0) {
+            return low;
+        }
+        let shrunk = self.crop_to_height(low, high, height);
+        self.branches = shrunk.branches;
+        self.height = height;
+        if self.is_recording() {
+            self.stop_recording();
+            self.start_recording();
+        }
+        self.dirty = None;
+        low
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::pseudo_random_indexes;
+
+    #[test]
+    fn crop_matches_voxels() {
+        let mut bitmap = OctreeBitmap::new(32);
+        bitmap.fill_sphere([30.0, 34.0, 28.0], 12.0, true);
+        bitmap.set_many(pseudo_random_indexes(64, 300), true);
+
+        for (min, max) in [
+            (Index::new(16, 24, 8), Index::new(48, 40, 40)),
+            (Index::new(3, 21, 17), Index::new(40, 30, 100)),
+            (Index::new(10, 10, 10), Index::new(10, 20, 20)),
+            (Index::new(12, 12, 12), Index::new(12, 12, 12)),
+        ] {
+            let cropped = bitmap.crop(min, max);
+            let mut expected: Vec<Index> = bitmap
+                .iter()
+                .filter(|idx| {
+                    (min.x..max.x).contains(&idx.x)
+                        && (min.y..max.y).contains(&idx.y)
+                        && (min.z..max.z).contains(&idx.z)
+                })
+                .map(|idx| Index::new(idx.x - min.x, idx.y - min.y, idx.z - min.z))
+                .collect();
+            expected.sort();
+            let mut actual: Vec<Index> = cropped.iter().collect();
+            actual.sort();
+            assert_eq!(actual, expected, "{:?} {:?}", min, max);
+            assert!(cropped.width() >= (max.x - min.x).min(64 - min.x));
+        }
+    }
+    #[test]
+    fn shrink_to_fit_bounds() {
+        let mut bitmap = OctreeBitmap::new(64);
+        bitmap.fill_sphere([30.0, 34.0, 28.0], 5.0, true);
+        bitmap.set(&Index::new(21, 40, 33), true);
+        let set: Vec<Index> = bitmap.iter().collect();
+
+        let mut shrunk = bitmap.clone();
+        shrunk.start_recording();
+        assert_eq!(shrunk.shrink_to_fit_bounds(false), Index::new(0, 0, 0));
+        assert_eq!(shrunk.width(), 64);
+        assert_eq!(shrunk.iter().collect::<Vec<_>>(), set);
+        shrunk.clear();
+        assert_eq!(shrunk.shrink_to_fit_bounds(false), Index::new(0, 0, 0));
+        assert_eq!(shrunk.width(), 2);
+
+        let mut shrunk = bitmap.clone();
+        let offset = shrunk.shrink_to_fit_bounds(true);
+        assert_eq!(offset, Index::new(21, 29, 23));
+        assert_eq!(shrunk.width(), 16);
+        let mut expected: Vec<Index> = set
+            .iter()
+            .map(|idx| Index::new(idx.x - offset.x, idx.y - offset.y, idx.z - offset.z))
+            .collect();
+        expected.sort_by_key(Index::morton);
+        assert_eq!(shrunk.iter().collect::<Vec<_>>(), expected);
+
+        bitmap.fill_with(Index::new(40, 0, 0), Index::new(64, 64, 64), |_| false);
+        bitmap.fill_with(Index::new(0, 0, 0), Index::new(30, 64, 64), |_| false);
+        bitmap.shrink_to_fit_bounds(false);
+        assert_eq!(bitmap.width(), 64);
+    }
+}
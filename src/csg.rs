@@ -0,0 +1,203 @@
+//! Constructive solid geometry over bitmaps, evaluated in one pass.
+
+use crate::{BranchIndex, Index, Occupancy, OctreeBitmap, Offset, View};
+
+/// An expression combining bitmaps by set operations, evaluated into a new
+/// bitmap by [`evaluate`](Self::evaluate).
+pub enum Csg<'a> {
+    /// The set voxels of a bitmap.
+    Leaf(&'a OctreeBitmap),
+    /// The voxels set in any of the operands, or none for no operands.
+    Union(Vec<Csg<'a>>),
+    /// The voxels set in every operand, or all voxels for no operands.
+    Intersect(Vec<Csg<'a>>),
+    /// The voxels set in the first operand and not in the second.
+    Subtract(Box<Csg<'a>>, Box<Csg<'a>>),
+    /// The operand with every voxel moved by the offset. Voxels moved
+    /// outside the result are discarded.
+    Translate(Box<Csg<'a>>, Offset),
+}
+
+impl Csg<'_> {
+    /// The result of the expression, as a bitmap as wide as the widest leaf.
+    ///
+    /// The whole expression is evaluated in a single traversal of the
+    /// result, without building the result of each operation on its own:
+    /// each octant is classified against every leaf at once, and octants
+    /// that the expression makes uniform, such as the inside of a union with
+    /// a full region or everything outside an intersection, are filled whole
+    /// without descending any of the leaves further. Only the voxels where
+    /// the result is mixed are evaluated one at a time.
+    pub fn evaluate(&self) -> OctreeBitmap {
+        trace_span!("csg_evaluate");
+        let mut result = OctreeBitmap::with_height(self.height().unwrap_or(1));
+        let width = result.width();
+        let low = |idx: Index| [idx.x, idx.y, idx.z].map(i64::from);
+        result.fill_with_hint(
+            Index::new(0, 0, 0),
+            Index::new(width, width, width),
+            |idx| {
+                let low = low(idx);
+                self.classify(low, low.map(|c| c + 1)) == Occupancy::Full
+            },
+            |first, last| match self.classify(low(first), low(last).map(|c| c + 1)) {
+                Occupancy::Empty => Some(false),
+                Occupancy::Full => Some(true),
+                Occupancy::Mixed => None,
+            },
+        );
+        result
+    }
+
+    /// The height of the tallest leaf, or `None` if there are no leaves.
+    fn height(&self) -> Option<u32> {
+        match self {
+            Self::Leaf(bitmap) => Some(bitmap.height),
+            Self::Union(operands) | Self::Intersect(operands) => {
+                operands.iter().filter_map(Self::height).max()
+            }
+            Self::Subtract(a, b) => a.height().max(b.height()),
+            Self::Translate(operand, _) => operand.height(),
+        }
+    }
+
+    /// The contents of the box from `low` to `high` (exclusive) in the
+    /// result. Operands are skipped once the result is known.
+    fn classify(&self, low: [i64; 3], high: [i64; 3]) -> Occupancy {
+        match self {
+            Self::Leaf(bitmap) => bitmap.box_occupancy(low, high),
+            Self::Union(operands) => {
+                let mut all_empty = true;
+                for operand in operands {
+                    match operand.classify(low, high) {
+                        Occupancy::Full => return Occupancy::Full,
+                        Occupancy::Mixed => all_empty = false,
+                        Occupancy::Empty => {}
+                    }
+                }
+                if all_empty {
+                    Occupancy::Empty
+                } else {
+                    Occupancy::Mixed
+                }
+            }
+            Self::Intersect(operands) => {
+                let mut all_full = true;
+                for operand in operands {
+                    match operand.classify(low, high) {
+                        Occupancy::Empty => return Occupancy::Empty,
+                        Occupancy::Mixed => all_full = false,
+                        Occupancy::Full => {}
+                    }
+                }
+                if all_full {
+                    Occupancy::Full
+                } else {
+                    Occupancy::Mixed
+                }
+            }
+            Self::Subtract(a, b) => match a.classify(low, high) {
+                Occupancy::Empty => Occupancy::Empty,
+                a => match (a, b.classify(low, high)) {
+                    (_, Occupancy::Full) => Occupancy::Empty,
+                    (Occupancy::Full, Occupancy::Empty) => Occupancy::Full,
+                    _ => Occupancy::Mixed,
+                },
+            },
+            Self::Translate(operand, offset) => {
+                let offset = [offset.x, offset.y, offset.z].map(i64::from);
+                operand.classify(
+                    [0, 1, 2].map(|i| low[i] - offset[i]),
+                    [0, 1, 2].map(|i| high[i] - offset[i]),
+                )
+            }
+        }
+    }
+}
+
+impl OctreeBitmap {
+    /// The contents of the box from `low` to `high` (exclusive), where the
+    /// outside of the bitmap counts as unset. Stops descending as soon as
+    /// both set and unset voxels are found.
+    fn box_occupancy(&self, low: [i64; 3], high: [i64; 3]) -> Occupancy {
+        let width = i64::from(self.width());
+        let (mut any_set, mut any_unset) = (false, false);
+        if (0..3).any(|i| low[i] < 0 || high[i] > width) {
+            any_unset = true;
+        }
+        let mut stack = vec![(BranchIndex::root(self.height), View::root(self))];
+        while let Some((node, view)) = stack.pop() {
+            let base = [node.base.x, node.base.y, node.base.z].map(i64::from);
+            let side = 1 << node.height;
+            if (0..3).any(|i| base[i] >= high[i] || base[i] + side <= low[i]) {
+                continue;
+            }
+            match view {
+                View::Uniform(true) => any_set = true,
+                View::Uniform(false) => any_unset = true,
+                // Mixed nodes are never all set or all unset.
+                _ if (0..3).all(|i| base[i] >= low[i] && base[i] + side <= high[i]) => {
+                    return Occupancy::Mixed;
+                }
+                _ => {
+                    for i in 0..8 {
+                        let child = node.child(i & 1, (i >> 1) & 1, i >> 2);
+                        stack.push((child, view.child(self, node, i)));
+                    }
+                }
+            }
+            if any_set && any_unset {
+                return Occupancy::Mixed;
+            }
+        }
+        match any_set {
+            true => Occupancy::Full,
+            false => Occupancy::Empty,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluate_matches_voxels() {
+        let mut a = OctreeBitmap::new(16);
+        a.fill_sphere([12.0, 12.0, 12.0], 9.0, true);
+        let mut b = OctreeBitmap::new(16);
+        b.fill_box([0, 0, 0], [16, 32, 32], true);
+        b.set(&Index::new(20, 3, 4), true);
+        let mut c = OctreeBitmap::new(8);
+        c.fill_sphere([4.0, 4.0, 4.0], 3.0, true);
+
+        let offset = Offset::new(5, -2, 10);
+        let expr = Csg::Union(vec![
+            Csg::Subtract(Box::new(Csg::Leaf(&a)), Box::new(Csg::Leaf(&b))),
+            Csg::Intersect(vec![
+                Csg::Leaf(&b),
+                Csg::Translate(Box::new(Csg::Leaf(&c)), offset),
+            ]),
+        ]);
+        let result = expr.evaluate();
+        assert_eq!(result.width(), a.width());
+
+        let moved = |idx: Index| {
+            idx.checked_sub(offset)
+                .is_some_and(|idx| c.in_bounds(&idx) && c.get(&idx))
+        };
+        let expected: Vec<Index> = (0..result.width().pow(3))
+            .map(|i| Index::from_morton(i.into()))
+            .filter(|&idx| (a.get(&idx) && !b.get(&idx)) || (b.get(&idx) && moved(idx)))
+            .collect();
+        assert_eq!(result.iter().collect::<Vec<_>>(), expected);
+
+        assert!(Csg::Union(Vec::new()).evaluate().is_empty());
+        assert!(
+            Csg::Intersect(vec![Csg::Leaf(&b), Csg::Intersect(Vec::new())])
+                .evaluate()
+                .iter()
+                .eq(b.iter())
+        );
+    }
+}
@@ -0,0 +1,175 @@
+//! Access to nearby voxels without descending from the root each time.
+
+use crate::{Index, OctreeBitmap};
+
+/// A handle for reading and writing voxels of a bitmap, which remembers where
+/// the last voxel it accessed is in the tree.
+///
+/// Each access descends from the deepest branch containing both the voxel
+/// and the last one accessed, rather than from the root, so scanning through
+/// nearby voxels in turn only visits the bottom levels of the tree.
+///
+/// Created by [`OctreeBitmap::cursor`].
+pub struct OctreeCursor<'a> {
+    bitmap: &'a mut OctreeBitmap,
+    /// The last voxel accessed.
+    last: Index,
+    /// The height of the lowest branch known to contain `last`.
+    height: u32,
+}
+
+/// The height of the lowest node containing both indexes, which is above the
+/// highest bit that differs between them.
+fn common_height(a: &Index, b: &Index) -> u32 {
+    let differ = (a.x ^ b.x) | (a.y ^ b.y) | (a.z ^ b.z);
+    u32::BITS - differ.leading_zeros()
+}
+
+impl OctreeCursor<'_> {
+    /// The height of the branch to descend from to reach the given index.
+    fn start(&self, idx: &Index) -> u32 {
+        if let Err(err) = self.bitmap.check_bounds(idx) {
+            panic!("{}", err);
+        }
+        common_height(idx, &self.last).max(self.height)
+    }
+
+    /// Get the current value of the bit at the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is outside the bitmap.
+    pub fn get(&mut self, idx: &Index) -> bool {
+        let (value, height) = self.bitmap.get_from(idx, self.start(idx));
+        self.last = *idx;
+        self.height = height;
+        value
+    }
+
+    /// Set the value at the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is outside the bitmap.
+    pub fn set(&mut self, idx: &Index, value: bool) {
+        self.modify(idx, |_| value);
+    }
+
+    /// Replace the value at the given index with the result of calling `f`
+    /// with its current value, returning the previous value, as with
+    /// [`OctreeBitmap::modify`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is outside the bitmap.
+    pub fn modify(&mut self, idx: &Index, f: impl FnOnce(bool) -> bool) -> bool {
+        let (old, mut height) = self.bitmap.update_from(idx, self.start(idx), f);
+        // Writing may have merged the branch holding the voxel into its
+        // ancestors.
+        while !self.bitmap.branches.contains_key(&idx.branch_at(height)) {
+            height += 1;
+        }
+        self.last = *idx;
+        self.height = height;
+        old
+    }
+
+    /// The bitmap being accessed.
+    pub fn bitmap(&self) -> &OctreeBitmap {
+        self.bitmap
+    }
+}
+
+impl OctreeBitmap {
+    /// Get the values of the bits at all of the given indexes, in the same
+    /// order.
+    ///
+    /// The indexes are sorted along the Z-order curve first, so that each one
+    /// is found by descending from the lowest ancestor it shares with the one
+    /// before it, instead of from the root.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any index is outside the bitmap.
+    pub fn get_many(&self, indices: &[Index]) -> Vec<bool> {
+        for idx in indices {
+            if let Err(err) = self.check_bounds(idx) {
+                panic!("{}", err);
+            }
+        }
+        let mut order: Vec<usize> = (0..indices.len()).collect();
+        order.sort_unstable_by_key(|&i| indices[i].morton());
+        let mut values = vec![false; indices.len()];
+        let mut last = (Index::default(), self.height);
+        for i in order {
+            let idx = &indices[i];
+            let start = common_height(idx, &last.0).max(last.1);
+            let (value, height) = self.get_from(idx, start);
+            values[i] = value;
+            last = (*idx, height);
+        }
+        values
+    }
+
+    /// A cursor for reading and writing runs of nearby voxels quickly.
+    pub fn cursor(&mut self) -> OctreeCursor<'_> {
+        let height = self.height;
+        OctreeCursor {
+            bitmap: self,
+            last: Index::default(),
+            height,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::pseudo_random_indexes;
+
+    #[test]
+    fn cursor_matches_get_and_set() {
+        let mut expected = OctreeBitmap::new(16);
+        expected.fill_sphere([10.0, 12.0, 14.0], 9.0, true);
+        let mut bitmap = OctreeBitmap::new(16);
+        bitmap.fill_sphere([10.0, 12.0, 14.0], 9.0, true);
+        let width = bitmap.width();
+        let mut cursor = bitmap.cursor();
+        for z in 0..width {
+            for y in 0..width {
+                for x in 0..width {
+                    let idx = Index::new(x, y, z);
+                    assert_eq!(cursor.get(&idx), expected.get(&idx));
+                    let value = (x * 3 + y * 5 + z) % 7 == 0 || (x < 8 && y < 4);
+                    expected.set(&idx, value);
+                    cursor.set(&idx, value);
+                }
+            }
+        }
+        for idx in [
+            Index::new(31, 0, 0),
+            Index::new(0, 0, 0),
+            Index::new(9, 9, 9),
+        ] {
+            let value = expected.get(&idx);
+            assert_eq!(cursor.modify(&idx, |value| !value), value);
+            expected.set(&idx, !value);
+        }
+        assert_eq!(bitmap.validate(), Ok(()));
+        assert!(bitmap == expected);
+        assert_eq!(bitmap.branches.len(), expected.branches.len());
+    }
+
+    #[test]
+    fn get_many_matches_get() {
+        let mut bitmap = OctreeBitmap::new(64);
+        bitmap.fill_sphere([40.0, 50.0, 60.0], 30.0, true);
+        bitmap.set_many(pseudo_random_indexes(128, 500), true);
+        let queries: Vec<Index> = (0..5000u32)
+            .map(|i| Index::new(i * 11 % 128, i * 17 % 128, i * 37 % 128))
+            .collect();
+        let expected: Vec<bool> = queries.iter().map(|idx| bitmap.get(idx)).collect();
+        assert_eq!(bitmap.get_many(&queries), expected);
+        assert!(bitmap.get_many(&[]).is_empty());
+    }
+}
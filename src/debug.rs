@@ -0,0 +1,130 @@
+//! Descriptions of the structure of a tree, for debugging.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+
+use crate::{BranchIndex, OctreeBitmap, RawNode};
+
+/// The number of nodes of each kind at one height.
+#[derive(Default)]
+struct LevelCounts {
+    branches: usize,
+    full: usize,
+    empty: usize,
+    bricks: usize,
+}
+
+impl OctreeBitmap {
+    /// Describes the structure of the tree: the number of nodes of each kind
+    /// at each height, followed by every branch and its children, indented
+    /// by depth.
+    ///
+    /// Each node is shown with its base index and height. The output is
+    /// intended for reading while debugging, and its format may change.
+    pub fn dump_tree(&self) -> String {
+        let mut levels: BTreeMap<u32, LevelCounts> = BTreeMap::new();
+        let mut tree = String::new();
+        let root = BranchIndex::root(self.height);
+        levels.entry(root.height).or_default().branches += 1;
+        writeln!(tree, "{} branch", describe(root)).unwrap();
+        self.dump_branch(root, 1, &mut levels, &mut tree);
+
+        let mut dump = format!(
+            "width {}, height {}, {} branches\n",
+            self.width(),
+            self.height,
+            self.branches.len()
+        );
+        for (height, counts) in levels.iter().rev() {
+            writeln!(
+                dump,
+                "height {}: {} branches, {} full, {} empty, {} bricks",
+                height, counts.branches, counts.full, counts.empty, counts.bricks
+            )
+            .unwrap();
+        }
+        dump + &tree
+    }
+
+    fn dump_branch(
+        &self,
+        index: BranchIndex,
+        depth: usize,
+        levels: &mut BTreeMap<u32, LevelCounts>,
+        tree: &mut String,
+    ) {
+        let branch = &self.branches[&index];
+        for i in 0..8 {
+            let (x, y, z) = (i & 1, (i >> 1) & 1, i >> 2);
+            let child = index.child(x, y, z);
+            let counts = levels.entry(child.height).or_default();
+            let indent = "  ".repeat(depth);
+            match branch.children[z][y][x] {
+                RawNode::False => {
+                    counts.empty += 1;
+                    writeln!(tree, "{}{} empty", indent, describe(child)).unwrap();
+                }
+                RawNode::True => {
+                    counts.full += 1;
+                    writeln!(tree, "{}{} full", indent, describe(child)).unwrap();
+                }
+                RawNode::Brick(bits) => {
+                    counts.bricks += 1;
+                    writeln!(tree, "{}{} brick {:#x}", indent, describe(child), bits).unwrap();
+                }
+                RawNode::Branch => {
+                    counts.branches += 1;
+                    writeln!(tree, "{}{} branch", indent, describe(child)).unwrap();
+                    self.dump_branch(child, depth + 1, levels, tree);
+                }
+            }
+        }
+    }
+}
+
+fn describe(node: BranchIndex) -> String {
+    format!(
+        "({}, {}, {}) h{}",
+        node.base.x, node.base.y, node.base.z, node.height
+    )
+}
+
+/// Shows the size of the bitmap and of its tree. Use
+/// [`dump_tree`](OctreeBitmap::dump_tree) to see the whole tree.
+impl fmt::Debug for OctreeBitmap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OctreeBitmap")
+            .field("width", &self.width())
+            .field("branches", &self.branches.len())
+            .field("recording", &self.is_recording())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Index;
+
+    #[test]
+    fn dump_small_tree() {
+        let mut bitmap = OctreeBitmap::new(8);
+        let root = BranchIndex::root(bitmap.height);
+        bitmap.fill_node(root.child(1, 0, 0), true);
+        bitmap.set(&Index::new(0, 0, 0), true);
+        let dump = bitmap.dump_tree();
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines[0], "width 16, height 4, 2 branches");
+        assert_eq!(lines[1], "height 4: 1 branches, 0 full, 0 empty, 0 bricks");
+        assert_eq!(lines[2], "height 3: 1 branches, 1 full, 6 empty, 0 bricks");
+        assert_eq!(lines[3], "height 2: 0 branches, 0 full, 7 empty, 1 bricks");
+        assert_eq!(lines[4], "(0, 0, 0) h4 branch");
+        assert_eq!(lines[5], "  (0, 0, 0) h3 branch");
+        assert_eq!(lines[6], "    (0, 0, 0) h2 brick 0x1");
+        assert_eq!(lines[14], "  (8, 0, 0) h3 full");
+        assert_eq!(
+            format!("{:?}", bitmap),
+            "OctreeBitmap { width: 16, branches: 2, recording: false }"
+        );
+    }
+}
@@ -0,0 +1,340 @@
+//! Ingestion of DICOM image series.
+
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use dicom_dictionary_std::tags;
+use dicom_object::{DefaultDicomObject, OpenFileOptions, ReadError, Tag};
+
+use crate::{Index, OctreeBitmap};
+
+/// The retired Explicit VR Big Endian transfer syntax.
+const EXPLICIT_VR_BIG_ENDIAN: &str = "1.2.840.10008.1.2.2";
+
+/// A bitmap segmented from a DICOM series, along with its placement in
+/// patient space.
+pub struct DicomVolume {
+    /// The voxels whose Hounsfield unit value is inside the requested window.
+    ///
+    /// The `x` axis runs along each image row, `y` along each column, and `z`
+    /// through the slices in order along the slice normal.
+    pub bitmap: OctreeBitmap,
+    /// The distance between voxel centers along each axis, in millimeters.
+    pub spacing: [f64; 3],
+    /// The patient-space position of the center of voxel `(0, 0, 0)`, in
+    /// millimeters.
+    pub origin: [f64; 3],
+}
+
+/// An error encountered while reading a DICOM series.
+#[derive(Debug)]
+pub enum DicomError {
+    Read(ReadError),
+    /// A required attribute is missing or has an invalid value.
+    Attribute(&'static str),
+    /// The image uses a feature that is not supported, such as compressed
+    /// pixel data.
+    Unsupported(String),
+    /// The slices do not all have the same dimensions.
+    InconsistentSlices,
+    /// No files were given.
+    EmptySeries,
+}
+
+impl fmt::Display for DicomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read(err) => write!(f, "failed to read DICOM file: {}", err),
+            Self::Attribute(name) => write!(f, "missing or invalid DICOM attribute {}", name),
+            Self::Unsupported(what) => write!(f, "unsupported DICOM image: {}", what),
+            Self::InconsistentSlices => write!(f, "slices in the series have different sizes"),
+            Self::EmptySeries => write!(f, "DICOM series is empty"),
+        }
+    }
+}
+
+impl std::error::Error for DicomError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Read(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ReadError> for DicomError {
+    fn from(err: ReadError) -> Self {
+        Self::Read(err)
+    }
+}
+
+fn floats(
+    obj: &DefaultDicomObject,
+    tag: Tag,
+    name: &'static str,
+) -> Result<Option<Vec<f64>>, DicomError> {
+    match obj
+        .element_opt(tag)
+        .map_err(|_| DicomError::Attribute(name))?
+    {
+        Some(elem) => elem
+            .to_multi_float64()
+            .map(Some)
+            .map_err(|_| DicomError::Attribute(name)),
+        None => Ok(None),
+    }
+}
+
+fn uint(obj: &DefaultDicomObject, tag: Tag, name: &'static str) -> Result<u32, DicomError> {
+    obj.element(tag)
+        .ok()
+        .and_then(|elem| elem.to_int().ok())
+        .ok_or(DicomError::Attribute(name))
+}
+
+/// The attributes of one slice that are needed before reading its pixels.
+struct Slice {
+    object: DefaultDicomObject,
+    /// The position of the slice along the slice normal.
+    depth: f64,
+    position: [f64; 3],
+}
+
+impl Slice {
+    fn new(object: DefaultDicomObject) -> Result<Self, DicomError> {
+        let position = floats(
+            &object,
+            tags::IMAGE_POSITION_PATIENT,
+            "ImagePositionPatient",
+        )?;
+        let orientation = floats(
+            &object,
+            tags::IMAGE_ORIENTATION_PATIENT,
+            "ImageOrientationPatient",
+        )?;
+        let (position, depth) = match (position, orientation) {
+            (Some(p), Some(o)) if p.len() == 3 && o.len() == 6 => {
+                let normal = [
+                    o[1] * o[5] - o[2] * o[4],
+                    o[2] * o[3] - o[0] * o[5],
+                    o[0] * o[4] - o[1] * o[3],
+                ];
+                let depth = p[0] * normal[0] + p[1] * normal[1] + p[2] * normal[2];
+                ([p[0], p[1], p[2]], depth)
+            }
+            (Some(p), _) if p.len() == 3 => ([p[0], p[1], p[2]], p[2]),
+            // Without a position, fall back to the acquisition order.
+            _ => {
+                let instance = uint(&object, tags::INSTANCE_NUMBER, "InstanceNumber")?;
+                ([0.0; 3], instance as f64)
+            }
+        };
+        Ok(Self {
+            object,
+            depth,
+            position,
+        })
+    }
+}
+
+impl DicomVolume {
+    /// Reads a series of single-frame DICOM images (such as a CT or MR
+    /// series), one file per slice, setting each voxel whose value in
+    /// Hounsfield units lies within `window`.
+    ///
+    /// The stored pixel values are converted to Hounsfield units with each
+    /// image's rescale slope and intercept. For example, bone can be
+    /// segmented with a window of `300.0..=3000.0`, and air with
+    /// `f64::NEG_INFINITY..=-400.0`.
+    ///
+    /// The slices may be given in any order; they are sorted by their
+    /// position along the slice normal. Only uncompressed pixel data is
+    /// supported.
+    pub fn from_series<I, P>(paths: I, window: RangeInclusive<f64>) -> Result<Self, DicomError>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        // Only the headers are needed to sort the slices, so the pixel data
+        // of every slice doesn't need to be in memory at once.
+        let mut slices = paths
+            .into_iter()
+            .map(|path| {
+                let object = OpenFileOptions::new()
+                    .read_until(tags::PIXEL_DATA)
+                    .open_file(path.as_ref())?;
+                Ok((path, Slice::new(object)?))
+            })
+            .collect::<Result<Vec<_>, DicomError>>()?;
+        if slices.is_empty() {
+            return Err(DicomError::EmptySeries);
+        }
+        slices.sort_by(|(_, a), (_, b)| a.depth.total_cmp(&b.depth));
+
+        let first = &slices[0].1.object;
+        let columns = uint(first, tags::COLUMNS, "Columns")?;
+        let rows = uint(first, tags::ROWS, "Rows")?;
+        let pixel_spacing = floats(first, tags::PIXEL_SPACING, "PixelSpacing")?
+            .filter(|spacing| spacing.len() == 2)
+            .unwrap_or(vec![1.0, 1.0]);
+        let slice_spacing = if slices.len() > 1 {
+            (slices[slices.len() - 1].1.depth - slices[0].1.depth) / (slices.len() - 1) as f64
+        } else {
+            floats(first, tags::SLICE_THICKNESS, "SliceThickness")?
+                .and_then(|thickness| thickness.first().copied())
+                .unwrap_or(1.0)
+        };
+        // Pixel spacing is given as (between rows, between columns).
+        let spacing = [pixel_spacing[1], pixel_spacing[0], slice_spacing];
+        let origin = slices[0].1.position;
+
+        let width = columns.max(rows).max(slices.len() as u32).max(1);
+        let mut bitmap = OctreeBitmap::new(width);
+        for (z, (path, _)) in slices.into_iter().enumerate() {
+            let object = dicom_object::open_file(path)?;
+            if object.meta().transfer_syntax().trim_end_matches('\0') == EXPLICIT_VR_BIG_ENDIAN {
+                return Err(DicomError::Unsupported(
+                    "big endian transfer syntax".to_owned(),
+                ));
+            }
+            if uint(&object, tags::COLUMNS, "Columns")? != columns
+                || uint(&object, tags::ROWS, "Rows")? != rows
+            {
+                return Err(DicomError::InconsistentSlices);
+            }
+            if object
+                .element_opt(tags::SAMPLES_PER_PIXEL)
+                .ok()
+                .flatten()
+                .is_some_and(|elem| elem.to_int::<u32>().ok() != Some(1))
+            {
+                return Err(DicomError::Unsupported(
+                    "multiple samples per pixel".to_owned(),
+                ));
+            }
+            if object
+                .element_opt(tags::NUMBER_OF_FRAMES)
+                .ok()
+                .flatten()
+                .is_some_and(|elem| elem.to_int::<u32>().ok() != Some(1))
+            {
+                return Err(DicomError::Unsupported("multi-frame image".to_owned()));
+            }
+            let bits = uint(&object, tags::BITS_ALLOCATED, "BitsAllocated")?;
+            let signed = uint(&object, tags::PIXEL_REPRESENTATION, "PixelRepresentation")? == 1;
+            let slope = floats(&object, tags::RESCALE_SLOPE, "RescaleSlope")?
+                .and_then(|slope| slope.first().copied())
+                .unwrap_or(1.0);
+            let intercept = floats(&object, tags::RESCALE_INTERCEPT, "RescaleIntercept")?
+                .and_then(|intercept| intercept.first().copied())
+                .unwrap_or(0.0);
+            let pixels = object
+                .element(tags::PIXEL_DATA)
+                .map_err(|_| DicomError::Attribute("PixelData"))?
+                .to_bytes()
+                .map_err(|_| DicomError::Unsupported("compressed pixel data".to_owned()))?;
+
+            let sample_size = match bits {
+                8 | 16 | 32 => bits as usize / 8,
+                _ => return Err(DicomError::Unsupported(format!("{} bits allocated", bits))),
+            };
+            if pixels.len() < columns as usize * rows as usize * sample_size {
+                return Err(DicomError::Attribute("PixelData"));
+            }
+            let indices = pixels
+                .chunks_exact(sample_size)
+                .take(columns as usize * rows as usize)
+                .enumerate()
+                .filter(|(_, sample)| {
+                    let value = match (sample_size, signed) {
+                        (1, false) => sample[0] as f64,
+                        (1, true) => sample[0] as i8 as f64,
+                        (2, false) => u16::from_le_bytes([sample[0], sample[1]]) as f64,
+                        (2, true) => i16::from_le_bytes([sample[0], sample[1]]) as f64,
+                        (_, false) => u32::from_le_bytes((*sample).try_into().unwrap()) as f64,
+                        (_, true) => i32::from_le_bytes((*sample).try_into().unwrap()) as f64,
+                    };
+                    window.contains(&(value * slope + intercept))
+                })
+                .map(|(i, _)| {
+                    let i = i as u32;
+                    Index::new(i % columns, i / columns, z as u32)
+                });
+            bitmap.set_many(indices, true);
+        }
+
+        Ok(Self {
+            bitmap,
+            spacing,
+            origin,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dicom_core::{dicom_value, DataElement, PrimitiveValue, VR};
+    use dicom_dictionary_std::uids;
+    use dicom_object::meta::FileMetaTableBuilder;
+    use dicom_object::InMemDicomObject;
+
+    use super::*;
+
+    fn write_slice(path: &Path, z: f64, pixels: [i16; 4]) {
+        let pixels: Vec<u8> = pixels.iter().flat_map(|p| p.to_le_bytes()).collect();
+        let instance = format!("2.25.{}", z as u32 + 1);
+        let obj = InMemDicomObject::from_element_iter([
+            DataElement::new(tags::SOP_CLASS_UID, VR::UI, uids::CT_IMAGE_STORAGE),
+            DataElement::new(tags::SOP_INSTANCE_UID, VR::UI, instance.as_str()),
+            DataElement::new(tags::ROWS, VR::US, dicom_value!(U16, [2])),
+            DataElement::new(tags::COLUMNS, VR::US, dicom_value!(U16, [2])),
+            DataElement::new(tags::BITS_ALLOCATED, VR::US, dicom_value!(U16, [16])),
+            DataElement::new(tags::PIXEL_REPRESENTATION, VR::US, dicom_value!(U16, [1])),
+            DataElement::new(
+                tags::RESCALE_INTERCEPT,
+                VR::FD,
+                dicom_value!(F64, [-1000.0]),
+            ),
+            DataElement::new(tags::PIXEL_SPACING, VR::FD, dicom_value!(F64, [0.5, 0.75])),
+            DataElement::new(
+                tags::IMAGE_ORIENTATION_PATIENT,
+                VR::FD,
+                dicom_value!(F64, [1.0, 0.0, 0.0, 0.0, 1.0, 0.0]),
+            ),
+            DataElement::new(
+                tags::IMAGE_POSITION_PATIENT,
+                VR::FD,
+                dicom_value!(F64, [10.0, 20.0, z]),
+            ),
+            DataElement::new(tags::PIXEL_DATA, VR::OW, PrimitiveValue::from(pixels)),
+        ]);
+        obj.with_meta(FileMetaTableBuilder::new().transfer_syntax(uids::EXPLICIT_VR_LITTLE_ENDIAN))
+            .unwrap()
+            .write_to_file(path)
+            .unwrap();
+    }
+
+    #[test]
+    fn segment_series() {
+        let dir = std::env::temp_dir().join(format!("october-dicom-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths = [dir.join("b.dcm"), dir.join("a.dcm")];
+        // Stored values are offset by the -1000 intercept.
+        write_slice(&paths[0], 2.5, [0, 1500, 1000, 1400]);
+        write_slice(&paths[1], 0.0, [1300, 0, 0, 0]);
+
+        let volume = DicomVolume::from_series(&paths, 300.0..=3000.0).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(volume.spacing, [0.75, 0.5, 2.5]);
+        assert_eq!(volume.origin, [10.0, 20.0, 0.0]);
+        assert_eq!(
+            volume.bitmap.iter().collect::<Vec<_>>(),
+            [
+                Index::new(0, 0, 0),
+                Index::new(1, 0, 1),
+                Index::new(1, 1, 1)
+            ]
+        );
+    }
+}
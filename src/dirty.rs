@@ -0,0 +1,165 @@
+//! Tracking of the regions changed by edits.
+
+use std::collections::HashSet;
+
+use crate::{BranchIndex, Index, NodeStore, OctreeBitmap};
+
+/// An octant of a bitmap that was changed, as returned by
+/// [`OctreeBitmap::take_dirty`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DirtyRegion {
+    /// The lowest index in the region.
+    pub base: Index,
+    /// The region is a cube with a side length of `1 << height`.
+    pub height: u32,
+}
+
+/// The octants changed since they were last taken.
+pub(crate) struct DirtySet {
+    /// The height of the smallest octants recorded.
+    height: u32,
+    regions: HashSet<BranchIndex>,
+}
+
+impl<S: NodeStore> OctreeBitmap<S> {
+    /// Starts tracking which octants of the given width are changed by
+    /// edits, such as the chunks a renderer builds meshes for, so they can be
+    /// found with [`take_dirty`](Self::take_dirty).
+    ///
+    /// Edits of larger octants, such as [`clear`](Self::clear), are tracked
+    /// as a single region covering the whole octant. Any regions tracked
+    /// until now are discarded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_width` is not a power of two no wider than the
+    /// bitmap.
+    pub fn track_dirty(&mut self, chunk_width: u32) {
+        assert!(
+            chunk_width.is_power_of_two() && chunk_width <= self.width(),
+            "invalid chunk width {} for bitmap of width {}",
+            chunk_width,
+            self.width()
+        );
+        self.dirty = Some(Box::new(DirtySet {
+            height: chunk_width.trailing_zeros(),
+            regions: HashSet::new(),
+        }));
+    }
+
+    /// Stops tracking changed regions and discards the ones tracked.
+    pub fn stop_tracking_dirty(&mut self) {
+        self.dirty = None;
+    }
+
+    /// Whether changed regions are being tracked.
+    pub fn is_tracking_dirty(&self) -> bool {
+        self.dirty.is_some()
+    }
+
+    /// The regions changed since tracking started or this was last called,
+    /// in Morton order. No region overlaps another, and only regions tracked
+    /// as a whole are larger than the chunks being tracked.
+    ///
+    /// Regions may be included where an edit left the contents as they were.
+    pub fn take_dirty(&mut self) -> Vec<DirtyRegion> {
+        let Some(dirty) = &mut self.dirty else {
+            return Vec::new();
+        };
+        let regions = std::mem::take(&mut dirty.regions);
+        let mut dirty: Vec<DirtyRegion> = regions
+            .iter()
+            .filter(|node| {
+                (node.height + 1..=self.height)
+                    .all(|height| !regions.contains(&node.base.branch_at(height)))
+            })
+            .map(|node| DirtyRegion {
+                base: node.base,
+                height: node.height,
+            })
+            .collect();
+        dirty.sort_unstable_by_key(|region| region.base.morton());
+        dirty
+    }
+
+    /// Marks the octant at the node as changed, when tracking.
+    pub(crate) fn mark_dirty(&mut self, node: BranchIndex) {
+        if let Some(dirty) = &mut self.dirty {
+            let height = node.height.max(dirty.height);
+            dirty.regions.insert(node.base.branch_at(height));
+        }
+    }
+
+    /// Marks the voxels as changed, when tracking.
+    pub(crate) fn mark_voxels_dirty(&mut self, indices: &[Index]) {
+        for idx in indices {
+            self.mark_dirty(idx.branch_at(0));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dirty_regions() {
+        let mut bitmap = OctreeBitmap::new(64);
+        bitmap.set(&Index::new(1, 2, 3), true);
+        assert!(bitmap.take_dirty().is_empty());
+
+        bitmap.track_dirty(16);
+        bitmap.set(&Index::new(1, 2, 3), true);
+        bitmap.set(&Index::new(17, 2, 3), true);
+        // Unchanged.
+        bitmap.set(&Index::new(5, 5, 5), false);
+        bitmap.set_many([Index::new(20, 2, 3), Index::new(100, 100, 100)], true);
+        assert_eq!(
+            bitmap.take_dirty(),
+            [
+                DirtyRegion {
+                    base: Index::new(16, 0, 0),
+                    height: 4,
+                },
+                DirtyRegion {
+                    base: Index::new(96, 96, 96),
+                    height: 4,
+                },
+            ]
+        );
+        assert!(bitmap.take_dirty().is_empty());
+
+        bitmap.start_recording();
+        bitmap.set(&Index::new(40, 0, 0), true);
+        bitmap.fill_box([0, 0, 64], [64, 64, 128], true);
+        bitmap.set(&Index::new(3, 10, 70), false);
+        let regions = bitmap.take_dirty();
+        assert!(regions.contains(&DirtyRegion {
+            base: Index::new(32, 0, 0),
+            height: 4,
+        }));
+        assert!(regions.contains(&DirtyRegion {
+            base: Index::new(0, 0, 64),
+            height: 6,
+        }));
+        assert_eq!(regions.len(), 2);
+
+        assert!(bitmap.undo());
+        assert!(bitmap.undo());
+        assert_eq!(
+            bitmap.take_dirty(),
+            [DirtyRegion {
+                base: Index::new(0, 0, 64),
+                height: 6,
+            }]
+        );
+        bitmap.clear();
+        assert_eq!(
+            bitmap.take_dirty(),
+            [DirtyRegion {
+                base: Index::new(0, 0, 0),
+                height: bitmap.height,
+            }]
+        );
+    }
+}
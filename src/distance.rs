@@ -0,0 +1,171 @@
+//! Distance fields around the set voxels.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::quads::{axis_of, positive};
+use crate::{Index, OctreeBitmap};
+
+/// The distance from each unset voxel near the set voxels of a bitmap to the
+/// nearest set voxel, as returned by [`OctreeBitmap::distance_field`].
+///
+/// Only the voxels within the maximum distance are stored.
+#[derive(Debug, Clone)]
+pub struct DistanceField {
+    distances: HashMap<Index, f32>,
+    max_dist: f32,
+}
+
+impl DistanceField {
+    /// The distance from an unset voxel to the nearest set voxel, between the
+    /// centers of the voxels.
+    ///
+    /// Returns `None` for set voxels and for voxels farther than the maximum
+    /// distance, or outside the bitmap.
+    pub fn get(&self, idx: &Index) -> Option<f32> {
+        self.distances.get(idx).copied()
+    }
+
+    /// The maximum distance that was computed.
+    pub fn max_dist(&self) -> f32 {
+        self.max_dist
+    }
+
+    /// The number of voxels within the maximum distance.
+    pub fn len(&self) -> usize {
+        self.distances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.distances.is_empty()
+    }
+
+    /// Iterates over the voxels within the maximum distance and their
+    /// distances, in Morton order.
+    pub fn iter(&self) -> impl Iterator<Item = (Index, f32)> + '_ {
+        let mut voxels: Vec<(Index, f32)> = self
+            .distances
+            .iter()
+            .map(|(&idx, &dist)| (idx, dist))
+            .collect();
+        voxels.sort_unstable_by_key(|(idx, _)| idx.morton());
+        voxels.into_iter()
+    }
+}
+
+impl OctreeBitmap {
+    /// Computes the Euclidean distance from every unset voxel within
+    /// `max_dist` of a set voxel to the nearest set voxel.
+    ///
+    /// The field grows outward from the exposed faces of the set voxels,
+    /// which are found without visiting the interiors of uniform regions.
+    /// Each voxel reached takes the nearest set voxel of the neighbor it was
+    /// reached from, so the distances are exact for isolated features and
+    /// otherwise may be slightly overestimated near where two features are
+    /// equally close. Voxels outside the bitmap are ignored.
+    pub fn distance_field(&self, max_dist: f32) -> DistanceField {
+        let width = i64::from(self.width());
+        let max_squared = if max_dist >= 0.0 {
+            (max_dist as f64).powi(2)
+        } else {
+            -1.0
+        };
+        let squared =
+            |a: [i64; 3], b: [i64; 3]| -> u64 { (0..3).map(|i| a[i].abs_diff(b[i]).pow(2)).sum() };
+
+        // The queue is ordered by squared distance, then by voxel and nearest
+        // set voxel to keep it deterministic.
+        let mut queue = BinaryHeap::new();
+        for ((face, plane), cells) in self.exposed_planes() {
+            let axis = axis_of(face);
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            let plane = i64::from(plane);
+            let (set, unset) = if positive(face) {
+                (plane - 1, plane)
+            } else {
+                (plane, plane - 1)
+            };
+            if !(0..width).contains(&unset) || max_squared < 1.0 {
+                continue;
+            }
+            for [cu, cv] in cells {
+                let mut site = [0; 3];
+                site[u] = i64::from(cu);
+                site[v] = i64::from(cv);
+                site[axis] = set;
+                let mut voxel = site;
+                voxel[axis] = unset;
+                queue.push(Reverse((1, voxel, site)));
+            }
+        }
+
+        let mut distances = HashMap::new();
+        while let Some(Reverse((dist, voxel, site))) = queue.pop() {
+            let idx = Index::new(voxel[0] as u32, voxel[1] as u32, voxel[2] as u32);
+            if distances.contains_key(&idx) {
+                continue;
+            }
+            distances.insert(idx, (dist as f64).sqrt() as f32);
+            for offset in 0..27 {
+                let neighbor = [offset % 3, offset / 3 % 3, offset / 9].map(|d| d - 1);
+                let neighbor = [0, 1, 2].map(|i| voxel[i] + neighbor[i]);
+                if neighbor.iter().any(|c| !(0..width).contains(c)) {
+                    continue;
+                }
+                let dist = squared(neighbor, site);
+                let index = Index::new(neighbor[0] as u32, neighbor[1] as u32, neighbor[2] as u32);
+                if dist as f64 <= max_squared
+                    && !distances.contains_key(&index)
+                    && !self.get(&index)
+                {
+                    queue.push(Reverse((dist, neighbor, site)));
+                }
+            }
+        }
+        DistanceField {
+            distances,
+            max_dist,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distances_to_isolated_features() {
+        let mut bitmap = OctreeBitmap::new(16);
+        let width = bitmap.width();
+        // A wall at x = 0, and a voxel far enough from it that its nearest
+        // set voxel is never ambiguous within the maximum distance.
+        bitmap.set_many(
+            (0..width * width).map(|i| Index::new(0, i % width, i / width)),
+            true,
+        );
+        bitmap.set(&Index::new(20, 10, 12), true);
+
+        let field = bitmap.distance_field(3.5);
+        let mut count = 0;
+        for z in 0..width {
+            for y in 0..width {
+                for x in 0..width {
+                    let idx = Index::new(x, y, z);
+                    let squared = [(x, 20), (y, 10), (z, 12)]
+                        .iter()
+                        .map(|&(a, b): &(u32, u32)| a.abs_diff(b).pow(2))
+                        .sum::<u32>()
+                        .min(x * x);
+                    let expected = (squared as f64).sqrt() as f32;
+                    if expected > 0.0 && expected <= 3.5 {
+                        count += 1;
+                        assert_eq!(field.get(&idx), Some(expected), "{:?}", idx);
+                    } else {
+                        assert_eq!(field.get(&idx), None, "{:?}", idx);
+                    }
+                }
+            }
+        }
+        assert_eq!(field.len(), count);
+    }
+}
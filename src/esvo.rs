@@ -0,0 +1,272 @@
+//! Flattening of the tree into the layout of an Efficient Sparse Voxel
+//! Octree, for ray marching on the GPU.
+
+use std::collections::HashMap;
+
+use crate::{BranchIndex, Index, OctreeBitmap, View};
+
+const POINTER_SHIFT: u32 = 17;
+const MAX_NEAR_POINTER: usize = (1 << (u32::BITS - POINTER_SHIFT)) - 1;
+const FAR_BIT: u32 = 1 << 16;
+
+/// A bitmap laid out as a contiguous array of child descriptors, in the
+/// layout of an Efficient Sparse Voxel Octree (Laine and Karras, 2010),
+/// without contours. Created by [`OctreeBitmap::to_esvo`].
+///
+/// Each descriptor is a `u32` describing the children of one node, which
+/// are numbered in Morton order, `x | y << 1 | z << 2`:
+///
+/// - Bits 0 to 7 are the leaf mask: the children that are completely full.
+/// - Bits 8 to 15 are the valid mask: the children that have any voxels set.
+/// - Bit 16 is the far bit.
+/// - Bits 17 to 31 are the child pointer.
+///
+/// The descriptor of the root is first. The children that are valid but not
+/// leaves have descriptors of their own, stored next to each other in order.
+/// The child pointer is the offset from the descriptor to the first of them,
+/// or if the far bit is set, the offset to a word holding that offset.
+/// Children at height zero are single voxels, and are always leaves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Esvo {
+    descriptors: Vec<u32>,
+    height: u32,
+}
+
+impl Esvo {
+    /// The descriptors and far pointers, starting with the root.
+    pub fn descriptors(&self) -> &[u32] {
+        &self.descriptors
+    }
+
+    /// The descriptors and far pointers, starting with the root.
+    pub fn into_descriptors(self) -> Vec<u32> {
+        self.descriptors
+    }
+
+    /// The height of the root, so the width of the bitmap is `1 << height`.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The value of the voxel at the given index, found by descending the
+    /// descriptors as a GPU traversal would.
+    pub fn get(&self, idx: &Index) -> bool {
+        esvo_get(self.height, idx, |position| self.descriptors[position])
+    }
+}
+
+/// The value of the voxel at the given index in the descriptors of a bitmap
+/// with the given height, each read by `descriptor`.
+pub(crate) fn esvo_get(height: u32, idx: &Index, descriptor: impl Fn(usize) -> u32) -> bool {
+    let mut position = 0;
+    for height in (1..=height).rev() {
+        let current = descriptor(position);
+        let (x, y, z) = idx.bit(height - 1);
+        let i = x | y << 1 | z << 2;
+        if (current >> 8) & (1 << i) == 0 {
+            return false;
+        }
+        if current & (1 << i) != 0 {
+            return true;
+        }
+        let mut offset = (current >> POINTER_SHIFT) as usize;
+        if current & FAR_BIT != 0 {
+            offset = descriptor(position + offset) as usize;
+        }
+        let branches = (current >> 8) & !current & 0xff;
+        position += offset + (branches & ((1 << i) - 1)).count_ones() as usize;
+    }
+    // Children at height zero are always leaves, so this is only reached in
+    // invalid descriptors.
+    false
+}
+
+/// The placement of the descriptors of the children of a node, which are
+/// followed by any far pointers and then the descendants of each child.
+struct Block {
+    /// The number of children with descriptors.
+    len: usize,
+    /// The number of far pointers.
+    far: usize,
+    /// The offset from the descriptor of each child to its own block.
+    offsets: Vec<usize>,
+}
+
+impl Block {
+    /// Places the descriptors of children whose descendants take up the given
+    /// number of words.
+    fn new(sizes: &[usize]) -> Self {
+        let len = sizes.len();
+        let mut far = 0;
+        loop {
+            let mut start = len + far;
+            let offsets: Vec<usize> = sizes
+                .iter()
+                .enumerate()
+                .map(|(j, size)| {
+                    let offset = start - j;
+                    start += size;
+                    offset
+                })
+                .collect();
+            let needed = offsets.iter().filter(|&&o| o > MAX_NEAR_POINTER).count();
+            // Adding far pointers only moves the blocks further away, so this
+            // settles after at most eight rounds.
+            if needed == far {
+                return Self { len, far, offsets };
+            }
+            far = needed;
+        }
+    }
+
+    /// The number of words taken up by the block and the descendants of its
+    /// children.
+    fn size(&self, sizes: &[usize]) -> usize {
+        self.len + self.far + sizes.iter().sum::<usize>()
+    }
+}
+
+impl OctreeBitmap {
+    /// Lays out the tree as an [`Esvo`], ready to be uploaded to the GPU.
+    ///
+    /// Bricks are expanded into descriptors down to single voxels.
+    pub fn to_esvo(&self) -> Esvo {
+        let root = BranchIndex::root(self.height);
+        let mut sizes = HashMap::new();
+        self.esvo_size(root, View::Branch(root), &mut sizes);
+        let mut descriptors = vec![1 << POINTER_SHIFT];
+        self.write_esvo(root, View::Branch(root), 0, &sizes, &mut descriptors);
+        Esvo {
+            descriptors,
+            height: self.height,
+        }
+    }
+
+    /// The children of the node that have any voxels set, and the ones that
+    /// are full, as ESVO masks, along with the ones that need descriptors.
+    fn esvo_children(&self, node: BranchIndex, view: View) -> (u32, u32, Vec<(BranchIndex, View)>) {
+        let (mut valid, mut leaf) = (0, 0);
+        let mut branches = Vec::new();
+        for i in 0..8 {
+            let child = node.child(i & 1, (i >> 1) & 1, i >> 2);
+            match view.child(self, node, i) {
+                View::Uniform(false) => {}
+                View::Uniform(true) => {
+                    valid |= 1 << i;
+                    leaf |= 1 << i;
+                }
+                // Voxels are leaves, even if they are part of a brick.
+                _ if child.height == 0 => {
+                    valid |= 1 << i;
+                    leaf |= 1 << i;
+                }
+                child_view => {
+                    valid |= 1 << i;
+                    branches.push((child, child_view));
+                }
+            }
+        }
+        (valid, leaf, branches)
+    }
+
+    /// Computes the number of words taken up by the descendants of the node
+    /// and of each of its mixed descendants.
+    fn esvo_size(
+        &self,
+        node: BranchIndex,
+        view: View,
+        sizes: &mut HashMap<BranchIndex, usize>,
+    ) -> usize {
+        let (_, _, branches) = self.esvo_children(node, view);
+        let child_sizes: Vec<usize> = branches
+            .iter()
+            .map(|&(child, view)| self.esvo_size(child, view, sizes))
+            .collect();
+        let size = Block::new(&child_sizes).size(&child_sizes);
+        sizes.insert(node, size);
+        size
+    }
+
+    /// Writes the masks into the descriptor of the node, whose child pointer
+    /// has already been written, followed by its descendants.
+    fn write_esvo(
+        &self,
+        node: BranchIndex,
+        view: View,
+        position: usize,
+        sizes: &HashMap<BranchIndex, usize>,
+        descriptors: &mut Vec<u32>,
+    ) {
+        let (valid, leaf, branches) = self.esvo_children(node, view);
+        descriptors[position] |= valid << 8 | leaf;
+        let child_sizes: Vec<usize> = branches.iter().map(|(child, _)| sizes[child]).collect();
+        let block = Block::new(&child_sizes);
+        let start = descriptors.len();
+        descriptors.resize(start + block.len + block.far, 0);
+        let mut far = 0;
+        for (j, &offset) in block.offsets.iter().enumerate() {
+            let descriptor = start + j;
+            if offset <= MAX_NEAR_POINTER {
+                descriptors[descriptor] = (offset as u32) << POINTER_SHIFT;
+            } else {
+                let pointer = start + block.len + far;
+                descriptors[descriptor] =
+                    ((pointer - descriptor) as u32) << POINTER_SHIFT | FAR_BIT;
+                descriptors[pointer] = offset as u32;
+                far += 1;
+            }
+        }
+        for (j, &(child, view)) in branches.iter().enumerate() {
+            debug_assert_eq!(descriptors.len(), start + j + block.offsets[j]);
+            self.write_esvo(child, view, start + j, sizes, descriptors);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::pseudo_random_indexes;
+
+    #[test]
+    fn esvo_matches_get() {
+        let mut bitmap = OctreeBitmap::new(32);
+        let width = bitmap.width();
+        bitmap.fill_sphere([30.0, 20.0, 34.0], 18.0, true);
+        bitmap.set_many(pseudo_random_indexes(64, 2000), true);
+        let esvo = bitmap.to_esvo();
+        assert_eq!(esvo.height(), bitmap.height);
+        for z in 0..width {
+            for y in 0..width {
+                for x in 0..width {
+                    let idx = Index::new(x, y, z);
+                    assert_eq!(esvo.get(&idx), bitmap.get(&idx), "{:?}", idx);
+                }
+            }
+        }
+        let empty = OctreeBitmap::new(4).to_esvo();
+        assert_eq!(empty.descriptors(), &[1 << POINTER_SHIFT]);
+    }
+
+    #[test]
+    fn far_pointers() {
+        // Enough scattered voxels that the first descendants of the root are
+        // further than a near pointer can reach.
+        let mut bitmap = OctreeBitmap::new(128);
+        let indexes = pseudo_random_indexes(256, 20_000);
+        bitmap.set_many(indexes.iter().copied(), true);
+        let esvo = bitmap.to_esvo();
+        assert!(esvo.descriptors().len() > MAX_NEAR_POINTER);
+        // The descendants of the first children of the root come between the
+        // last ones and their descendants.
+        let root = esvo.descriptors()[0];
+        assert_eq!(root >> 8 & 0xff, 0xff);
+        assert_eq!(esvo.descriptors()[1] & FAR_BIT, 0);
+        assert_ne!(esvo.descriptors()[8] & FAR_BIT, 0);
+        for idx in indexes {
+            assert!(esvo.get(&idx));
+            let neighbor = Index::new(idx.x ^ 1, idx.y, idx.z);
+            assert_eq!(esvo.get(&neighbor), bitmap.get(&neighbor));
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! Bitmaps limited to exactly the width they were created with.
+
+use crate::{Index, NewError, OctreeBitmap, OutOfBounds};
+
+/// A bitmap whose indexes must be within the width it was created with,
+/// rather than the power of two that [`OctreeBitmap::new`] rounds it up to.
+///
+/// A bitmap created with a width of 100 is stored in a tree whose width is a
+/// larger power of two, and an [`OctreeBitmap`] accepts writes anywhere in
+/// it. This wrapper rejects indexes from 100 up instead, so an off-by-one
+/// in the caller is an error rather than a silent write into the padding,
+/// and no voxel in the padding is ever set or returned by
+/// [`iter`](Self::iter). Operations on the underlying bitmap that only read
+/// it are available through [`as_bitmap`](Self::as_bitmap).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExactOctreeBitmap {
+    bitmap: OctreeBitmap,
+    width: u32,
+}
+
+impl ExactOctreeBitmap {
+    /// Creates a new, empty bitmap of exactly the given width.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `width` is zero or greater than
+    /// [`OctreeBitmap::MAX_WIDTH`]. See [`try_new`](Self::try_new) for a
+    /// version that returns an error instead.
+    pub fn new(width: u32) -> Self {
+        match Self::try_new(width) {
+            Ok(bitmap) => bitmap,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Creates a new, empty bitmap of exactly the given width, or returns an
+    /// error if `width` is zero or greater than [`OctreeBitmap::MAX_WIDTH`].
+    pub fn try_new(width: u32) -> Result<Self, NewError> {
+        Ok(Self {
+            bitmap: OctreeBitmap::try_new(width)?,
+            width,
+        })
+    }
+
+    /// The width of the map, as given when it was created. Index values in
+    /// each dimension must be within the range `0..map.width()`.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn check_bounds(&self, idx: &Index) -> Result<(), OutOfBounds> {
+        if idx.x < self.width && idx.y < self.width && idx.z < self.width {
+            Ok(())
+        } else {
+            Err(OutOfBounds {
+                index: *idx,
+                width: self.width,
+            })
+        }
+    }
+
+    /// Get the current value of the bit at the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is outside the width. See
+    /// [`try_get`](Self::try_get) for a version that returns an error
+    /// instead.
+    pub fn get(&self, idx: &Index) -> bool {
+        if let Err(err) = self.check_bounds(idx) {
+            panic!("{}", err);
+        }
+        self.bitmap.get(idx)
+    }
+
+    /// Get the current value of the bit at the given index, or an error if
+    /// the index is outside the width.
+    pub fn try_get(&self, idx: &Index) -> Result<bool, OutOfBounds> {
+        self.check_bounds(idx)?;
+        Ok(self.bitmap.get(idx))
+    }
+
+    /// Set the value at the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is outside the width. See
+    /// [`try_set`](Self::try_set) for a version that returns an error
+    /// instead.
+    pub fn set(&mut self, idx: &Index, value: bool) {
+        if let Err(err) = self.check_bounds(idx) {
+            panic!("{}", err);
+        }
+        self.bitmap.set(idx, value);
+    }
+
+    /// Set the value at the given index, or return an error without changing
+    /// anything if the index is outside the width.
+    pub fn try_set(&mut self, idx: &Index, value: bool) -> Result<(), OutOfBounds> {
+        self.check_bounds(idx)?;
+        self.bitmap.set(idx, value);
+        Ok(())
+    }
+
+    /// Set all of the given indexes to `value`, as for
+    /// [`OctreeBitmap::set_many`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if any index is outside the width, in which case nothing is
+    /// changed.
+    pub fn set_many<I>(&mut self, indices: I, value: bool)
+    where
+        I: IntoIterator<Item = Index>,
+    {
+        let indices: Vec<Index> = indices.into_iter().collect();
+        for idx in &indices {
+            if let Err(err) = self.check_bounds(idx) {
+                panic!("{}", err);
+            }
+        }
+        self.bitmap.set_many(indices, value);
+    }
+
+    /// Set every voxel from `min` to `max` (exclusive) on each axis to the
+    /// result of calling `f` with its index, as for
+    /// [`OctreeBitmap::fill_with`]. Parts of the box outside the width are
+    /// ignored.
+    pub fn fill_with(&mut self, min: Index, max: Index, f: impl Fn(Index) -> bool) {
+        let max = Index::new(
+            max.x.min(self.width),
+            max.y.min(self.width),
+            max.z.min(self.width),
+        );
+        self.bitmap.fill_with(min, max, f);
+    }
+
+    /// Clears every voxel.
+    pub fn clear(&mut self) {
+        self.bitmap.clear();
+    }
+
+    /// An iterator over the indexes of all set voxels, in Morton order, none
+    /// of which are outside the width.
+    pub fn iter(&self) -> impl Iterator<Item = Index> + '_ {
+        self.bitmap.iter()
+    }
+
+    /// The underlying bitmap, whose [`width`](OctreeBitmap::width) is
+    /// rounded up, for queries such as ray casts. No voxel outside the
+    /// width of this bitmap is set in it.
+    pub fn as_bitmap(&self) -> &OctreeBitmap {
+        &self.bitmap
+    }
+
+    /// The underlying bitmap, which is then free to be written anywhere.
+    pub fn into_bitmap(self) -> OctreeBitmap {
+        self.bitmap
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_padding() {
+        let mut bitmap = ExactOctreeBitmap::new(10);
+        assert_eq!(bitmap.width(), 10);
+        assert!(bitmap.as_bitmap().width() > 10);
+        bitmap.set(&Index::new(9, 9, 9), true);
+        assert_eq!(bitmap.try_get(&Index::new(9, 9, 9)), Ok(true));
+        let outside = Index::new(10, 0, 3);
+        let error = OutOfBounds {
+            index: outside,
+            width: 10,
+        };
+        assert_eq!(bitmap.try_set(&outside, true), Err(error));
+        assert_eq!(bitmap.try_get(&outside), Err(error));
+        assert!(!bitmap.as_bitmap().get(&outside));
+
+        bitmap.fill_with(Index::new(5, 0, 0), Index::new(100, 100, 1), |_| true);
+        assert_eq!(bitmap.iter().count(), 5 * 10 + 1);
+        assert!(bitmap
+            .iter()
+            .all(|idx| idx.x < 10 && idx.y < 10 && idx.z < 10));
+        assert_eq!(ExactOctreeBitmap::try_new(0), Err(NewError::ZeroWidth));
+    }
+}
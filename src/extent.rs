@@ -0,0 +1,87 @@
+//! Extents of the set voxels along each axis.
+
+use std::ops::Range;
+
+use crate::{Axis, BranchIndex, OctreeBitmap, View};
+
+impl OctreeBitmap {
+    /// The range of coordinates along the axis that contain set voxels, from
+    /// the lowest to one past the highest, or `None` if the bitmap is empty.
+    ///
+    /// The ends are found by descending the tree toward each side of the
+    /// axis, visiting the nearer children first and skipping nodes that
+    /// cannot improve on the set voxels already found, so this is much
+    /// cheaper than iterating the voxels when only one axis matters.
+    pub fn extent(&self, axis: Axis) -> Option<Range<u32>> {
+        let axis = axis.index();
+        let root = BranchIndex::root(self.height);
+        let view = View::root(self);
+        let mut low = None;
+        self.extent_end(root, view, axis, false, &mut low);
+        let mut high = None;
+        self.extent_end(root, view, axis, true, &mut high);
+        Some(low?..high?)
+    }
+
+    /// Narrows `best` to the lowest start, or the highest end if `upper`, of
+    /// the set voxels in the node along the axis.
+    fn extent_end(
+        &self,
+        index: BranchIndex,
+        view: View,
+        axis: usize,
+        upper: bool,
+        best: &mut Option<u32>,
+    ) {
+        let base = [index.base.x, index.base.y, index.base.z][axis];
+        let side = 1 << index.height;
+        let reach = if upper { base + side } else { base };
+        let improves = |best: Option<u32>| match best {
+            None => true,
+            Some(best) if upper => reach > best,
+            Some(best) => reach < best,
+        };
+        if !improves(*best) {
+            return;
+        }
+        match view {
+            View::Uniform(false) => {}
+            View::Uniform(true) => *best = Some(reach),
+            _ => {
+                // The children on the side being searched for first.
+                let sides = if upper { [1, 0] } else { [0, 1] };
+                for near in sides {
+                    for i in (0..8).filter(|i| (i >> axis) & 1 == near) {
+                        let (x, y, z) = (i & 1, (i >> 1) & 1, i >> 2);
+                        let child = view.child(self, index, i);
+                        self.extent_end(index.child(x, y, z), child, axis, upper, best);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Index;
+
+    #[test]
+    fn extents_match_voxels() {
+        let mut bitmap = OctreeBitmap::new(64);
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            assert_eq!(bitmap.extent(axis), None);
+        }
+        bitmap.fill_box([8, 16, 0], [16, 32, 64], true);
+        bitmap.set(&Index::new(3, 40, 7), true);
+        bitmap.set(&Index::new(57, 41, 9), true);
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            let a = axis.index();
+            let coords: Vec<u32> = bitmap.iter().map(|idx| [idx.x, idx.y, idx.z][a]).collect();
+            let expected = *coords.iter().min().unwrap()..coords.iter().max().unwrap() + 1;
+            assert_eq!(bitmap.extent(axis), Some(expected), "{:?}", axis);
+        }
+        assert_eq!(bitmap.extent(Axis::Y), Some(16..42));
+    }
+}
@@ -0,0 +1,236 @@
+//! A C ABI for embedding bitmaps in programs written in other languages.
+//!
+//! Bitmaps are passed as opaque pointers created by [`october_new`] or
+//! [`october_from_binary`], and must be freed with [`october_free`]. No
+//! function panics across the boundary: invalid arguments are reported
+//! through return values instead.
+//!
+//! A matching C declaration of each function is given in its documentation.
+
+use std::ffi::c_void;
+use std::os::raw::c_int;
+use std::{ptr, slice};
+
+use crate::{Index, OctreeBitmap};
+
+/// Called by [`october_for_each`] with each set voxel, and the `user`
+/// pointer. Iteration stops early if it returns `false`.
+pub type OctoberVisit = extern "C" fn(user: *mut c_void, x: u32, y: u32, z: u32) -> bool;
+
+/// Creates a new, empty bitmap, or returns null if the width is zero or too
+/// large.
+///
+/// ```c
+/// OctreeBitmap *october_new(uint32_t width);
+/// ```
+#[no_mangle]
+pub extern "C" fn october_new(width: u32) -> *mut OctreeBitmap {
+    match OctreeBitmap::try_new(width) {
+        Ok(bitmap) => Box::into_raw(Box::new(bitmap)),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Frees a bitmap. Does nothing if `bitmap` is null.
+///
+/// ```c
+/// void october_free(OctreeBitmap *bitmap);
+/// ```
+///
+/// # Safety
+///
+/// `bitmap` must be null or a pointer returned by this module that has not
+/// been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn october_free(bitmap: *mut OctreeBitmap) {
+    if !bitmap.is_null() {
+        drop(Box::from_raw(bitmap));
+    }
+}
+
+/// The width of the bitmap.
+///
+/// ```c
+/// uint32_t october_width(const OctreeBitmap *bitmap);
+/// ```
+///
+/// # Safety
+///
+/// `bitmap` must be a valid pointer to a bitmap.
+#[no_mangle]
+pub unsafe extern "C" fn october_width(bitmap: *const OctreeBitmap) -> u32 {
+    (*bitmap).width()
+}
+
+/// The value of the voxel at the given index: `1` if it is set, `0` if it is
+/// not, or `-1` if the index is out of bounds.
+///
+/// ```c
+/// int october_get(const OctreeBitmap *bitmap, uint32_t x, uint32_t y, uint32_t z);
+/// ```
+///
+/// # Safety
+///
+/// `bitmap` must be a valid pointer to a bitmap.
+#[no_mangle]
+pub unsafe extern "C" fn october_get(bitmap: *const OctreeBitmap, x: u32, y: u32, z: u32) -> c_int {
+    match (*bitmap).try_get(&Index::new(x, y, z)) {
+        Ok(value) => c_int::from(value),
+        Err(_) => -1,
+    }
+}
+
+/// Sets the value of the voxel at the given index, returning `0`, or `-1` if
+/// the index is out of bounds.
+///
+/// ```c
+/// int october_set(OctreeBitmap *bitmap, uint32_t x, uint32_t y, uint32_t z, bool value);
+/// ```
+///
+/// # Safety
+///
+/// `bitmap` must be a valid pointer to a bitmap, which is not being accessed
+/// by any other thread.
+#[no_mangle]
+pub unsafe extern "C" fn october_set(
+    bitmap: *mut OctreeBitmap,
+    x: u32,
+    y: u32,
+    z: u32,
+    value: bool,
+) -> c_int {
+    match (*bitmap).try_set(&Index::new(x, y, z), value) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// Calls `visit` with each set voxel of the bitmap in Morton order, until it
+/// returns `false`.
+///
+/// ```c
+/// typedef bool (*october_visit)(void *user, uint32_t x, uint32_t y, uint32_t z);
+/// void october_for_each(const OctreeBitmap *bitmap, october_visit visit, void *user);
+/// ```
+///
+/// # Safety
+///
+/// `bitmap` must be a valid pointer to a bitmap, which must not be modified
+/// by `visit`.
+#[no_mangle]
+pub unsafe extern "C" fn october_for_each(
+    bitmap: *const OctreeBitmap,
+    visit: OctoberVisit,
+    user: *mut c_void,
+) {
+    for idx in (*bitmap).iter() {
+        if !visit(user, idx.x, idx.y, idx.z) {
+            break;
+        }
+    }
+}
+
+/// Encodes the bitmap in the binary format, returning a buffer that must be
+/// freed with [`october_free_bytes`] and storing its length in `len`.
+///
+/// ```c
+/// uint8_t *october_to_binary(const OctreeBitmap *bitmap, size_t *len);
+/// ```
+///
+/// # Safety
+///
+/// `bitmap` must be a valid pointer to a bitmap, and `len` a valid pointer
+/// to write the length to.
+#[no_mangle]
+pub unsafe extern "C" fn october_to_binary(
+    bitmap: *const OctreeBitmap,
+    len: *mut usize,
+) -> *mut u8 {
+    let bytes = (*bitmap).to_binary().into_boxed_slice();
+    *len = bytes.len();
+    Box::into_raw(bytes) as *mut u8
+}
+
+/// Frees a buffer returned by [`october_to_binary`]. Does nothing if `bytes`
+/// is null.
+///
+/// ```c
+/// void october_free_bytes(uint8_t *bytes, size_t len);
+/// ```
+///
+/// # Safety
+///
+/// `bytes` must be null or a buffer returned by [`october_to_binary`] that
+/// has not been freed yet, and `len` its length.
+#[no_mangle]
+pub unsafe extern "C" fn october_free_bytes(bytes: *mut u8, len: usize) {
+    if !bytes.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(bytes, len)));
+    }
+}
+
+/// Decodes a bitmap in the binary format, or returns null if the data is
+/// invalid.
+///
+/// ```c
+/// OctreeBitmap *october_from_binary(const uint8_t *bytes, size_t len);
+/// ```
+///
+/// # Safety
+///
+/// `bytes` must be a valid pointer to `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn october_from_binary(bytes: *const u8, len: usize) -> *mut OctreeBitmap {
+    match OctreeBitmap::from_binary(slice::from_raw_parts(bytes, len)) {
+        Ok(bitmap) => Box::into_raw(Box::new(bitmap)),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern "C" fn collect(user: *mut c_void, x: u32, y: u32, z: u32) -> bool {
+        let indexes = unsafe { &mut *(user as *mut Vec<Index>) };
+        indexes.push(Index::new(x, y, z));
+        indexes.len() < 3
+    }
+
+    #[test]
+    fn c_abi_round_trip() {
+        assert!(october_new(0).is_null());
+        unsafe {
+            let bitmap = october_new(16);
+            let width = october_width(bitmap);
+            assert_eq!(october_set(bitmap, 1, 2, 3, true), 0);
+            assert_eq!(october_set(bitmap, 5, 2, 3, true), 0);
+            assert_eq!(october_set(bitmap, width, 0, 0, true), -1);
+            assert_eq!(october_get(bitmap, 1, 2, 3), 1);
+            assert_eq!(october_get(bitmap, 2, 2, 3), 0);
+            assert_eq!(october_get(bitmap, 0, 0, width), -1);
+
+            let mut len = 0;
+            let bytes = october_to_binary(bitmap, &mut len);
+            let copy = october_from_binary(bytes, len);
+            october_free_bytes(bytes, len);
+            assert!(!copy.is_null());
+            assert_eq!(*copy, *bitmap);
+            assert!(october_from_binary([0u8; 3].as_ptr(), 3).is_null());
+
+            october_set(copy, 0, 0, 0, true);
+            october_set(copy, 9, 9, 9, true);
+            let mut indexes = Vec::new();
+            october_for_each(
+                copy,
+                collect,
+                &mut indexes as *mut Vec<Index> as *mut c_void,
+            );
+            assert_eq!(indexes, (*copy).iter().take(3).collect::<Vec<_>>());
+
+            october_free(copy);
+            october_free(bitmap);
+            october_free(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,476 @@
+//! Filling of shapes by classifying whole octants.
+
+use crate::{BranchIndex, Index, OctreeBitmap};
+
+/// Where a region lies relative to a shape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Containment {
+    Outside,
+    Inside,
+    Partial,
+}
+
+/// A shape that can be filled by [`OctreeBitmap::fill_shape`].
+pub(crate) trait Shape {
+    /// Where the axis-aligned box from `low` to `high` (inclusive) lies
+    /// relative to the shape. This may be conservative, returning `Partial`
+    /// for boxes that are really inside or outside, but must be exact when
+    /// `low == high`.
+    fn classify(&self, low: [f64; 3], high: [f64; 3]) -> Containment;
+}
+
+/// An axis-aligned ellipsoid; a sphere when all radii are equal.
+struct Ellipsoid {
+    center: [f64; 3],
+    radii: [f64; 3],
+}
+
+impl Shape for Ellipsoid {
+    fn classify(&self, low: [f64; 3], high: [f64; 3]) -> Containment {
+        // Scaled by the radii, the ellipsoid is the unit sphere.
+        let mut near = 0.0;
+        let mut far = 0.0;
+        for i in 0..3 {
+            let low = (low[i] - self.center[i]) / self.radii[i];
+            let high = (high[i] - self.center[i]) / self.radii[i];
+            let nearest = if low > 0.0 {
+                low
+            } else if high < 0.0 {
+                high
+            } else {
+                0.0
+            };
+            near += nearest * nearest;
+            far += low.abs().max(high.abs()).powi(2);
+        }
+        if near > 1.0 {
+            Containment::Outside
+        } else if far <= 1.0 {
+            Containment::Inside
+        } else {
+            Containment::Partial
+        }
+    }
+}
+
+/// An axis-aligned box, given by the range of voxel centers it contains.
+struct Cuboid {
+    low: [f64; 3],
+    high: [f64; 3],
+}
+
+impl Shape for Cuboid {
+    fn classify(&self, low: [f64; 3], high: [f64; 3]) -> Containment {
+        if (0..3).any(|i| low[i] > self.high[i] || high[i] < self.low[i]) {
+            Containment::Outside
+        } else if (0..3).all(|i| low[i] >= self.low[i] && high[i] <= self.high[i]) {
+            Containment::Inside
+        } else {
+            Containment::Partial
+        }
+    }
+}
+
+/// A box with arbitrary orientation, whose local axes are the given
+/// orthonormal directions.
+struct OrientedBox {
+    center: [f64; 3],
+    half_extents: [f64; 3],
+    axes: [[f64; 3]; 3],
+}
+
+impl OrientedBox {
+    /// The position of the point in the box's local coordinates.
+    fn local(&self, point: [f64; 3]) -> [f64; 3] {
+        let d: [f64; 3] = std::array::from_fn(|i| point[i] - self.center[i]);
+        self.axes.map(|axis| (0..3).map(|i| d[i] * axis[i]).sum())
+    }
+}
+
+impl Shape for OrientedBox {
+    fn classify(&self, low: [f64; 3], high: [f64; 3]) -> Containment {
+        // Separating axes: those of the bitmap and those of the box. The
+        // cross products of the two are not tested, so a few boxes near an
+        // edge of this one are only found to be partial.
+        let separated_on_grid = (0..3).any(|i| {
+            let extent: f64 = (0..3)
+                .map(|j| self.axes[j][i].abs() * self.half_extents[j])
+                .sum();
+            low[i] > self.center[i] + extent || high[i] < self.center[i] - extent
+        });
+        let middle: [f64; 3] = std::array::from_fn(|i| (low[i] + high[i]) / 2.0);
+        let half: [f64; 3] = std::array::from_fn(|i| (high[i] - low[i]) / 2.0);
+        let local = self.local(middle);
+        let separated_on_box = (0..3).any(|j| {
+            let radius: f64 = (0..3).map(|i| self.axes[j][i].abs() * half[i]).sum();
+            local[j].abs() > self.half_extents[j] + radius
+        });
+        if separated_on_grid || separated_on_box {
+            return Containment::Outside;
+        }
+        let inside = (0..8).all(|corner| {
+            let point = std::array::from_fn(|i| {
+                if corner >> i & 1 == 0 {
+                    low[i]
+                } else {
+                    high[i]
+                }
+            });
+            let local = self.local(point);
+            (0..3).all(|j| local[j].abs() <= self.half_extents[j])
+        });
+        if inside {
+            Containment::Inside
+        } else {
+            Containment::Partial
+        }
+    }
+}
+
+/// The state of [`OctreeBitmap::fill_with_hint`]: the box being filled and
+/// the values found for it so far.
+struct Pattern<F, H> {
+    low: [u32; 3],
+    high: [u32; 3],
+    f: F,
+    hint: H,
+    /// Uniform octants to fill whole.
+    nodes: Vec<(BranchIndex, bool)>,
+    /// The remaining voxels to clear and to set.
+    voxels: [Vec<Index>; 2],
+}
+
+impl<F, H> Pattern<F, H>
+where
+    F: Fn(Index) -> bool,
+    H: Fn(Index, Index) -> Option<bool>,
+{
+    /// Evaluates the voxels of the node inside the box. If the node is inside
+    /// the box and uniform, returns its value and leaves filling it to the
+    /// caller, so that it can be merged with its siblings.
+    fn evaluate(&mut self, node: BranchIndex) -> Option<bool> {
+        let base = [node.base.x, node.base.y, node.base.z];
+        let side = 1 << node.height;
+        if (0..3).any(|i| base[i] >= self.high[i] || base[i] + side <= self.low[i]) {
+            return None;
+        }
+        let inside = (0..3).all(|i| base[i] >= self.low[i] && base[i] + side <= self.high[i]);
+        if node.height == 0 {
+            return Some((self.f)(node.base));
+        }
+        if inside {
+            let last = Index::from(base.map(|c| c + (side - 1)));
+            if let Some(value) = (self.hint)(node.base, last) {
+                return Some(value);
+            }
+        }
+        let children: Vec<(BranchIndex, Option<bool>)> = (0..8)
+            .map(|i| {
+                let child = node.child(i & 1, (i >> 1) & 1, i >> 2);
+                (child, self.evaluate(child))
+            })
+            .collect();
+        let first = children[0].1;
+        if inside && children.iter().all(|&(_, value)| value == first) {
+            return first;
+        }
+        for (child, value) in children {
+            match value {
+                Some(value) if child.height == 0 => self.voxels[value as usize].push(child.base),
+                Some(value) => self.nodes.push((child, value)),
+                None => {}
+            }
+        }
+        None
+    }
+}
+
+impl OctreeBitmap {
+    /// Set every voxel whose center is inside the shape to `value`.
+    ///
+    /// Octants entirely inside the shape are filled whole and octants outside
+    /// it are skipped, so only the voxels on its boundary are visited one at
+    /// a time. In recording mode, this is recorded as a single edit.
+    pub(crate) fn fill_shape<S: Shape>(&mut self, shape: &S, value: bool) {
+        trace_span!("fill_shape", value);
+        let mut voxels = Vec::new();
+        let mut stack = vec![BranchIndex::root(self.height)];
+        self.record_group(|bitmap| {
+            while let Some(node) = stack.pop() {
+                // The box spanned by the centers of the voxels in the node.
+                let low = [node.base.x, node.base.y, node.base.z].map(|c| c as f64 + 0.5);
+                let high = low.map(|c| c + ((1u64 << node.height) - 1) as f64);
+                match shape.classify(low, high) {
+                    Containment::Outside => {}
+                    Containment::Inside if node.height == 0 => voxels.push(node.base),
+                    Containment::Inside => bitmap.fill_node(node, value),
+                    Containment::Partial => {
+                        debug_assert!(node.height > 0, "partial voxel");
+                        for i in 0..8 {
+                            stack.push(node.child(i & 1, (i >> 1) & 1, i >> 2));
+                        }
+                    }
+                }
+            }
+            trace_event!(voxels = voxels.len(), "filled shape interior");
+            bitmap.set_many(voxels, value);
+            trace_event!(branches = bitmap.branches.len(), "filled shape");
+        });
+    }
+
+    /// Set every voxel from `min` to `max` (exclusive) on each axis to the
+    /// result of calling `f` with its index, such as to stamp procedural
+    /// patterns. Parts of the box outside the bitmap are ignored.
+    ///
+    /// `f` is called once for each voxel, but octants where it gives the same
+    /// value for every voxel are filled whole rather than voxel by voxel. See
+    /// [`fill_with_hint`](Self::fill_with_hint) to skip calling `f` for such
+    /// octants. In recording mode, this is recorded as a single edit.
+    pub fn fill_with(&mut self, min: Index, max: Index, f: impl Fn(Index) -> bool) {
+        self.fill_with_hint(min, max, f, |_, _| None);
+    }
+
+    /// Set every voxel from `min` to `max` (exclusive) on each axis to the
+    /// result of calling `f` with its index, as for
+    /// [`fill_with`](Self::fill_with).
+    ///
+    /// Before descending into an octant inside the box, `hint` is called with
+    /// its lowest and highest (inclusive) indexes, and returns the value `f`
+    /// gives every voxel in it if that is known to be constant, such as for
+    /// an octant entirely on one side of a plane, or `None` otherwise.
+    /// Octants with a known value are filled whole without calling `f`.
+    pub fn fill_with_hint(
+        &mut self,
+        min: Index,
+        max: Index,
+        f: impl Fn(Index) -> bool,
+        hint: impl Fn(Index, Index) -> Option<bool>,
+    ) {
+        trace_span!("fill_with", ?min, ?max);
+        let width = self.width();
+        let low = [min.x, min.y, min.z];
+        let high = [max.x, max.y, max.z].map(|c| c.min(width));
+        if (0..3).any(|i| low[i] >= high[i]) {
+            return;
+        }
+        let mut pattern = Pattern {
+            low,
+            high,
+            f,
+            hint,
+            nodes: Vec::new(),
+            voxels: [Vec::new(), Vec::new()],
+        };
+        if let Some(value) = pattern.evaluate(BranchIndex::root(self.height)) {
+            pattern.nodes.push((BranchIndex::root(self.height), value));
+        }
+        trace_event!(
+            nodes = pattern.nodes.len(),
+            voxels = pattern.voxels[0].len() + pattern.voxels[1].len(),
+            "evaluated pattern"
+        );
+        self.record_group(|bitmap| {
+            for (node, value) in pattern.nodes {
+                bitmap.fill_node(node, value);
+            }
+            let [clear, set] = pattern.voxels;
+            bitmap.set_many(clear, false);
+            bitmap.set_many(set, true);
+        });
+    }
+
+    /// Set every voxel from `low` to `high` (exclusive) on each axis to
+    /// `value`. The box may extend past the bitmap, which is ignored.
+    pub(crate) fn fill_box(&mut self, low: [i64; 3], high: [i64; 3], value: bool) {
+        if (0..3).any(|i| low[i] >= high[i]) {
+            return;
+        }
+        self.fill_shape(
+            &Cuboid {
+                low: low.map(|c| c as f64 + 0.5),
+                high: high.map(|c| c as f64 - 0.5),
+            },
+            value,
+        );
+    }
+
+    /// Set every voxel whose center is within `radius` of `center` to
+    /// `value`.
+    ///
+    /// The sphere is in the space where the voxel at index `(x, y, z)` spans
+    /// `x..x + 1` (and so on) along each axis, so the center of that voxel
+    /// is at `x + 0.5`. Parts of the sphere outside the bitmap are ignored.
+    /// Octants are classified as inside, outside or crossing the surface
+    /// while descending the tree, so the interior is filled in large blocks.
+    pub fn fill_sphere(&mut self, center: [f32; 3], radius: f32, value: bool) {
+        self.fill_ellipsoid(center, [radius; 3], value);
+    }
+
+    /// Set every voxel whose center is inside an axis-aligned ellipsoid to
+    /// `value`, as for [`fill_sphere`](Self::fill_sphere).
+    ///
+    /// `radii` are the semi-axes along `x`, `y` and `z`. Nothing is filled if
+    /// any of them is not positive.
+    pub fn fill_ellipsoid(&mut self, center: [f32; 3], radii: [f32; 3], value: bool) {
+        if radii.iter().any(|&r| r.is_nan() || r <= 0.0) {
+            return;
+        }
+        self.fill_shape(
+            &Ellipsoid {
+                center: center.map(f64::from),
+                radii: radii.map(f64::from),
+            },
+            value,
+        );
+    }
+
+    /// Set every voxel whose center is inside a rotated box to `value`, such
+    /// as to stamp a prefab at an arbitrary orientation.
+    ///
+    /// The box has its center at `center`, in the same space as for
+    /// [`fill_sphere`](Self::fill_sphere), and reaches `half_extents[j]` from
+    /// it along its `j`th local axis in each direction. The columns of
+    /// `rotation` are the directions of the local `x`, `y` and `z` axes, so
+    /// `rotation[i][j]` is component `i` of axis `j`; it should be a rotation
+    /// matrix, with orthonormal columns. Octants are classified against the
+    /// box while descending the tree, as for the sphere. Nothing is filled if
+    /// any half extent is negative.
+    pub fn fill_obb(
+        &mut self,
+        center: [f32; 3],
+        half_extents: [f32; 3],
+        rotation: [[f32; 3]; 3],
+        value: bool,
+    ) {
+        if half_extents.iter().any(|&h| h.is_nan() || h < 0.0) {
+            return;
+        }
+        self.fill_shape(
+            &OrientedBox {
+                center: center.map(f64::from),
+                half_extents: half_extents.map(f64::from),
+                axes: std::array::from_fn(|j| std::array::from_fn(|i| f64::from(rotation[i][j]))),
+            },
+            value,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    #[test]
+    fn sphere_and_ellipsoid() {
+        let mut bitmap = OctreeBitmap::new(32);
+        bitmap.fill_sphere([12.0, 14.0, 10.5], 9.5, true);
+        bitmap.fill_ellipsoid([14.0, 14.0, 14.0], [6.0, 2.0, 3.5], false);
+        let mut expected = Vec::new();
+        for z in 0..bitmap.width() {
+            for y in 0..bitmap.width() {
+                for x in 0..bitmap.width() {
+                    let [px, py, pz] = [x, y, z].map(|c| c as f64 + 0.5);
+                    let sphere = (px - 12.0).powi(2) + (py - 14.0).powi(2) + (pz - 10.5).powi(2)
+                        <= 9.5 * 9.5;
+                    let ellipsoid = ((px - 14.0) / 6.0).powi(2)
+                        + ((py - 14.0) / 2.0).powi(2)
+                        + ((pz - 14.0) / 3.5).powi(2)
+                        <= 1.0;
+                    if sphere && !ellipsoid {
+                        expected.push(Index::new(x, y, z));
+                    }
+                }
+            }
+        }
+        let mut actual: Vec<Index> = bitmap.iter().collect();
+        actual.sort_by_key(|idx| (idx.z, idx.y, idx.x));
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn oriented_box() {
+        let (sin, cos) = 0.5f32.sin_cos();
+        // A turn of half a radian about z, then tipped a quarter turn about x.
+        let rotation = [[cos, 0.0, sin], [sin, 0.0, -cos], [0.0, 1.0, 0.0]];
+        let (center, half_extents) = ([15.0, 13.5, 16.25], [9.0, 2.5, 4.0]);
+        let mut bitmap = OctreeBitmap::new(32);
+        bitmap.fill_obb(center, half_extents, rotation, true);
+        let mut expected = Vec::new();
+        for z in 0..bitmap.width() {
+            for y in 0..bitmap.width() {
+                for x in 0..bitmap.width() {
+                    let d = [x, y, z].map(|c| c as f32 + 0.5);
+                    let d: [f32; 3] = std::array::from_fn(|i| d[i] - center[i]);
+                    let inside = (0..3).all(|j| {
+                        let along: f32 = (0..3).map(|i| d[i] * rotation[i][j]).sum();
+                        along.abs() <= half_extents[j]
+                    });
+                    if inside {
+                        expected.push(Index::new(x, y, z));
+                    }
+                }
+            }
+        }
+        assert!(expected.len() > 500, "{}", expected.len());
+        let mut actual: Vec<Index> = bitmap.iter().collect();
+        actual.sort_by_key(|idx| (idx.z, idx.y, idx.x));
+        assert_eq!(actual, expected);
+
+        let identity = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        let mut aligned = OctreeBitmap::new(32);
+        aligned.fill_obb([8.0, 8.0, 8.0], [4.0, 4.0, 4.0], identity, true);
+        let mut expected = OctreeBitmap::new(32);
+        expected.fill_box([4, 4, 4], [12, 12, 12], true);
+        assert!(aligned == expected);
+        assert_eq!(aligned.branches.len(), expected.branches.len());
+    }
+
+    #[test]
+    fn fill_with_pattern() {
+        let mut bitmap = OctreeBitmap::new(32);
+        bitmap.fill_box([0, 0, 0], [32, 32, 32], true);
+        let checker = |idx: Index| (idx.x + idx.y + idx.z) & 1 == 0;
+        bitmap.fill_with(Index::new(3, 0, 5), Index::new(40, 7, 6), checker);
+        for z in 0..32 {
+            for y in 0..32 {
+                for x in 0..32 {
+                    let idx = Index::new(x, y, z);
+                    let inside = x >= 3 && y < 7 && z == 5;
+                    assert_eq!(bitmap.get(&idx), !inside || checker(idx), "{:?}", idx);
+                }
+            }
+        }
+
+        // A half-space, with and without hints.
+        let calls = Cell::new(0);
+        let below = |idx: Index| {
+            calls.set(calls.get() + 1);
+            idx.x + idx.y < 20
+        };
+        let mut plain = OctreeBitmap::new(32);
+        plain.fill_with(Index::new(0, 0, 0), Index::new(32, 32, 32), below);
+        assert_eq!(calls.replace(0), 32 * 32 * 32);
+        let mut hinted = OctreeBitmap::new(32);
+        hinted.fill_with_hint(
+            Index::new(0, 0, 0),
+            Index::new(32, 32, 32),
+            below,
+            |min, max| {
+                if max.x + max.y < 20 {
+                    Some(true)
+                } else if min.x + min.y >= 20 {
+                    Some(false)
+                } else {
+                    None
+                }
+            },
+        );
+        assert!(calls.get() < 32 * 32 * 32 / 4, "{}", calls.get());
+        assert!(plain == hinted);
+        assert_eq!(plain.branches.len(), hinted.branches.len());
+        assert!(plain.get(&Index::new(10, 9, 31)));
+        assert!(!plain.get(&Index::new(10, 10, 31)));
+    }
+}
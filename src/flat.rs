@@ -0,0 +1,284 @@
+//! A plain array form of the tree, for custom serializers and transfer
+//! between programs.
+
+use std::fmt;
+
+use crate::{brick_mask, Branch, BranchIndex, LeafSize, OctreeBitmap, RawNode, View};
+
+/// A child of a node in a [`FlatOctree`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlatChild {
+    /// Every voxel in the octant is false.
+    Empty,
+    /// Every voxel in the octant is true.
+    Full,
+    /// The octant is split into the node at this position in
+    /// [`FlatOctree::nodes`].
+    Node(u32),
+    /// The voxels of an octant at the height of the dense leaf bricks, one
+    /// bit each: the voxel at a position within the brick is the bit given by
+    /// the [Morton code](crate::Index::morton) of that position.
+    Brick(u64),
+}
+
+/// A bitmap's tree as an array of nodes that refer to their children by
+/// position, returned by [`OctreeBitmap::flatten`].
+///
+/// This is a stable intermediate form with no hashing or pointers, for
+/// writing custom serializers, uploading to the GPU, or passing across an
+/// FFI boundary. Bricks are the children of nodes of height
+/// `min(3, height)`, except in bitmaps of width 2, whose voxels are the
+/// children of the root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlatOctree {
+    /// The children of each node, in Morton order: the child at position
+    /// `(x, y, z)` within its parent, each `0` or `1`, is at
+    /// `x | y << 1 | z << 2`. Every node comes before its children.
+    pub nodes: Vec<[FlatChild; 8]>,
+    /// The whole bitmap, which is a [`FlatChild::Node`] unless the bitmap is
+    /// uniform.
+    pub root: FlatChild,
+    /// The height of the root, so the width of the bitmap is `1 << height`.
+    pub height: u32,
+}
+
+/// An error encountered while rebuilding a bitmap from a [`FlatOctree`].
+///
+/// `node` is the position of the node whose child has the problem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FlatError {
+    /// The height is zero or too large for the width to fit in a `u32`.
+    InvalidHeight(u32),
+    /// The root is a brick or a node that does not exist.
+    InvalidRoot,
+    /// A child refers to a node that does not exist, does not come after
+    /// its parent, or is already the child of another node.
+    BadReference { node: u32, child: u32 },
+    /// A child at brick height is a node, or a child above it is a brick.
+    WrongLeafKind { node: u32 },
+    /// A brick has bits set outside its size.
+    InvalidBrick { node: u32, bits: u64 },
+}
+
+impl fmt::Display for FlatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidHeight(height) => write!(f, "invalid flat bitmap height {}", height),
+            Self::InvalidRoot => write!(f, "invalid flat bitmap root"),
+            Self::BadReference { node, child } => {
+                write!(f, "node {} has invalid child reference {}", node, child)
+            }
+            Self::WrongLeafKind { node } => {
+                write!(
+                    f,
+                    "node {} has a child of the wrong kind for its height",
+                    node
+                )
+            }
+            Self::InvalidBrick { node, bits } => {
+                write!(f, "node {} has invalid brick bits {:#x}", node, bits)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FlatError {}
+
+impl OctreeBitmap {
+    /// The tree as an array of nodes with the positions of their children,
+    /// in depth-first order starting with the root.
+    ///
+    /// The bricks are always those of the default [`LeafSize`], so a bitmap
+    /// with a different leaf size is rebuilt with the default one first.
+    pub fn flatten(&self) -> FlatOctree {
+        let bitmap = self.leaves_of(LeafSize::default());
+        let mut flat = FlatOctree {
+            nodes: Vec::with_capacity(bitmap.branches.len()),
+            root: FlatChild::Empty,
+            height: bitmap.height,
+        };
+        let root = BranchIndex::root(bitmap.height);
+        flat.root = match View::root(&bitmap) {
+            View::Uniform(false) => FlatChild::Empty,
+            View::Uniform(true) => FlatChild::Full,
+            view => FlatChild::Node(bitmap.flatten_node(root, view, &mut flat.nodes)),
+        };
+        flat
+    }
+
+    /// Adds the branch at the given index and the branches below it, returning
+    /// its position.
+    fn flatten_node(&self, index: BranchIndex, view: View, nodes: &mut Vec<[FlatChild; 8]>) -> u32 {
+        let n = nodes.len();
+        nodes.push([FlatChild::Empty; 8]);
+        for i in 0..8 {
+            let child_index = index.child(i & 1, (i >> 1) & 1, i >> 2);
+            nodes[n][i] = match view.child(self, index, i) {
+                View::Uniform(false) => FlatChild::Empty,
+                View::Uniform(true) => FlatChild::Full,
+                View::Brick(bits) => FlatChild::Brick(bits),
+                child_view @ View::Branch(_) => {
+                    FlatChild::Node(self.flatten_node(child_index, child_view, nodes))
+                }
+            };
+        }
+        n as u32
+    }
+
+    /// Rebuilds a bitmap from the form returned by
+    /// [`flatten`](Self::flatten), which may also be built by hand.
+    ///
+    /// Nodes need not be compressed: nodes and bricks whose voxels are all
+    /// equal are read as uniform. Each node must be the child of at most one
+    /// parent, since a node shared between parents would be copied into each,
+    /// and a few of them could expand into far more branches than were given.
+    pub fn from_flat(flat: &FlatOctree) -> Result<OctreeBitmap, FlatError> {
+        if flat.height == 0 || flat.height >= u32::BITS {
+            return Err(FlatError::InvalidHeight(flat.height));
+        }
+        let mut bitmap = OctreeBitmap::with_height(flat.height);
+        let root = BranchIndex::root(flat.height);
+        match flat.root {
+            FlatChild::Empty => {}
+            FlatChild::Full => bitmap.fill_node(root, true),
+            FlatChild::Node(n) if (n as usize) < flat.nodes.len() => {
+                let mut used = vec![false; flat.nodes.len()];
+                let children = bitmap.unflatten(flat, n, root, &mut used)?;
+                bitmap.branches.insert(root, Branch { children });
+            }
+            _ => return Err(FlatError::InvalidRoot),
+        }
+        Ok(bitmap)
+    }
+
+    /// The children of the branch at the given index, read from the node at
+    /// position `n`, adding the branches below it. `used` marks the nodes
+    /// already referred to by a parent.
+    fn unflatten(
+        &mut self,
+        flat: &FlatOctree,
+        n: u32,
+        index: BranchIndex,
+        used: &mut [bool],
+    ) -> Result<[[[RawNode; 2]; 2]; 2], FlatError> {
+        let brick_height = self.brick_height();
+        let mut children = [[[RawNode::False; 2]; 2]; 2];
+        for (i, &child) in flat.nodes[n as usize].iter().enumerate() {
+            let (x, y, z) = (i & 1, (i >> 1) & 1, i >> 2);
+            let child_index = index.child(x, y, z);
+            children[z][y][x] = match child {
+                FlatChild::Empty => RawNode::False,
+                FlatChild::Full => RawNode::True,
+                FlatChild::Brick(bits)
+                    if child_index.height == brick_height && brick_height > 0 =>
+                {
+                    if bits & !brick_mask(brick_height) != 0 {
+                        return Err(FlatError::InvalidBrick { node: n, bits });
+                    }
+                    RawNode::from_brick(bits, brick_height)
+                }
+                FlatChild::Node(c) if child_index.height > brick_height => {
+                    if c <= n || c as usize >= flat.nodes.len() || used[c as usize] {
+                        return Err(FlatError::BadReference { node: n, child: c });
+                    }
+                    used[c as usize] = true;
+                    let grandchildren = self.unflatten(flat, c, child_index, used)?;
+                    if grandchildren == [[[RawNode::False; 2]; 2]; 2] {
+                        RawNode::False
+                    } else if grandchildren == [[[RawNode::True; 2]; 2]; 2] {
+                        RawNode::True
+                    } else {
+                        self.branches.insert(
+                            child_index,
+                            Branch {
+                                children: grandchildren,
+                            },
+                        );
+                        RawNode::Branch
+                    }
+                }
+                _ => return Err(FlatError::WrongLeafKind { node: n }),
+            };
+        }
+        Ok(children)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::pseudo_random_indexes;
+    use crate::Index;
+
+    #[test]
+    fn flat_round_trip() {
+        let mut bitmap = OctreeBitmap::new(16);
+        bitmap.fill_sphere([10.0, 12.0, 9.0], 6.0, true);
+        bitmap.set_many(pseudo_random_indexes(32, 300), true);
+        let flat = bitmap.flatten();
+        assert_eq!(flat.root, FlatChild::Node(0));
+        assert_eq!(flat.nodes.len(), bitmap.branches.len());
+        let read = OctreeBitmap::from_flat(&flat).unwrap();
+        assert_eq!(read, bitmap);
+        assert_eq!(read.branches.len(), bitmap.branches.len());
+
+        let widest = OctreeBitmap::new(OctreeBitmap::MAX_WIDTH);
+        assert_eq!(OctreeBitmap::from_flat(&widest.flatten()).unwrap(), widest);
+
+        let mut small = OctreeBitmap::new(1);
+        assert_eq!(small.flatten().root, FlatChild::Empty);
+        small.set(&Index::new(1, 0, 1), true);
+        assert_eq!(OctreeBitmap::from_flat(&small.flatten()).unwrap(), small);
+
+        // An uncompressed node.
+        let mut root = [FlatChild::Empty; 8];
+        root[..3].copy_from_slice(&[FlatChild::Node(1), FlatChild::Node(2), FlatChild::Full]);
+        let mut flat = FlatOctree {
+            nodes: vec![
+                root,
+                [FlatChild::Brick(1); 8],
+                [FlatChild::Brick(u64::MAX); 8],
+            ],
+            root: FlatChild::Node(0),
+            height: 4,
+        };
+        let read = OctreeBitmap::from_flat(&flat).unwrap();
+        assert_eq!(read.iter().count(), 8 + 2 * 8 * 8 * 8);
+        assert!(read.get(&Index::new(0, 8, 0)) && read.get(&Index::new(12, 4, 4)));
+        assert_eq!(read.flatten().nodes.len(), 2);
+
+        // A node shared by two parents.
+        flat.nodes[0][1] = FlatChild::Node(1);
+        assert_eq!(
+            OctreeBitmap::from_flat(&flat),
+            Err(FlatError::BadReference { node: 0, child: 1 })
+        );
+
+        let invalid = |nodes: Vec<[FlatChild; 8]>, height| {
+            OctreeBitmap::from_flat(&FlatOctree {
+                nodes,
+                root: FlatChild::Node(0),
+                height,
+            })
+            .unwrap_err()
+        };
+        assert_eq!(invalid(vec![], 0), FlatError::InvalidHeight(0));
+        assert_eq!(invalid(vec![], 32), FlatError::InvalidHeight(32));
+        assert_eq!(invalid(vec![], 3), FlatError::InvalidRoot);
+        assert_eq!(
+            invalid(vec![[FlatChild::Node(0); 8]], 4),
+            FlatError::BadReference { node: 0, child: 0 }
+        );
+        assert_eq!(
+            invalid(vec![[FlatChild::Brick(1); 8]], 4),
+            FlatError::WrongLeafKind { node: 0 }
+        );
+        assert_eq!(
+            invalid(vec![[FlatChild::Brick(1 << 8); 8]], 2),
+            FlatError::InvalidBrick {
+                node: 0,
+                bits: 1 << 8
+            }
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! Flood filling of connected regions.
+
+use std::collections::{HashSet, VecDeque};
+
+use crate::{BranchIndex, Index, Occupancy, OctreeBitmap, View};
+
+impl OctreeBitmap {
+    /// The largest uniform octant containing the octant at the given node, and
+    /// its value, or `None` if the octant at the node is mixed.
+    pub(crate) fn uniform_octant(&self, node: BranchIndex) -> Option<(BranchIndex, bool)> {
+        let mut index = BranchIndex::root(self.height);
+        let mut view = View::root(self);
+        loop {
+            if let View::Uniform(value) = view {
+                return Some((index, value));
+            }
+            if index.height == node.height {
+                return None;
+            }
+            let (x, y, z) = node.base.bit(index.height - 1);
+            view = view.child(self, index, x | y << 1 | z << 2);
+            index = index.child(x, y, z);
+        }
+    }
+
+    /// Adds the largest uniform octants with the given value that touch the
+    /// face of an octant, given the neighboring octant of the same size on
+    /// the other side of the face.
+    fn adjacent_octants(
+        &self,
+        neighbor: BranchIndex,
+        axis: usize,
+        positive: bool,
+        value: bool,
+        found: &mut Vec<BranchIndex>,
+    ) {
+        match self.uniform_octant(neighbor) {
+            Some((octant, octant_value)) => {
+                if octant_value == value {
+                    found.push(octant);
+                }
+            }
+            None => {
+                let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+                for (du, dv) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    // The children on the near side of the neighbor.
+                    let mut bits = [0; 3];
+                    bits[axis] = usize::from(!positive);
+                    bits[u] = du;
+                    bits[v] = dv;
+                    let child = neighbor.child(bits[0], bits[1], bits[2]);
+                    self.adjacent_octants(child, axis, positive, value, found);
+                }
+            }
+        }
+    }
+
+    /// Set every voxel that is connected to `seed` through voxels of the same
+    /// value, including `seed` itself, to `value`, like a paint bucket tool.
+    ///
+    /// Voxels are connected when they share a face. Does nothing if `seed` is
+    /// already `value`. The region is explored as whole uniform octants of
+    /// the tree rather than voxel by voxel, so large cavities are filled
+    /// quickly. In recording mode, this is recorded as a single edit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `seed` is outside of the range `0..self.width()`.
+    pub fn flood_fill(&mut self, seed: Index, value: bool) {
+        let width = self.width();
+        assert!(
+            seed.x < width && seed.y < width && seed.z < width,
+            "index {:?} out of bounds for width {}",
+            seed,
+            width
+        );
+        let (start, old) = self.uniform_octant(seed.branch_at(0)).unwrap();
+        if old == value {
+            return;
+        }
+        let visited = self.connected_octants(vec![start], old);
+        self.fill_octants(visited, value);
+    }
+
+    /// Set every unset voxel that is not connected to the outside of the
+    /// bitmap through unset voxels, filling the enclosed cavities of solids,
+    /// such as to clean up the result of voxelizing a surface.
+    ///
+    /// Voxels are connected when they share a face. The unset voxels on the
+    /// bounds of the bitmap are flood filled as for
+    /// [`flood_fill`](Self::flood_fill), and every unset octant that was not
+    /// reached is then filled whole. In recording mode, this is recorded as a
+    /// single edit.
+    pub fn fill_cavities(&mut self) {
+        let root = BranchIndex::root(self.height);
+        let mut starts = Vec::new();
+        for axis in 0..3 {
+            for positive in [false, true] {
+                // The octants touching the far face of the root, as seen from
+                // outside of it.
+                self.adjacent_octants(root, axis, positive, false, &mut starts);
+            }
+        }
+        let exterior = self.connected_octants(starts, false);
+        let mut cavities = Vec::new();
+        self.visit_nodes(|base, height, occupancy| {
+            let node = BranchIndex { base, height };
+            if occupancy == Occupancy::Empty && !exterior.contains(&node) {
+                cavities.push(node);
+            }
+            occupancy == Occupancy::Mixed
+        });
+        self.fill_octants(cavities, true);
+    }
+
+    /// Set every voxel of the octants to `value`, as a single edit.
+    fn fill_octants(&mut self, octants: impl IntoIterator<Item = BranchIndex>, value: bool) {
+        self.record_group(|bitmap| {
+            let (voxels, octants): (Vec<_>, Vec<_>) =
+                octants.into_iter().partition(|octant| octant.height == 0);
+            for octant in octants {
+                bitmap.fill_node(octant, value);
+            }
+            bitmap.set_many(voxels.into_iter().map(|octant| octant.base), value);
+        });
+    }
+
+    /// The largest uniform octants with the given value that are connected
+    /// through each other to any of the `starts`, which must be such octants
+    /// themselves, including the `starts`.
+    fn connected_octants(&self, starts: Vec<BranchIndex>, old: bool) -> HashSet<BranchIndex> {
+        let mut visited: HashSet<BranchIndex> = starts.iter().copied().collect();
+        let mut queue = VecDeque::from(starts);
+        let mut found = Vec::new();
+        while let Some(octant) = queue.pop_front() {
+            self.neighbor_octants(octant, old, &mut found);
+            for octant in found.drain(..) {
+                if visited.insert(octant) {
+                    queue.push_back(octant);
+                }
+            }
+        }
+        visited
+    }
+
+    /// Adds the largest uniform octants with the given value that share a
+    /// face with the octant at the given node.
+    pub(crate) fn neighbor_octants(
+        &self,
+        octant: BranchIndex,
+        value: bool,
+        found: &mut Vec<BranchIndex>,
+    ) {
+        let width = self.width();
+        let side = 1 << octant.height;
+        let base = [octant.base.x, octant.base.y, octant.base.z];
+        for axis in 0..3 {
+            for positive in [false, true] {
+                let coordinate = if positive {
+                    Some(base[axis] + side).filter(|&c| c < width)
+                } else {
+                    base[axis].checked_sub(side)
+                };
+                let Some(coordinate) = coordinate else {
+                    continue;
+                };
+                let mut neighbor = base;
+                neighbor[axis] = coordinate;
+                let neighbor = BranchIndex {
+                    base: Index::from(neighbor),
+                    height: octant.height,
+                };
+                self.adjacent_octants(neighbor, axis, positive, value, found);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_inside_and_outside() {
+        // The shell of the box from 2 to 12.
+        let shell = || {
+            let mut bitmap = OctreeBitmap::new(32);
+            bitmap.set_many(
+                (2..=12)
+                    .flat_map(|x| {
+                        (2..=12).flat_map(move |y| (2..=12).map(move |z| Index::new(x, y, z)))
+                    })
+                    .filter(|idx| [idx.x, idx.y, idx.z].iter().any(|&c| c == 2 || c == 12)),
+                true,
+            );
+            bitmap
+        };
+        let mut bitmap = shell();
+        let width = bitmap.width();
+
+        let mut inside = shell();
+        inside.flood_fill(Index::new(5, 6, 7), true);
+        assert_eq!(inside.iter().count(), 11 * 11 * 11);
+
+        let mut outside = shell();
+        outside.flood_fill(Index::new(20, 0, 31), true);
+        assert_eq!(outside.iter().count() as u32, width.pow(3) - 9 * 9 * 9);
+        assert!(!outside.get(&Index::new(5, 6, 7)));
+
+        // With a hole in one side, the inside and outside are connected.
+        bitmap.set(&Index::new(12, 7, 7), false);
+        bitmap.flood_fill(Index::new(0, 0, 0), true);
+        assert_eq!(bitmap.iter().count() as u32, width.pow(3));
+
+        // Clearing with a paint bucket on set voxels.
+        bitmap.flood_fill(Index::new(3, 3, 3), false);
+        assert_eq!(bitmap.iter().next(), None);
+    }
+
+    #[test]
+    fn cavities() {
+        let mut bitmap = OctreeBitmap::new(32);
+        bitmap.fill_box([2, 2, 2], [13, 13, 13], true);
+        bitmap.fill_box([3, 3, 3], [12, 12, 12], false);
+        // A second cavity, opened to the outside, and an empty pocket in
+        // the corner on the bounds.
+        bitmap.fill_box([20, 20, 20], [30, 30, 30], true);
+        bitmap.fill_box([21, 21, 21], [29, 29, 29], false);
+        bitmap.set(&Index::new(29, 25, 25), false);
+        bitmap.fill_box([0, 0, 0], [2, 2, 1], true);
+        let before = bitmap.iter().count();
+
+        bitmap.start_recording();
+        bitmap.fill_cavities();
+        assert_eq!(bitmap.iter().count(), before + 9 * 9 * 9);
+        assert!(bitmap.get(&Index::new(7, 7, 7)));
+        assert!(!bitmap.get(&Index::new(25, 25, 25)));
+        assert!(!bitmap.get(&Index::new(0, 0, 1)));
+        assert!(bitmap.undo());
+        assert_eq!(bitmap.iter().count(), before);
+    }
+}
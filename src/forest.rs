@@ -0,0 +1,207 @@
+//! Unbounded worlds made up of fixed-width chunks.
+
+use std::collections::HashMap;
+
+use crate::{Index, OctreeBitmap};
+
+type LoadHook = Box<dyn FnMut([i32; 3], &mut OctreeBitmap)>;
+type UnloadHook = Box<dyn FnMut([i32; 3], OctreeBitmap)>;
+
+/// A world of independent [`OctreeBitmap`] chunks, each covering a cube of
+/// positions, so the world is not limited to the width of a single root and
+/// parts of it can be streamed in and out.
+///
+/// Positions are signed and chunk coordinates are the positions divided by
+/// the chunk width, rounding down. Chunks that are not loaded read as empty,
+/// and are loaded when written to.
+pub struct OctreeForest {
+    chunks: HashMap<[i32; 3], OctreeBitmap>,
+    /// The height of the root of each chunk.
+    height: u32,
+    load: Option<LoadHook>,
+    unload: Option<UnloadHook>,
+}
+
+impl OctreeForest {
+    /// Creates a new forest with no chunks loaded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_width` is not a power of two between 2 and
+    /// [`OctreeBitmap::MAX_WIDTH`].
+    pub fn new(chunk_width: u32) -> Self {
+        assert!(
+            chunk_width.is_power_of_two() && (2..=OctreeBitmap::MAX_WIDTH).contains(&chunk_width),
+            "invalid chunk width {}",
+            chunk_width
+        );
+        Self {
+            chunks: HashMap::new(),
+            height: chunk_width.trailing_zeros(),
+            load: None,
+            unload: None,
+        }
+    }
+
+    /// The width of each chunk.
+    pub fn chunk_width(&self) -> u32 {
+        1 << self.height
+    }
+
+    /// Sets the function called with each new, empty chunk as it is loaded,
+    /// to fill it in, such as from disk or a world generator. The chunk may be
+    /// replaced, but only with one of the same width.
+    pub fn on_load(&mut self, hook: impl FnMut([i32; 3], &mut OctreeBitmap) + 'static) {
+        self.load = Some(Box::new(hook));
+    }
+
+    /// Sets the function called with each chunk as it is unloaded, such as
+    /// to save it.
+    pub fn on_unload(&mut self, hook: impl FnMut([i32; 3], OctreeBitmap) + 'static) {
+        self.unload = Some(Box::new(hook));
+    }
+
+    /// The coordinates of the chunk containing the position, and the
+    /// position within it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the chunk coordinates do not fit in an `i32`.
+    fn locate(&self, position: [i64; 3]) -> ([i32; 3], Index) {
+        let width = i64::from(self.chunk_width());
+        let chunk = position.map(|x| {
+            i32::try_from(x.div_euclid(width)).expect("position is too far from the origin")
+        });
+        let [x, y, z] = position.map(|x| x.rem_euclid(width) as u32);
+        (chunk, Index::new(x, y, z))
+    }
+
+    /// Get the current value of the bit at the given position. Positions in
+    /// chunks that are not loaded are empty.
+    pub fn get(&self, position: [i64; 3]) -> bool {
+        let (chunk, idx) = self.locate(position);
+        self.chunks
+            .get(&chunk)
+            .is_some_and(|bitmap| bitmap.get(&idx))
+    }
+
+    /// Set the value at the given position, loading its chunk if needed.
+    pub fn set(&mut self, position: [i64; 3], value: bool) {
+        let (chunk, idx) = self.locate(position);
+        self.load(chunk).set(&idx, value);
+    }
+
+    /// The chunk at the given coordinates, loading it if needed.
+    pub fn load(&mut self, chunk: [i32; 3]) -> &mut OctreeBitmap {
+        let height = self.height;
+        let load = &mut self.load;
+        self.chunks.entry(chunk).or_insert_with(|| {
+            let mut bitmap = OctreeBitmap::with_height(height);
+            if let Some(load) = load {
+                load(chunk, &mut bitmap);
+                assert_eq!(bitmap.height, height, "loaded chunk has the wrong width");
+            }
+            bitmap
+        })
+    }
+
+    /// Unloads the chunk at the given coordinates, passing it to the unload
+    /// hook. Returns whether it was loaded.
+    pub fn unload(&mut self, chunk: [i32; 3]) -> bool {
+        let Some(bitmap) = self.chunks.remove(&chunk) else {
+            return false;
+        };
+        if let Some(unload) = &mut self.unload {
+            unload(chunk, bitmap);
+        }
+        true
+    }
+
+    /// The chunk at the given coordinates, if it is loaded.
+    pub fn chunk(&self, chunk: [i32; 3]) -> Option<&OctreeBitmap> {
+        self.chunks.get(&chunk)
+    }
+
+    /// The chunk at the given coordinates, if it is loaded.
+    pub fn chunk_mut(&mut self, chunk: [i32; 3]) -> Option<&mut OctreeBitmap> {
+        self.chunks.get_mut(&chunk)
+    }
+
+    /// The coordinates of the loaded chunks, in no particular order.
+    pub fn loaded(&self) -> impl Iterator<Item = [i32; 3]> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    /// An iterator over the positions of all set bits in the loaded chunks.
+    ///
+    /// The chunks are visited in order of their coordinates, sorted by `z`,
+    /// then `y`, then `x`, and each in Morton order.
+    pub fn iter(&self) -> impl Iterator<Item = [i64; 3]> + '_ {
+        let mut chunks: Vec<[i32; 3]> = self.loaded().collect();
+        chunks.sort_unstable_by_key(|&[x, y, z]| [z, y, x]);
+        let width = i64::from(self.chunk_width());
+        chunks.into_iter().flat_map(move |chunk| {
+            let [cx, cy, cz] = chunk.map(|c| i64::from(c) * width);
+            self.chunks[&chunk].iter().map(move |idx| {
+                [
+                    cx + i64::from(idx.x),
+                    cy + i64::from(idx.y),
+                    cz + i64::from(idx.z),
+                ]
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    #[test]
+    fn forest_streams_chunks() {
+        let saved = Rc::new(RefCell::new(HashMap::new()));
+        let mut forest = OctreeForest::new(16);
+        forest.on_unload({
+            let saved = saved.clone();
+            move |chunk, bitmap: OctreeBitmap| {
+                saved.borrow_mut().insert(chunk, bitmap.to_binary());
+            }
+        });
+        forest.on_load({
+            let saved = saved.clone();
+            move |chunk, bitmap| {
+                if let Some(bytes) = saved.borrow().get(&chunk) {
+                    *bitmap = OctreeBitmap::from_binary(&bytes[..]).unwrap();
+                }
+            }
+        });
+
+        let positions = [[-1, 0, 0], [0, 0, 0], [15, 16, -17], [1000, -2000, 3]];
+        for &position in &positions {
+            forest.set(position, true);
+        }
+        assert!(forest.get([-1, 0, 0]));
+        assert!(!forest.get([-2, 0, 0]));
+        assert!(!forest.get([i64::from(i32::MAX), 0, 0]));
+        assert_eq!(forest.loaded().count(), 4);
+        assert_eq!(
+            forest.chunk([-1, 0, 0]).unwrap().iter().collect::<Vec<_>>(),
+            [Index::new(15, 0, 0)]
+        );
+        let mut iterated: Vec<[i64; 3]> = forest.iter().collect();
+        iterated.sort_unstable();
+        let mut expected = positions.to_vec();
+        expected.sort_unstable();
+        assert_eq!(iterated, expected);
+
+        assert!(forest.unload([62, -125, 0]));
+        assert!(!forest.unload([62, -125, 0]));
+        assert!(!forest.get([1000, -2000, 3]));
+        assert!(saved.borrow().contains_key(&[62, -125, 0]));
+        forest.load([62, -125, 0]);
+        assert!(forest.get([1000, -2000, 3]));
+    }
+}
@@ -0,0 +1,110 @@
+//! Immutable snapshots of a bitmap laid out for fast concurrent reads.
+
+use crate::{FlatChild, FlatOctree, Index, OctreeBitmap};
+
+/// A read-only copy of a bitmap with its branches in one contiguous array,
+/// each holding the positions of its children in the array.
+///
+/// Looking up a voxel follows those positions from the root instead of
+/// hashing the index of each branch on the way, and nothing is ever written,
+/// so any number of threads can query it at once without locking, such as to
+/// share a static world between the threads answering queries about it.
+/// Branches are stored depth first, so each is near its first children.
+/// Created by [`OctreeBitmap::freeze`].
+#[derive(Debug, Clone)]
+pub struct FrozenOctree {
+    /// The branches, as returned by [`OctreeBitmap::flatten`].
+    flat: FlatOctree,
+}
+
+impl FrozenOctree {
+    pub fn width(&self) -> u32 {
+        1 << self.flat.height
+    }
+
+    /// The number of branches stored, the same as in the bitmap it was
+    /// frozen from.
+    pub fn node_count(&self) -> usize {
+        self.flat.nodes.len()
+    }
+
+    /// Get the value of the bit at the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is outside the bitmap.
+    pub fn get(&self, idx: &Index) -> bool {
+        let width = self.width();
+        assert!(
+            idx.x < width && idx.y < width && idx.z < width,
+            "index {:?} is out of bounds for width {}",
+            idx,
+            width
+        );
+        let mut node = self.flat.root;
+        let mut height = self.flat.height;
+        loop {
+            match node {
+                FlatChild::Empty => return false,
+                FlatChild::Full => return true,
+                FlatChild::Brick(bits) => return (bits >> idx.brick_offset(height)) & 1 != 0,
+                FlatChild::Node(n) => {
+                    let (x, y, z) = idx.bit(height - 1);
+                    node = self.flat.nodes[n as usize][x | y << 1 | z << 2];
+                    height -= 1;
+                }
+            }
+        }
+    }
+}
+
+impl OctreeBitmap {
+    /// A [`FrozenOctree`] with the same contents as the bitmap, for sharing
+    /// a snapshot of it between threads that only read it.
+    pub fn freeze(&self) -> FrozenOctree {
+        FrozenOctree {
+            flat: self.flatten(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::pseudo_random_indexes;
+
+    #[test]
+    fn frozen_reads_match() {
+        let mut bitmap = OctreeBitmap::new(16);
+        let width = bitmap.width();
+        bitmap.fill_sphere([10.0, 12.0, 9.0], 6.0, true);
+        bitmap.set_many(pseudo_random_indexes(32, 300), true);
+        let frozen = bitmap.freeze();
+        assert_eq!(frozen.width(), width);
+        assert_eq!(frozen.node_count(), bitmap.branches.len());
+
+        std::thread::scope(|scope| {
+            for z in 0..4 {
+                let (frozen, bitmap) = (&frozen, &bitmap);
+                scope.spawn(move || {
+                    for z in z * width / 4..(z + 1) * width / 4 {
+                        for y in 0..width {
+                            for x in 0..width {
+                                let idx = Index::new(x, y, z);
+                                assert_eq!(frozen.get(&idx), bitmap.get(&idx), "{:?}", idx);
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        let full = {
+            let mut full = OctreeBitmap::new(4);
+            full.fill_with(Index::new(0, 0, 0), Index::new(8, 8, 8), |_| true);
+            full.freeze()
+        };
+        assert_eq!(full.node_count(), 0);
+        assert!(full.get(&Index::new(1, 2, 3)));
+    }
+}
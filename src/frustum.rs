@@ -0,0 +1,160 @@
+//! View frustum queries.
+
+use crate::fill::Containment;
+use crate::{BranchIndex, Index, OctreeBitmap, View};
+
+/// A plane bounding a half-space, containing the points `p` where
+/// `normal · p + distance >= 0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plane {
+    /// The normal, pointing into the half-space. It does not need to be
+    /// normalized.
+    pub normal: [f32; 3],
+    pub distance: f32,
+}
+
+impl Plane {
+    pub fn new(normal: [f32; 3], distance: f32) -> Self {
+        Self { normal, distance }
+    }
+
+    /// The signed distance of the point from the plane, scaled by the length
+    /// of the normal.
+    fn signed_distance(&self, point: [f64; 3]) -> f64 {
+        (0..3)
+            .map(|i| self.normal[i] as f64 * point[i])
+            .sum::<f64>()
+            + self.distance as f64
+    }
+}
+
+/// A cubic octant of a bitmap in which every voxel is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Block {
+    /// The lowest index in the block.
+    pub base: Index,
+    /// The block is a cube with a side length of `1 << height`.
+    pub height: u32,
+}
+
+fn classify(planes: &[Plane; 6], node: BranchIndex) -> Containment {
+    let low = [node.base.x, node.base.y, node.base.z].map(f64::from);
+    let side = (1u64 << node.height) as f64;
+    let mut containment = Containment::Inside;
+    for plane in planes {
+        // The corners of the box furthest along and against the normal.
+        let far = [0, 1, 2].map(|i| low[i] + if plane.normal[i] >= 0.0 { side } else { 0.0 });
+        let near = [0, 1, 2].map(|i| low[i] + if plane.normal[i] >= 0.0 { 0.0 } else { side });
+        if plane.signed_distance(far) < 0.0 {
+            return Containment::Outside;
+        }
+        if plane.signed_distance(near) < 0.0 {
+            containment = Containment::Partial;
+        }
+    }
+    containment
+}
+
+/// An iterator over the uniformly-set blocks of a bitmap that intersect a
+/// view frustum.
+///
+/// This struct is created by [`OctreeBitmap::query_frustum`].
+pub struct FrustumIter<'a> {
+    bitmap: &'a OctreeBitmap,
+    planes: [Plane; 6],
+    stack: Vec<(BranchIndex, View)>,
+}
+
+impl Iterator for FrustumIter<'_> {
+    type Item = Block;
+
+    fn next(&mut self) -> Option<Block> {
+        while let Some((index, view)) = self.stack.pop() {
+            match view {
+                View::Uniform(false) => {}
+                View::Uniform(true) => {
+                    return Some(Block {
+                        base: index.base,
+                        height: index.height,
+                    })
+                }
+                _ => {
+                    for i in (0..8).rev() {
+                        let child = index.child(i & 1, (i >> 1) & 1, i >> 2);
+                        if classify(&self.planes, child) != Containment::Outside {
+                            self.stack.push((child, view.child(self.bitmap, index, i)));
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+impl std::iter::FusedIterator for FrustumIter<'_> {}
+
+impl OctreeBitmap {
+    /// Iterate over the uniformly-set blocks of the bitmap that intersect a
+    /// view frustum, for render culling.
+    ///
+    /// The frustum is the intersection of the half-spaces of the six planes,
+    /// in the space where the voxel at index `(x, y, z)` spans `x..x + 1`
+    /// (and so on) along each axis. Octants outside any one plane are pruned
+    /// whole. Blocks are yielded in Morton order and are as large as the
+    /// tree allows, so a block may extend outside the frustum. Like most
+    /// frustum culling, the test is conservative: a block near a corner of
+    /// the frustum may be yielded even if it is just outside.
+    pub fn query_frustum(&self, planes: &[Plane; 6]) -> FrustumIter<'_> {
+        let root = BranchIndex::root(self.height);
+        let stack = match classify(planes, root) {
+            Containment::Outside => Vec::new(),
+            _ => vec![(root, View::root(self))],
+        };
+        FrustumIter {
+            bitmap: self,
+            planes: *planes,
+            stack,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn box_frustum() {
+        let mut bitmap = OctreeBitmap::new(16);
+        bitmap.fill_node(Index::new(8, 8, 8).branch_at(3), true);
+        bitmap.set(&Index::new(1, 1, 1), true);
+        bitmap.set(&Index::new(5, 5, 5), true);
+
+        // The box 4.5..12 along each axis.
+        let planes = [
+            Plane::new([1.0, 0.0, 0.0], -4.5),
+            Plane::new([-1.0, 0.0, 0.0], 12.0),
+            Plane::new([0.0, 1.0, 0.0], -4.5),
+            Plane::new([0.0, -1.0, 0.0], 12.0),
+            Plane::new([0.0, 0.0, 2.0], -9.0),
+            Plane::new([0.0, 0.0, -1.0], 12.0),
+        ];
+        let blocks: Vec<Block> = bitmap.query_frustum(&planes).collect();
+        assert_eq!(
+            blocks,
+            [
+                Block {
+                    base: Index::new(5, 5, 5),
+                    height: 0,
+                },
+                Block {
+                    base: Index::new(8, 8, 8),
+                    height: 3,
+                },
+            ]
+        );
+
+        let planes = [Plane::new([1.0, 0.0, 0.0], -100.0); 6];
+        assert_eq!(bitmap.query_frustum(&planes).next(), None);
+    }
+}
@@ -0,0 +1,356 @@
+//! Export to the binary glTF 2.0 (`.glb`) format.
+
+use std::io::{self, Write};
+
+use serde_json::{json, Value};
+
+use crate::{Index, Occupancy, OctreeBitmap, Quad};
+
+/// How the volume is represented in an exported glTF file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GltfMode {
+    /// A single mesh containing a cube for every uniformly-set node.
+    Merged,
+    /// A single unit cube, instanced once per uniformly-set node with the
+    /// `EXT_mesh_gpu_instancing` extension.
+    Instanced,
+    /// A single mesh of the boundary between set and unset voxels, with the
+    /// rectangles of [`OctreeBitmap::extract_quads`], so faces hidden inside
+    /// solid regions are left out.
+    Surface,
+}
+
+/// The base color of the exported material.
+const BASE_COLOR: [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+
+/// The faces of the unit cube, as (normal, four corners in counterclockwise
+/// order when viewed from outside).
+const FACES: [([f32; 3], [[f32; 3]; 4]); 6] = [
+    (
+        [1.0, 0.0, 0.0],
+        [
+            [1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [1.0, 1.0, 1.0],
+            [1.0, 0.0, 1.0],
+        ],
+    ),
+    (
+        [-1.0, 0.0, 0.0],
+        [
+            [0.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.0, 1.0, 1.0],
+            [0.0, 1.0, 0.0],
+        ],
+    ),
+    (
+        [0.0, 1.0, 0.0],
+        [
+            [0.0, 1.0, 0.0],
+            [0.0, 1.0, 1.0],
+            [1.0, 1.0, 1.0],
+            [1.0, 1.0, 0.0],
+        ],
+    ),
+    (
+        [0.0, -1.0, 0.0],
+        [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 0.0, 1.0],
+            [0.0, 0.0, 1.0],
+        ],
+    ),
+    (
+        [0.0, 0.0, 1.0],
+        [
+            [0.0, 0.0, 1.0],
+            [1.0, 0.0, 1.0],
+            [1.0, 1.0, 1.0],
+            [0.0, 1.0, 1.0],
+        ],
+    ),
+    (
+        [0.0, 0.0, -1.0],
+        [
+            [0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [1.0, 0.0, 0.0],
+        ],
+    ),
+];
+
+/// Accumulates the binary buffer and the views and accessors into it.
+#[derive(Default)]
+struct Builder {
+    buffer: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+}
+
+impl Builder {
+    /// Appends a float vector accessor, returning its index.
+    fn vec3(&mut self, data: &[[f32; 3]], target: Option<u32>) -> usize {
+        let mut min = [f32::INFINITY; 3];
+        let mut max = [f32::NEG_INFINITY; 3];
+        let offset = self.buffer.len();
+        for v in data {
+            for i in 0..3 {
+                min[i] = min[i].min(v[i]);
+                max[i] = max[i].max(v[i]);
+                self.buffer.extend_from_slice(&v[i].to_le_bytes());
+            }
+        }
+        let mut view = json!({
+            "buffer": 0,
+            "byteOffset": offset,
+            "byteLength": self.buffer.len() - offset,
+        });
+        if let Some(target) = target {
+            view["target"] = json!(target);
+        }
+        self.buffer_views.push(view);
+        self.accessors.push(json!({
+            "bufferView": self.buffer_views.len() - 1,
+            "componentType": FLOAT,
+            "count": data.len(),
+            "type": "VEC3",
+            "min": min,
+            "max": max,
+        }));
+        self.accessors.len() - 1
+    }
+
+    /// Appends a vertex index accessor, returning its index.
+    fn indices(&mut self, data: &[u32]) -> usize {
+        let offset = self.buffer.len();
+        for i in data {
+            self.buffer.extend_from_slice(&i.to_le_bytes());
+        }
+        self.buffer_views.push(json!({
+            "buffer": 0,
+            "byteOffset": offset,
+            "byteLength": self.buffer.len() - offset,
+            "target": ELEMENT_ARRAY_BUFFER,
+        }));
+        self.accessors.push(json!({
+            "bufferView": self.buffer_views.len() - 1,
+            "componentType": UNSIGNED_INT,
+            "count": data.len(),
+            "type": "SCALAR",
+        }));
+        self.accessors.len() - 1
+    }
+}
+
+/// Appends the faces of the cube with the given corner and side length.
+fn push_cube(
+    origin: [f32; 3],
+    side: f32,
+    positions: &mut Vec<[f32; 3]>,
+    normals: &mut Vec<[f32; 3]>,
+    indices: &mut Vec<u32>,
+) {
+    for (normal, corners) in FACES {
+        let first = positions.len() as u32;
+        for corner in corners {
+            positions.push([
+                origin[0] + corner[0] * side,
+                origin[1] + corner[1] * side,
+                origin[2] + corner[2] * side,
+            ]);
+            normals.push(normal);
+        }
+        indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+}
+
+fn push_quad(
+    quad: &Quad,
+    positions: &mut Vec<[f32; 3]>,
+    normals: &mut Vec<[f32; 3]>,
+    indices: &mut Vec<u32>,
+) {
+    let first = positions.len() as u32;
+    for corner in quad.corners() {
+        positions.push(corner.map(|c| c as f32));
+        normals.push(quad.normal().map(|c| c as f32));
+    }
+    indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+}
+
+impl OctreeBitmap {
+    /// Writes the set voxels of the bitmap as a binary glTF (`.glb`) file.
+    ///
+    /// Except with [`GltfMode::Surface`], each uniformly-set node of the tree
+    /// becomes one cube, in model space where a voxel at index `(x, y, z)`
+    /// spans `x..x + 1` (and so on) along each axis. All cubes share a single
+    /// basic material.
+    pub fn export_gltf<W: Write>(&self, mut writer: W, mode: GltfMode) -> io::Result<()> {
+        let mut cubes: Vec<(Index, u32)> = Vec::new();
+        self.visit_nodes(|base, height, occupancy| {
+            if occupancy == Occupancy::Full {
+                cubes.push((base, height));
+            }
+            true
+        });
+
+        let mut builder = Builder::default();
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut indices = Vec::new();
+        let mut node = json!({ "mesh": 0 });
+        match mode {
+            GltfMode::Merged => {
+                for &(base, height) in &cubes {
+                    let origin = [base.x as f32, base.y as f32, base.z as f32];
+                    let side = (1u64 << height) as f32;
+                    push_cube(origin, side, &mut positions, &mut normals, &mut indices);
+                }
+            }
+            GltfMode::Instanced => {
+                push_cube([0.0; 3], 1.0, &mut positions, &mut normals, &mut indices);
+            }
+            GltfMode::Surface => {
+                for quad in self.extract_quads() {
+                    push_quad(&quad, &mut positions, &mut normals, &mut indices);
+                }
+            }
+        }
+
+        let mut gltf = json!({
+            "asset": { "version": "2.0", "generator": "october" },
+            "scene": 0,
+            "scenes": [{ "nodes": [] }],
+            "materials": [{
+                "pbrMetallicRoughness": {
+                    "baseColorFactor": BASE_COLOR,
+                    "metallicFactor": 0.0,
+                    "roughnessFactor": 1.0,
+                },
+            }],
+        });
+        if !cubes.is_empty() {
+            let position = builder.vec3(&positions, Some(ARRAY_BUFFER));
+            let normal = builder.vec3(&normals, Some(ARRAY_BUFFER));
+            let indices = builder.indices(&indices);
+            if mode == GltfMode::Instanced {
+                let translations: Vec<[f32; 3]> = cubes
+                    .iter()
+                    .map(|(base, _)| [base.x as f32, base.y as f32, base.z as f32])
+                    .collect();
+                let scales: Vec<[f32; 3]> = cubes
+                    .iter()
+                    .map(|&(_, height)| [(1u64 << height) as f32; 3])
+                    .collect();
+                let translation = builder.vec3(&translations, None);
+                let scale = builder.vec3(&scales, None);
+                node["extensions"] = json!({
+                    "EXT_mesh_gpu_instancing": {
+                        "attributes": { "TRANSLATION": translation, "SCALE": scale },
+                    },
+                });
+                gltf["extensionsUsed"] = json!(["EXT_mesh_gpu_instancing"]);
+            }
+            gltf["scenes"][0]["nodes"] = json!([0]);
+            gltf["nodes"] = json!([node]);
+            gltf["meshes"] = json!([{
+                "primitives": [{
+                    "attributes": { "POSITION": position, "NORMAL": normal },
+                    "indices": indices,
+                    "material": 0,
+                }],
+            }]);
+            gltf["buffers"] = json!([{ "byteLength": builder.buffer.len() }]);
+            gltf["bufferViews"] = Value::Array(builder.buffer_views);
+            gltf["accessors"] = Value::Array(builder.accessors);
+        }
+
+        let mut json = serde_json::to_vec(&gltf)?;
+        while json.len() % 4 != 0 {
+            json.push(b' ');
+        }
+        let mut bin = builder.buffer;
+        while bin.len() % 4 != 0 {
+            bin.push(0);
+        }
+        let mut length = 12 + 8 + json.len();
+        if !bin.is_empty() {
+            length += 8 + bin.len();
+        }
+
+        writer.write_all(b"glTF")?;
+        writer.write_all(&2u32.to_le_bytes())?;
+        writer.write_all(&(length as u32).to_le_bytes())?;
+        writer.write_all(&(json.len() as u32).to_le_bytes())?;
+        writer.write_all(b"JSON")?;
+        writer.write_all(&json)?;
+        if !bin.is_empty() {
+            writer.write_all(&(bin.len() as u32).to_le_bytes())?;
+            writer.write_all(b"BIN\0")?;
+            writer.write_all(&bin)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json_chunk(glb: &[u8]) -> Value {
+        assert_eq!(&glb[0..4], b"glTF");
+        let length = u32::from_le_bytes(glb[8..12].try_into().unwrap()) as usize;
+        assert_eq!(length, glb.len());
+        let json_length = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+        assert_eq!(&glb[16..20], b"JSON");
+        serde_json::from_slice(&glb[20..20 + json_length]).unwrap()
+    }
+
+    #[test]
+    fn instanced_export() {
+        let mut octree = OctreeBitmap::new(8);
+        octree.set(&Index::new(1, 2, 3), true);
+        octree.set(&Index::new(4, 4, 4), true);
+
+        let mut glb = Vec::new();
+        octree.export_gltf(&mut glb, GltfMode::Instanced).unwrap();
+        let gltf = json_chunk(&glb);
+        let instancing = &gltf["nodes"][0]["extensions"]["EXT_mesh_gpu_instancing"];
+        let translation = instancing["attributes"]["TRANSLATION"].as_u64().unwrap();
+        assert_eq!(gltf["accessors"][translation as usize]["count"], 2);
+    }
+
+    #[test]
+    fn merged_export() {
+        let mut octree = OctreeBitmap::new(8);
+        octree.set(&Index::new(1, 2, 3), true);
+
+        let mut glb = Vec::new();
+        octree.export_gltf(&mut glb, GltfMode::Merged).unwrap();
+        let gltf = json_chunk(&glb);
+        assert_eq!(gltf["accessors"][0]["count"], 24);
+        assert_eq!(gltf["accessors"][0]["min"], json!([1.0, 2.0, 3.0]));
+
+        let mut glb = Vec::new();
+        OctreeBitmap::new(8)
+            .export_gltf(&mut glb, GltfMode::Merged)
+            .unwrap();
+        assert!(json_chunk(&glb).get("meshes").is_none());
+
+        // Two cubes side by side, of which the surface has no inner faces.
+        octree.set(&Index::new(2, 2, 3), true);
+        let mut glb = Vec::new();
+        octree.export_gltf(&mut glb, GltfMode::Surface).unwrap();
+        let gltf = json_chunk(&glb);
+        assert_eq!(gltf["accessors"][0]["count"], 6 * 4);
+        assert_eq!(gltf["accessors"][0]["max"], json!([3.0, 3.0, 4.0]));
+    }
+}
@@ -0,0 +1,139 @@
+//! Buffers for uploading a bitmap to the GPU, as with `wgpu`.
+//!
+//! The nodes are the descriptors of an [`Esvo`], to be bound as a read-only
+//! storage buffer of `u32`s, alongside a uniform buffer holding the
+//! [`GpuHeader`]. [`ESVO_WGSL`] declares both bindings and a function
+//! looking up voxels in them.
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::{Esvo, OctreeBitmap};
+
+/// WGSL declarations of the header and node buffers, at bindings 0 and 1 of
+/// group 0, and of `octree_get`, which looks up a voxel by descending the
+/// nodes. Change the bindings to suit before including it in a shader.
+pub const ESVO_WGSL: &str = r#"struct OctreeHeader {
+    root: u32,
+    height: u32,
+    len: u32,
+    _padding: u32,
+}
+
+@group(0) @binding(0) var<uniform> octree: OctreeHeader;
+@group(0) @binding(1) var<storage, read> octree_nodes: array<u32>;
+
+// Whether the voxel at the given index is set. Indexes outside the bitmap
+// are empty.
+fn octree_get(index: vec3<u32>) -> bool {
+    if any(index >= vec3<u32>(1u << octree.height)) {
+        return false;
+    }
+    var position = octree.root;
+    for (var height = octree.height; height > 0u; height -= 1u) {
+        let descriptor = octree_nodes[position];
+        let bits = (index >> vec3<u32>(height - 1u)) & vec3<u32>(1u);
+        let bit = 1u << (bits.x | (bits.y << 1u) | (bits.z << 2u));
+        if ((descriptor >> 8u) & bit) == 0u {
+            return false;
+        }
+        if (descriptor & bit) != 0u {
+            return true;
+        }
+        var offset = descriptor >> 17u;
+        if (descriptor & 0x10000u) != 0u {
+            offset = octree_nodes[position + offset];
+        }
+        let branches = (descriptor >> 8u) & ~descriptor & 0xffu;
+        position += offset + countOneBits(branches & (bit - 1u));
+    }
+    // Children at height zero are always leaves, so this is unreachable.
+    return false;
+}
+"#;
+
+/// The metadata needed to traverse the nodes of a [`GpuOctree`], laid out to
+/// be used as a uniform buffer.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+pub struct GpuHeader {
+    /// The position of the descriptor of the root among the nodes.
+    pub root: u32,
+    /// The height of the root, so the width of the bitmap is `1 << height`.
+    pub height: u32,
+    /// The number of nodes.
+    pub len: u32,
+    pub _padding: u32,
+}
+
+/// A bitmap flattened into buffers ready to be uploaded to the GPU.
+///
+/// Created by [`OctreeBitmap::to_gpu`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GpuOctree {
+    header: GpuHeader,
+    nodes: Vec<u32>,
+}
+
+impl GpuOctree {
+    pub fn header(&self) -> &GpuHeader {
+        &self.header
+    }
+
+    /// The ESVO descriptors and far pointers.
+    pub fn nodes(&self) -> &[u32] {
+        &self.nodes
+    }
+
+    /// The contents of the uniform buffer.
+    pub fn header_bytes(&self) -> &[u8] {
+        bytemuck::bytes_of(&self.header)
+    }
+
+    /// The contents of the storage buffer.
+    pub fn node_bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(&self.nodes)
+    }
+}
+
+impl From<Esvo> for GpuOctree {
+    fn from(esvo: Esvo) -> Self {
+        let header = GpuHeader {
+            root: 0,
+            height: esvo.height(),
+            len: esvo.descriptors().len() as u32,
+            _padding: 0,
+        };
+        Self {
+            header,
+            nodes: esvo.into_descriptors(),
+        }
+    }
+}
+
+impl OctreeBitmap {
+    /// Flattens the bitmap into buffers for the GPU, to be traversed as in
+    /// [`ESVO_WGSL`].
+    pub fn to_gpu(&self) -> GpuOctree {
+        self.to_esvo().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Index;
+
+    #[test]
+    fn gpu_buffers() {
+        let mut bitmap = OctreeBitmap::new(16);
+        bitmap.fill_sphere([8.0, 8.0, 8.0], 6.0, true);
+        bitmap.set(&Index::new(30, 1, 2), true);
+        let gpu = bitmap.to_gpu();
+        let esvo = bitmap.to_esvo();
+        assert_eq!(gpu.header().height, esvo.height());
+        assert_eq!(gpu.header().len as usize, gpu.nodes().len());
+        assert_eq!(gpu.header_bytes().len(), 16);
+        let nodes: &[u32] = bytemuck::cast_slice(gpu.node_bytes());
+        assert_eq!(nodes, esvo.descriptors());
+    }
+}
@@ -0,0 +1,93 @@
+//! Building terrain from heightmaps.
+
+use crate::OctreeBitmap;
+
+impl OctreeBitmap {
+    /// A bitmap with each column along `z` filled solid from the bottom up to
+    /// its height, such as to import terrain.
+    ///
+    /// `heights` has one entry for each column in rows along `x`, so the
+    /// height of the column at `(x, y)` is `heights[x + y * width]`, and the
+    /// voxels from `z = 0` up to but not including that height are set. This
+    /// is laid out as a cross section along `z` is in
+    /// [`slice`](Self::slice). Heights past the top of the bitmap are cut
+    /// off. Runs of columns of equal height are filled as single boxes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is not exactly one height for each column, or if
+    /// `width` is zero or greater than [`MAX_WIDTH`](Self::MAX_WIDTH).
+    pub fn from_heightmap(width: u32, heights: &[u32]) -> OctreeBitmap {
+        assert_eq!(
+            heights.len() as u64,
+            u64::from(width) * u64::from(width),
+            "expected one height for each of the {}x{} columns",
+            width,
+            width
+        );
+        let mut bitmap = OctreeBitmap::new(width);
+        bitmap.fill_heightmap(width, heights);
+        bitmap
+    }
+
+    /// Fills the columns of a heightmap with rows of `row_width` columns, as
+    /// for [`from_heightmap`](Self::from_heightmap).
+    pub(crate) fn fill_heightmap(&mut self, row_width: u32, heights: &[u32]) {
+        trace_span!("fill_heightmap", columns = heights.len());
+        if heights.is_empty() {
+            return;
+        }
+        let row_width = row_width as usize;
+        self.record_group(|bitmap| {
+            for (y, row) in heights.chunks(row_width).enumerate() {
+                let mut start = 0;
+                while start < row.len() {
+                    let height = row[start];
+                    let end = start + row[start..].iter().take_while(|&&h| h == height).count();
+                    if height > 0 {
+                        let (y, height) = (y as i64, i64::from(height));
+                        bitmap.fill_box([start as i64, y, 0], [end as i64, y + 1, height], true);
+                    }
+                    start = end;
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Index;
+
+    #[test]
+    fn columns_match_heights() {
+        let width = 12;
+        let heights: Vec<u32> = (0..width * width)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                if x < 4 {
+                    3
+                } else {
+                    (x * 7 + y * 3) % 11 + if y == 5 { 100 } else { 0 }
+                }
+            })
+            .collect();
+        let bitmap = OctreeBitmap::from_heightmap(width, &heights);
+        for z in 0..bitmap.width() {
+            for y in 0..bitmap.width() {
+                for x in 0..bitmap.width() {
+                    let expected = x < width && y < width && z < heights[(x + y * width) as usize];
+                    assert_eq!(
+                        bitmap.get(&Index::new(x, y, z)),
+                        expected,
+                        "{} {} {}",
+                        x,
+                        y,
+                        z
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,431 @@
+//! Convex hulls and bounding spheres of the set voxels.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::fill::{Containment, Shape};
+use crate::{Occupancy, OctreeBitmap};
+
+type Point = [i64; 3];
+
+fn sub(a: Point, b: Point) -> Point {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: Point, b: Point) -> Point {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn dot(a: Point, b: Point) -> i128 {
+    (0..3).map(|i| i128::from(a[i]) * i128::from(b[i])).sum()
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// The vector in lowest terms, or `None` if it is zero.
+fn direction(v: Point) -> Option<Point> {
+    let divisor = gcd(gcd(v[0], v[1]), v[2]);
+    (divisor != 0).then(|| v.map(|c| c / divisor))
+}
+
+/// An incremental convex hull of integer points, whose faces are triangles
+/// wound counterclockwise seen from outside.
+struct Hull {
+    points: Vec<Point>,
+    faces: Vec<[usize; 3]>,
+}
+
+impl Hull {
+    /// The outward normal of the face, scaled by twice its area.
+    fn normal(&self, face: [usize; 3]) -> Point {
+        let [a, b, c] = face.map(|i| self.points[i]);
+        cross(sub(b, a), sub(c, a))
+    }
+
+    /// How far the point is in front of the plane of the face, scaled by the
+    /// length of its normal.
+    fn height(&self, face: [usize; 3], p: Point) -> i128 {
+        dot(self.normal(face), sub(p, self.points[face[0]]))
+    }
+
+    /// The hull of the points, which must not all lie in one plane.
+    fn new(points: Vec<Point>) -> Self {
+        let mut hull = Self {
+            points,
+            faces: Vec::new(),
+        };
+        let p = &hull.points;
+        // The first tetrahedron, from points as far apart as possible.
+        let a = (0..p.len()).min_by_key(|&i| p[i]).unwrap();
+        let squared = |v: Point| dot(v, v);
+        let b = (0..p.len())
+            .max_by_key(|&i| squared(sub(p[i], p[a])))
+            .unwrap();
+        let c = (0..p.len())
+            .max_by_key(|&i| squared(cross(sub(p[b], p[a]), sub(p[i], p[a]))))
+            .unwrap();
+        let normal = cross(sub(p[b], p[a]), sub(p[c], p[a]));
+        let d = (0..p.len())
+            .max_by_key(|&i| dot(normal, sub(p[i], p[a])).abs())
+            .unwrap();
+        assert!(dot(normal, sub(p[d], p[a])) != 0, "points are coplanar");
+        let (b, c) = if dot(normal, sub(p[d], p[a])) > 0 {
+            (c, b)
+        } else {
+            (b, c)
+        };
+        hull.faces = vec![[a, b, c], [a, d, b], [b, d, c], [c, d, a]];
+
+        for i in 0..hull.points.len() {
+            hull.add(i);
+        }
+        hull
+    }
+
+    /// Extends the hull to the point at the given index, if it is outside.
+    fn add(&mut self, i: usize) {
+        let p = self.points[i];
+        let (visible, hidden): (Vec<[usize; 3]>, Vec<[usize; 3]>) = self
+            .faces
+            .iter()
+            .partition(|&&face| self.height(face, p) > 0);
+        if visible.is_empty() {
+            return;
+        }
+        let edges: HashSet<(usize, usize)> = visible
+            .iter()
+            .flat_map(|&[a, b, c]| [(a, b), (b, c), (c, a)])
+            .collect();
+        self.faces = hidden;
+        for &(a, b) in &edges {
+            // The edges of the visible region that border hidden faces.
+            if !edges.contains(&(b, a)) {
+                self.faces.push([a, b, i]);
+            }
+        }
+    }
+
+    /// The points that are corners of the hull, rather than on its edges or
+    /// faces: those where at least three differently-oriented faces meet.
+    fn vertices(&self) -> Vec<Point> {
+        let mut planes: HashMap<usize, HashSet<Point>> = HashMap::new();
+        for &face in &self.faces {
+            let Some(direction) = direction(self.normal(face)) else {
+                continue;
+            };
+            for i in face {
+                planes.entry(i).or_default().insert(direction);
+            }
+        }
+        let mut vertices: Vec<Point> = planes
+            .into_iter()
+            .filter(|(_, directions)| directions.len() >= 3)
+            .map(|(i, _)| self.points[i])
+            .collect();
+        vertices.sort_unstable();
+        vertices
+    }
+}
+
+/// The solid inside a convex hull, as the planes bounding it: the points `x`
+/// with `normal . x <= offset` for each.
+struct HullSolid {
+    planes: Vec<(Point, i128)>,
+}
+
+impl Shape for HullSolid {
+    fn classify(&self, low: [f64; 3], high: [f64; 3]) -> Containment {
+        // Voxel centers are at half-integers, so doubled they are exact.
+        let low = low.map(|c| (c * 2.0) as i64);
+        let high = high.map(|c| (c * 2.0) as i64);
+        let mut inside = true;
+        for &(normal, offset) in &self.planes {
+            // The corners of the box nearest and farthest along the normal.
+            let near = [0, 1, 2].map(|i| if normal[i] > 0 { low[i] } else { high[i] });
+            let far = [0, 1, 2].map(|i| if normal[i] > 0 { high[i] } else { low[i] });
+            if dot(normal, near) > 2 * offset {
+                return Containment::Outside;
+            }
+            inside &= dot(normal, far) <= 2 * offset;
+        }
+        if inside {
+            Containment::Inside
+        } else {
+            Containment::Partial
+        }
+    }
+}
+
+/// The smallest sphere with all of the points on its surface, which must
+/// number at most four.
+fn circumsphere(boundary: &[[f64; 3]]) -> ([f64; 3], f64) {
+    let sub = |a: [f64; 3], b: [f64; 3]| [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+    let dot = |a: [f64; 3], b: [f64; 3]| a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+    let cross = |a: [f64; 3], b: [f64; 3]| {
+        [
+            a[1] * b[2] - a[2] * b[1],
+            a[2] * b[0] - a[0] * b[2],
+            a[0] * b[1] - a[1] * b[0],
+        ]
+    };
+    let at = |origin: [f64; 3], offset: [f64; 3]| {
+        let center = [0, 1, 2].map(|i| origin[i] + offset[i]);
+        (center, dot(offset, offset).sqrt())
+    };
+    match *boundary {
+        [] => ([0.0; 3], -1.0),
+        [a] => (a, 0.0),
+        [a, b] => at(a, sub(b, a).map(|c| c / 2.0)),
+        [a, b, c] => {
+            // The circumcenter, within the plane of the triangle.
+            let (u, v) = (sub(b, a), sub(c, a));
+            let n = cross(u, v);
+            let scale = 2.0 * dot(n, n);
+            if scale == 0.0 {
+                return circumsphere(&[a, b]);
+            }
+            let w = [0, 1, 2].map(|i| dot(u, u) * cross(v, n)[i] + dot(v, v) * cross(n, u)[i]);
+            at(a, w.map(|c| c / scale))
+        }
+        [a, b, c, d] => {
+            let (u, v, w) = (sub(b, a), sub(c, a), sub(d, a));
+            let scale = 2.0 * dot(u, cross(v, w));
+            if scale == 0.0 {
+                return circumsphere(&[a, b, c]);
+            }
+            let offset = [0, 1, 2].map(|i| {
+                dot(u, u) * cross(v, w)[i] + dot(v, v) * cross(w, u)[i] + dot(w, w) * cross(u, v)[i]
+            });
+            at(a, offset.map(|c| c / scale))
+        }
+        _ => unreachable!("too many boundary points"),
+    }
+}
+
+/// The smallest sphere enclosing the points, with the boundary points on its
+/// surface, by Welzl's algorithm. Only the boundary grows with each level of
+/// recursion, so it is at most four deep.
+fn enclose(points: &[[f64; 3]], boundary: &mut Vec<[f64; 3]>) -> ([f64; 3], f64) {
+    let mut sphere = circumsphere(boundary);
+    if boundary.len() == 4 {
+        return sphere;
+    }
+    for (i, &p) in points.iter().enumerate() {
+        let (center, radius) = sphere;
+        let distance = (0..3)
+            .map(|j| (p[j] - center[j]).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        if distance > radius + 1e-9 * radius.max(1.0) {
+            boundary.push(p);
+            sphere = enclose(&points[..i], boundary);
+            boundary.pop();
+        }
+    }
+    sphere
+}
+
+impl OctreeBitmap {
+    /// The hull of the corners of the set voxels, or `None` if no voxels are
+    /// set.
+    fn hull(&self) -> Option<Hull> {
+        // Only the lowest and highest corners along each column can be on
+        // the hull.
+        let mut columns: HashMap<[i64; 2], (i64, i64)> = HashMap::new();
+        self.visit_nodes(|base, height, occupancy| {
+            if occupancy == Occupancy::Full {
+                let side = 1 << height;
+                let [x, y, z] = [base.x, base.y, base.z].map(i64::from);
+                for column in [[x, y], [x + side, y], [x, y + side], [x + side, y + side]] {
+                    let range = columns.entry(column).or_insert((z, z + side));
+                    range.0 = range.0.min(z);
+                    range.1 = range.1.max(z + side);
+                }
+            }
+            occupancy == Occupancy::Mixed
+        });
+        if columns.is_empty() {
+            return None;
+        }
+        let points: Vec<Point> = columns
+            .into_iter()
+            .flat_map(|([x, y], (low, high))| [[x, y, low], [x, y, high]])
+            .collect();
+        // Nor can points that are not also the lowest or highest along the
+        // other two axes.
+        let mut ranges: [HashMap<[i64; 2], (i64, i64)>; 2] = Default::default();
+        for p in &points {
+            for (axis, ranges) in ranges.iter_mut().enumerate() {
+                let key = [p[(axis + 1) % 3], p[(axis + 2) % 3]];
+                let range = ranges.entry(key).or_insert((p[axis], p[axis]));
+                range.0 = range.0.min(p[axis]);
+                range.1 = range.1.max(p[axis]);
+            }
+        }
+        let mut points: Vec<Point> = points
+            .into_iter()
+            .filter(|p| {
+                ranges.iter().enumerate().all(|(axis, ranges)| {
+                    let (low, high) = ranges[&[p[(axis + 1) % 3], p[(axis + 2) % 3]]];
+                    p[axis] == low || p[axis] == high
+                })
+            })
+            .collect();
+        points.sort_unstable();
+        Some(Hull::new(points))
+    }
+
+    /// The corners of the convex hull of the set voxels, as the corners of
+    /// voxels, where the voxel at index `(x, y, z)` spans `x..x + 1` (and so
+    /// on) along each axis, sorted. Empty if no voxels are set.
+    ///
+    /// Only the corners of full nodes that are the lowest or highest along
+    /// their column are candidates, so the interiors of solid regions are
+    /// never visited voxel by voxel. Points on the edges and faces of the
+    /// hull are not included.
+    pub fn convex_hull(&self) -> Vec<[u32; 3]> {
+        self.hull().map_or(Vec::new(), |hull| {
+            hull.vertices()
+                .into_iter()
+                .map(|p| p.map(|c| c as u32))
+                .collect()
+        })
+    }
+
+    /// The center and radius of the smallest sphere enclosing the set voxels,
+    /// as cubes where the voxel at index `(x, y, z)` spans `x..x + 1` (and so
+    /// on) along each axis, or `None` if no voxels are set. Such as for
+    /// culling or the broad phase of collision detection.
+    ///
+    /// The sphere is found by Welzl's algorithm over the corners of the
+    /// [`convex_hull`](Self::convex_hull), in a fixed shuffled order, so it
+    /// takes expected linear time in the number of hull corners.
+    pub fn bounding_sphere(&self) -> Option<([f64; 3], f64)> {
+        let mut points: Vec<[f64; 3]> = self
+            .convex_hull()
+            .into_iter()
+            .map(|p| p.map(f64::from))
+            .collect();
+        if points.is_empty() {
+            return None;
+        }
+        // Welzl's algorithm is slow on points in sorted order.
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        for i in (1..points.len()).rev() {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            points.swap(i, (state % (i as u64 + 1)) as usize);
+        }
+        Some(enclose(&points, &mut Vec::with_capacity(4)))
+    }
+
+    /// A bitmap of the same width with every voxel whose center is inside
+    /// the [`convex_hull`](Self::convex_hull) of this one set, such as a
+    /// solid bounding volume. Every voxel set in this bitmap is set in it.
+    pub fn convex_hull_bitmap(&self) -> OctreeBitmap {
+        let mut filled = self.with_height_like(self.height);
+        if let Some(hull) = self.hull() {
+            // Many faces share a plane.
+            let planes: HashSet<(Point, i128)> = hull
+                .faces
+                .iter()
+                .filter_map(|&face| {
+                    let normal = direction(hull.normal(face))?;
+                    Some((normal, dot(normal, hull.points[face[0]])))
+                })
+                .collect();
+            let planes = planes.into_iter().collect();
+            filled.fill_shape(&HullSolid { planes }, true);
+        }
+        filled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Index;
+
+    #[test]
+    fn hull_of_boxes() {
+        let mut bitmap = OctreeBitmap::new(16);
+        assert!(bitmap.convex_hull().is_empty());
+        assert!(bitmap.convex_hull_bitmap().is_empty());
+
+        bitmap.set(&Index::new(3, 4, 5), true);
+        assert_eq!(bitmap.convex_hull().len(), 8);
+
+        bitmap.fill_box([2, 2, 2], [10, 6, 4], true);
+        bitmap.set(&Index::new(20, 3, 3), true);
+        let hull = bitmap.convex_hull();
+        assert!(hull.contains(&[21, 4, 4]));
+        assert!(hull.contains(&[2, 2, 2]));
+        assert!(!hull.contains(&[3, 4, 5]));
+        assert!(hull.contains(&[3, 4, 6]));
+
+        let filled = bitmap.convex_hull_bitmap();
+        assert!(bitmap.iter().all(|idx| filled.get(&idx)));
+        assert!(filled.get(&Index::new(15, 3, 3)));
+        assert!(!filled.get(&Index::new(15, 5, 5)));
+        assert!(!filled.get(&Index::new(22, 3, 3)));
+
+        // A ball is its own hull, up to the voxels its corners reach.
+        let mut ball = OctreeBitmap::new(32);
+        ball.fill_sphere([30.0, 30.0, 30.0], 20.0, true);
+        let filled = ball.convex_hull_bitmap();
+        assert!(ball.iter().all(|idx| filled.get(&idx)));
+        let extra = filled.iter().count() - ball.iter().count();
+        assert!(extra < ball.surface().iter().count(), "{}", extra);
+    }
+
+    #[test]
+    fn bounding_sphere() {
+        let mut bitmap = OctreeBitmap::new(16);
+        assert_eq!(bitmap.bounding_sphere(), None);
+
+        bitmap.set(&Index::new(3, 4, 5), true);
+        let (center, radius) = bitmap.bounding_sphere().unwrap();
+        assert_eq!(center, [3.5, 4.5, 5.5]);
+        assert!((radius - 0.75f64.sqrt()).abs() < 1e-9);
+
+        bitmap.fill_sphere([12.0, 14.0, 10.0], 5.0, true);
+        bitmap.set(&Index::new(30, 14, 10), true);
+        let (center, radius) = bitmap.bounding_sphere().unwrap();
+        let corners = bitmap.convex_hull();
+        let distance = |p: [u32; 3]| {
+            (0..3)
+                .map(|i| (f64::from(p[i]) - center[i]).powi(2))
+                .sum::<f64>()
+                .sqrt()
+        };
+        assert!(corners.iter().all(|&p| distance(p) <= radius + 1e-6));
+        // Tight, by Jung's theorem: within `sqrt(3/8)` of the diameter.
+        let diameter = corners
+            .iter()
+            .flat_map(|&a| corners.iter().map(move |&b| (a, b)))
+            .map(|(a, b)| {
+                (0..3)
+                    .map(|i| (f64::from(a[i]) - f64::from(b[i])).powi(2))
+                    .sum::<f64>()
+                    .sqrt()
+            })
+            .fold(0.0, f64::max);
+        assert!(radius >= diameter / 2.0 - 1e-6);
+        assert!(
+            radius <= diameter * 0.375f64.sqrt(),
+            "{} {}",
+            radius,
+            diameter
+        );
+    }
+}
@@ -0,0 +1,403 @@
+//! A bitmap of any number of dimensions, implemented as a 2^N-tree.
+
+use std::collections::HashMap;
+
+use crate::OctreeBitmap;
+
+/// The operations shared by bitmaps of `N` dimensions, so that code and tests
+/// written once work with a [`HypertreeBitmap`] of any dimension, a
+/// [`QuadtreeBitmap`](crate::QuadtreeBitmap) and an [`OctreeBitmap`] alike.
+///
+/// The octree keeps its own core behind this trait for now: its bricks,
+/// Morton keys and journal have no `N`-dimensional counterpart yet.
+pub trait Hypertree<const N: usize> {
+    /// The width of the bitmap on every axis.
+    fn width(&self) -> u32;
+
+    /// Clears the map.
+    fn clear(&mut self);
+
+    /// Whether no bits are set.
+    fn is_empty(&self) -> bool;
+
+    /// Get the current value of the bit at the given position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is outside the bitmap.
+    fn get(&self, position: &[u32; N]) -> bool;
+
+    /// Set the value at the given position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is outside the bitmap.
+    fn set(&mut self, position: &[u32; N], value: bool);
+
+    /// An iterator over the positions of all set bits. The order depends on
+    /// the bitmap.
+    fn iter(&self) -> impl Iterator<Item = [u32; N]> + '_;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HyperNode {
+    False,
+    True,
+    Branch,
+}
+
+impl From<bool> for HyperNode {
+    fn from(x: bool) -> Self {
+        if x {
+            Self::True
+        } else {
+            Self::False
+        }
+    }
+}
+
+/// The position of a branch: its base, with the low `height` bits of each
+/// coordinate clear, and its height.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct HyperIndex<const N: usize> {
+    base: [u32; N],
+    height: u32,
+}
+
+impl<const N: usize> HyperIndex<N> {
+    fn at(position: &[u32; N], height: u32) -> Self {
+        let mask = !((1u64 << height) - 1) as u32;
+        Self {
+            base: position.map(|x| x & mask),
+            height,
+        }
+    }
+
+    /// The child with the given index, whose bit `k` is its offset along
+    /// axis `k`.
+    fn child(&self, i: usize) -> Self {
+        let height = self.height - 1;
+        let mut base = self.base;
+        for (k, x) in base.iter_mut().enumerate() {
+            *x |= ((i >> k) as u32 & 1) << height;
+        }
+        Self { base, height }
+    }
+}
+
+/// The index of the child containing the position, among the children of a
+/// branch one level above the given height.
+fn child_index<const N: usize>(position: &[u32; N], height: u32) -> usize {
+    position
+        .iter()
+        .enumerate()
+        .fold(0, |i, (k, x)| i | ((x >> height) as usize & 1) << k)
+}
+
+/// A bitmap of `N` dimensions, such as 2 for terrain masks, 3 for voxels, or
+/// 4 for voxels over time.
+///
+/// Each branch has `2^N` children, and uniform regions are stored as single
+/// nodes, and merged as soon as they become uniform, just like the
+/// quadrants and octants of [`QuadtreeBitmap`](crate::QuadtreeBitmap) and
+/// [`OctreeBitmap`](crate::OctreeBitmap).
+pub struct HypertreeBitmap<const N: usize> {
+    branches: HashMap<HyperIndex<N>, Box<[HyperNode]>>,
+    height: u32,
+}
+
+impl<const N: usize> HypertreeBitmap<N> {
+    /// Creates a new, empty bitmap, with coordinates in the range `0..width`
+    /// on every axis.
+    ///
+    /// As with [`OctreeBitmap::new`](crate::OctreeBitmap::new), the width is
+    /// rounded up to a power of two.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `N` is zero, or too large for the positions of all bits to
+    /// be counted in a `u128`.
+    pub fn new(width: u32) -> Self {
+        // ceil(log2(width)), and at least one level below the root.
+        let height = (u32::BITS - width.next_power_of_two().leading_zeros()).max(1);
+        assert!(
+            N > 0 && N as u32 * height < u128::BITS,
+            "unsupported number of dimensions"
+        );
+        let mut bitmap = Self {
+            branches: HashMap::new(),
+            height,
+        };
+        bitmap.clear();
+        bitmap
+    }
+
+    pub fn width(&self) -> u32 {
+        1 << self.height
+    }
+
+    fn root(&self) -> HyperIndex<N> {
+        HyperIndex::at(&[0; N], self.height)
+    }
+
+    fn uniform(state: HyperNode) -> Box<[HyperNode]> {
+        vec![state; 1 << N].into_boxed_slice()
+    }
+
+    /// Clears the map.
+    pub fn clear(&mut self) {
+        self.branches.clear();
+        self.branches
+            .insert(self.root(), Self::uniform(HyperNode::False));
+    }
+
+    /// Whether no bits are set.
+    pub fn is_empty(&self) -> bool {
+        self.branches[&self.root()]
+            .iter()
+            .all(|&child| child == HyperNode::False)
+    }
+
+    fn check_bounds(&self, position: &[u32; N]) {
+        assert!(
+            position.iter().all(|&x| x < self.width()),
+            "position is out of bounds"
+        );
+    }
+
+    /// Get the current value of the bit at the given position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is outside the bitmap.
+    pub fn get(&self, position: &[u32; N]) -> bool {
+        self.check_bounds(position);
+        let mut height = self.height;
+        loop {
+            let branch = &self.branches[&HyperIndex::at(position, height)];
+            match branch[child_index(position, height - 1)] {
+                HyperNode::False => return false,
+                HyperNode::True => return true,
+                HyperNode::Branch => height -= 1,
+            }
+        }
+    }
+
+    /// Set the value at the given position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is outside the bitmap.
+    pub fn set(&mut self, position: &[u32; N], value: bool) {
+        self.check_bounds(position);
+        let state = HyperNode::from(value);
+        let mut height = self.height;
+        loop {
+            let index = HyperIndex::at(position, height);
+            let i = child_index(position, height - 1);
+            let child = &mut self.branches.get_mut(&index).unwrap()[i];
+            match *child {
+                HyperNode::Branch => height -= 1,
+                other if other == state => return,
+                _ if height == 1 => {
+                    *child = state;
+                    self.compress(position, state, height);
+                    return;
+                }
+                other => {
+                    *child = HyperNode::Branch;
+                    self.branches.insert(index.child(i), Self::uniform(other));
+                }
+            }
+        }
+    }
+
+    /// Merge the branches containing the position, from the given height up,
+    /// that have become uniformly `state`.
+    fn compress(&mut self, position: &[u32; N], state: HyperNode, from_height: u32) {
+        // The root is always a branch.
+        for height in from_height..self.height {
+            let index = HyperIndex::at(position, height);
+            if self.branches[&index].iter().any(|&child| child != state) {
+                return;
+            }
+            self.branches.remove(&index);
+            self.branches
+                .get_mut(&HyperIndex::at(position, height + 1))
+                .unwrap()[child_index(position, height)] = state;
+        }
+    }
+
+    /// An iterator over the positions of all set bits, in Z order.
+    pub fn iter(&self) -> impl Iterator<Item = [u32; N]> + '_ {
+        let mut stack = vec![(self.root(), 0)];
+        // A full node, and the Morton code of the next position in it.
+        let mut full: Option<(HyperIndex<N>, u128)> = None;
+        std::iter::from_fn(move || loop {
+            if let Some((index, next)) = &mut full {
+                if *next >> (N as u32 * index.height) == 0 {
+                    let mut position = index.base;
+                    for (k, x) in position.iter_mut().enumerate() {
+                        *x |= compact::<N>(*next >> k);
+                    }
+                    *next += 1;
+                    return Some(position);
+                }
+                full = None;
+            }
+            let (index, next) = stack.last_mut()?;
+            if *next == 1 << N {
+                stack.pop();
+                continue;
+            }
+            let i = *next;
+            *next += 1;
+            let child = index.child(i);
+            match self.branches[index][i] {
+                HyperNode::False => {}
+                HyperNode::True => full = Some((child, 0)),
+                HyperNode::Branch => stack.push((child, 0)),
+            }
+        })
+    }
+}
+
+impl<const N: usize> Hypertree<N> for HypertreeBitmap<N> {
+    fn width(&self) -> u32 {
+        Self::width(self)
+    }
+
+    fn clear(&mut self) {
+        Self::clear(self);
+    }
+
+    fn is_empty(&self) -> bool {
+        Self::is_empty(self)
+    }
+
+    fn get(&self, position: &[u32; N]) -> bool {
+        Self::get(self, position)
+    }
+
+    fn set(&mut self, position: &[u32; N], value: bool) {
+        Self::set(self, position, value);
+    }
+
+    fn iter(&self) -> impl Iterator<Item = [u32; N]> + '_ {
+        Self::iter(self)
+    }
+}
+
+impl Hypertree<3> for OctreeBitmap {
+    fn width(&self) -> u32 {
+        Self::width(self)
+    }
+
+    fn clear(&mut self) {
+        Self::clear(self);
+    }
+
+    fn is_empty(&self) -> bool {
+        Self::is_empty(self)
+    }
+
+    fn get(&self, position: &[u32; 3]) -> bool {
+        Self::get(self, &(*position).into())
+    }
+
+    fn set(&mut self, position: &[u32; 3], value: bool) {
+        Self::set(self, &(*position).into(), value);
+    }
+
+    fn iter(&self) -> impl Iterator<Item = [u32; 3]> + '_ {
+        Self::iter(self).map(<[u32; 3]>::from)
+    }
+}
+
+/// Every `N`th bit of `bits`, starting from the lowest.
+fn compact<const N: usize>(bits: u128) -> u32 {
+    (0..u32::BITS).fold(0, |acc, i| {
+        acc | (((bits >> (N as u32 * i)) & 1) as u32) << i
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::QuadtreeBitmap;
+
+    /// Sets a solid block and a scattered pattern, checking every position
+    /// and the iterator, and returns the positions that were set.
+    fn check_set_and_get<const N: usize>(bitmap: &mut impl Hypertree<N>) -> Vec<[u32; N]> {
+        let width = bitmap.width();
+        let positions: Vec<[u32; N]> = (0..width.pow(N as u32))
+            .map(|j| std::array::from_fn(|k| j / width.pow(k as u32) % width))
+            .collect();
+        let pattern = |position: &[u32; N]| {
+            position.iter().all(|&x| x < width / 2)
+                || position
+                    .iter()
+                    .enumerate()
+                    .map(|(k, x)| x * (k as u32 + 3))
+                    .sum::<u32>()
+                    % 5
+                    == 0
+        };
+        let mut expected = Vec::new();
+        for position in &positions {
+            bitmap.set(position, pattern(position));
+            if pattern(position) {
+                expected.push(*position);
+            }
+        }
+        for position in &positions {
+            assert_eq!(bitmap.get(position), pattern(position));
+        }
+        let mut iterated: Vec<[u32; N]> = bitmap.iter().collect();
+        assert_eq!(iterated.len(), expected.len());
+        iterated.sort_by_key(|position| position.iter().rev().copied().collect::<Vec<_>>());
+        assert_eq!(iterated, expected);
+        expected
+    }
+
+    /// Clears the positions set by [`check_set_and_get`] again.
+    fn check_clear<const N: usize>(bitmap: &mut impl Hypertree<N>, set: &[[u32; N]]) {
+        for position in set {
+            bitmap.set(position, false);
+        }
+        assert!(bitmap.is_empty());
+        assert!(bitmap.iter().next().is_none());
+    }
+
+    fn check_hypertree<const N: usize>(width: u32) {
+        let mut bitmap = HypertreeBitmap::<N>::new(width);
+        let set = check_set_and_get(&mut bitmap);
+        // The full block is stored as a single node.
+        let block = HyperIndex::at(&[0; N], bitmap.height - 1);
+        assert!(!bitmap.branches.contains_key(&block));
+        check_clear(&mut bitmap, &set);
+        assert_eq!(bitmap.branches.len(), 1);
+    }
+
+    #[test]
+    fn hypertree_set_and_get() {
+        check_hypertree::<1>(64);
+        check_hypertree::<2>(16);
+        check_hypertree::<3>(8);
+        check_hypertree::<4>(4);
+    }
+
+    #[test]
+    fn quadtree_and_octree_set_and_get() {
+        let mut quadtree = QuadtreeBitmap::new(16);
+        let set = check_set_and_get(&mut quadtree);
+        check_clear(&mut quadtree, &set);
+
+        let mut octree = OctreeBitmap::new(8);
+        let set = check_set_and_get(&mut octree);
+        check_clear(&mut octree, &set);
+        Hypertree::set(&mut octree, &[1, 2, 3], true);
+        Hypertree::clear(&mut octree);
+        assert!(Hypertree::is_empty(&octree));
+    }
+}
@@ -0,0 +1,128 @@
+//! Import and export of cross sections as grayscale images.
+
+use ::image::{GrayImage, Luma};
+
+use crate::{Axis, Layer, OctreeBitmap};
+
+impl OctreeBitmap {
+    /// The cross section of the bitmap at coordinate `layer` along an axis as
+    /// an image as wide and tall as the bitmap, with white pixels for set
+    /// voxels and black for unset ones, such as to look at in an ordinary
+    /// image viewer.
+    ///
+    /// Pixel `(u, v)` is the voxel at those coordinates on the other two axes,
+    /// in cyclic order after the axis as for [`slice`](Self::slice).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `layer` is outside the bitmap.
+    pub fn slice_to_image(&self, axis: Axis, layer: u32) -> GrayImage {
+        let slice = self.slice(axis, layer);
+        GrayImage::from_fn(slice.width(), slice.width(), |u, v| {
+            Luma([if slice.get(u, v) { u8::MAX } else { 0 }])
+        })
+    }
+
+    /// Overwrites the cross section of the bitmap at coordinate `layer` along
+    /// an axis with a mask painted in an image editor, laid out as by
+    /// [`slice_to_image`](Self::slice_to_image). Pixels at least as bright as
+    /// the threshold set their voxels, and the rest clear them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `layer` is outside the bitmap, or if the image is not as
+    /// wide and tall as the bitmap.
+    pub fn set_slice_from_image(
+        &mut self,
+        axis: Axis,
+        layer: u32,
+        image: &GrayImage,
+        threshold: u8,
+    ) {
+        let width = self.width();
+        assert!(
+            image.width() == width && image.height() == width,
+            "image is {}x{}, not {}x{}",
+            image.width(),
+            image.height(),
+            width,
+            width
+        );
+        let mut slice = Layer::new(width);
+        for (u, v, &Luma([luma])) in image.enumerate_pixels() {
+            if luma >= threshold {
+                slice.set(u, v, true);
+            }
+        }
+        self.set_slice(axis, layer, &slice);
+    }
+
+    /// A bitmap with the terrain of a grayscale heightmap image, as for
+    /// [`from_heightmap`](Self::from_heightmap), with pixel `(x, y)` giving
+    /// the height of the column at `(x, y)`.
+    ///
+    /// Black is a height of zero and white is `max_height`, with the levels
+    /// between scaled linearly and rounded to the nearest voxel. The bitmap
+    /// is wide enough for the image and `max_height`, and the image need not
+    /// be square.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the image is empty and `max_height` is zero, or if either is
+    /// greater than [`MAX_WIDTH`](Self::MAX_WIDTH).
+    pub fn from_heightmap_image(image: &GrayImage, max_height: u32) -> OctreeBitmap {
+        let heights: Vec<u32> = image
+            .pixels()
+            .map(|&Luma([luma])| {
+                let height = u64::from(luma) * u64::from(max_height);
+                ((height + u64::from(u8::MAX / 2)) / u64::from(u8::MAX)) as u32
+            })
+            .collect();
+        let mut bitmap = OctreeBitmap::new(image.width().max(image.height()).max(max_height));
+        bitmap.fill_heightmap(image.width(), &heights);
+        bitmap
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Index;
+
+    #[test]
+    fn image_round_trip() {
+        let mut bitmap = OctreeBitmap::new(16);
+        bitmap.fill_sphere([10.0, 12.0, 14.0], 6.0, true);
+        let mut image = bitmap.slice_to_image(Axis::Z, 14);
+        assert_eq!(image.dimensions(), (bitmap.width(), bitmap.width()));
+        assert_eq!(image.get_pixel(10, 12).0, [255]);
+        assert_eq!(image.get_pixel(0, 0).0, [0]);
+
+        image.put_pixel(1, 2, Luma([100]));
+        image.put_pixel(3, 4, Luma([99]));
+        image.put_pixel(10, 12, Luma([0]));
+        bitmap.set_slice_from_image(Axis::Z, 14, &image, 100);
+        assert!(bitmap.get(&Index::new(1, 2, 14)));
+        assert!(!bitmap.get(&Index::new(3, 4, 14)));
+        assert!(!bitmap.get(&Index::new(10, 12, 14)));
+        assert!(bitmap.get(&Index::new(10, 12, 13)));
+    }
+
+    #[test]
+    fn heightmap_image() {
+        let image = GrayImage::from_fn(6, 3, |x, y| Luma([(x * 51) as u8 / (y as u8 + 1)]));
+        let bitmap = OctreeBitmap::from_heightmap_image(&image, 10);
+        for y in 0..3 {
+            for x in 0..6 {
+                let column = (0..bitmap.width())
+                    .take_while(|&z| bitmap.get(&Index::new(x, y, z)))
+                    .count() as u32;
+                assert_eq!(column, ((x * 51 / (y + 1)) * 10 + 127) / 255, "{} {}", x, y);
+            }
+        }
+        assert_eq!(bitmap.iter().filter(|idx| idx.y >= 3).count(), 0);
+
+        let empty = OctreeBitmap::from_heightmap_image(&GrayImage::new(0, 0), 10);
+        assert!(empty.is_empty());
+    }
+}
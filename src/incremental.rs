@@ -0,0 +1,340 @@
+//! Streaming of the binary encoding in resumable sections.
+//!
+//! The stream starts with the magic bytes `OCTI`, a format version byte, the
+//! height of the root and the height of the sections, followed by the
+//! height of the leaves in version 2, as in the binary format. It is
+//! followed by the nodes at the section height, in Morton order, each
+//! encoded as in the binary format, along with any nodes below it. The
+//! levels above the sections are implied, so each section starts at a known
+//! position in the order, and a stream that was cut off can be resumed after
+//! the last complete section.
+
+use std::io::{self, Read, Write};
+
+use crate::binary::{
+    brick_bytes, read_leaf_height, LEAF_VERSION, TAG_BRANCH, TAG_BRICK, TAG_EMPTY, TAG_FULL,
+};
+use crate::{BinaryError, Branch, BranchIndex, Index, OctreeBitmap, RawNode, View, BRICK_HEIGHT};
+
+const MAGIC: &[u8; 4] = b"OCTI";
+const VERSION: u8 = 1;
+
+/// The number of levels above the sections, so there are at most `8^4`
+/// sections.
+const SECTION_DEPTH: u32 = 4;
+
+/// How much of an incremental stream was written, as found by
+/// [`OctreeBitmap::incremental_progress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IncrementalProgress {
+    sections: u64,
+    len: u64,
+    /// The heights of the root and of the leaves, if the header was written.
+    heights: Option<(u32, u32)>,
+}
+
+impl IncrementalProgress {
+    /// The number of complete sections.
+    pub fn sections(&self) -> u64 {
+        self.sections
+    }
+
+    /// The length in bytes of the header and the complete sections. Anything
+    /// after this should be cut off before resuming.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether nothing, not even the header, was written completely.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// A reader that counts the bytes read from it.
+struct Counting<R> {
+    reader: R,
+    count: u64,
+}
+
+impl<R: Read> Read for Counting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+fn is_eof(err: &BinaryError) -> bool {
+    matches!(err, BinaryError::Io(err) if err.kind() == io::ErrorKind::UnexpectedEof)
+}
+
+/// The height of the sections of a bitmap with the given root and leaf
+/// heights.
+fn section_height(height: u32, leaf_height: u32) -> u32 {
+    let brick_height = leaf_height.min(height - 1);
+    height.saturating_sub(SECTION_DEPTH).max(brick_height)
+}
+
+/// Reads the header, returning the heights of the root and of the leaves.
+fn read_header<R: Read>(reader: &mut R) -> Result<(u32, u32), BinaryError> {
+    let mut header = [0; 7];
+    reader.read_exact(&mut header)?;
+    if &header[..4] != MAGIC {
+        return Err(BinaryError::Invalid("missing magic bytes"));
+    }
+    let leaf_height = match header[4] {
+        VERSION => BRICK_HEIGHT,
+        LEAF_VERSION => read_leaf_height(reader)?,
+        version => return Err(BinaryError::UnsupportedVersion(version)),
+    };
+    let height = u32::from(header[5]);
+    if height == 0 || height >= u32::BITS {
+        return Err(BinaryError::Invalid("root height"));
+    }
+    if u32::from(header[6]) != section_height(height, leaf_height) {
+        return Err(BinaryError::Invalid("section height"));
+    }
+    Ok((height, leaf_height))
+}
+
+impl OctreeBitmap {
+    /// Writes the bitmap as an incremental stream, one section at a time, so
+    /// that nothing but the current path through the tree is held in memory
+    /// besides the bitmap itself.
+    ///
+    /// If writing is interrupted, find out how far it got with
+    /// [`incremental_progress`](Self::incremental_progress) and continue with
+    /// [`resume_incremental`](Self::resume_incremental). The undo history is
+    /// not written.
+    pub fn write_incremental<W: Write>(&self, writer: W) -> io::Result<()> {
+        self.write_sections(writer, 0, true)
+    }
+
+    /// Continues writing an incremental stream of this bitmap that was cut
+    /// off, after the stream has been truncated to the length of the
+    /// progress. The bitmap must not have changed since the stream was
+    /// started.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the progress is from a stream of a bitmap of a different
+    /// width or [leaf size](Self::leaf_size).
+    pub fn resume_incremental<W: Write>(
+        &self,
+        writer: W,
+        progress: &IncrementalProgress,
+    ) -> io::Result<()> {
+        match progress.heights {
+            None => self.write_sections(writer, 0, true),
+            Some(heights) => {
+                assert_eq!(
+                    heights,
+                    (self.height, self.leaf_height),
+                    "cannot resume a stream of a bitmap of a different width or leaf size"
+                );
+                self.write_sections(writer, progress.sections, false)
+            }
+        }
+    }
+
+    fn write_sections<W: Write>(&self, writer: W, skip: u64, header: bool) -> io::Result<()> {
+        let mut writer = io::BufWriter::new(writer);
+        let section_height = section_height(self.height, self.leaf_height);
+        if header {
+            writer.write_all(MAGIC)?;
+            if self.leaf_height == BRICK_HEIGHT {
+                writer.write_all(&[VERSION, self.height as u8, section_height as u8])?;
+            } else {
+                writer.write_all(&[
+                    LEAF_VERSION,
+                    self.height as u8,
+                    section_height as u8,
+                    self.leaf_height as u8,
+                ])?;
+            }
+        }
+        let root = BranchIndex::root(self.height);
+        let mut skip = skip;
+        self.write_level(
+            &mut writer,
+            root,
+            View::root(self),
+            section_height,
+            &mut skip,
+        )?;
+        writer.flush()
+    }
+
+    /// Writes the sections below the node, after skipping the given number
+    /// of them.
+    fn write_level<W: Write>(
+        &self,
+        writer: &mut W,
+        index: BranchIndex,
+        view: View,
+        section_height: u32,
+        skip: &mut u64,
+    ) -> io::Result<()> {
+        for i in 0..8 {
+            let child = index.child(i & 1, (i >> 1) & 1, i >> 2);
+            let sections = 1u64 << (3 * (child.height - section_height));
+            if *skip >= sections {
+                *skip -= sections;
+                continue;
+            }
+            let child_view = view.child(self, index, i);
+            if child.height > section_height {
+                self.write_level(writer, child, child_view, section_height, skip)?;
+                continue;
+            }
+            match child_view {
+                View::Uniform(false) => writer.write_all(&[TAG_EMPTY])?,
+                View::Uniform(true) => writer.write_all(&[TAG_FULL])?,
+                View::Branch(child) => {
+                    writer.write_all(&[TAG_BRANCH])?;
+                    self.write_branch(writer, child)?;
+                }
+                View::Brick(bits) => {
+                    writer.write_all(&[TAG_BRICK])?;
+                    writer.write_all(&bits.to_le_bytes()[..brick_bytes(child.height)])?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a bitmap written by [`write_incremental`](Self::write_incremental).
+    ///
+    /// As with [`from_binary`](Self::from_binary), the tree is checked as it
+    /// is read, and the reader should be buffered.
+    pub fn read_incremental<R: Read>(mut reader: R) -> Result<Self, BinaryError> {
+        let (height, leaf_height) = read_header(&mut reader)?;
+        let mut bitmap = Self::with_height(height);
+        bitmap.leaf_height = leaf_height;
+        let root = BranchIndex::root(height);
+        let section_height = section_height(height, leaf_height);
+        let children = bitmap.read_level(&mut reader, root, section_height)?;
+        bitmap.branches.insert(root, Branch { children });
+        Ok(bitmap)
+    }
+
+    /// Reads the children of the node, whose levels down to the sections are
+    /// implied.
+    fn read_level<R: Read>(
+        &mut self,
+        reader: &mut R,
+        index: BranchIndex,
+        section_height: u32,
+    ) -> Result<[[[RawNode; 2]; 2]; 2], BinaryError> {
+        let mut children = [[[RawNode::False; 2]; 2]; 2];
+        for i in 0..8 {
+            let (x, y, z) = (i & 1, (i >> 1) & 1, i >> 2);
+            let child = index.child(x, y, z);
+            children[z][y][x] = if child.height > section_height {
+                let grandchildren = self.read_level(reader, child, section_height)?;
+                self.insert_branch(child, grandchildren)
+            } else {
+                self.read_node(reader, child)?
+            };
+        }
+        Ok(children)
+    }
+
+    /// Finds how much of an incremental stream, which may have been cut off,
+    /// was written completely.
+    ///
+    /// Each section is checked as if it were being read, but only one is held
+    /// in memory at a time. A stream that ends partway through is not an
+    /// error, while one with invalid contents is.
+    pub fn incremental_progress<R: Read>(reader: R) -> Result<IncrementalProgress, BinaryError> {
+        let mut reader = Counting { reader, count: 0 };
+        let mut progress = IncrementalProgress {
+            sections: 0,
+            len: 0,
+            heights: None,
+        };
+        let (height, leaf_height) = match read_header(&mut reader) {
+            Ok(heights) => heights,
+            Err(err) if is_eof(&err) => return Ok(progress),
+            Err(err) => return Err(err),
+        };
+        progress.len = reader.count;
+        progress.heights = Some((height, leaf_height));
+        let section_height = section_height(height, leaf_height);
+        let mut scratch = Self::with_height(height);
+        scratch.leaf_height = leaf_height;
+        for section in 0..1u64 << (3 * (height - section_height)) {
+            let base = Index::from_morton(u128::from(section) << (3 * section_height));
+            match scratch.read_node(&mut reader, base.branch_at(section_height)) {
+                Ok(_) => {}
+                Err(err) if is_eof(&err) => break,
+                Err(err) => return Err(err),
+            }
+            scratch.branches.clear();
+            progress.sections += 1;
+            progress.len = reader.count;
+        }
+        Ok(progress)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::pseudo_random_indexes;
+    use crate::LeafSize;
+
+    #[test]
+    fn resume_interrupted_stream() {
+        let mut bitmap = OctreeBitmap::new(64);
+        bitmap.fill_sphere([40.0, 50.0, 60.0], 30.0, true);
+        bitmap.set_many(pseudo_random_indexes(128, 500), true);
+        let mut full = Vec::new();
+        bitmap.write_incremental(&mut full).unwrap();
+        let read = OctreeBitmap::read_incremental(&full[..]).unwrap();
+        assert_eq!(read.validate(), Ok(()));
+        assert!(read == bitmap);
+        assert_eq!(read.branches.len(), bitmap.branches.len());
+
+        let done = OctreeBitmap::incremental_progress(&full[..]).unwrap();
+        assert_eq!(done.sections(), 8u64.pow(SECTION_DEPTH));
+        assert_eq!(done.len(), full.len() as u64);
+        for cut in [0, 3, 7, 100, full.len() / 2, full.len() - 1] {
+            let mut stream = full[..cut].to_vec();
+            let progress = OctreeBitmap::incremental_progress(&stream[..]).unwrap();
+            assert!(progress.len() <= cut as u64);
+            stream.truncate(progress.len() as usize);
+            bitmap.resume_incremental(&mut stream, &progress).unwrap();
+            assert_eq!(stream, full);
+        }
+
+        let leafy = bitmap.clone().with_leaf_size(LeafSize::Two);
+        let mut stream = Vec::new();
+        leafy.write_incremental(&mut stream).unwrap();
+        assert_eq!(stream[4], LEAF_VERSION);
+        let read = OctreeBitmap::read_incremental(&stream[..]).unwrap();
+        assert_eq!(read.leaf_size(), LeafSize::Two);
+        assert!(read == bitmap);
+        stream.truncate(stream.len() / 2);
+        let progress = OctreeBitmap::incremental_progress(&stream[..]).unwrap();
+        assert!(progress.sections() > 0);
+        stream.truncate(progress.len() as usize);
+        leafy.resume_incremental(&mut stream, &progress).unwrap();
+        assert!(OctreeBitmap::read_incremental(&stream[..]).unwrap() == bitmap);
+
+        let mut bytes = full.clone();
+        bytes[7] = 9;
+        assert!(matches!(
+            OctreeBitmap::incremental_progress(&bytes[..]),
+            Err(BinaryError::Invalid(_))
+        ));
+        for width in [1, 2, 4] {
+            let mut bitmap = OctreeBitmap::new(width);
+            bitmap.set(&Index::new(0, 1, 0), true);
+            let mut bytes = Vec::new();
+            bitmap.write_incremental(&mut bytes).unwrap();
+            assert!(OctreeBitmap::read_incremental(&bytes[..]).unwrap() == bitmap);
+        }
+    }
+}
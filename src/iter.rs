@@ -0,0 +1,528 @@
+//! Iteration over the set or unset voxels of a bitmap.
+
+use std::collections::hash_map;
+use std::iter::FusedIterator;
+use std::ops::ControlFlow;
+
+use crate::{brick_mask, Branch, BranchIndex, Index, OctreeBitmap, RawNode, View};
+
+/// The voxels of a single node that has no branch children which have the
+/// value being iterated over.
+pub(crate) enum LeafVoxels {
+    /// An octant uniformly of that value, along with the Morton offset of the
+    /// next voxel within it.
+    Full {
+        base: Index,
+        height: u32,
+        next: u128,
+    },
+    /// A brick, with the bits of the voxels that are yet to be yielded.
+    Brick { base: Index, bits: u64 },
+}
+
+impl LeafVoxels {
+    /// The voxels of a child node with the given value, or `None` if it has
+    /// none or is a branch.
+    pub(crate) fn new(index: BranchIndex, node: RawNode, value: bool) -> Option<Self> {
+        match node {
+            RawNode::True | RawNode::False if node == RawNode::from(value) => Some(Self::Full {
+                base: index.base,
+                height: index.height,
+                next: 0,
+            }),
+            RawNode::Brick(bits) => Some(Self::Brick {
+                base: index.base,
+                bits: if value {
+                    bits
+                } else {
+                    !bits & brick_mask(index.height)
+                },
+            }),
+            RawNode::True | RawNode::False | RawNode::Branch => None,
+        }
+    }
+}
+
+impl Iterator for LeafVoxels {
+    type Item = Index;
+
+    fn next(&mut self) -> Option<Index> {
+        let (base, offset) = match self {
+            Self::Full { base, height, next } => {
+                if *next >> (3 * *height) != 0 {
+                    return None;
+                }
+                *next += 1;
+                (*base, Index::from_morton(*next - 1))
+            }
+            Self::Brick { base, bits } => {
+                if *bits == 0 {
+                    return None;
+                }
+                let offset = bits.trailing_zeros();
+                *bits &= *bits - 1;
+                (*base, Index::from_morton(offset as u128))
+            }
+        };
+        Some(Index {
+            x: base.x | offset.x,
+            y: base.y | offset.y,
+            z: base.z | offset.z,
+        })
+    }
+}
+
+/// An iterator over the set voxels of a bitmap, or the unset ones, in Morton
+/// order.
+///
+/// Created by [`OctreeBitmap::iter`] and [`OctreeBitmap::iter_unset`].
+pub struct Iter<'a> {
+    bitmap: &'a OctreeBitmap,
+    /// The value of the voxels yielded.
+    value: bool,
+    /// The branches being visited, each with the position of the next child
+    /// to visit.
+    stack: Vec<(BranchIndex, &'a Branch, usize)>,
+    leaf: Option<LeafVoxels>,
+}
+
+impl<'a> Iter<'a> {
+    /// An iterator over the set voxels of a single node of the bitmap.
+    pub(crate) fn node(bitmap: &'a OctreeBitmap, index: BranchIndex, node: RawNode) -> Self {
+        Self::node_with(bitmap, index, node, true)
+    }
+
+    /// An iterator over the voxels of a single node of the bitmap with the
+    /// given value.
+    fn node_with(bitmap: &'a OctreeBitmap, index: BranchIndex, node: RawNode, value: bool) -> Self {
+        match node {
+            RawNode::Branch => Self {
+                bitmap,
+                value,
+                stack: vec![(index, &bitmap.branches[&index], 0)],
+                leaf: None,
+            },
+            node => Self {
+                bitmap,
+                value,
+                stack: Vec::new(),
+                leaf: LeafVoxels::new(index, node, value),
+            },
+        }
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Index;
+
+    fn next(&mut self) -> Option<Index> {
+        loop {
+            if let Some(idx) = self.leaf.as_mut().and_then(Iterator::next) {
+                return Some(idx);
+            }
+            self.leaf = None;
+
+            let (index, branch, next_child) = self.stack.last_mut()?;
+            if *next_child == 8 {
+                self.stack.pop();
+                continue;
+            }
+            let (x, y, z) = (*next_child & 1, (*next_child >> 1) & 1, *next_child >> 2);
+            *next_child += 1;
+            let child_index = index.child(x, y, z);
+            match branch.children[z][y][x] {
+                RawNode::Branch => {
+                    let child = &self.bitmap.branches[&child_index];
+                    self.stack.push((child_index, child, 0));
+                }
+                node => self.leaf = LeafVoxels::new(child_index, node, self.value),
+            }
+        }
+    }
+}
+
+impl FusedIterator for Iter<'_> {}
+
+/// An iterator over the set voxels of a bitmap inside a box, or the unset
+/// ones, in Morton order.
+///
+/// Created by [`OctreeBitmap::iter_in_box`] and
+/// [`OctreeBitmap::iter_unset_in_box`].
+pub struct BoxIter<'a> {
+    bitmap: &'a OctreeBitmap,
+    /// The value of the voxels yielded.
+    value: bool,
+    min: [u32; 3],
+    max: [u32; 3],
+    /// The nodes that cross the edges of the box and are yet to be visited,
+    /// with the next one on top.
+    stack: Vec<(BranchIndex, View)>,
+    /// The voxels of a node that is inside the box.
+    inside: Option<Iter<'a>>,
+}
+
+impl Iterator for BoxIter<'_> {
+    type Item = Index;
+
+    fn next(&mut self) -> Option<Index> {
+        loop {
+            if let Some(idx) = self.inside.as_mut().and_then(Iterator::next) {
+                return Some(idx);
+            }
+            self.inside = None;
+
+            let (node, view) = self.stack.pop()?;
+            let low = [node.base.x, node.base.y, node.base.z];
+            let side = 1u64 << node.height;
+            let overlaps = (0..3)
+                .all(|i| u64::from(low[i]) + side > u64::from(self.min[i]) && low[i] < self.max[i]);
+            if !overlaps {
+                continue;
+            }
+            let inside = (0..3).all(|i| {
+                low[i] >= self.min[i] && u64::from(low[i]) + side <= u64::from(self.max[i])
+            });
+            match view {
+                View::Uniform(value) if value != self.value => {}
+                _ if inside => {
+                    let raw = match view {
+                        View::Uniform(value) => RawNode::from(value),
+                        View::Branch(_) => RawNode::Branch,
+                        View::Brick(bits) => RawNode::Brick(bits),
+                    };
+                    self.inside = Some(Iter::node_with(self.bitmap, node, raw, self.value));
+                }
+                // Uniform nodes are of the value being looked for here, so
+                // only their part inside the box is visited.
+                _ => {
+                    for i in (0..8).rev() {
+                        let child = node.child(i & 1, (i >> 1) & 1, i >> 2);
+                        self.stack.push((child, view.child(self.bitmap, node, i)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl FusedIterator for BoxIter<'_> {}
+
+/// The predicate selecting the branches removed by [`Drain`].
+type NotRoot = Box<dyn FnMut(&BranchIndex, &mut Branch) -> bool>;
+
+/// A draining iterator over the set voxels of a bitmap, in no particular
+/// order.
+///
+/// Created by [`OctreeBitmap::drain`]. The bitmap is left empty when this is
+/// dropped, even if it was not fully consumed.
+pub struct Drain<'a> {
+    branches: hash_map::ExtractIf<'a, BranchIndex, Branch, NotRoot>,
+    /// The children of the branch being drained, and the position of the next
+    /// child.
+    current: Option<(BranchIndex, Branch, usize)>,
+    leaf: Option<LeafVoxels>,
+}
+
+impl Iterator for Drain<'_> {
+    type Item = Index;
+
+    fn next(&mut self) -> Option<Index> {
+        loop {
+            if let Some(idx) = self.leaf.as_mut().and_then(Iterator::next) {
+                return Some(idx);
+            }
+            self.leaf = None;
+
+            match &mut self.current {
+                Some((index, branch, next_child)) if *next_child < 8 => {
+                    let (x, y, z) = (*next_child & 1, (*next_child >> 1) & 1, *next_child >> 2);
+                    *next_child += 1;
+                    self.leaf =
+                        LeafVoxels::new(index.child(x, y, z), branch.children[z][y][x], true);
+                }
+                _ => {
+                    let (index, branch) = self.branches.next()?;
+                    self.current = Some((index, branch, 0));
+                }
+            }
+        }
+    }
+}
+
+impl FusedIterator for Drain<'_> {}
+
+impl Drop for Drain<'_> {
+    fn drop(&mut self) {
+        // Removing the remaining branches is enough; their voxels don't need
+        // to be visited.
+        self.branches.by_ref().for_each(drop);
+    }
+}
+
+/// The order of the indexes returned by [`OctreeBitmap::to_indices`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IndexOrder {
+    /// Morton (Z-order) order, as for [`OctreeBitmap::iter`].
+    Morton,
+    /// By `x`, then `y`, then `z`, as for the [`Ord`] implementation of
+    /// [`Index`].
+    Lexicographic,
+}
+
+impl OctreeBitmap {
+    /// The indexes of all set voxels, in the given order, such as for
+    /// exporting them as a flat list of points.
+    ///
+    /// The voxels are counted first, a node at a time, so the list is
+    /// allocated once at its final size, and sorted in place when not in
+    /// Morton order.
+    pub fn to_indices(&self, order: IndexOrder) -> Vec<Index> {
+        let mut indices = Vec::with_capacity(self.count_set() as usize);
+        indices.extend(self.iter());
+        if order == IndexOrder::Lexicographic {
+            indices.sort_unstable();
+        }
+        indices
+    }
+
+    /// An iterator over the indexes of all set voxels, in Morton
+    /// (Z-order) order.
+    ///
+    /// The order only depends on which voxels are set, not on the order they
+    /// were set in or how the tree is stored, so output written from it is
+    /// the same across runs.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter::node(self, BranchIndex::root(self.height), RawNode::Branch)
+    }
+
+    /// An iterator over the indexes of the set voxels from `min` to `max`
+    /// (exclusive) on each axis, in Morton order.
+    ///
+    /// Only the nodes that cross the edges of the box are descended into to
+    /// test their voxels, and nodes outside the box are skipped whole.
+    pub fn iter_in_box(&self, min: Index, max: Index) -> BoxIter<'_> {
+        self.box_iter(min, max, true)
+    }
+
+    /// An iterator over the indexes of all unset voxels, in Morton order,
+    /// such as to sample free space or choose spawn points.
+    ///
+    /// Empty octants are yielded voxel by voxel, without visiting the full
+    /// ones, so the iterator takes time proportional to the number of unset
+    /// voxels.
+    pub fn iter_unset(&self) -> Iter<'_> {
+        Iter::node_with(self, BranchIndex::root(self.height), RawNode::Branch, false)
+    }
+
+    /// An iterator over the indexes of the unset voxels from `min` to `max`
+    /// (exclusive) on each axis, in Morton order, as for
+    /// [`iter_in_box`](Self::iter_in_box).
+    pub fn iter_unset_in_box(&self, min: Index, max: Index) -> BoxIter<'_> {
+        self.box_iter(min, max, false)
+    }
+
+    fn box_iter(&self, min: Index, max: Index, value: bool) -> BoxIter<'_> {
+        let root = BranchIndex::root(self.height);
+        BoxIter {
+            bitmap: self,
+            value,
+            min: [min.x, min.y, min.z],
+            max: [max.x, max.y, max.z],
+            stack: vec![(root, View::Branch(root))],
+            inside: None,
+        }
+    }
+
+    /// Calls `f` with the index of each set voxel, in Morton order, until it
+    /// returns [`ControlFlow::Break`], such as to search for a voxel
+    /// satisfying some condition. Returns the value it broke with, or
+    /// [`ControlFlow::Continue`] if it never did.
+    ///
+    /// Voxels after the one that broke the loop are never visited.
+    pub fn try_for_each_set<B>(&self, f: impl FnMut(Index) -> ControlFlow<B>) -> ControlFlow<B> {
+        self.iter().try_for_each(f)
+    }
+
+    /// Clears the map, returning the indexes of all voxels that were set as
+    /// an iterator.
+    ///
+    /// The voxels are yielded in no particular order, which may differ from
+    /// run to run; sort them by [`Index::morton`] for the order of
+    /// [`iter`](Self::iter). The memory used by the
+    /// removed branches is kept for reuse, as with [`HashMap::drain`].
+    ///
+    /// [`HashMap::drain`]: std::collections::HashMap::drain
+    pub fn drain(&mut self) -> Drain<'_> {
+        let root = BranchIndex::root(self.height);
+        self.record_subtree(root);
+        let root_children = std::mem::replace(
+            &mut self.branches.get_mut(&root).unwrap().children,
+            [[[RawNode::False; 2]; 2]; 2],
+        );
+        let height = self.height;
+        Drain {
+            branches: self
+                .branches
+                .extract_if(Box::new(move |index, _| index.height != height)),
+            current: Some((
+                root,
+                Branch {
+                    children: root_children,
+                },
+                0,
+            )),
+            leaf: None,
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a OctreeBitmap {
+    type Item = Index;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> (OctreeBitmap, Vec<Index>) {
+        let mut octree = OctreeBitmap::new(16);
+        let mut expected = Vec::new();
+        for x in 0..16 {
+            for y in 0..16 {
+                for z in 0..16 {
+                    let idx = Index::new(x, y, z);
+                    if (x < 8 && y < 8 && z < 8) || (x * y + z) % 7 == 0 {
+                        octree.set(&idx, true);
+                        expected.push(idx);
+                    }
+                }
+            }
+        }
+        (octree, expected)
+    }
+
+    #[test]
+    fn iter_in_morton_order() {
+        let (octree, mut expected) = sample();
+        expected.sort_by_key(Index::morton);
+        assert_eq!(octree.iter().collect::<Vec<_>>(), expected);
+        assert_eq!(OctreeBitmap::new(16).iter().next(), None);
+    }
+
+    #[test]
+    fn to_indices_sorted() {
+        let (octree, expected) = sample();
+        let indices = octree.to_indices(IndexOrder::Lexicographic);
+        assert_eq!(indices, expected);
+        assert_eq!(indices.capacity(), expected.len());
+        assert_eq!(
+            octree.to_indices(IndexOrder::Morton),
+            octree.iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn order_is_independent_of_history() {
+        let (octree, mut voxels) = sample();
+        voxels.reverse();
+        let mut reversed = OctreeBitmap::new(16);
+        reversed.fill_node(BranchIndex::root(reversed.height), true);
+        reversed.clear();
+        for idx in &voxels {
+            reversed.set(idx, true);
+        }
+        assert!(reversed.iter().eq(octree.iter()));
+        assert_eq!(reversed.to_binary(), octree.to_binary());
+
+        let mut drained: Vec<Index> = reversed.drain().collect();
+        drained.sort_by_key(Index::morton);
+        assert!(drained.into_iter().eq(octree.iter()));
+    }
+
+    #[test]
+    fn iter_in_box_matches_filter() {
+        let (octree, _) = sample();
+        for (min, max) in [
+            (Index::new(0, 0, 0), Index::new(32, 32, 32)),
+            (Index::new(3, 1, 4), Index::new(11, 9, 7)),
+            (Index::new(8, 0, 0), Index::new(16, 8, 8)),
+            (Index::new(5, 5, 5), Index::new(5, 9, 9)),
+        ] {
+            let expected: Vec<Index> = octree
+                .iter()
+                .filter(|idx| {
+                    (min.x..max.x).contains(&idx.x)
+                        && (min.y..max.y).contains(&idx.y)
+                        && (min.z..max.z).contains(&idx.z)
+                })
+                .collect();
+            assert_eq!(octree.iter_in_box(min, max).collect::<Vec<_>>(), expected);
+        }
+    }
+
+    #[test]
+    fn try_for_each_set_stops() {
+        let mut bitmap = OctreeBitmap::new(32);
+        bitmap.fill_box([0, 0, 0], [16, 16, 16], true);
+        bitmap.set(&Index::new(20, 1, 1), true);
+        let mut visited = 0;
+        let found = bitmap.try_for_each_set(|idx| {
+            visited += 1;
+            match idx.x == 3 && idx.y == 3 {
+                true => ControlFlow::Break(idx),
+                false => ControlFlow::Continue(()),
+            }
+        });
+        assert_eq!(found, ControlFlow::Break(Index::new(3, 3, 0)));
+        assert_eq!(visited, Index::new(3, 3, 0).morton() + 1);
+        assert_eq!(
+            bitmap.try_for_each_set(|_| ControlFlow::<()>::Continue(())),
+            ControlFlow::Continue(())
+        );
+    }
+
+    #[test]
+    fn iter_unset_is_complement() {
+        let (octree, _) = sample();
+        let width = octree.width();
+        let mut expected: Vec<Index> = (0..1u128 << (3 * octree.height))
+            .map(Index::from_morton)
+            .filter(|idx| !octree.get(idx))
+            .collect();
+        assert_eq!(octree.iter_unset().collect::<Vec<_>>(), expected);
+
+        let (min, max) = (Index::new(3, 1, 4), Index::new(width, 9, 7));
+        expected.retain(|idx| {
+            (min.x..max.x).contains(&idx.x)
+                && (min.y..max.y).contains(&idx.y)
+                && (min.z..max.z).contains(&idx.z)
+        });
+        assert_eq!(
+            octree.iter_unset_in_box(min, max).collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    #[test]
+    fn drain_empties() {
+        let (mut octree, mut expected) = sample();
+        let mut drained: Vec<Index> = octree.drain().collect();
+        drained.sort();
+        expected.sort();
+        assert_eq!(drained, expected);
+        assert_eq!(octree.iter().next(), None);
+        assert_eq!(octree.branches.len(), 1);
+
+        let (mut octree, _) = sample();
+        octree.drain().take(10).for_each(drop);
+        assert_eq!(octree.iter().next(), None);
+        assert_eq!(octree.branches.len(), 1);
+        octree.set(&Index::new(3, 3, 3), true);
+        assert_eq!(octree.iter().collect::<Vec<_>>(), [Index::new(3, 3, 3)]);
+    }
+}
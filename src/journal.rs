@@ -0,0 +1,269 @@
+//! Recording of edits for undo and redo.
+
+use crate::{Branch, BranchIndex, Index, NodeStore, OctreeBitmap, RawNode};
+
+/// The change that reverses one edit.
+enum Inverse {
+    /// Set all of these voxels to the value.
+    Voxels(Vec<Index>, bool),
+    /// Replace the octant at the node with its recorded contents.
+    Subtree {
+        node: BranchIndex,
+        raw: RawNode,
+        branches: Vec<(BranchIndex, Branch)>,
+    },
+    /// Apply all of these changes, in reverse order.
+    Group(Vec<Inverse>),
+}
+
+/// The edits recorded while in recording mode.
+#[derive(Default)]
+pub(crate) struct Journal {
+    undo: Vec<Inverse>,
+    redo: Vec<Inverse>,
+}
+
+impl<S: NodeStore> OctreeBitmap<S> {
+    /// Starts recording every change made to the bitmap, so that it can be
+    /// reverted with [`undo`](Self::undo).
+    ///
+    /// Each call to a mutating method ([`set`](Self::set),
+    /// [`set_many`](Self::set_many), [`clear`](Self::clear) and so on) is
+    /// recorded as a single edit. Edits of individual voxels record just the
+    /// voxels that changed; edits that replace whole octants record a copy
+    /// of the branches they replace. The history grows until recording is
+    /// stopped.
+    pub fn start_recording(&mut self) {
+        self.journal.get_or_insert_with(Default::default);
+    }
+
+    /// Stops recording changes and discards the undo and redo history.
+    pub fn stop_recording(&mut self) {
+        self.journal = None;
+    }
+
+    /// Whether changes are being recorded.
+    pub fn is_recording(&self) -> bool {
+        self.journal.is_some()
+    }
+
+    /// Reverts the most recent recorded edit that has not been undone, making
+    /// it available to [`redo`](Self::redo). Returns whether there was an
+    /// edit to undo.
+    pub fn undo(&mut self) -> bool {
+        let Some(inverse) = self.journal.as_mut().and_then(|journal| journal.undo.pop()) else {
+            return false;
+        };
+        let redo = self.replay(inverse);
+        self.journal.as_mut().unwrap().redo.push(redo);
+        true
+    }
+
+    /// Reapplies the most recently undone edit. Returns whether there was an
+    /// edit to redo; making any other edit discards the edits that could be
+    /// redone.
+    pub fn redo(&mut self) -> bool {
+        let Some(inverse) = self.journal.as_mut().and_then(|journal| journal.redo.pop()) else {
+            return false;
+        };
+        let undo = self.replay(inverse);
+        self.journal.as_mut().unwrap().undo.push(undo);
+        true
+    }
+
+    /// Applies the change without recording it, returning the change that
+    /// reverses it.
+    fn replay(&mut self, inverse: Inverse) -> Inverse {
+        let journal = self.journal.take();
+        let reverse = self.apply_inverse(inverse);
+        self.journal = journal;
+        reverse
+    }
+
+    fn apply_inverse(&mut self, inverse: Inverse) -> Inverse {
+        match inverse {
+            Inverse::Voxels(indices, value) => {
+                self.set_many(indices.iter().copied(), value);
+                Inverse::Voxels(indices, !value)
+            }
+            Inverse::Subtree {
+                node,
+                raw,
+                branches,
+            } => {
+                let reverse = self.snapshot(node);
+                self.mark_dirty(node);
+                self.restore(node, raw, branches);
+                reverse
+            }
+            Inverse::Group(inverses) => Inverse::Group(
+                inverses
+                    .into_iter()
+                    .rev()
+                    .map(|inverse| self.apply_inverse(inverse))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Calls `f`, recording all of the edits it makes as a single edit.
+    pub(crate) fn record_group<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        let start = self.journal.as_ref().map(|journal| journal.undo.len());
+        let result = f(self);
+        if let (Some(start), Some(journal)) = (start, &mut self.journal) {
+            if journal.undo.len() > start + 1 {
+                let group = journal.undo.split_off(start);
+                journal.undo.push(Inverse::Group(group));
+            }
+        }
+        result
+    }
+
+    /// Records an edit that sets the given voxels to `value`, when
+    /// recording. The voxels must all currently be `!value`.
+    pub(crate) fn record_voxels(&mut self, indices: Vec<Index>, value: bool) {
+        if !indices.is_empty() {
+            self.record(Inverse::Voxels(indices, !value));
+        }
+    }
+
+    /// Records an edit that replaces the octant at the node, when recording,
+    /// and marks it as changed. The node must be above brick height.
+    pub(crate) fn record_subtree(&mut self, node: BranchIndex) {
+        self.mark_dirty(node);
+        if self.journal.is_some() {
+            let snapshot = self.snapshot(node);
+            self.record(snapshot);
+        }
+    }
+
+    fn record(&mut self, inverse: Inverse) {
+        if let Some(journal) = &mut self.journal {
+            journal.undo.push(inverse);
+            journal.redo.clear();
+        }
+    }
+
+    /// Copies the contents of the octant at the node, which must be above
+    /// brick height.
+    fn snapshot(&self, node: BranchIndex) -> Inverse {
+        let mut raw = RawNode::Branch;
+        let mut current_height = self.height;
+        while current_height > node.height {
+            let (x, y, z) = node.base.bit(current_height - 1);
+            raw = self.branches[&node.base.branch_at(current_height)].children[z][y][x];
+            if raw != RawNode::Branch {
+                break;
+            }
+            current_height -= 1;
+        }
+        let mut branches = Vec::new();
+        let mut stack = Vec::new();
+        if raw == RawNode::Branch {
+            stack.push(node);
+        }
+        while let Some(index) = stack.pop() {
+            let branch = &self.branches[&index];
+            for z in 0..2 {
+                for y in 0..2 {
+                    for x in 0..2 {
+                        if branch.children[z][y][x] == RawNode::Branch {
+                            stack.push(index.child(x, y, z));
+                        }
+                    }
+                }
+            }
+            branches.push((index, branch.clone()));
+        }
+        Inverse::Subtree {
+            node,
+            raw,
+            branches,
+        }
+    }
+
+    /// Replaces the octant at the node with recorded contents.
+    fn restore(&mut self, node: BranchIndex, raw: RawNode, branches: Vec<(BranchIndex, Branch)>) {
+        if node.height == self.height {
+            self.branches.clear();
+            self.branches.extend(branches);
+            return;
+        }
+        // Split the uniform nodes above the octant.
+        for current_height in (node.height + 2..=self.height).rev() {
+            let (x, y, z) = node.base.bit(current_height - 1);
+            let current_index = node.base.branch_at(current_height);
+            let child = &mut self.branches.get_mut(&current_index).unwrap().children[z][y][x];
+            if let current @ (RawNode::False | RawNode::True) = *child {
+                *child = RawNode::Branch;
+                self.branches.insert(
+                    node.base.branch_at(current_height - 1),
+                    Branch {
+                        children: [[[current; 2]; 2]; 2],
+                    },
+                );
+            }
+        }
+        let (x, y, z) = node.base.bit(node.height);
+        let parent = node.base.branch_at(node.height + 1);
+        let child = &mut self.branches.get_mut(&parent).unwrap().children[z][y][x];
+        if std::mem::replace(child, raw) == RawNode::Branch {
+            self.remove_branch(node);
+        }
+        self.branches.extend(branches);
+        if raw != RawNode::Branch {
+            self.compress(&node.base, raw, node.height + 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(bitmap: &OctreeBitmap) -> (Vec<Index>, usize) {
+        (bitmap.iter().collect(), bitmap.branches.len())
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut bitmap = OctreeBitmap::new(32);
+        bitmap.set(&Index::new(1, 1, 1), true);
+        bitmap.start_recording();
+        let initial = contents(&bitmap);
+
+        bitmap.set(&Index::new(20, 3, 9), true);
+        // Edits that change nothing are not recorded.
+        bitmap.set(&Index::new(20, 3, 9), true);
+        bitmap.set_many([Index::new(1, 1, 1)], true);
+        let after_set = contents(&bitmap);
+        bitmap.set_many((0..32).map(|x| Index::new(x, 3, 9)), true);
+        let after_set_many = contents(&bitmap);
+        bitmap.fill_node(Index::new(0, 0, 0).branch_at(3), true);
+        let after_fill = contents(&bitmap);
+        bitmap.clear();
+
+        assert!(bitmap.undo());
+        assert_eq!(contents(&bitmap), after_fill);
+        assert!(bitmap.undo());
+        assert_eq!(contents(&bitmap), after_set_many);
+        assert!(bitmap.undo());
+        assert_eq!(contents(&bitmap), after_set);
+        assert!(bitmap.undo());
+        assert_eq!(contents(&bitmap), initial);
+        assert!(!bitmap.undo());
+
+        assert!(bitmap.redo());
+        assert!(bitmap.redo());
+        assert!(bitmap.redo());
+        assert_eq!(contents(&bitmap), after_fill);
+        bitmap.drain().for_each(drop);
+        assert!(!bitmap.redo());
+        assert!(bitmap.undo());
+        assert_eq!(contents(&bitmap), after_fill);
+
+        bitmap.stop_recording();
+        bitmap.set(&Index::new(2, 2, 2), false);
+        assert!(!bitmap.undo());
+    }
+}
@@ -0,0 +1,189 @@
+//! A human-readable JSON form of bitmaps, for debugging and test fixtures.
+
+use std::fmt;
+
+use serde_json::{json, Value};
+
+use crate::{BranchIndex, OctreeBitmap, View};
+
+/// An error encountered while reading a bitmap from JSON.
+#[derive(Debug)]
+pub enum JsonError {
+    Json(serde_json::Error),
+    /// The JSON is not a bitmap in the documented form.
+    Invalid(String),
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json(err) => write!(f, "failed to parse bitmap JSON: {}", err),
+            Self::Invalid(reason) => write!(f, "invalid bitmap JSON: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for JsonError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Json(err) => Some(err),
+            Self::Invalid(_) => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for JsonError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
+
+impl OctreeBitmap {
+    /// The bitmap in a human-readable JSON form, such as for golden files
+    /// in tests or for inspecting a tree while debugging.
+    ///
+    /// A bitmap is an object with its `width` and its `root` node:
+    ///
+    /// ```json
+    /// {"width": 4, "root": ["t", "f", "f", "f", "f", "f", "f", ["f", "f", "f", "f", "f", "f", "f", "t"]]}
+    /// ```
+    ///
+    /// Each node is `"t"` if every voxel in it is set, `"f"` if none are, or
+    /// an array of its eight children otherwise, in Morton order: the child
+    /// at position `(x, y, z)` within the node, each `0` or `1`, is at
+    /// `x | y << 1 | z << 2`. The width is a power of two of at least 2, and
+    /// the children of a node of width 2 are single voxels. The example has
+    /// the eight voxels from `(0, 0, 0)` to `(1, 1, 1)` set, and the voxel at
+    /// `(3, 3, 3)`.
+    ///
+    /// Uniform octants are written as a single string, so the output is as
+    /// large as the tree, with bricks written out voxel by voxel.
+    pub fn to_json(&self) -> String {
+        let root = BranchIndex::root(self.height);
+        let value = json!({
+            "width": self.width(),
+            "root": self.node_json(root, View::root(self)),
+        });
+        value.to_string()
+    }
+
+    fn node_json(&self, node: BranchIndex, view: View) -> Value {
+        match view {
+            View::Uniform(true) => json!("t"),
+            View::Uniform(false) => json!("f"),
+            _ => Value::Array(
+                (0..8)
+                    .map(|i| {
+                        let child = node.child(i & 1, (i >> 1) & 1, i >> 2);
+                        self.node_json(child, view.child(self, node, i))
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Reads a bitmap from the JSON form written by
+    /// [`to_json`](Self::to_json), which may also be written by hand. Nodes
+    /// need not be compressed: an array of eight equal strings is read as
+    /// a uniform node.
+    pub fn from_json(json: &str) -> Result<OctreeBitmap, JsonError> {
+        let value: Value = serde_json::from_str(json)?;
+        let invalid = |reason: &str| JsonError::Invalid(reason.to_string());
+        let width = value
+            .get("width")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| invalid("missing width"))?;
+        if !width.is_power_of_two() || !(2..=1 << (u32::BITS - 1)).contains(&width) {
+            return Err(invalid("width is not a power of two from 2 to 2^31"));
+        }
+        let root = value.get("root").ok_or_else(|| invalid("missing root"))?;
+
+        let mut bitmap = OctreeBitmap::with_height(width.trailing_zeros());
+        let mut nodes = Vec::new();
+        let mut voxels = Vec::new();
+        let mut stack = vec![(BranchIndex::root(bitmap.height), root)];
+        while let Some((node, value)) = stack.pop() {
+            match value {
+                Value::String(s) if s == "f" => {}
+                Value::String(s) if s == "t" && node.height == 0 => voxels.push(node.base),
+                Value::String(s) if s == "t" => nodes.push(node),
+                Value::Array(children) if children.len() == 8 && node.height > 0 => {
+                    for (i, child) in children.iter().enumerate() {
+                        stack.push((node.child(i & 1, (i >> 1) & 1, i >> 2), child));
+                    }
+                }
+                Value::Array(_) if node.height == 0 => {
+                    return Err(invalid(&format!("voxel {:?} has children", node.base)));
+                }
+                _ => {
+                    return Err(invalid(&format!(
+                        "node at {:?} is not \"t\", \"f\", or eight children",
+                        node.base
+                    )));
+                }
+            }
+        }
+        for node in nodes {
+            bitmap.fill_node(node, true);
+        }
+        bitmap.set_many(voxels, true);
+        Ok(bitmap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Index;
+
+    #[test]
+    fn json_round_trip() {
+        let example = r#"{"width": 4, "root": ["t", "f", "f", "f", "f", "f", "f", ["f", "f", "f", "f", "f", "f", "f", "t"]]}"#;
+        let bitmap = OctreeBitmap::from_json(example).unwrap();
+        assert_eq!(bitmap.width(), 4);
+        let mut expected = vec![
+            Index::new(0, 0, 0),
+            Index::new(1, 0, 0),
+            Index::new(0, 1, 0),
+            Index::new(1, 1, 0),
+            Index::new(0, 0, 1),
+            Index::new(1, 0, 1),
+            Index::new(0, 1, 1),
+            Index::new(1, 1, 1),
+            Index::new(3, 3, 3),
+        ];
+        expected.sort_by_key(Index::morton);
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), expected);
+        let written: Value = serde_json::from_str(&bitmap.to_json()).unwrap();
+        assert_eq!(written, serde_json::from_str::<Value>(example).unwrap());
+
+        let mut bitmap = OctreeBitmap::new(16);
+        bitmap.fill_sphere([10.0, 12.0, 9.0], 6.0, true);
+        bitmap.set(&Index::new(31, 0, 2), true);
+        let read = OctreeBitmap::from_json(&bitmap.to_json()).unwrap();
+        assert_eq!(read, bitmap);
+
+        let uncompressed = r#"{"width": 4, "root": [["t", "t", "t", "t", "t", "t", "t", "t"], "t", "t", "t", "t", "t", "t", "t"]}"#;
+        assert!(OctreeBitmap::from_json(uncompressed).unwrap().is_full());
+        let widest = OctreeBitmap::new(OctreeBitmap::MAX_WIDTH);
+        assert_eq!(OctreeBitmap::from_json(&widest.to_json()).unwrap(), widest);
+
+        for invalid in [
+            r#"{"width": 3, "root": "f"}"#,
+            r#"{"width": 4294967296, "root": "f"}"#,
+            r#"{"width": 2, "root": ["t", "f"]}"#,
+            r#"{"width": 2, "root": ["t", "f", "f", "f", "f", "f", "f", ["t"]]}"#,
+            r#"{"width": 2, "root": "x"}"#,
+            r#"{"root": "f"}"#,
+        ] {
+            assert!(matches!(
+                OctreeBitmap::from_json(invalid),
+                Err(JsonError::Invalid(_))
+            ));
+        }
+        assert!(matches!(
+            OctreeBitmap::from_json("{"),
+            Err(JsonError::Json(_))
+        ));
+    }
+}
@@ -0,0 +1,279 @@
+//! Two-dimensional views of a bitmap: cross sections and projections.
+
+use crate::{Axis, BranchIndex, Index, OctreeBitmap, View};
+
+/// A square two-dimensional bitmap, stored as rows of packed bits.
+///
+/// It covers the plane perpendicular to an axis, with coordinates along the
+/// two other axes in cyclic order after it, as for [`Quad`](crate::Quad):
+/// `(y, z)` for the `x` axis, `(z, x)` for `y` and `(x, y)` for `z`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layer {
+    width: u32,
+    /// The bits of each row in turn, `words_per_row` words at a time, with
+    /// `u` increasing from the lowest bit of the first word.
+    words: Vec<u64>,
+}
+
+impl Layer {
+    /// Creates a new, empty layer.
+    pub fn new(width: u32) -> Self {
+        Self {
+            width,
+            words: vec![0; Self::words_per_row(width) * width as usize],
+        }
+    }
+
+    fn words_per_row(width: u32) -> usize {
+        width.div_ceil(64) as usize
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Get the value of the bit at the given position.
+    pub fn get(&self, u: u32, v: u32) -> bool {
+        (self.row(v)[u as usize / 64] >> (u % 64)) & 1 != 0
+    }
+
+    /// Set the value of the bit at the given position.
+    pub fn set(&mut self, u: u32, v: u32, value: bool) {
+        let word = &mut self.row_mut(v)[u as usize / 64];
+        if value {
+            *word |= 1 << (u % 64);
+        } else {
+            *word &= !(1 << (u % 64));
+        }
+    }
+
+    /// The packed bits of one row, with `u` increasing from the lowest bit of
+    /// the first word.
+    pub fn row(&self, v: u32) -> &[u64] {
+        let words = Self::words_per_row(self.width);
+        &self.words[v as usize * words..][..words]
+    }
+
+    /// The packed bits of one row, as for [`row`](Self::row). Bits past the
+    /// width must be left unset.
+    pub fn row_mut(&mut self, v: u32) -> &mut [u64] {
+        let words = Self::words_per_row(self.width);
+        &mut self.words[v as usize * words..][..words]
+    }
+
+    /// The number of bits that are set.
+    pub fn count(&self) -> u64 {
+        self.words
+            .iter()
+            .map(|word| u64::from(word.count_ones()))
+            .sum()
+    }
+
+    /// Sets the square of bits with the given corner and side.
+    fn fill_square(&mut self, corner: [u32; 2], side: u32) {
+        let words = Self::words_per_row(self.width);
+        for v in corner[1]..corner[1] + side {
+            let row = &mut self.words[v as usize * words..][..words];
+            let mut u = corner[0];
+            while u < corner[0] + side {
+                let bits = (corner[0] + side - u).min(64 - u % 64);
+                row[u as usize / 64] |= (u64::MAX >> (64 - bits)) << (u % 64);
+                u += bits;
+            }
+        }
+    }
+}
+
+impl OctreeBitmap {
+    /// The cross section of the bitmap at coordinate `layer` along an axis.
+    ///
+    /// Only the nodes that intersect the plane are descended into, and
+    /// uniform nodes are copied as whole squares.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `layer` is outside the bitmap.
+    pub fn slice(&self, axis: Axis, layer: u32) -> Layer {
+        assert!(layer < self.width(), "layer is out of bounds");
+        let axis = axis.index();
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        let mut slice = Layer::new(self.width());
+        let mut stack = vec![(BranchIndex::root(self.height), View::root(self))];
+        while let Some((node, view)) = stack.pop() {
+            let base = [node.base.x, node.base.y, node.base.z];
+            match view {
+                View::Uniform(false) => {}
+                View::Uniform(true) => slice.fill_square([base[u], base[v]], 1 << node.height),
+                _ => {
+                    let mut bits = [0; 3];
+                    bits[axis] = (layer as usize >> (node.height - 1)) & 1;
+                    for (du, dv) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                        bits[u] = du;
+                        bits[v] = dv;
+                        let i = bits[0] | bits[1] << 1 | bits[2] << 2;
+                        stack.push((
+                            node.child(bits[0], bits[1], bits[2]),
+                            view.child(self, node, i),
+                        ));
+                    }
+                }
+            }
+        }
+        slice
+    }
+
+    /// Overwrites the cross section of the bitmap at coordinate `layer` along
+    /// an axis with the bits of a layer, as returned by
+    /// [`slice`](Self::slice).
+    ///
+    /// The plane is cleared as a whole first, so only the set bits are
+    /// visited one at a time. In recording mode, this is recorded as a single
+    /// edit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `layer` is outside the bitmap, or if the layer is not as
+    /// wide as the bitmap.
+    pub fn set_slice(&mut self, axis: Axis, layer: u32, slice: &Layer) {
+        assert!(layer < self.width(), "layer is out of bounds");
+        assert_eq!(slice.width(), self.width(), "layer has the wrong width");
+        let axis = axis.index();
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        let mut low = [0; 3];
+        let mut high = [i64::from(self.width()); 3];
+        low[axis] = i64::from(layer);
+        high[axis] = i64::from(layer) + 1;
+        let mut voxels = Vec::new();
+        for row in 0..slice.width() {
+            for (i, &word) in slice.row(row).iter().enumerate() {
+                let mut word = word;
+                while word != 0 {
+                    let mut coords = [0; 3];
+                    coords[axis] = layer;
+                    coords[u] = i as u32 * 64 + word.trailing_zeros();
+                    coords[v] = row;
+                    voxels.push(Index::from(coords));
+                    word &= word - 1;
+                }
+            }
+        }
+        self.record_group(|bitmap| {
+            bitmap.fill_box(low, high, false);
+            bitmap.set_many(voxels, true);
+        });
+    }
+
+    /// The shadow of the bitmap along an axis: each bit of the result is set
+    /// if any voxel in the column through it is set.
+    ///
+    /// Where either of the two children of a node along a column is full,
+    /// the other is skipped, and uniform nodes are copied as whole squares,
+    /// so solid regions are never descended into.
+    pub fn project(&self, axis: Axis) -> Layer {
+        let axis = axis.index();
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        let mut projection = Layer::new(self.width());
+        let mut stack = vec![(BranchIndex::root(self.height), View::root(self))];
+        while let Some((node, view)) = stack.pop() {
+            let base = [node.base.x, node.base.y, node.base.z];
+            match view {
+                View::Uniform(false) => {}
+                View::Uniform(true) => projection.fill_square([base[u], base[v]], 1 << node.height),
+                _ => {
+                    for (du, dv) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                        let children = [0, 1].map(|da| {
+                            let mut bits = [0; 3];
+                            bits[axis] = da;
+                            bits[u] = du;
+                            bits[v] = dv;
+                            let i = bits[0] | bits[1] << 1 | bits[2] << 2;
+                            (
+                                node.child(bits[0], bits[1], bits[2]),
+                                view.child(self, node, i),
+                            )
+                        });
+                        match children
+                            .iter()
+                            .find(|(_, view)| matches!(view, View::Uniform(true)))
+                        {
+                            Some(&full) => stack.push(full),
+                            None => stack.extend(children),
+                        }
+                    }
+                }
+            }
+        }
+        projection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::pseudo_random_indexes;
+
+    #[test]
+    fn slice_matches_get() {
+        let mut bitmap = OctreeBitmap::new(64);
+        let width = bitmap.width();
+        bitmap.fill_sphere([60.0, 70.0, 64.0], 40.0, true);
+        bitmap.set_many(pseudo_random_indexes(128, 500), true);
+        for (axis, layer) in [(Axis::X, 3), (Axis::Y, 70), (Axis::Z, 127)] {
+            let slice = bitmap.slice(axis, layer);
+            let mut count = 0;
+            for v in 0..width {
+                for u in 0..width {
+                    let idx = match axis {
+                        Axis::X => Index::new(layer, u, v),
+                        Axis::Y => Index::new(v, layer, u),
+                        Axis::Z => Index::new(u, v, layer),
+                    };
+                    assert_eq!(slice.get(u, v), bitmap.get(&idx), "{:?}", idx);
+                    count += u64::from(bitmap.get(&idx));
+                }
+            }
+            assert_eq!(slice.count(), count);
+        }
+    }
+
+    #[test]
+    fn set_slice_round_trip() {
+        let mut bitmap = OctreeBitmap::new(32);
+        bitmap.fill_sphere([20.0, 30.0, 40.0], 15.0, true);
+        let mut slice = bitmap.slice(Axis::Y, 30);
+        slice.set(0, 0, true);
+        slice.set(40, 20, false);
+        bitmap.set_slice(Axis::Y, 3, &slice);
+        assert!(bitmap.slice(Axis::Y, 3) == slice);
+        assert!(bitmap.get(&Index::new(0, 3, 0)));
+        assert!(!bitmap.get(&Index::new(20, 3, 40)));
+        assert!(bitmap.get(&Index::new(20, 30, 40)));
+
+        bitmap.set_slice(Axis::Y, 30, &Layer::new(bitmap.width()));
+        assert_eq!(bitmap.slice(Axis::Y, 30).count(), 0);
+        assert_eq!(bitmap.validate(), Ok(()));
+    }
+
+    #[test]
+    fn project_matches_columns() {
+        let mut bitmap = OctreeBitmap::new(32);
+        let width = bitmap.width();
+        bitmap.fill_sphere([20.0, 30.0, 40.0], 15.0, true);
+        bitmap.set_many(pseudo_random_indexes(64, 200), true);
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            let projection = bitmap.project(axis);
+            for v in 0..width {
+                for u in 0..width {
+                    let column = (0..width).any(|layer| {
+                        bitmap.get(&match axis {
+                            Axis::X => Index::new(layer, u, v),
+                            Axis::Y => Index::new(v, layer, u),
+                            Axis::Z => Index::new(u, v, layer),
+                        })
+                    });
+                    assert_eq!(projection.get(u, v), column);
+                }
+            }
+        }
+    }
+}
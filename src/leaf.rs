@@ -0,0 +1,188 @@
+//! Choosing the size of the dense leaves of the tree.
+
+use std::borrow::Cow;
+
+use crate::{Occupancy, OctreeBitmap, BRICK_HEIGHT};
+
+/// The size of the dense leaves of an [`OctreeBitmap`], set with
+/// [`OctreeBitmap::with_leaf_size`].
+///
+/// Mixed octants of this size are stored as the packed bits of their voxels
+/// in a single `u64` rather than split into branches. Larger leaves take
+/// fewer branches, and so less memory and fewer lookups, for noisy data;
+/// smaller ones keep the bits of sparse voxels apart from each other at the
+/// cost of more branches. Leaves of 8x8x8 voxels would not fit in a `u64`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum LeafSize {
+    /// Single voxels: every mixed octant is a branch.
+    One,
+    /// Bricks of 2x2x2 voxels.
+    Two,
+    /// Bricks of 4x4x4 voxels.
+    #[default]
+    Four,
+}
+
+impl LeafSize {
+    fn from_height(height: u32) -> Self {
+        match height {
+            0 => Self::One,
+            1 => Self::Two,
+            _ => Self::Four,
+        }
+    }
+
+    /// The height of the leaves, so their width is `1 << height`.
+    pub(crate) fn height(self) -> u32 {
+        match self {
+            Self::One => 0,
+            Self::Two => 1,
+            Self::Four => BRICK_HEIGHT,
+        }
+    }
+}
+
+impl OctreeBitmap {
+    /// The size of the dense leaves of the tree. Bitmaps narrower than
+    /// twice the leaf size store their voxels in smaller leaves.
+    pub fn leaf_size(&self) -> LeafSize {
+        LeafSize::from_height(self.leaf_height)
+    }
+
+    /// The bitmap with its mixed octants stored in leaves of the given size,
+    /// such as `OctreeBitmap::new(width).with_leaf_size(LeafSize::Two)`.
+    ///
+    /// The contents are unchanged. If the leaf size is different, the tree
+    /// is rebuilt, and the undo history and changed regions are discarded.
+    /// Bitmaps derived from this one, by operations such as
+    /// [`crop`](Self::crop) and [`split`](Self::split), have the same leaf
+    /// size.
+    pub fn with_leaf_size(self, leaf_size: LeafSize) -> Self {
+        match self.leaves_of(leaf_size) {
+            Cow::Borrowed(_) => self,
+            Cow::Owned(bitmap) => bitmap,
+        }
+    }
+
+    /// The bitmap with leaves of the given size, rebuilt if they differ.
+    pub(crate) fn leaves_of(&self, leaf_size: LeafSize) -> Cow<'_, OctreeBitmap> {
+        if self.leaf_height == leaf_size.height() {
+            return Cow::Borrowed(self);
+        }
+        let mut bitmap = OctreeBitmap::with_height(self.height);
+        bitmap.leaf_height = leaf_size.height();
+        let mut voxels = Vec::new();
+        self.visit_nodes(|base, height, occupancy| match occupancy {
+            Occupancy::Empty => false,
+            Occupancy::Full if height == 0 => {
+                voxels.push(base);
+                false
+            }
+            Occupancy::Full => {
+                bitmap.fill_node(base.branch_at(height), true);
+                false
+            }
+            Occupancy::Mixed => true,
+        });
+        bitmap.set_many(voxels, true);
+        Cow::Owned(bitmap)
+    }
+
+    /// A new, empty bitmap with the given root height and the same leaf size
+    /// as this one.
+    pub(crate) fn with_height_like(&self, height: u32) -> OctreeBitmap {
+        let mut bitmap = OctreeBitmap::with_height(height);
+        bitmap.leaf_height = self.leaf_height;
+        bitmap
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    use super::*;
+    use crate::tests::pseudo_random_indexes;
+    use crate::{BlitMode, EdgeMode, Index, PersistentOctreeBitmap};
+
+    #[test]
+    fn leaf_sizes() {
+        let mut bitmap = OctreeBitmap::new(32);
+        bitmap.fill_sphere([20.0, 12.0, 18.0], 9.0, true);
+        bitmap.set_many(pseudo_random_indexes(32, 500), true);
+        assert_eq!(bitmap.leaf_size(), LeafSize::Four);
+        let expected: Vec<Index> = bitmap.iter().collect();
+
+        for leaf_size in [LeafSize::One, LeafSize::Two, LeafSize::Four] {
+            let mut resized = bitmap.clone().with_leaf_size(leaf_size);
+            assert_eq!(resized.leaf_size(), leaf_size);
+            assert_eq!(resized.validate(), Ok(()));
+            assert!(resized.iter().eq(expected.iter().copied()));
+            assert!(resized == bitmap);
+            let stats = resized.stats();
+            assert_eq!(stats.bricks == 0, leaf_size == LeafSize::One);
+
+            // Edits keep the leaves at their size.
+            resized.set(&Index::new(1, 2, 3), true);
+            resized.fill_box([0, 0, 0], [5, 6, 7], false);
+            resized.set_many(pseudo_random_indexes(32, 100), false);
+            assert_eq!(resized.validate(), Ok(()));
+            let mut edited = bitmap.clone();
+            edited.set(&Index::new(1, 2, 3), true);
+            edited.fill_box([0, 0, 0], [5, 6, 7], false);
+            edited.set_many(pseudo_random_indexes(32, 100), false);
+            assert!(resized == edited);
+        }
+
+        let mut small = OctreeBitmap::new(2).with_leaf_size(LeafSize::One);
+        small.set(&Index::new(1, 1, 1), true);
+        assert_eq!(small.validate(), Ok(()));
+        assert_eq!(small.stats().bricks, 0);
+    }
+
+    #[test]
+    fn derived_bitmaps_keep_leaf_size() {
+        let mut bitmap = OctreeBitmap::new(64);
+        bitmap.fill_sphere([30.0, 34.0, 28.0], 14.0, true);
+        bitmap.set_many(pseudo_random_indexes(64, 500), true);
+        let hash = |bitmap: &OctreeBitmap| {
+            let mut hasher = DefaultHasher::new();
+            bitmap.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        for leaf_size in [LeafSize::One, LeafSize::Two] {
+            let leafy = bitmap.clone().with_leaf_size(leaf_size);
+            assert_eq!(hash(&leafy), hash(&bitmap));
+            let (min, max) = (Index::new(16, 0, 8), Index::new(48, 32, 40));
+            let cropped = leafy.crop(min, max);
+            assert_eq!(cropped.leaf_size(), leaf_size);
+            assert_eq!(cropped.validate(), Ok(()));
+            assert!(cropped == bitmap.crop(min, max));
+            let translated = leafy.translated([16, -32, 8], EdgeMode::Wrap);
+            assert_eq!(translated.validate(), Ok(()));
+            assert!(translated == bitmap.translated([16, -32, 8], EdgeMode::Wrap));
+
+            let octants = leafy.clone().split();
+            assert!(octants.iter().all(|octant| octant.leaf_size() == leaf_size));
+            let merged = OctreeBitmap::from_octants(octants);
+            assert_eq!(merged.validate(), Ok(()));
+            assert!(merged == bitmap);
+
+            // Subtrees are not grafted between different leaf sizes.
+            for (mut dest, src) in [(bitmap.clone(), &leafy), (leafy.clone(), &bitmap)] {
+                dest.clear();
+                dest.blit(src, Index::new(0, 0, 0), BlitMode::Union);
+                assert_eq!(dest.validate(), Ok(()));
+                assert!(dest == bitmap);
+            }
+
+            let dag = leafy.to_dag().to_octree();
+            assert_eq!((dag.leaf_size(), dag.validate()), (leaf_size, Ok(())));
+            let flat = OctreeBitmap::from_flat(&leafy.flatten()).unwrap();
+            assert!(flat == bitmap && flat.leaf_size() == LeafSize::Four);
+            assert!(PersistentOctreeBitmap::from(&leafy).to_bitmap() == bitmap);
+        }
+    }
+}
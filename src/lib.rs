@@ -1,4 +1,186 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::ops::{Add, Neg, Sub};
+
+/// Enters a `tracing` span at debug level for the rest of the enclosing
+/// block, with the `tracing` feature. Otherwise the fields are not evaluated.
+macro_rules! trace_span {
+    ($name:literal $(, $($fields:tt)*)?) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!($name $(, $($fields)*)?).entered();
+    };
+}
+
+/// Emits a `tracing` event at debug level, with the `tracing` feature.
+/// Otherwise the fields are not evaluated.
+macro_rules! trace_event {
+    ($($args:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($args)*);
+    };
+}
+
+mod ao;
+#[cfg(feature = "bench")]
+pub mod bench;
+mod binary;
+#[cfg(feature = "bitvec")]
+mod bitvec;
+mod blit;
+mod bulk;
+mod compare;
+mod compression;
+mod concurrent;
+mod crop;
+mod csg;
+mod cursor;
+mod debug;
+#[cfg(feature = "dicom")]
+mod dicom;
+mod dirty;
+mod distance;
+mod esvo;
+mod exact;
+mod extent;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod fill;
+mod flat;
+mod flood;
+mod forest;
+mod frozen;
+mod frustum;
+#[cfg(feature = "gltf")]
+mod gltf;
+#[cfg(feature = "wgpu")]
+mod gpu;
+mod heightmap;
+mod hull;
+mod hypertree;
+#[cfg(feature = "image")]
+mod image;
+mod incremental;
+mod iter;
+mod journal;
+#[cfg(feature = "serde")]
+mod json;
+mod layer;
+mod leaf;
+mod line;
+mod log_odds;
+#[cfg(feature = "mmap")]
+mod mapped;
+mod metrics;
+mod moments;
+mod morphology;
+mod nearest;
+mod neighbors;
+mod normals;
+mod obj;
+mod octants;
+mod optimize;
+#[cfg(feature = "rayon")]
+mod par_iter;
+mod patch;
+mod path;
+mod persistent;
+mod points;
+#[cfg(feature = "python")]
+mod python;
+mod quads;
+mod quadtree;
+mod ray;
+mod refine;
+mod resample;
+mod retain;
+mod runs;
+#[cfg(feature = "rand")]
+mod sample;
+#[cfg(feature = "schematic")]
+mod schematic;
+mod stats;
+mod store;
+#[cfg(feature = "surface-nets")]
+mod surface_nets;
+mod svdag;
+mod temporal;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod transform;
+mod translate;
+mod validate;
+mod volume;
+mod voxelize;
+#[cfg(feature = "wasm")]
+mod wasm;
+mod world;
+#[cfg(feature = "zarr")]
+mod zarr;
+
+pub use binary::BinaryError;
+pub use blit::BlitMode;
+pub use compare::BranchHashes;
+pub use compression::Compression;
+pub use concurrent::ConcurrentOctreeBitmap;
+pub use csg::Csg;
+pub use cursor::OctreeCursor;
+#[cfg(feature = "dicom")]
+pub use dicom::{DicomError, DicomVolume};
+pub use dirty::DirtyRegion;
+pub use distance::DistanceField;
+pub use esvo::Esvo;
+pub use exact::ExactOctreeBitmap;
+pub use flat::{FlatChild, FlatError, FlatOctree};
+pub use forest::OctreeForest;
+pub use frozen::FrozenOctree;
+pub use frustum::{Block, FrustumIter, Plane};
+#[cfg(feature = "gltf")]
+pub use gltf::GltfMode;
+#[cfg(feature = "wgpu")]
+pub use gpu::{GpuHeader, GpuOctree, ESVO_WGSL};
+pub use hypertree::{Hypertree, HypertreeBitmap};
+pub use incremental::IncrementalProgress;
+pub use iter::{BoxIter, Drain, IndexOrder, Iter};
+#[cfg(feature = "serde")]
+pub use json::JsonError;
+pub use layer::Layer;
+pub use leaf::LeafSize;
+pub use log_odds::{LogOddsOctree, LogOddsParams};
+#[cfg(feature = "mmap")]
+pub use mapped::MappedOctreeBitmap;
+pub use moments::Moments;
+pub use morphology::Neighborhood;
+pub use nearest::NearestIter;
+pub use patch::{Patch, PatchRegion};
+pub use persistent::PersistentOctreeBitmap;
+#[cfg(any(feature = "ply", feature = "las"))]
+pub use points::PointCloudError;
+pub use points::{OutOfRange, PointOutOfRange, VoxelTransform};
+#[cfg(feature = "python")]
+pub use python::PyOctreeBitmap;
+pub use quads::Quad;
+pub use quadtree::QuadtreeBitmap;
+pub use ray::{Face, RayHit, RayIter};
+pub use refine::{RefinedRegion, Refinement};
+pub use resample::ResamplePolicy;
+#[cfg(feature = "schematic")]
+pub use schematic::SchematicError;
+pub use stats::Stats;
+pub use store::{NodeStore, SharedStore};
+#[cfg(feature = "surface-nets")]
+pub use surface_nets::TriangleMesh;
+pub use svdag::SvdagBitmap;
+pub use temporal::TemporalOctree;
+pub use transform::Axis;
+pub use translate::EdgeMode;
+pub use validate::ValidationError;
+pub use volume::{DataType, Endian, ScalarType, VolumeError};
+pub use voxelize::VoxelizeMode;
+#[cfg(feature = "wasm")]
+pub use wasm::WasmOctreeBitmap;
+pub use world::WorldGrid;
+#[cfg(feature = "zarr")]
+pub use zarr::ZarrError;
 
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Index {
@@ -12,6 +194,49 @@ impl Index {
         Self { x, y, z }
     }
 
+    /// The index moved by the given amounts, or `None` if it would leave the
+    /// range of `u32`.
+    pub fn offset(self, dx: i32, dy: i32, dz: i32) -> Option<Self> {
+        self.checked_add(Offset::new(dx, dy, dz))
+    }
+
+    /// The index moved by `offset`, or `None` if any coordinate would leave
+    /// the range of `u32`.
+    pub fn checked_add(self, offset: Offset) -> Option<Self> {
+        Some(Self {
+            x: self.x.checked_add_signed(offset.x)?,
+            y: self.y.checked_add_signed(offset.y)?,
+            z: self.z.checked_add_signed(offset.z)?,
+        })
+    }
+
+    /// The index moved back by `offset`, or `None` if any coordinate would
+    /// leave the range of `u32`.
+    pub fn checked_sub(self, offset: Offset) -> Option<Self> {
+        let [x, y, z] = [(self.x, offset.x), (self.y, offset.y), (self.z, offset.z)]
+            .map(|(c, d)| i64::from(c) - i64::from(d))
+            .map(u32::try_from);
+        Some(Self::new(x.ok()?, y.ok()?, z.ok()?))
+    }
+
+    /// The index moved by `offset`, with each coordinate clamped to the range
+    /// of `u32`.
+    pub fn saturating_add(self, offset: Offset) -> Self {
+        Self {
+            x: self.x.saturating_add_signed(offset.x),
+            y: self.y.saturating_add_signed(offset.y),
+            z: self.z.saturating_add_signed(offset.z),
+        }
+    }
+
+    /// The index moved back by `offset`, with each coordinate clamped to the
+    /// range of `u32`.
+    pub fn saturating_sub(self, offset: Offset) -> Self {
+        let [x, y, z] = [(self.x, offset.x), (self.y, offset.y), (self.z, offset.z)]
+            .map(|(c, d)| (i64::from(c) - i64::from(d)).clamp(0, u32::MAX.into()) as u32);
+        Self::new(x, y, z)
+    }
+
     fn bit(&self, height: u32) -> (usize, usize, usize) {
         (
             (self.x as usize >> height) & 1,
@@ -20,6 +245,36 @@ impl Index {
         )
     }
 
+    /// The offset of this index's bit within the packed brick of the given
+    /// height that contains it. Bits are stored in Morton order.
+    fn brick_offset(&self, height: u32) -> u32 {
+        let mut offset = 0;
+        for h in 0..height {
+            let (x, y, z) = self.bit(h);
+            offset |= ((x | y << 1 | z << 2) as u32) << (3 * h);
+        }
+        offset
+    }
+
+    /// The position of this index along the Z-order (Morton) curve, the order
+    /// in which voxels are iterated.
+    ///
+    /// Sorting by this key puts indexes from iterators without a fixed order,
+    /// such as [`OctreeBitmap::drain`], in the same order as
+    /// [`OctreeBitmap::iter`].
+    pub fn morton(&self) -> u128 {
+        spread(self.x) | spread(self.y) << 1 | spread(self.z) << 2
+    }
+
+    /// The index at the given position along the Z-order curve.
+    pub fn from_morton(key: u128) -> Self {
+        Self {
+            x: compact(key),
+            y: compact(key >> 1),
+            z: compact(key >> 2),
+        }
+    }
+
     fn branch_at(&self, height: u32) -> BranchIndex {
         let mask = !((1 << height) - 1);
         BranchIndex {
@@ -33,6 +288,35 @@ impl Index {
     }
 }
 
+/// Each byte value with its bits spread out to every third bit.
+const SPREAD_BYTE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut bit = 0;
+        while bit < 8 {
+            table[byte] |= ((byte as u32 >> bit) & 1) << (3 * bit);
+            bit += 1;
+        }
+        byte += 1;
+    }
+    table
+};
+
+/// Spreads the bits of `x` out to every third bit of the result.
+fn spread(x: u32) -> u128 {
+    x.to_le_bytes()
+        .iter()
+        .enumerate()
+        .map(|(i, &byte)| (SPREAD_BYTE[byte as usize] as u128) << (24 * i))
+        .fold(0, |acc, bits| acc | bits)
+}
+
+/// Gathers every third bit of `bits`, the inverse of [`spread`].
+fn compact(bits: u128) -> u32 {
+    (0..u32::BITS).fold(0, |acc, i| acc | (((bits >> (3 * i)) & 1) as u32) << i)
+}
+
 impl From<(u32, u32, u32)> for Index {
     fn from((x, y, z): (u32, u32, u32)) -> Self {
         Self { x, y, z }
@@ -45,8 +329,170 @@ impl From<[u32; 3]> for Index {
     }
 }
 
+impl From<Index> for (u32, u32, u32) {
+    fn from(idx: Index) -> Self {
+        (idx.x, idx.y, idx.z)
+    }
+}
+
+impl From<Index> for [u32; 3] {
+    fn from(idx: Index) -> Self {
+        [idx.x, idx.y, idx.z]
+    }
+}
+
+/// Moves the index by the offset.
+///
+/// # Panics
+///
+/// Panics if any coordinate would leave the range of `u32`. See
+/// [`Index::checked_add`] and [`Index::saturating_add`] for other ways of
+/// handling this.
+impl Add<Offset> for Index {
+    type Output = Index;
+
+    fn add(self, offset: Offset) -> Index {
+        self.checked_add(offset).expect("index out of range")
+    }
+}
+
+/// Moves the index back by the offset.
+///
+/// # Panics
+///
+/// Panics if any coordinate would leave the range of `u32`.
+impl Sub<Offset> for Index {
+    type Output = Index;
+
+    fn sub(self, offset: Offset) -> Index {
+        self.checked_sub(offset).expect("index out of range")
+    }
+}
+
+/// A signed displacement between indexes.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Offset {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl Offset {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+}
+
+impl Add for Offset {
+    type Output = Offset;
+
+    fn add(self, other: Offset) -> Offset {
+        Offset::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Offset {
+    type Output = Offset;
+
+    fn sub(self, other: Offset) -> Offset {
+        Offset::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Neg for Offset {
+    type Output = Offset;
+
+    fn neg(self) -> Offset {
+        Offset::new(-self.x, -self.y, -self.z)
+    }
+}
+
+impl From<(i32, i32, i32)> for Offset {
+    fn from((x, y, z): (i32, i32, i32)) -> Self {
+        Self { x, y, z }
+    }
+}
+
+impl From<[i32; 3]> for Offset {
+    fn from([x, y, z]: [i32; 3]) -> Self {
+        Self { x, y, z }
+    }
+}
+
+impl From<Offset> for [i32; 3] {
+    fn from(offset: Offset) -> Self {
+        [offset.x, offset.y, offset.z]
+    }
+}
+
+/// An index that is outside the range of a bitmap, returned by
+/// [`OctreeBitmap::try_get`] and [`OctreeBitmap::try_set`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OutOfBounds {
+    pub index: Index,
+    /// The width of the bitmap.
+    pub width: u32,
+}
+
+impl fmt::Display for OutOfBounds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "index {:?} is out of bounds for width {}",
+            self.index, self.width
+        )
+    }
+}
+
+impl std::error::Error for OutOfBounds {}
+
+/// A width that a bitmap cannot be created with, returned by
+/// [`OctreeBitmap::try_new`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NewError {
+    /// The width is zero.
+    ZeroWidth,
+    /// The width is greater than [`OctreeBitmap::MAX_WIDTH`].
+    TooWide { width: u32 },
+}
+
+impl fmt::Display for NewError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ZeroWidth => write!(f, "width must not be zero"),
+            Self::TooWide { width } => write!(
+                f,
+                "width {} is greater than the maximum of {}",
+                width,
+                OctreeBitmap::MAX_WIDTH
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NewError {}
+
+/// The hasher of the map of branches.
+///
+/// `BranchIndex` keys are small and hashed on every access, so SipHash, the
+/// standard library's default, takes a noticeable part of the time of
+/// [`OctreeBitmap::get`] and [`OctreeBitmap::set`]. The `fxhash` feature
+/// switches to FxHash, which is the fastest but not resistant to keys chosen
+/// to collide, and the `ahash` feature to aHash, which is both fast and
+/// resistant. With both, FxHash is used.
+#[cfg(feature = "fxhash")]
+type BranchHasher = rustc_hash::FxBuildHasher;
+#[cfg(all(feature = "ahash", not(feature = "fxhash")))]
+type BranchHasher = ahash::RandomState;
+#[cfg(not(any(feature = "ahash", feature = "fxhash")))]
+type BranchHasher = std::collections::hash_map::RandomState;
+
+type BranchMap = HashMap<BranchIndex, Branch, BranchHasher>;
+
+/// The position and height of a branch of an [`OctreeBitmap`], by which it
+/// is kept in a [`NodeStore`].
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-struct BranchIndex {
+pub struct BranchIndex {
     base: Index,
     height: u32,
 }
@@ -58,6 +504,43 @@ impl BranchIndex {
             height,
         }
     }
+
+    /// The child octant at the given position within this one.
+    fn child(&self, x: usize, y: usize, z: usize) -> Self {
+        let height = self.height - 1;
+        Self {
+            base: Index {
+                x: self.base.x | (x as u32) << height,
+                y: self.base.y | (y as u32) << height,
+                z: self.base.z | (z as u32) << height,
+            },
+            height,
+        }
+    }
+}
+
+/// The contents of an octant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Occupancy {
+    /// Every voxel is false.
+    Empty,
+    /// Every voxel is true.
+    Full,
+    /// Some voxels are true and some are false.
+    Mixed,
+}
+
+/// The maximum height of a dense leaf brick, unless a different
+/// [`LeafSize`] is chosen.
+///
+/// Octants at this height are not split into branches; when they are neither
+/// uniformly true nor false, their voxels are stored as packed bits in a single
+/// `u64` (one 4x4x4 brick).
+const BRICK_HEIGHT: u32 = 2;
+
+/// A mask of the bits used by a brick of the given height.
+fn brick_mask(height: u32) -> u64 {
+    u64::MAX >> (64 - (1 << (3 * height)))
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -65,6 +548,34 @@ enum RawNode {
     False,
     True,
     Branch,
+    /// A dense leaf brick with one bit per voxel. Never all-false or all-true;
+    /// those are compressed into `False` and `True`.
+    Brick(u64),
+}
+
+impl RawNode {
+    /// The node for a brick of the given height with the given bits,
+    /// compressing it if it is uniform.
+    fn from_brick(bits: u64, height: u32) -> Self {
+        if bits == 0 {
+            Self::False
+        } else if bits == brick_mask(height) {
+            Self::True
+        } else {
+            Self::Brick(bits)
+        }
+    }
+
+    /// The bits of a brick of the given height with the same contents as this
+    /// node.
+    fn to_brick(self, height: u32) -> u64 {
+        match self {
+            Self::False => 0,
+            Self::True => brick_mask(height),
+            Self::Brick(bits) => bits,
+            Self::Branch => unreachable!("branch node at brick height"),
+        }
+    }
 }
 
 impl From<bool> for RawNode {
@@ -76,42 +587,198 @@ impl From<bool> for RawNode {
     }
 }
 
-struct Branch {
+/// The children of a branch of an [`OctreeBitmap`], as kept in a
+/// [`NodeStore`].
+#[derive(Clone)]
+pub struct Branch {
     children: [[[RawNode; 2]; 2]; 2],
 }
 
+/// A node of a tree, which may be an octant inside a brick.
+#[derive(Clone, Copy)]
+enum View {
+    Uniform(bool),
+    Branch(BranchIndex),
+    /// The bits of an octant of a brick, in the low bits.
+    Brick(u64),
+}
+
+impl View {
+    /// The root node of the bitmap.
+    fn root(bitmap: &OctreeBitmap) -> Self {
+        let root = BranchIndex::root(bitmap.height);
+        match bitmap.node_occupancy(root) {
+            Occupancy::Empty => Self::Uniform(false),
+            Occupancy::Full => Self::Uniform(true),
+            Occupancy::Mixed => Self::Branch(root),
+        }
+    }
+
+    fn from_raw(raw: RawNode, index: BranchIndex) -> Self {
+        match raw {
+            RawNode::False => Self::Uniform(false),
+            RawNode::True => Self::Uniform(true),
+            RawNode::Branch => Self::Branch(index),
+            RawNode::Brick(bits) => Self::Brick(bits),
+        }
+    }
+
+    /// The `i`th child, in Morton order, of this node at the given index.
+    fn child(self, bitmap: &OctreeBitmap, index: BranchIndex, i: usize) -> Self {
+        let (x, y, z) = (i & 1, (i >> 1) & 1, i >> 2);
+        match self {
+            Self::Uniform(value) => Self::Uniform(value),
+            Self::Branch(index) => Self::from_raw(
+                bitmap.branches[&index].children[z][y][x],
+                index.child(x, y, z),
+            ),
+            Self::Brick(bits) => {
+                let child_height = index.height - 1;
+                let bits = (bits >> (i << (3 * child_height))) & brick_mask(child_height);
+                Self::from_raw(RawNode::from_brick(bits, child_height), index)
+            }
+        }
+    }
+}
+
 /// A three-dimensional bitmap, implemented as an octree.
-pub struct OctreeBitmap {
-    branches: HashMap<BranchIndex, Branch>,
+///
+/// The branches of the tree are kept in a [`NodeStore`], a [`HashMap`] by
+/// default.
+pub struct OctreeBitmap<S = BranchMap> {
+    branches: S,
     height: u32,
+    /// The maximum height of the dense leaf bricks, as set by [`LeafSize`].
+    leaf_height: u32,
+    /// The undo history, in recording mode.
+    journal: Option<Box<journal::Journal>>,
+    /// The changed regions, when tracking them.
+    dirty: Option<Box<dirty::DirtySet>>,
 }
 
 impl OctreeBitmap {
+    /// The largest width a bitmap can be created with. The rounded-up width
+    /// of the map must still fit in the `u32` coordinate space.
+    pub const MAX_WIDTH: u32 = 1 << 30;
+
     /// Creates a new, empty bitmap.
     ///
     /// The indexes allowed in the set are limited to a certain range, specified
     /// by the `width` parameter; the values of indexes on each dimension must
     /// be within the range `0..width`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `width` is zero or greater than [`MAX_WIDTH`](Self::MAX_WIDTH).
+    /// See [`try_new`](Self::try_new) for a version that returns an error
+    /// instead.
     pub fn new(width: u32) -> Self {
+        match Self::try_new(width) {
+            Ok(bitmap) => bitmap,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Creates a new, empty bitmap, or returns an error if `width` is zero or
+    /// greater than [`MAX_WIDTH`](Self::MAX_WIDTH).
+    pub fn try_new(width: u32) -> Result<Self, NewError> {
+        Self::try_with_store(width, BranchMap::default())
+    }
+
+    /// Creates a new, empty bitmap with room for at least `expected_nodes`
+    /// branches, as with [`new`](Self::new), so that building a bitmap of
+    /// known density does not repeatedly grow its map.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `width` is zero or greater than [`MAX_WIDTH`](Self::MAX_WIDTH).
+    pub fn with_capacity(width: u32, expected_nodes: usize) -> Self {
+        let store = BranchMap::with_capacity_and_hasher(expected_nodes, Default::default());
+        Self::with_store(width, store)
+    }
+
+    /// Creates a new, empty bitmap with the given root height.
+    fn with_height(height: u32) -> Self {
+        Self::with_height_in(height, BranchMap::default())
+    }
+}
+
+impl<S: NodeStore> OctreeBitmap<S> {
+    /// Creates a new, empty bitmap keeping its branches in `store`, as with
+    /// [`new`](OctreeBitmap::new).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `width` is zero or greater than
+    /// [`MAX_WIDTH`](OctreeBitmap::MAX_WIDTH).
+    pub fn with_store(width: u32, store: S) -> Self {
+        match Self::try_with_store(width, store) {
+            Ok(bitmap) => bitmap,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Creates a new, empty bitmap keeping its branches in `store`, or
+    /// returns an error if `width` is zero or greater than
+    /// [`MAX_WIDTH`](OctreeBitmap::MAX_WIDTH).
+    pub fn try_with_store(width: u32, store: S) -> Result<Self, NewError> {
+        if width == 0 {
+            return Err(NewError::ZeroWidth);
+        }
+        if width > OctreeBitmap::MAX_WIDTH {
+            return Err(NewError::TooWide { width });
+        }
         // ceil(log2(width))
         let height = u32::BITS - width.next_power_of_two().leading_zeros();
-        let mut nodes = HashMap::new();
-        nodes.insert(
+        Ok(Self::with_height_in(height, store))
+    }
+
+    /// Creates a new, empty bitmap with the given root height, keeping its
+    /// branches in `store`, which is cleared first.
+    fn with_height_in(height: u32, mut store: S) -> Self {
+        store.clear();
+        store.insert(
             BranchIndex::root(height),
             Branch {
                 children: [[[RawNode::False; 2]; 2]; 2],
             },
         );
         Self {
-            branches: nodes,
+            branches: store,
             height,
+            leaf_height: BRICK_HEIGHT,
+            journal: None,
+            dirty: None,
         }
     }
 
+    /// Moves the branches of the bitmap into a different kind of
+    /// [`NodeStore`], keeping its undo history and changed regions.
+    pub fn into_store<T: NodeStore>(self) -> OctreeBitmap<T> {
+        let mut branches = T::default();
+        branches.extend(self.branches);
+        OctreeBitmap {
+            branches,
+            height: self.height,
+            leaf_height: self.leaf_height,
+            journal: self.journal,
+            dirty: self.dirty,
+        }
+    }
+
+    /// Makes room for at least `additional_nodes` more branches, such as
+    /// before voxelizing a scene of known density. The number of branches
+    /// is reported by [`stats`](OctreeBitmap::stats). Stores that cannot be
+    /// pre-sized ignore this.
+    pub fn reserve(&mut self, additional_nodes: usize) {
+        self.branches.reserve(additional_nodes);
+    }
+
     /// Clears the map.
     ///
     /// After this is called, [`get`] will return `false` for all indexes.
     pub fn clear(&mut self) {
+        self.record_subtree(BranchIndex::root(self.height));
         self.branches.clear();
         self.branches.insert(
             BranchIndex::root(self.height),
@@ -132,50 +799,237 @@ impl OctreeBitmap {
         1 << self.height
     }
 
+    /// Whether no voxels are set.
+    ///
+    /// This only inspects the children of the root, since uniform subtrees are
+    /// always compressed.
+    pub fn is_empty(&self) -> bool {
+        self.node_occupancy(BranchIndex::root(self.height)) == Occupancy::Empty
+    }
+
+    /// Whether every voxel is set.
+    ///
+    /// Like [`is_empty`](Self::is_empty), this only inspects the children of
+    /// the root.
+    pub fn is_full(&self) -> bool {
+        self.node_occupancy(BranchIndex::root(self.height)) == Occupancy::Full
+    }
+
+    /// The height of the children of the lowest branches, which are stored as
+    /// bricks when they are not uniform.
+    fn brick_height(&self) -> u32 {
+        self.leaf_height.min(self.height - 1)
+    }
+
+    /// Whether the index is inside the bitmap.
+    fn in_bounds(&self, idx: &Index) -> bool {
+        let width = self.width();
+        idx.x < width && idx.y < width && idx.z < width
+    }
+
+    fn check_bounds(&self, idx: &Index) -> Result<(), OutOfBounds> {
+        if self.in_bounds(idx) {
+            Ok(())
+        } else {
+            Err(OutOfBounds {
+                index: *idx,
+                width: self.width(),
+            })
+        }
+    }
+
     /// Get the current value of the bit at the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is outside the bitmap. See [`try_get`](Self::try_get)
+    /// for a version that returns an error instead.
     pub fn get(&self, idx: &Index) -> bool {
-        let mut current_height = self.height;
+        if let Err(err) = self.check_bounds(idx) {
+            panic!("{}", err);
+        }
+        self.get_from(idx, self.height).0
+    }
+
+    /// The contents of the octant of width `2^level` containing the given
+    /// index, found without descending below it, such as for choosing a
+    /// level of detail. Level 0 is the voxel itself, and the level of the
+    /// root is `log2(width)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is outside the bitmap or the level is above the
+    /// root.
+    pub fn get_coarse(&self, idx: &Index, level: u32) -> Occupancy {
+        if let Err(err) = self.check_bounds(idx) {
+            panic!("{}", err);
+        }
+        assert!(
+            level <= self.height,
+            "level {} is above the root of a bitmap of width {}",
+            level,
+            self.width()
+        );
+        self.node_occupancy(idx.branch_at(level))
+    }
+
+    /// Get the current value of the bit at the given index, descending from
+    /// its ancestor branch at the given height, along with the height of the
+    /// branch holding it.
+    fn get_from(&self, idx: &Index, height: u32) -> (bool, u32) {
+        let mut current_height = height;
         loop {
             let current_branch = &self.branches[&idx.branch_at(current_height)];
             let (x, y, z) = idx.bit(current_height - 1);
-            match current_branch.children[z][y][x] {
-                RawNode::False => return false,
-                RawNode::True => return true,
+            let value = match current_branch.children[z][y][x] {
+                RawNode::False => false,
+                RawNode::True => true,
+                RawNode::Brick(bits) => (bits >> idx.brick_offset(current_height - 1)) & 1 != 0,
                 RawNode::Branch => {
                     current_height -= 1;
                     if current_height == 0 {
                         unreachable!("branch node at height zero");
                     }
+                    continue;
                 }
-            }
+            };
+            return (value, current_height);
         }
     }
 
+    /// Get the current value of the bit at the given index, or an error if
+    /// the index is outside the bitmap.
+    pub fn try_get(&self, idx: &Index) -> Result<bool, OutOfBounds> {
+        self.check_bounds(idx)?;
+        Ok(self.get(idx))
+    }
+
     /// Set the value at the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is outside the bitmap. See [`try_set`](Self::try_set)
+    /// for a version that returns an error instead. The same applies to
+    /// [`modify`](Self::modify), [`toggle`](Self::toggle) and
+    /// [`replace`](Self::replace).
     pub fn set(&mut self, idx: &Index, value: bool) {
-        let desired_state = RawNode::from(value);
-        let mut current_height = self.height;
+        self.update(idx, |_| value);
+    }
+
+    /// Set the value at the given index, or return an error if the index is
+    /// outside the bitmap.
+    pub fn try_set(&mut self, idx: &Index, value: bool) -> Result<(), OutOfBounds> {
+        self.check_bounds(idx)?;
+        self.update(idx, |_| value);
+        Ok(())
+    }
+
+    /// Replace the value at the given index with the result of calling `f`
+    /// with its current value, returning the previous value.
+    ///
+    /// The value is read and written in a single traversal of the tree,
+    /// rather than the two of a [`get`](Self::get) followed by a
+    /// [`set`](Self::set).
+    pub fn modify(&mut self, idx: &Index, f: impl FnOnce(bool) -> bool) -> bool {
+        self.update(idx, f)
+    }
+
+    /// Set the value at the given index, returning whether it changed.
+    pub fn replace(&mut self, idx: &Index, value: bool) -> bool {
+        self.update(idx, |_| value) != value
+    }
+
+    /// Flip the value at the given index, returning the new value.
+    pub fn toggle(&mut self, idx: &Index) -> bool {
+        !self.update(idx, |value| !value)
+    }
+
+    /// Set the value at the given index to the result of calling `f` with its
+    /// current value, returning the previous value.
+    fn update(&mut self, idx: &Index, f: impl FnOnce(bool) -> bool) -> bool {
+        if let Err(err) = self.check_bounds(idx) {
+            panic!("{}", err);
+        }
+        self.update_from(idx, self.height, f).0
+    }
+
+    /// Set the value at the given index to the result of calling `f` with its
+    /// current value, descending from its ancestor branch at the given
+    /// height. Returns the previous value, and the height of the branch that
+    /// held it, which may have been merged into its parent since.
+    fn update_from(
+        &mut self,
+        idx: &Index,
+        height: u32,
+        f: impl FnOnce(bool) -> bool,
+    ) -> (bool, u32) {
+        let mut f = Some(f);
+        // The previous and new values, once the leaf has been found.
+        let mut values = None;
+        let brick_height = self.brick_height();
+        let mut current_height = height;
         loop {
             let current_index = idx.branch_at(current_height);
-            let current_branch = self.branches.get_mut(&current_index).unwrap();
             let (x, y, z) = idx.bit(current_height - 1);
-            match current_branch.children[z][y][x] {
+            let node = self.branches[&current_index].children[z][y][x];
+            let (old, value) = match (values, node) {
+                (Some(values), _) => values,
+                (None, RawNode::Branch) => (false, false),
+                (None, leaf) => {
+                    let old = match leaf {
+                        RawNode::Brick(bits) => {
+                            (bits >> idx.brick_offset(current_height - 1)) & 1 != 0
+                        }
+                        leaf => leaf == RawNode::True,
+                    };
+                    let value = f.take().unwrap()(old);
+                    if value == old {
+                        return (old, current_height);
+                    }
+                    self.mark_dirty(idx.branch_at(0));
+                    if self.is_recording() {
+                        self.record_voxels(vec![*idx], value);
+                    }
+                    values = Some((old, value));
+                    (old, value)
+                }
+            };
+            let desired_state = RawNode::from(value);
+            let child = &mut self.branches.get_mut(&current_index).unwrap().children[z][y][x];
+            match *child {
                 RawNode::Branch => {
                     current_height -= 1;
                     if current_height == 0 {
                         unreachable!("branch node at height zero");
                     }
                 }
+                RawNode::Brick(bits) => {
+                    let bit = 1 << idx.brick_offset(current_height - 1);
+                    let new_bits = if value { bits | bit } else { bits & !bit };
+                    if new_bits == 0 || new_bits == brick_mask(current_height - 1) {
+                        *child = desired_state;
+                        self.compress(idx, desired_state, current_height);
+                    } else {
+                        *child = RawNode::Brick(new_bits);
+                    }
+                    return (old, current_height);
+                }
                 other => {
-                    if desired_state == other {
-                        // Already
-                        return;
-                    } else if current_height == 1 {
-                        current_branch.children[z][y][x] = desired_state;
-                        self.compress(idx, desired_state);
-                        return;
+                    if current_height == 1 {
+                        *child = desired_state;
+                        self.compress(idx, desired_state, current_height);
+                        return (old, current_height);
+                    } else if current_height - 1 == brick_height {
+                        // Bricks have more than one voxel, so flipping a single
+                        // bit never makes them uniform.
+                        let fill = match other {
+                            RawNode::True => brick_mask(brick_height),
+                            _ => 0,
+                        };
+                        *child = RawNode::Brick(fill ^ (1 << idx.brick_offset(brick_height)));
+                        return (old, current_height);
                     } else {
-                        current_branch.children[z][y][x] = RawNode::Branch;
+                        *child = RawNode::Branch;
                         self.branches.insert(
                             idx.branch_at(current_height - 1),
                             Branch {
@@ -188,13 +1042,120 @@ impl OctreeBitmap {
         }
     }
 
-    /// Traverse the tree from the specified leaf to the root, replacing all
-    /// branches that have uniform child values with a single node of that
-    /// value.
-    fn compress(&mut self, idx: &Index, state: RawNode) {
+    /// Set the value at all of the given indexes.
+    ///
+    /// This is equivalent to calling [`set`](Self::set) for each index, but
+    /// the indexes are sorted along the Z-order curve first so that nearby
+    /// indexes share a single traversal of the tree, and branches are
+    /// compressed once after all of their voxels have been updated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any index is outside of the range `0..self.width()`.
+    pub fn set_many<I>(&mut self, indices: I, value: bool)
+    where
+        I: IntoIterator<Item = Index>,
+    {
+        let width = self.width();
+        let mut indices: Vec<(u128, Index)> = indices
+            .into_iter()
+            .inspect(|idx| {
+                assert!(
+                    idx.x < width && idx.y < width && idx.z < width,
+                    "index {:?} out of bounds for width {}",
+                    idx,
+                    width
+                );
+            })
+            .map(|idx| (idx.morton(), idx))
+            .collect();
+        indices.sort_unstable_by_key(|&(key, _)| key);
+        indices.dedup_by_key(|&mut (key, _)| key);
+        let indices: Vec<Index> = indices.into_iter().map(|(_, idx)| idx).collect();
+        if self.is_recording() || self.is_tracking_dirty() {
+            let changed: Vec<Index> = indices
+                .iter()
+                .filter(|idx| self.get(idx) != value)
+                .copied()
+                .collect();
+            self.mark_voxels_dirty(&changed);
+            self.record_voxels(changed, value);
+        }
+        self.set_sorted(
+            BranchIndex::root(self.height),
+            &indices,
+            RawNode::from(value),
+        );
+    }
+
+    /// Set the given indexes to `state`. The indexes must all be within the
+    /// branch, sorted in Morton order and unique.
+    ///
+    /// If this leaves the branch uniformly equal to `state`, it is not
+    /// compressed; that is left to the caller.
+    fn set_sorted(&mut self, index: BranchIndex, indices: &[Index], state: RawNode) {
+        let brick_height = self.brick_height();
+        let child_height = index.height - 1;
+        let mut rest = indices;
+        while let Some(first) = rest.first() {
+            let child_index = first.branch_at(child_height);
+            let len = rest
+                .iter()
+                .position(|idx| idx.branch_at(child_height) != child_index)
+                .unwrap_or(rest.len());
+            let (group, tail) = rest.split_at(len);
+            rest = tail;
+
+            let (x, y, z) = first.bit(child_height);
+            let child = &mut self.branches.get_mut(&index).unwrap().children[z][y][x];
+            match *child {
+                current if current == state => {}
+                RawNode::Branch => {
+                    self.set_sorted(child_index, group, state);
+                    if self.branches[&child_index].children == [[[state; 2]; 2]; 2] {
+                        self.branches.remove(&child_index);
+                        self.branches.get_mut(&index).unwrap().children[z][y][x] = state;
+                    }
+                }
+                current if child_height == 0 || group.len() as u64 == 1 << (3 * child_height) => {
+                    // Every voxel of the child is changing.
+                    debug_assert!(!matches!(current, RawNode::Branch));
+                    *child = state;
+                }
+                current if child_height <= brick_height => {
+                    let mut bits = current.to_brick(child_height);
+                    for idx in group {
+                        let bit = 1 << idx.brick_offset(child_height);
+                        match state {
+                            RawNode::True => bits |= bit,
+                            _ => bits &= !bit,
+                        }
+                    }
+                    *child = RawNode::from_brick(bits, child_height);
+                }
+                current => {
+                    // Only part of the child is changing, so it cannot become
+                    // uniform.
+                    *child = RawNode::Branch;
+                    self.branches.insert(
+                        child_index,
+                        Branch {
+                            children: [[[current; 2]; 2]; 2],
+                        },
+                    );
+                    self.set_sorted(child_index, group, state);
+                }
+            }
+        }
+    }
+
+    /// Traverse the tree from the branch at the given height containing the
+    /// specified index to the root, replacing all branches that have uniform
+    /// child values with a single node of that value.
+    fn compress(&mut self, idx: &Index, state: RawNode, from_height: u32) {
         // Root node (==self.height) is intentionally excluded as it is always
         // a branch node.
-        for current_height in 1..self.height {
+        for current_height in from_height..self.height {
             let current_index = idx.branch_at(current_height);
             let current_branch = self.branches.get_mut(&current_index).unwrap();
             if current_branch.children != [[[state; 2]; 2]; 2] {
@@ -208,6 +1169,261 @@ impl OctreeBitmap {
                 .children[z][y][x] = state;
         }
     }
+
+    /// The contents of the octant at the given node.
+    fn node_occupancy(&self, node: BranchIndex) -> Occupancy {
+        let classify = |raw| match raw {
+            RawNode::False => Occupancy::Empty,
+            RawNode::True => Occupancy::Full,
+            _ => Occupancy::Mixed,
+        };
+        if node.height == self.height {
+            return match self.branches[&node].children {
+                children if children == [[[RawNode::False; 2]; 2]; 2] => Occupancy::Empty,
+                children if children == [[[RawNode::True; 2]; 2]; 2] => Occupancy::Full,
+                _ => Occupancy::Mixed,
+            };
+        }
+        let mut current_height = self.height;
+        loop {
+            let current_branch = &self.branches[&node.base.branch_at(current_height)];
+            let child_height = current_height - 1;
+            let (x, y, z) = node.base.bit(child_height);
+            match current_branch.children[z][y][x] {
+                RawNode::Branch if child_height > node.height => current_height = child_height,
+                RawNode::Brick(bits) if child_height > node.height => {
+                    let bits = bits >> node.base.brick_offset(child_height);
+                    return classify(RawNode::from_brick(
+                        bits & brick_mask(node.height),
+                        node.height,
+                    ));
+                }
+                child => return classify(child),
+            }
+        }
+    }
+
+    /// Set every voxel of the octant at the given node to `value`, replacing
+    /// its whole subtree at once.
+    fn fill_node(&mut self, node: BranchIndex, value: bool) {
+        self.mark_dirty(node);
+        if self.is_recording() {
+            if node.height > self.brick_height() {
+                self.record_subtree(node);
+            } else {
+                let side = 1 << node.height;
+                let changed = (0..side * side * side)
+                    .map(|i| {
+                        Index::new(
+                            node.base.x + i % side,
+                            node.base.y + i / side % side,
+                            node.base.z + i / (side * side),
+                        )
+                    })
+                    .filter(|idx| self.get(idx) != value)
+                    .collect();
+                self.record_voxels(changed, value);
+            }
+        }
+        let state = RawNode::from(value);
+        if node.height == self.height {
+            self.clear();
+            self.branches.get_mut(&node).unwrap().children = [[[state; 2]; 2]; 2];
+            return;
+        }
+        let brick_height = self.brick_height();
+        let mut current_height = self.height;
+        while current_height > node.height + 1 {
+            let current_index = node.base.branch_at(current_height);
+            let child_height = current_height - 1;
+            let (x, y, z) = node.base.bit(child_height);
+            let child = &mut self.branches.get_mut(&current_index).unwrap().children[z][y][x];
+            match *child {
+                RawNode::Branch => {}
+                current if current == state => return,
+                current if child_height <= brick_height => {
+                    // The node is an octant inside this brick.
+                    let mask = brick_mask(node.height) << node.base.brick_offset(child_height);
+                    let bits = match state {
+                        RawNode::True => current.to_brick(child_height) | mask,
+                        _ => current.to_brick(child_height) & !mask,
+                    };
+                    *child = RawNode::from_brick(bits, child_height);
+                    if *child == state {
+                        self.compress(&node.base, state, current_height);
+                    }
+                    return;
+                }
+                current => {
+                    *child = RawNode::Branch;
+                    self.branches.insert(
+                        node.base.branch_at(child_height),
+                        Branch {
+                            children: [[[current; 2]; 2]; 2],
+                        },
+                    );
+                }
+            }
+            current_height = child_height;
+        }
+
+        let (x, y, z) = node.base.bit(node.height);
+        let child = &mut self
+            .branches
+            .get_mut(&node.base.branch_at(node.height + 1))
+            .unwrap()
+            .children[z][y][x];
+        let old = std::mem::replace(child, state);
+        if old == RawNode::Branch {
+            self.remove_branch(node);
+        }
+        if old != state {
+            self.compress(&node.base, state, node.height + 1);
+        }
+    }
+
+    /// Remove the branch at the given index and all branches below it.
+    fn remove_branch(&mut self, index: BranchIndex) {
+        let branch = self.branches.remove(&index).unwrap();
+        for z in 0..2 {
+            for y in 0..2 {
+                for x in 0..2 {
+                    if branch.children[z][y][x] == RawNode::Branch {
+                        self.remove_branch(index.child(x, y, z));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Walks the tree depth-first in Morton order, calling `visitor` with each
+    /// node whose parent it descended into, starting with the root.
+    ///
+    /// Each node is given as its base index and height, so it covers the
+    /// indexes from `base` to `base + (1 << height)` (exclusive) on each axis,
+    /// along with whether it is empty, full or mixed. `visitor` returns
+    /// whether to descend into a mixed node; uniform nodes have no children.
+    /// Nodes are split into octants down to single voxels, regardless of how
+    /// they are stored.
+    pub fn visit<F>(&self, visitor: F)
+    where
+        F: FnMut(Index, u32, Occupancy) -> bool,
+    {
+        self.visit_nodes(visitor);
+    }
+
+    /// Walk the tree depth-first in Morton order, calling `f` with the base
+    /// index, height and occupancy of each node, including the octants inside
+    /// bricks. `f` returns whether to descend into a mixed node.
+    fn visit_nodes<F>(&self, mut f: F)
+    where
+        F: FnMut(Index, u32, Occupancy) -> bool,
+    {
+        let root = BranchIndex::root(self.height);
+        let occupancy = match self.branches[&root].children {
+            children if children == [[[RawNode::False; 2]; 2]; 2] => Occupancy::Empty,
+            children if children == [[[RawNode::True; 2]; 2]; 2] => Occupancy::Full,
+            _ => Occupancy::Mixed,
+        };
+        if f(root.base, root.height, occupancy) && occupancy == Occupancy::Mixed {
+            self.visit_branch(root, &mut f);
+        }
+    }
+
+    fn visit_branch<F>(&self, index: BranchIndex, f: &mut F)
+    where
+        F: FnMut(Index, u32, Occupancy) -> bool,
+    {
+        let branch = &self.branches[&index];
+        for z in 0..2 {
+            for y in 0..2 {
+                for x in 0..2 {
+                    let child = index.child(x, y, z);
+                    match branch.children[z][y][x] {
+                        RawNode::False => {
+                            f(child.base, child.height, Occupancy::Empty);
+                        }
+                        RawNode::True => {
+                            f(child.base, child.height, Occupancy::Full);
+                        }
+                        RawNode::Branch => {
+                            if f(child.base, child.height, Occupancy::Mixed) {
+                                self.visit_branch(child, f);
+                            }
+                        }
+                        RawNode::Brick(bits) => {
+                            if f(child.base, child.height, Occupancy::Mixed) {
+                                visit_brick(child, bits, f);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A copy of the bitmap's contents, without its undo history or changed
+/// regions. With a [`SharedStore`], the copy shares its branches with the
+/// original until either of them is changed.
+impl<S: NodeStore + Clone> Clone for OctreeBitmap<S> {
+    fn clone(&self) -> Self {
+        Self {
+            branches: self.branches.clone(),
+            height: self.height,
+            leaf_height: self.leaf_height,
+            journal: None,
+            dirty: None,
+        }
+    }
+}
+
+/// Reads the value at an index, as with [`OctreeBitmap::get`], so that
+/// `bitmap[idx]` can be used in expressions.
+///
+/// # Panics
+///
+/// Panics if the index is outside the bitmap.
+impl<S: NodeStore> std::ops::Index<Index> for OctreeBitmap<S> {
+    type Output = bool;
+
+    fn index(&self, idx: Index) -> &bool {
+        // Constant booleans are promoted to statics.
+        if self.get(&idx) {
+            &true
+        } else {
+            &false
+        }
+    }
+}
+
+impl<S: NodeStore> std::ops::Index<(u32, u32, u32)> for OctreeBitmap<S> {
+    type Output = bool;
+
+    fn index(&self, idx: (u32, u32, u32)) -> &bool {
+        &self[Index::from(idx)]
+    }
+}
+
+/// Visit the children of a mixed octant of a brick, whose bits (in Morton
+/// order) are the low bits of `bits`.
+fn visit_brick<F>(index: BranchIndex, bits: u64, f: &mut F)
+where
+    F: FnMut(Index, u32, Occupancy) -> bool,
+{
+    let child_bits = 1 << (3 * (index.height - 1));
+    let child_mask = brick_mask(index.height - 1);
+    for i in 0..8 {
+        let child = index.child(i & 1, (i >> 1) & 1, i >> 2);
+        let bits = (bits >> (i as u32 * child_bits)) & child_mask;
+        if bits == 0 {
+            f(child.base, child.height, Occupancy::Empty);
+        } else if bits == child_mask {
+            f(child.base, child.height, Occupancy::Full);
+        } else if f(child.base, child.height, Occupancy::Mixed) {
+            visit_brick(child, bits, f);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -238,4 +1454,299 @@ mod tests {
         assert!(!octree.get(&a));
         assert!(!octree.get(&a));
     }
+
+    #[test]
+    fn bricks_store_noisy_leaves_densely() {
+        let mut octree = OctreeBitmap::new(16);
+        for x in 0..8 {
+            for y in 0..8 {
+                for z in 0..8 {
+                    octree.set(&Index::new(x, y, z), (x + y + z) % 2 == 0);
+                }
+            }
+        }
+        for x in 0..16 {
+            for y in 0..16 {
+                for z in 0..16 {
+                    let expected = x < 8 && y < 8 && z < 8 && (x + y + z) % 2 == 0;
+                    assert_eq!(octree.get(&Index::new(x, y, z)), expected);
+                }
+            }
+        }
+        // The checkerboard lives entirely in bricks below one branch.
+        assert_eq!(octree.branches.len(), 3);
+
+        for x in 0..8 {
+            for y in 0..8 {
+                for z in 0..8 {
+                    octree.set(&Index::new(x, y, z), true);
+                }
+            }
+        }
+        assert_eq!(octree.branches.len(), 2);
+    }
+
+    #[test]
+    fn small_widths() {
+        for width in 1..=4 {
+            let mut octree = OctreeBitmap::new(width);
+            let a = Index::new(1, 0, 1);
+            octree.set(&a, true);
+            assert!(octree.get(&a));
+            assert!(!octree.get(&Index::new(0, 0, 1)));
+            octree.set(&a, false);
+            assert!(!octree.get(&a));
+            assert_eq!(octree.branches.len(), 1);
+        }
+    }
+
+    #[test]
+    fn bounds_checked_access() {
+        let mut octree = OctreeBitmap::new(16);
+        let width = octree.width();
+        let inside = Index::new(width - 1, 0, 3);
+        assert_eq!(octree.try_set(&inside, true), Ok(()));
+        assert_eq!(octree.try_get(&inside), Ok(true));
+        let outside = Index::new(1, width, 0);
+        let err = OutOfBounds {
+            index: outside,
+            width,
+        };
+        assert_eq!(octree.try_get(&outside), Err(err));
+        assert_eq!(octree.try_set(&outside, true), Err(err));
+        let panic = std::panic::catch_unwind(|| octree.get(&outside)).unwrap_err();
+        assert_eq!(panic.downcast_ref::<String>(), Some(&err.to_string()));
+    }
+
+    #[test]
+    fn invalid_widths() {
+        assert_eq!(OctreeBitmap::try_new(0).unwrap_err(), NewError::ZeroWidth);
+        let max = OctreeBitmap::try_new(OctreeBitmap::MAX_WIDTH).unwrap();
+        assert!(max.width() >= OctreeBitmap::MAX_WIDTH);
+        assert_eq!(
+            OctreeBitmap::try_new(OctreeBitmap::MAX_WIDTH + 1).unwrap_err(),
+            NewError::TooWide {
+                width: OctreeBitmap::MAX_WIDTH + 1
+            }
+        );
+        assert!(OctreeBitmap::try_new(u32::MAX).is_err());
+    }
+
+    #[test]
+    fn empty_and_full() {
+        let mut octree = OctreeBitmap::new(16);
+        assert!(octree.is_empty() && !octree.is_full());
+        octree.set(&Index::new(3, 4, 5), true);
+        assert!(!octree.is_empty() && !octree.is_full());
+        octree.fill_node(BranchIndex::root(octree.height), true);
+        assert!(!octree.is_empty() && octree.is_full());
+        octree.set(&Index::new(3, 4, 5), false);
+        assert!(!octree.is_empty() && !octree.is_full());
+        octree.set(&Index::new(3, 4, 5), true);
+        assert!(octree.is_full());
+        octree.clear();
+        assert!(octree.is_empty());
+    }
+
+    #[test]
+    fn coarse_queries() {
+        let mut octree = OctreeBitmap::new(16);
+        octree.fill_with(Index::new(8, 0, 0), Index::new(16, 8, 8), |_| true);
+        octree.set(&Index::new(1, 2, 3), true);
+        let idx = Index::new(9, 1, 2);
+        let levels: Vec<_> = (0..=5)
+            .map(|level| octree.get_coarse(&idx, level))
+            .collect();
+        use Occupancy::*;
+        assert_eq!(levels, [Full, Full, Full, Full, Mixed, Mixed]);
+        let levels: Vec<_> = (0..=3)
+            .map(|level| octree.get_coarse(&Index::new(1, 2, 3), level))
+            .collect();
+        assert_eq!(levels, [Full, Mixed, Mixed, Mixed]);
+        assert_eq!(octree.get_coarse(&Index::new(0, 0, 0), 0), Empty);
+        assert_eq!(octree.get_coarse(&Index::new(4, 4, 4), 1), Empty);
+        assert_eq!(octree.get_coarse(&Index::new(31, 31, 31), 4), Empty);
+    }
+
+    #[test]
+    fn visit_prunes_nodes() {
+        let mut octree = OctreeBitmap::new(16);
+        octree.fill_node(Index::new(16, 0, 0).branch_at(4), true);
+        octree.set(&Index::new(1, 2, 3), true);
+        let mut nodes = Vec::new();
+        octree.visit(|base, height, occupancy| {
+            nodes.push((base, height, occupancy));
+            height > 2
+        });
+        assert_eq!(nodes[0], (Index::new(0, 0, 0), 5, Occupancy::Mixed));
+        assert_eq!(nodes[1], (Index::new(0, 0, 0), 4, Occupancy::Mixed));
+        assert!(nodes.contains(&(Index::new(16, 0, 0), 4, Occupancy::Full)));
+        assert!(nodes.contains(&(Index::new(0, 0, 0), 2, Occupancy::Mixed)));
+        assert!(nodes.iter().all(|&(_, height, _)| height >= 2));
+        // The root, two mixed nodes above height 2, and their children.
+        assert_eq!(nodes.len(), 1 + 3 * 8);
+    }
+
+    #[test]
+    fn index_arithmetic() {
+        let idx = Index::new(5, 0, u32::MAX - 1);
+        assert_eq!(idx.offset(1, 2, 1), Some(Index::new(6, 2, u32::MAX)));
+        assert_eq!(idx.offset(0, -1, 0), None);
+        assert_eq!(idx.offset(0, 0, 2), None);
+        let offset = Offset::new(-5, 3, 1);
+        assert_eq!(idx + offset, Index::new(0, 3, u32::MAX));
+        assert_eq!(idx + offset - offset, idx);
+        assert_eq!(idx.checked_sub(-offset), Some(idx + offset));
+        assert_eq!(idx.checked_sub(offset), None);
+        assert_eq!(
+            idx.saturating_add(Offset::new(-9, -1, 9)),
+            Index::new(0, 0, u32::MAX)
+        );
+        assert_eq!(idx.saturating_sub(offset), Index::new(10, 0, u32::MAX - 2));
+        assert_eq!(offset + offset - offset, offset);
+        assert_eq!(<[u32; 3]>::from(idx), [5, 0, u32::MAX - 1]);
+        assert_eq!(<(u32, u32, u32)>::from(idx), (5, 0, u32::MAX - 1));
+        assert_eq!(Offset::from([1, 2, 3]), Offset::from((1, 2, 3)));
+    }
+
+    #[test]
+    fn index_operator() {
+        let mut octree = OctreeBitmap::new(16);
+        octree.set(&Index::new(1, 2, 3), true);
+        assert!(octree[Index::new(1, 2, 3)]);
+        assert!(octree[(1, 2, 3)]);
+        assert!(!octree[(3, 2, 1)]);
+    }
+
+    #[test]
+    fn capacity() {
+        let mut bitmap = OctreeBitmap::with_capacity(16, 1000);
+        assert_eq!(bitmap.width(), 32);
+        assert!(bitmap.is_empty());
+        assert!(bitmap.branches.capacity() >= 1000);
+        bitmap.reserve(5000);
+        assert!(bitmap.branches.capacity() >= 5001);
+        bitmap.set(&Index::new(1, 2, 3), true);
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), [Index::new(1, 2, 3)]);
+    }
+
+    #[test]
+    fn fill_node_matches_set_many() {
+        let points = pseudo_random_indexes(16, 1000);
+        let nodes = [
+            Index::new(8, 0, 8).branch_at(3),
+            Index::new(4, 4, 4).branch_at(2),
+            Index::new(6, 2, 0).branch_at(1),
+            Index::new(3, 5, 7).branch_at(0),
+        ];
+        for value in [true, false] {
+            let mut filled = OctreeBitmap::new(16);
+            let mut expected = OctreeBitmap::new(16);
+            filled.set_many(points.iter().copied(), !value);
+            expected.set_many(points.iter().copied(), !value);
+            for node in nodes {
+                filled.fill_node(node, value);
+                let side = 1 << node.height;
+                expected.set_many(
+                    (0..side).flat_map(|x| {
+                        (0..side).flat_map(move |y| {
+                            (0..side).map(move |z| {
+                                Index::new(node.base.x + x, node.base.y + y, node.base.z + z)
+                            })
+                        })
+                    }),
+                    value,
+                );
+                assert_eq!(filled.branches.len(), expected.branches.len());
+                assert!(filled.iter().eq(expected.iter()));
+            }
+        }
+
+        let mut octree = OctreeBitmap::new(16);
+        octree.fill_node(Index::new(0, 0, 0).branch_at(3), true);
+        octree.fill_node(BranchIndex::root(4), false);
+        assert_eq!(octree.branches.len(), 1);
+        assert_eq!(octree.iter().count(), 0);
+    }
+
+    #[test]
+    fn modify_matches_get_and_set() {
+        let points = pseudo_random_indexes(16, 2000);
+        let mut modified = OctreeBitmap::new(16);
+        let mut expected = OctreeBitmap::new(16);
+        modified.fill_node(Index::new(0, 8, 0).branch_at(3), true);
+        expected.fill_node(Index::new(0, 8, 0).branch_at(3), true);
+        modified.start_recording();
+        for (i, idx) in points.iter().enumerate() {
+            let f = |value: bool| if i % 3 == 0 { value } else { !value };
+            let old = expected.get(idx);
+            expected.set(idx, f(old));
+            assert_eq!(modified.modify(idx, f), old);
+        }
+        assert_eq!(modified.branches.len(), expected.branches.len());
+        assert!(modified.iter().eq(expected.iter()));
+
+        let idx = Index::new(3, 9, 2);
+        let value = modified.get(&idx);
+        assert_eq!(modified.toggle(&idx), !value);
+        assert_eq!(modified.get(&idx), !value);
+        assert_eq!(modified.toggle(&idx), value);
+        assert!(!modified.replace(&idx, value));
+        assert!(modified.replace(&idx, !value));
+        assert!(modified.replace(&idx, value));
+
+        while modified.undo() {}
+        let mut original = OctreeBitmap::new(16);
+        original.fill_node(Index::new(0, 8, 0).branch_at(3), true);
+        assert!(modified.iter().eq(original.iter()));
+    }
+
+    /// A small deterministic generator of pseudo-random indexes for tests.
+    pub(crate) fn pseudo_random_indexes(width: u32, count: usize) -> Vec<Index> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % width as u64) as u32
+        };
+        (0..count)
+            .map(|_| Index::new(next(), next(), next()))
+            .collect()
+    }
+
+    #[test]
+    fn set_many_matches_set() {
+        let points = pseudo_random_indexes(16, 3000);
+        let mut bulk = OctreeBitmap::new(16);
+        let mut single = OctreeBitmap::new(16);
+        bulk.set_many(points.iter().copied(), true);
+        for idx in &points {
+            single.set(idx, true);
+        }
+        bulk.set_many(points[..1000].iter().copied(), false);
+        for idx in &points[..1000] {
+            single.set(idx, false);
+        }
+        assert_eq!(bulk.branches.len(), single.branches.len());
+        for x in 0..16 {
+            for y in 0..16 {
+                for z in 0..16 {
+                    let idx = Index::new(x, y, z);
+                    assert_eq!(bulk.get(&idx), single.get(&idx));
+                }
+            }
+        }
+
+        bulk.set_many(
+            (0..16).flat_map(|x| (0..16).map(move |y| Index::new(x, y, 0))),
+            true,
+        );
+        bulk.set_many(
+            (0..32)
+                .flat_map(|x| (0..32).flat_map(move |y| (0..32).map(move |z| Index::new(x, y, z)))),
+            true,
+        );
+        assert_eq!(bulk.branches.len(), 1);
+    }
 }
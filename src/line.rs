@@ -0,0 +1,86 @@
+//! Rasterization of line segments.
+
+use crate::{Index, OctreeBitmap};
+
+/// The voxels of a 3D Bresenham line from `a` to `b`, inclusive.
+///
+/// The line steps one voxel at a time along its longest axis, so
+/// consecutive voxels share at least a corner.
+fn bresenham(a: Index, b: Index) -> Vec<Index> {
+    let start = [a.x, a.y, a.z].map(i64::from);
+    let end = [b.x, b.y, b.z].map(i64::from);
+    let delta = [0, 1, 2].map(|i| (end[i] - start[i]).abs());
+    let step = [0, 1, 2].map(|i| (end[i] - start[i]).signum());
+    let major = (0..3).max_by_key(|&i| delta[i]).unwrap();
+    let length = delta[major];
+
+    let mut point = start;
+    // The accumulated error along each minor axis, scaled by 2 * length.
+    let mut error = [0, 1, 2].map(|i| 2 * delta[i] - length);
+    let mut line = Vec::with_capacity(length as usize + 1);
+    for _ in 0..=length {
+        line.push(Index::from(point.map(|c| c as u32)));
+        for i in (0..3).filter(|&i| i != major) {
+            if error[i] > 0 {
+                point[i] += step[i];
+                error[i] -= 2 * length;
+            }
+            error[i] += 2 * delta[i];
+        }
+        point[major] += step[major];
+    }
+    line
+}
+
+impl OctreeBitmap {
+    /// Set every voxel along the line segment from `a` to `b`, inclusive, to
+    /// `value`.
+    ///
+    /// The segment is rasterized with the 3D Bresenham algorithm, giving one
+    /// voxel per step along its longest axis. Drawing from `b` to `a` may
+    /// choose different voxels where the line passes exactly between two.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either end is outside of the range `0..self.width()`.
+    pub fn draw_line(&mut self, a: Index, b: Index, value: bool) {
+        self.set_many(bresenham(a, b), value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bresenham_lines() {
+        assert_eq!(
+            bresenham(Index::new(0, 0, 0), Index::new(4, 2, 0)),
+            [
+                Index::new(0, 0, 0),
+                Index::new(1, 0, 0),
+                Index::new(2, 1, 0),
+                Index::new(3, 1, 0),
+                Index::new(4, 2, 0),
+            ]
+        );
+        assert_eq!(
+            bresenham(Index::new(3, 3, 3), Index::new(3, 3, 3)),
+            [Index::new(3, 3, 3)]
+        );
+
+        let mut bitmap = OctreeBitmap::new(32);
+        let (a, b) = (Index::new(30, 1, 5), Index::new(2, 20, 27));
+        bitmap.draw_line(a, b, true);
+        let line = bresenham(a, b);
+        assert_eq!(line.len(), 29);
+        assert_eq!((line[0], line[28]), (a, b));
+        assert!(line.windows(2).all(|w| {
+            [(w[0].x, w[1].x), (w[0].y, w[1].y), (w[0].z, w[1].z)]
+                .iter()
+                .all(|&(p, q)| p.abs_diff(q) <= 1)
+        }));
+        assert_eq!(bitmap.iter().count(), 29);
+        assert!(line.iter().all(|idx| bitmap.get(idx)));
+    }
+}
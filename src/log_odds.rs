@@ -0,0 +1,311 @@
+//! Probabilistic occupancy maps, as in OctoMap (Hornung et al., 2013).
+
+use std::collections::HashMap;
+
+use crate::ray::{Ray, VoxelWalk};
+use crate::{BranchIndex, Index, OctreeBitmap};
+
+/// The parameters of the sensor model of a [`LogOddsOctree`], all in
+/// log-odds, `ln(p / (1 - p))` for a probability `p`.
+///
+/// The defaults are those of OctoMap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LogOddsParams {
+    /// Added to a voxel where a ray ended. The default is for a probability
+    /// of 0.7.
+    pub hit: f32,
+    /// Added to a voxel a ray passed through. The default is for a
+    /// probability of 0.4.
+    pub miss: f32,
+    /// The lowest log-odds a voxel is clamped to, for a probability of
+    /// 0.1192 by default.
+    pub min: f32,
+    /// The highest log-odds a voxel is clamped to, for a probability of
+    /// 0.971 by default.
+    pub max: f32,
+    /// Voxels with log-odds above this are occupied. The default is for a
+    /// probability of 0.5.
+    pub threshold: f32,
+}
+
+impl Default for LogOddsParams {
+    fn default() -> Self {
+        Self {
+            hit: 0.847_298,
+            miss: -0.405_465,
+            min: -1.999_824,
+            max: 3.511_942,
+            threshold: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LogNode {
+    /// Never observed.
+    Unknown,
+    /// Every voxel in the octant has these log-odds.
+    Leaf(f32),
+    Branch,
+}
+
+/// An octree of occupancy probabilities, updated with sensor measurements,
+/// for mapping with ray-casting sensors such as LiDAR and depth cameras.
+///
+/// Each voxel is unknown until it is observed, and then holds the log-odds
+/// that it is occupied. Updates are clamped to the bounds of the
+/// [`LogOddsParams`], so voxels that are observed repeatedly converge to
+/// one of them, and octants whose voxels all have the same log-odds are
+/// merged into a single node, just as uniform octants are in an
+/// [`OctreeBitmap`].
+pub struct LogOddsOctree {
+    branches: HashMap<BranchIndex, [[[LogNode; 2]; 2]; 2]>,
+    height: u32,
+    params: LogOddsParams,
+}
+
+impl LogOddsOctree {
+    /// Creates a new map where every voxel is unknown, with the default
+    /// parameters.
+    ///
+    /// As with [`OctreeBitmap::new`], the width is rounded up to a power of
+    /// two.
+    pub fn new(width: u32) -> Self {
+        Self::with_params(width, LogOddsParams::default())
+    }
+
+    /// Creates a new map where every voxel is unknown.
+    pub fn with_params(width: u32, params: LogOddsParams) -> Self {
+        // ceil(log2(width)), and at least one level below the root.
+        let height = (u32::BITS - width.next_power_of_two().leading_zeros()).max(1);
+        let mut branches = HashMap::new();
+        branches.insert(BranchIndex::root(height), [[[LogNode::Unknown; 2]; 2]; 2]);
+        Self {
+            branches,
+            height,
+            params,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        1 << self.height
+    }
+
+    pub fn params(&self) -> &LogOddsParams {
+        &self.params
+    }
+
+    fn check_bounds(&self, idx: &Index) {
+        let width = self.width();
+        assert!(
+            idx.x < width && idx.y < width && idx.z < width,
+            "index {:?} is out of bounds for width {}",
+            idx,
+            width
+        );
+    }
+
+    /// The log-odds that the voxel at the given index is occupied, or `None`
+    /// if it has never been observed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is outside the map.
+    pub fn log_odds(&self, idx: &Index) -> Option<f32> {
+        self.check_bounds(idx);
+        let mut height = self.height;
+        loop {
+            let (x, y, z) = idx.bit(height - 1);
+            match self.branches[&idx.branch_at(height)][z][y][x] {
+                LogNode::Unknown => return None,
+                LogNode::Leaf(log_odds) => return Some(log_odds),
+                LogNode::Branch => height -= 1,
+            }
+        }
+    }
+
+    /// The probability that the voxel at the given index is occupied, or
+    /// `None` if it has never been observed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is outside the map.
+    pub fn probability(&self, idx: &Index) -> Option<f32> {
+        self.log_odds(idx)
+            .map(|log_odds| 1.0 - 1.0 / (1.0 + log_odds.exp()))
+    }
+
+    /// Whether the voxel at the given index has been observed and is more
+    /// likely than the threshold to be occupied.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is outside the map.
+    pub fn is_occupied(&self, idx: &Index) -> bool {
+        self.log_odds(idx)
+            .is_some_and(|log_odds| log_odds > self.params.threshold)
+    }
+
+    /// Adds `delta` to the log-odds of the voxel at the given index, which
+    /// start at zero if it is unknown, clamping them to the bounds of the
+    /// parameters.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is outside the map.
+    pub fn update(&mut self, idx: &Index, delta: f32) {
+        self.check_bounds(idx);
+        let mut height = self.height;
+        loop {
+            let index = idx.branch_at(height);
+            let (x, y, z) = idx.bit(height - 1);
+            let child = &mut self.branches.get_mut(&index).unwrap()[z][y][x];
+            match *child {
+                LogNode::Branch => height -= 1,
+                current if height == 1 => {
+                    let old = match current {
+                        LogNode::Leaf(log_odds) => log_odds,
+                        _ => 0.0,
+                    };
+                    let new = (old + delta).clamp(self.params.min, self.params.max);
+                    *child = LogNode::Leaf(new);
+                    self.prune(idx, new);
+                    return;
+                }
+                current => {
+                    *child = LogNode::Branch;
+                    self.branches
+                        .insert(index.child(x, y, z), [[[current; 2]; 2]; 2]);
+                    height -= 1;
+                }
+            }
+        }
+    }
+
+    /// Merge the branches containing the index, from the bottom up, whose
+    /// voxels have all converged to the same log-odds.
+    fn prune(&mut self, idx: &Index, log_odds: f32) {
+        // The root is always a branch.
+        for height in 1..self.height {
+            let index = idx.branch_at(height);
+            let leaf = LogNode::Leaf(log_odds);
+            if self.branches[&index] != [[[leaf; 2]; 2]; 2] {
+                return;
+            }
+            self.branches.remove(&index);
+            let (x, y, z) = idx.bit(height);
+            self.branches.get_mut(&idx.branch_at(height + 1)).unwrap()[z][y][x] = leaf;
+        }
+    }
+
+    /// Integrates a measurement of the voxel at the given index being
+    /// occupied.
+    pub fn integrate_hit(&mut self, idx: &Index) {
+        self.update(idx, self.params.hit);
+    }
+
+    /// Integrates a measurement of the voxel at the given index being free.
+    pub fn integrate_miss(&mut self, idx: &Index) {
+        self.update(idx, self.params.miss);
+    }
+
+    /// Integrates a sensor ray from `origin` that ended at an obstacle at
+    /// `hit`, in the space where the voxel at index `(x, y, z)` spans
+    /// `x..x + 1` (and so on) along each axis.
+    ///
+    /// Every voxel the ray passes through before the one containing `hit` is
+    /// updated as free, and that one as occupied. Parts of the ray outside the
+    /// map are ignored.
+    pub fn insert_ray(&mut self, origin: [f32; 3], hit: [f32; 3]) {
+        let width = self.width() as f32;
+        let end = hit.iter().all(|c| (0.0..width).contains(c)).then(|| {
+            let [x, y, z] = hit.map(|c| c as u32);
+            Index::new(x, y, z)
+        });
+        let dir = [0, 1, 2].map(|i| hit[i] - origin[i]);
+        let mut misses = Vec::new();
+        if let Some(ray) = Ray::new(origin, dir) {
+            let root = BranchIndex::root(self.height);
+            if let Some((enter, exit, axis)) = ray.intersect(root) {
+                let mut walk = VoxelWalk::new(&ray, root, enter, exit.min(1.0), axis);
+                while let Some((voxel, ..)) = walk.next() {
+                    let idx = Index::from(voxel.map(|c| c as u32));
+                    if Some(idx) != end {
+                        misses.push(idx);
+                    }
+                }
+            }
+        }
+        for idx in &misses {
+            self.integrate_miss(idx);
+        }
+        if let Some(end) = end {
+            self.integrate_hit(&end);
+        }
+    }
+
+    /// The voxels that are occupied, as a bitmap of the same width.
+    pub fn to_bitmap(&self) -> OctreeBitmap {
+        let mut bitmap = OctreeBitmap::with_height(self.height);
+        let mut stack = vec![BranchIndex::root(self.height)];
+        while let Some(index) = stack.pop() {
+            for i in 0..8 {
+                let (x, y, z) = (i & 1, (i >> 1) & 1, i >> 2);
+                let child = index.child(x, y, z);
+                match self.branches[&index][z][y][x] {
+                    LogNode::Branch => stack.push(child),
+                    LogNode::Leaf(log_odds) if log_odds > self.params.threshold => {
+                        bitmap.fill_node(child, true);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        bitmap
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rays_converge_and_prune() {
+        let mut map = LogOddsOctree::new(16);
+        let params = *map.params();
+        assert_eq!(map.log_odds(&Index::new(3, 3, 3)), None);
+
+        for _ in 0..10 {
+            for y in 0..4 {
+                for x in 0..4 {
+                    map.insert_ray(
+                        [x as f32 + 0.5, y as f32 + 0.5, 0.5],
+                        [x as f32 + 0.5, y as f32 + 0.5, 20.5],
+                    );
+                }
+            }
+        }
+        assert_eq!(map.log_odds(&Index::new(1, 2, 20)), Some(params.max));
+        assert!(map.is_occupied(&Index::new(1, 2, 20)));
+        assert_eq!(map.log_odds(&Index::new(1, 2, 7)), Some(params.min));
+        assert!(!map.is_occupied(&Index::new(1, 2, 7)));
+        assert!(map.probability(&Index::new(1, 2, 7)).unwrap() < 0.2);
+        assert_eq!(map.log_odds(&Index::new(5, 2, 7)), None);
+        // The free column is merged into a few nodes.
+        assert!(!map.branches.contains_key(&Index::new(0, 0, 0).branch_at(1)));
+
+        let bitmap = map.to_bitmap();
+        assert_eq!(bitmap.width(), map.width());
+        let occupied: Vec<Index> = bitmap.iter().collect();
+        assert_eq!(occupied.len(), 16);
+        assert!(occupied
+            .iter()
+            .all(|idx| idx.z == 20 && idx.x < 4 && idx.y < 4));
+
+        map.integrate_miss(&Index::new(1, 2, 20));
+        assert_eq!(
+            map.log_odds(&Index::new(1, 2, 20)),
+            Some(params.max + params.miss)
+        );
+    }
+}
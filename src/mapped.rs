@@ -0,0 +1,149 @@
+//! Bitmaps queried straight from memory-mapped files, for bitmaps too large
+//! to load into memory.
+//!
+//! The file starts with the magic bytes `OCTM`, a format version byte, the
+//! height of the root and two bytes of padding, followed by the descriptors
+//! of an [`Esvo`](crate::Esvo) as little-endian `u32`s. Only the pages of
+//! the file holding the descriptors that are visited are read, as they are
+//! needed, and the operating system evicts them again under memory pressure.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::esvo::esvo_get;
+use crate::{BinaryError, Index, OctreeBitmap, OutOfBounds};
+
+const MAGIC: &[u8; 4] = b"OCTM";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 8;
+
+/// A read-only bitmap backed by a memory-mapped file, as written by
+/// [`OctreeBitmap::write_mapped`].
+pub struct MappedOctreeBitmap {
+    map: Mmap,
+    height: u32,
+}
+
+impl MappedOctreeBitmap {
+    /// Maps the bitmap in the file at the given path.
+    ///
+    /// The file must not be modified while it is mapped. Only the header and
+    /// the length of the file are checked, so a corrupted file may give wrong
+    /// results, or panic when it is queried.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, BinaryError> {
+        let file = File::open(path)?;
+        // SAFETY: The map is only ever read, and the caller is required not
+        // to modify the file while it is mapped.
+        let map = unsafe { Mmap::map(&file)? };
+        let header = map
+            .get(..HEADER_LEN)
+            .ok_or(BinaryError::Invalid("missing header"))?;
+        if &header[..4] != MAGIC {
+            return Err(BinaryError::Invalid("missing magic bytes"));
+        }
+        if header[4] != VERSION {
+            return Err(BinaryError::UnsupportedVersion(header[4]));
+        }
+        let height = u32::from(header[5]);
+        if height == 0 || height > OctreeBitmap::MAX_WIDTH.trailing_zeros() + 1 {
+            return Err(BinaryError::Invalid("root height"));
+        }
+        let len = map.len() - HEADER_LEN;
+        if len == 0 || !len.is_multiple_of(4) {
+            return Err(BinaryError::Invalid("truncated descriptors"));
+        }
+        Ok(Self { map, height })
+    }
+
+    /// The width of the map. Index values in each dimension must be within the
+    /// range `0..map.width()`.
+    pub fn width(&self) -> u32 {
+        1 << self.height
+    }
+
+    fn descriptor(&self, position: usize) -> u32 {
+        let start = HEADER_LEN + 4 * position;
+        let bytes = self
+            .map
+            .get(start..start + 4)
+            .expect("descriptor out of range in mapped bitmap");
+        u32::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    /// Get the current value of the bit at the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is outside the bitmap.
+    pub fn get(&self, idx: &Index) -> bool {
+        if let Err(err) = self.check_bounds(idx) {
+            panic!("{}", err);
+        }
+        esvo_get(self.height, idx, |position| self.descriptor(position))
+    }
+
+    /// Get the current value of the bit at the given index, or an error if
+    /// the index is outside the bitmap.
+    pub fn try_get(&self, idx: &Index) -> Result<bool, OutOfBounds> {
+        self.check_bounds(idx)?;
+        Ok(self.get(idx))
+    }
+
+    fn check_bounds(&self, idx: &Index) -> Result<(), OutOfBounds> {
+        let width = self.width();
+        if idx.x < width && idx.y < width && idx.z < width {
+            Ok(())
+        } else {
+            Err(OutOfBounds { index: *idx, width })
+        }
+    }
+}
+
+impl OctreeBitmap {
+    /// Writes the bitmap in the format read by [`MappedOctreeBitmap::open`].
+    pub fn write_mapped<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut writer = io::BufWriter::new(writer);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION, self.height as u8, 0, 0])?;
+        for descriptor in self.to_esvo().descriptors() {
+            writer.write_all(&descriptor.to_le_bytes())?;
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mapped_matches_get() {
+        let mut bitmap = OctreeBitmap::new(16);
+        bitmap.fill_sphere([10.0, 12.0, 14.0], 9.0, true);
+        bitmap.set_many((0..32).map(|x| Index::new(x, 31 - x, x / 2)), true);
+        let path = std::env::temp_dir().join(format!("october-{}.octm", std::process::id()));
+        bitmap.write_mapped(File::create(&path).unwrap()).unwrap();
+        let mapped = MappedOctreeBitmap::open(&path).unwrap();
+        assert_eq!(mapped.width(), bitmap.width());
+        let width = bitmap.width();
+        for z in 0..width {
+            for y in 0..width {
+                for x in 0..width {
+                    let idx = Index::new(x, y, z);
+                    assert_eq!(mapped.get(&idx), bitmap.get(&idx));
+                }
+            }
+        }
+        assert!(mapped.try_get(&Index::new(width, 0, 0)).is_err());
+
+        std::fs::write(&path, b"OCTB\x01\x05").unwrap();
+        assert!(matches!(
+            MappedOctreeBitmap::open(&path),
+            Err(BinaryError::Invalid(_))
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,141 @@
+//! Surface area and topology of the set voxels.
+
+use std::collections::HashSet;
+
+use crate::quads::axis_of;
+use crate::{Index, OctreeBitmap};
+
+impl OctreeBitmap {
+    /// The number of faces between a set voxel and an unset voxel or the
+    /// outside of the bitmap, such as for measuring how compact a shape is.
+    ///
+    /// Faces are found node by node, as for
+    /// [`extract_quads`](Self::extract_quads), so the interiors of solid
+    /// regions and large empty regions are never visited voxel by voxel.
+    pub fn surface_area(&self) -> u64 {
+        self.exposed_planes()
+            .values()
+            .map(|cells| cells.len() as u64)
+            .sum()
+    }
+
+    /// The Euler characteristic of the set voxels, as a union of closed
+    /// unit cubes: the number of connected components, minus the number of
+    /// tunnels through them, plus the number of cavities inside them.
+    /// Voxels touching only at an edge or corner are connected.
+    ///
+    /// This is the sum over the corners of the voxels of a term that depends
+    /// only on the eight voxels around the corner, which is zero unless some
+    /// of them are set and some are not. Such corners are all on the exposed
+    /// faces of [`surface_area`](Self::surface_area), so only those are
+    /// visited.
+    pub fn euler_characteristic(&self) -> i64 {
+        let width = self.width();
+        let mut corners = HashSet::new();
+        for (&(face, plane), cells) in &self.exposed_planes() {
+            let axis = axis_of(face);
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            for &[a, b] in cells {
+                for (du, dv) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let mut corner = [0; 3];
+                    corner[axis] = plane;
+                    corner[u] = a + du;
+                    corner[v] = b + dv;
+                    corners.insert(corner);
+                }
+            }
+        }
+        let mut sum = 0;
+        for corner in corners {
+            // The voxels around the corner, by their Morton position among
+            // them, with those past the edges of the bitmap unset.
+            let set: [bool; 8] = std::array::from_fn(|i| {
+                let offset = [i & 1, (i >> 1) & 1, i >> 2];
+                let mut idx = [0; 3];
+                for j in 0..3 {
+                    match (corner[j] + offset[j] as u32).checked_sub(1) {
+                        Some(c) if c < width => idx[j] = c,
+                        _ => return false,
+                    }
+                }
+                self.get(&Index::from(idx))
+            });
+            sum += corner_term(set);
+        }
+        sum / 8
+    }
+}
+
+/// Eight times the share of the corner in the Euler characteristic: it is a
+/// vertex, an endpoint of half of each edge, a corner of a quarter of each
+/// face, and a corner of an eighth of each cube around it that is in the
+/// complex.
+fn corner_term(set: [bool; 8]) -> i64 {
+    let any = |fixed: &[(usize, usize)]| {
+        (0..8).any(|i| set[i] && fixed.iter().all(|&(axis, side)| (i >> axis) & 1 == side))
+    };
+    if !any(&[]) {
+        return 0;
+    }
+    let mut term = 8;
+    for axis in 0..3 {
+        // The edges from the corner along the axis.
+        for side in 0..2 {
+            if any(&[(axis, side)]) {
+                term -= 4;
+            }
+        }
+        // The faces in the plane through the corner across the axis.
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        for (u_side, v_side) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            if any(&[(u, u_side), (v, v_side)]) {
+                term += 2;
+            }
+        }
+    }
+    term - set.iter().filter(|&&set| set).count() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn area_and_euler_characteristic() {
+        let mut bitmap = OctreeBitmap::new(16);
+        assert_eq!(bitmap.surface_area(), 0);
+        assert_eq!(bitmap.euler_characteristic(), 0);
+
+        bitmap.set(&Index::new(3, 4, 5), true);
+        assert_eq!(bitmap.surface_area(), 6);
+        assert_eq!(bitmap.euler_characteristic(), 1);
+
+        // A voxel touching it at a corner, and a box on the edge of the
+        // bitmap.
+        bitmap.set(&Index::new(4, 5, 6), true);
+        assert_eq!(bitmap.surface_area(), 12);
+        assert_eq!(bitmap.euler_characteristic(), 1);
+        bitmap.fill_box([0, 0, 0], [4, 3, 2], true);
+        assert_eq!(bitmap.surface_area(), 12 + 2 * (12 + 8 + 6));
+        assert_eq!(bitmap.euler_characteristic(), 2);
+
+        // A torus.
+        let mut torus = OctreeBitmap::new(16);
+        torus.fill_box([10, 10, 10], [15, 15, 12], true);
+        torus.fill_box([12, 12, 10], [13, 13, 12], false);
+        assert_eq!(torus.euler_characteristic(), 0);
+        torus.fill_box([20, 20, 20], [23, 23, 23], true);
+        assert_eq!(torus.euler_characteristic(), 1);
+        // A hollow cube, with a cavity inside.
+        torus.set(&Index::new(21, 21, 21), false);
+        assert_eq!(torus.euler_characteristic(), 2);
+        assert_eq!(
+            torus.surface_area(),
+            torus
+                .extract_quads()
+                .iter()
+                .map(|quad| u64::from(quad.size[0] * quad.size[1]))
+                .sum::<u64>()
+        );
+    }
+}
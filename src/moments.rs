@@ -0,0 +1,143 @@
+//! Centroids and second moments of the set voxels, for mass properties.
+
+use crate::{Occupancy, OctreeBitmap};
+
+/// The volume, centroid, and second moments of the set voxels of a bitmap,
+/// as solid unit cubes where the voxel at index `(x, y, z)` spans `x..x + 1`
+/// (and so on) along each axis. Returned by [`OctreeBitmap::moments`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Moments {
+    /// The number of set voxels.
+    pub volume: u128,
+    /// The center of mass.
+    pub centroid: [f64; 3],
+    /// The second moments about the centroid: the integral of
+    /// `(p[i] - centroid[i]) * (p[j] - centroid[j])` over the set voxels,
+    /// indexed by `[i][j]`.
+    pub second: [[f64; 3]; 3],
+}
+
+impl Moments {
+    /// The inertia tensor about the centroid, for a mass of one per voxel.
+    /// Scale it by the mass of a voxel for other densities.
+    pub fn inertia_tensor(&self) -> [[f64; 3]; 3] {
+        let trace = self.second[0][0] + self.second[1][1] + self.second[2][2];
+        let mut inertia = self.second.map(|row| row.map(|c| -c));
+        for (i, row) in inertia.iter_mut().enumerate() {
+            row[i] += trace;
+        }
+        inertia
+    }
+}
+
+impl OctreeBitmap {
+    /// The center of mass of the set voxels, as for
+    /// [`moments`](Self::moments), or `None` if no voxels are set.
+    pub fn centroid(&self) -> Option<[f64; 3]> {
+        self.moments().map(|moments| moments.centroid)
+    }
+
+    /// The volume, centroid, and second moments of the set voxels, or `None`
+    /// if no voxels are set, from which physics systems can derive mass
+    /// properties.
+    ///
+    /// Full nodes contribute the moments of a solid cube in closed form, so
+    /// this takes time proportional to the number of branches rather than of
+    /// voxels. The second moments are summed about the centroid, found in a
+    /// first pass, so they stay precise far from the origin.
+    pub fn moments(&self) -> Option<Moments> {
+        // Calls `f` with the side and center of each full node.
+        let full_nodes = |f: &mut dyn FnMut(f64, [f64; 3])| {
+            self.visit_nodes(|base, height, occupancy| {
+                if occupancy == Occupancy::Full {
+                    let side = (1u64 << height) as f64;
+                    let center = [base.x, base.y, base.z].map(|c| f64::from(c) + side / 2.0);
+                    f(side, center);
+                }
+                occupancy == Occupancy::Mixed
+            });
+        };
+
+        let mut volume = 0u128;
+        let mut first = [0.0; 3];
+        full_nodes(&mut |side, center| {
+            let cube = side.powi(3);
+            volume += cube as u128;
+            for i in 0..3 {
+                first[i] += cube * center[i];
+            }
+        });
+        if volume == 0 {
+            return None;
+        }
+        let centroid = first.map(|c| c / volume as f64);
+
+        let mut second = [[0.0; 3]; 3];
+        full_nodes(&mut |side, center| {
+            // A cube of side `s` has `s^5 / 12` about its own center along
+            // each axis, moved to the centroid by the parallel axis theorem.
+            let cube = side.powi(3);
+            let offset = [0, 1, 2].map(|i| center[i] - centroid[i]);
+            for i in 0..3 {
+                for j in 0..3 {
+                    second[i][j] += cube * offset[i] * offset[j];
+                }
+                second[i][i] += cube * side * side / 12.0;
+            }
+        });
+        Some(Moments {
+            volume,
+            centroid,
+            second,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Index;
+
+    #[test]
+    fn moments_match_voxels() {
+        let mut bitmap = OctreeBitmap::new(16);
+        assert_eq!(bitmap.moments(), None);
+
+        bitmap.set(&Index::new(3, 4, 5), true);
+        let moments = bitmap.moments().unwrap();
+        assert_eq!(moments.centroid, [3.5, 4.5, 5.5]);
+        let cube = 1.0 / 6.0;
+        assert!((moments.inertia_tensor()[0][0] - cube).abs() < 1e-12);
+
+        bitmap.fill_box([8, 0, 0], [16, 8, 8], true);
+        bitmap.fill_sphere([20.0, 22.0, 9.0], 5.0, true);
+        bitmap.set(&Index::new(30, 1, 29), true);
+        let moments = bitmap.moments().unwrap();
+
+        // Summed voxel by voxel.
+        let voxels: Vec<[f64; 3]> = bitmap
+            .iter()
+            .map(|idx| [idx.x, idx.y, idx.z].map(|c| f64::from(c) + 0.5))
+            .collect();
+        assert_eq!(moments.volume, voxels.len() as u128);
+        let n = voxels.len() as f64;
+        let centroid = [0, 1, 2].map(|i| voxels.iter().map(|v| v[i]).sum::<f64>() / n);
+        for i in 0..3 {
+            assert!((moments.centroid[i] - centroid[i]).abs() < 1e-9);
+            for j in 0..3 {
+                let mut expected: f64 = voxels
+                    .iter()
+                    .map(|v| (v[i] - centroid[i]) * (v[j] - centroid[j]))
+                    .sum();
+                if i == j {
+                    expected += n / 12.0;
+                }
+                let actual = moments.second[i][j];
+                assert!((actual - expected).abs() < 1e-6 * expected.abs().max(1.0));
+            }
+        }
+        let inertia = moments.inertia_tensor();
+        assert_eq!(inertia[0][1], -moments.second[0][1]);
+        assert_eq!(inertia[2][2], moments.second[0][0] + moments.second[1][1]);
+    }
+}
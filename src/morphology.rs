@@ -0,0 +1,303 @@
+//! Morphological operations with voxel neighborhoods as structuring elements.
+
+use crate::quads::{axis_of, positive};
+use crate::{brick_mask, BlitMode, Index, Occupancy, OctreeBitmap, RawNode};
+
+/// Which of the voxels around a voxel count as its neighbors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Neighborhood {
+    /// The 6 voxels that share a face with it.
+    Six,
+    /// The 18 voxels that share a face or an edge with it.
+    Eighteen,
+    /// The 26 voxels that share a face, an edge or a corner with it.
+    TwentySix,
+}
+
+impl Neighborhood {
+    /// The offsets from a voxel to each of its neighbors, ordered by `z`,
+    /// then `y`, then `x`.
+    pub fn offsets(self) -> impl Iterator<Item = [i32; 3]> {
+        let max_axes = match self {
+            Self::Six => 1,
+            Self::Eighteen => 2,
+            Self::TwentySix => 3,
+        };
+        (0..27)
+            .map(|i| [i % 3 - 1, i / 3 % 3 - 1, i / 9 - 1])
+            .filter(move |offset| {
+                let axes = offset.iter().filter(|&&d| d != 0).count();
+                axes > 0 && axes <= max_axes
+            })
+    }
+}
+
+impl OctreeBitmap {
+    /// The full octants of the bitmap, each as the box of indexes from its
+    /// low corner to its high corner (exclusive).
+    fn full_boxes(&self) -> Vec<([i64; 3], [i64; 3])> {
+        let mut boxes = Vec::new();
+        self.visit_nodes(|base, height, occupancy| {
+            if occupancy == Occupancy::Full {
+                let low = [base.x, base.y, base.z].map(i64::from);
+                boxes.push((low, low.map(|c| c + (1 << height))));
+            }
+            occupancy == Occupancy::Mixed
+        });
+        boxes
+    }
+
+    /// A copy of the bitmap with every voxel flipped.
+    pub(crate) fn complement(&self) -> OctreeBitmap {
+        let mut complement = self.clone();
+        for (index, branch) in &mut complement.branches {
+            for child in branch.children.iter_mut().flatten().flatten() {
+                *child = match *child {
+                    RawNode::False => RawNode::True,
+                    RawNode::True => RawNode::False,
+                    RawNode::Branch => RawNode::Branch,
+                    RawNode::Brick(bits) => RawNode::Brick(!bits & brick_mask(index.height - 1)),
+                };
+            }
+        }
+        complement
+    }
+
+    /// A copy of the bitmap with every voxel within `radius` steps of a set
+    /// voxel also set, where each step moves to a voxel in the given
+    /// neighborhood. This pads the set voxels by `radius`, for example to
+    /// give obstacles clearance for collision.
+    ///
+    /// The bitmap is grown by whole uniform octants: each full octant is
+    /// expanded into the boxes it covers after one step (or, for the
+    /// 26-neighborhood, after all `radius` steps at once), and those boxes
+    /// are filled, so the work depends on the number of octants rather than
+    /// the number of voxels. Voxels outside the bitmap are ignored.
+    pub fn dilate(&self, radius: u32, neighborhood: Neighborhood) -> OctreeBitmap {
+        let mut dilated = self.clone();
+        // The axes along which a box grows in one step. Every other voxel one
+        // step away from the box is covered by one of these.
+        let (steps, grow, axes): (u32, i64, &[[i64; 3]]) = match neighborhood {
+            Neighborhood::Six => (radius, 1, &[[1, 0, 0], [0, 1, 0], [0, 0, 1]]),
+            Neighborhood::Eighteen => (radius, 1, &[[1, 1, 0], [0, 1, 1], [1, 0, 1]]),
+            // Growing a box into a cube repeatedly is the same as growing it
+            // once by the total distance.
+            Neighborhood::TwentySix if radius > 0 => (1, radius.into(), &[[1, 1, 1]]),
+            Neighborhood::TwentySix => (0, 0, &[]),
+        };
+        for _ in 0..steps {
+            for (low, high) in dilated.full_boxes() {
+                for axes in axes {
+                    dilated.fill_box(
+                        [0, 1, 2].map(|i| low[i] - axes[i] * grow),
+                        [0, 1, 2].map(|i| high[i] + axes[i] * grow),
+                        true,
+                    );
+                }
+            }
+        }
+        dilated
+    }
+
+    /// A copy of the bitmap with only the set voxels whose every voxel
+    /// within `radius` steps is also set, the dual of
+    /// [`dilate`](Self::dilate). This shrinks the set voxels by `radius`.
+    ///
+    /// Voxels outside the bitmap count as set, so nothing is eroded away
+    /// from the bounds of the bitmap.
+    pub fn erode(&self, radius: u32, neighborhood: Neighborhood) -> OctreeBitmap {
+        self.complement().dilate(radius, neighborhood).complement()
+    }
+
+    /// Erodes and then dilates the bitmap, removing features thinner than
+    /// `radius` (such as noise in scanned data) while keeping the shape of
+    /// larger ones.
+    pub fn opening(&self, radius: u32, neighborhood: Neighborhood) -> OctreeBitmap {
+        self.erode(radius, neighborhood)
+            .dilate(radius, neighborhood)
+    }
+
+    /// Dilates and then erodes the bitmap, filling holes and gaps narrower
+    /// than `radius` while keeping the shape of larger features.
+    pub fn closing(&self, radius: u32, neighborhood: Neighborhood) -> OctreeBitmap {
+        self.dilate(radius, neighborhood)
+            .erode(radius, neighborhood)
+    }
+
+    /// A copy of the bitmap with only the set voxels that have at least one
+    /// unset voxel among their 6 face neighbors, such as to hollow out a
+    /// solid or to render only the voxels that can be seen.
+    ///
+    /// Unlike [`erode`](Self::erode), the outside of the bitmap counts as
+    /// unset, as for [`extract_quads`](Self::extract_quads). The faces of each
+    /// full node are compared against the nodes beside them, which are split
+    /// only where they are mixed, so the interiors of solid regions are never
+    /// visited voxel by voxel.
+    pub fn surface(&self) -> OctreeBitmap {
+        let mut voxels = Vec::new();
+        for ((face, plane), cells) in self.exposed_planes() {
+            let axis = axis_of(face);
+            let depth = if positive(face) { plane - 1 } else { plane };
+            voxels.extend(cells.into_iter().map(|[u, v]| {
+                let mut coords = [0; 3];
+                coords[axis] = depth;
+                coords[(axis + 1) % 3] = u;
+                coords[(axis + 2) % 3] = v;
+                Index::from(coords)
+            }));
+        }
+        let mut surface = self.with_height_like(self.height);
+        surface.set_many(voxels, true);
+        surface
+    }
+
+    /// A copy of the bitmap with only the set voxels within `thickness` steps
+    /// of an unset voxel or the outside of the bitmap, where each step moves
+    /// to a face neighbor. This leaves a shell `thickness` voxels thick,
+    /// such as to save memory and triangles for models that are only
+    /// displayed.
+    ///
+    /// A thickness of one is the [`surface`](Self::surface), and a thickness
+    /// of zero leaves nothing.
+    pub fn hollow(&self, thickness: u32) -> OctreeBitmap {
+        if thickness == 0 {
+            return self.with_height_like(self.height);
+        }
+        // Every set voxel within `thickness` steps of an unset one is within
+        // one step fewer of a surface voxel.
+        let shell = self.surface().dilate(thickness - 1, Neighborhood::Six);
+        let mut hollow = self.clone();
+        hollow.blit(&shell.complement(), Index::new(0, 0, 0), BlitMode::Subtract);
+        hollow
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Index;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn dilate_matches_distance() {
+        let mut bitmap = OctreeBitmap::new(16);
+        let width = bitmap.width();
+        bitmap.fill_sphere([20.0, 20.0, 20.0], 5.0, true);
+        bitmap.set_many([Index::new(0, 3, 1), Index::new(9, 30, 4)], true);
+        let set: Vec<Index> = bitmap.iter().collect();
+
+        for neighborhood in [
+            Neighborhood::Six,
+            Neighborhood::Eighteen,
+            Neighborhood::TwentySix,
+        ] {
+            let radius = 2;
+            let mut actual: Vec<Index> = bitmap.dilate(radius, neighborhood).iter().collect();
+            actual.sort_by_key(|idx| (idx.z, idx.y, idx.x));
+            let r = radius as i64;
+            let mut expected = BTreeSet::new();
+            for idx in &set {
+                for (dx, dy, dz) in (-r..=r).flat_map(|dz| {
+                    (-r..=r).flat_map(move |dy| (-r..=r).map(move |dx| (dx, dy, dz)))
+                }) {
+                    let d = [dx, dy, dz].map(i64::abs);
+                    let (sum, max) = (d.iter().sum::<i64>(), *d.iter().max().unwrap());
+                    let near = match neighborhood {
+                        Neighborhood::Six => sum <= r,
+                        Neighborhood::Eighteen => sum <= 2 * r,
+                        Neighborhood::TwentySix => max <= r,
+                    };
+                    let [x, y, z] =
+                        [(idx.x, dx), (idx.y, dy), (idx.z, dz)].map(|(c, d)| c as i64 + d);
+                    let inside = [x, y, z].iter().all(|&c| (0..width as i64).contains(&c));
+                    if near && inside {
+                        expected.insert((z, y, x));
+                    }
+                }
+            }
+            let expected: Vec<Index> = expected
+                .into_iter()
+                .map(|(z, y, x)| Index::new(x as u32, y as u32, z as u32))
+                .collect();
+            assert_eq!(actual, expected, "{:?}", neighborhood);
+        }
+    }
+
+    #[test]
+    fn surface_is_shell() {
+        let mut bitmap = OctreeBitmap::new(16);
+        bitmap.fill_sphere([12.0, 12.0, 12.0], 7.0, true);
+        bitmap.fill_sphere([0.0, 0.0, 0.0], 3.0, true);
+        bitmap.set(&Index::new(12, 12, 12), false);
+
+        let surface = bitmap.surface();
+        let width = bitmap.width() as i64;
+        let exposed = |idx: &Index| {
+            let [x, y, z] = [idx.x, idx.y, idx.z].map(|c| c as i64);
+            [(1, 0, 0), (0, 1, 0), (0, 0, 1)]
+                .iter()
+                .flat_map(|&(dx, dy, dz)| [(x + dx, y + dy, z + dz), (x - dx, y - dy, z - dz)])
+                .any(|(x, y, z)| {
+                    ![x, y, z].iter().all(|&c| (0..width).contains(&c))
+                        || !bitmap.get(&Index::new(x as u32, y as u32, z as u32))
+                })
+        };
+        let expected: Vec<Index> = bitmap.iter().filter(exposed).collect();
+        assert_eq!(surface.iter().collect::<Vec<_>>(), expected);
+        // The voxels around the hole in the middle are exposed, and the
+        // corner sphere is exposed to the bounds.
+        assert!(surface.get(&Index::new(12, 12, 13)));
+        assert!(!surface.get(&Index::new(12, 12, 14)));
+        assert!(surface.get(&Index::new(0, 0, 0)));
+    }
+
+    #[test]
+    fn hollow_cube() {
+        let mut bitmap = OctreeBitmap::new(16);
+        bitmap.fill_box([2, 3, 4], [12, 12, 14], true);
+        let depth = |idx: &Index| {
+            [(idx.x, 2, 12), (idx.y, 3, 12), (idx.z, 4, 14)]
+                .iter()
+                .map(|&(c, low, high)| (c - low).min(high - 1 - c) + 1)
+                .min()
+                .unwrap()
+        };
+        let hollow = bitmap.hollow(2);
+        let expected: Vec<Index> = bitmap.iter().filter(|idx| depth(idx) <= 2).collect();
+        assert_eq!(hollow.iter().collect::<Vec<_>>(), expected);
+        assert!(bitmap.hollow(1) == bitmap.surface());
+        assert!(bitmap.hollow(0).is_empty());
+        assert!(bitmap.hollow(10) == bitmap);
+    }
+
+    #[test]
+    fn erode_and_open() {
+        let mut bitmap = OctreeBitmap::new(16);
+        bitmap.fill_sphere([12.0, 12.0, 12.0], 7.0, true);
+        bitmap.fill_sphere([0.0, 0.0, 0.0], 3.0, true);
+        bitmap.set(&Index::new(28, 3, 5), true);
+        bitmap.set(&Index::new(12, 12, 12), false);
+
+        let radius = 1;
+        let eroded = bitmap.erode(radius, Neighborhood::Six);
+        let width = bitmap.width();
+        let kept = |idx: &Index| {
+            let [x, y, z] = [idx.x, idx.y, idx.z].map(|c| c as i64);
+            [(1, 0, 0), (0, 1, 0), (0, 0, 1)]
+                .iter()
+                .flat_map(|&(dx, dy, dz)| [(x + dx, y + dy, z + dz), (x - dx, y - dy, z - dz)])
+                .filter(|&(x, y, z)| [x, y, z].iter().all(|&c| (0..width as i64).contains(&c)))
+                .all(|(x, y, z)| bitmap.get(&Index::new(x as u32, y as u32, z as u32)))
+        };
+        let expected: Vec<Index> = bitmap.iter().filter(kept).collect();
+        assert_eq!(eroded.iter().collect::<Vec<_>>(), expected);
+        // The corner sphere touches the bounds, which count as set.
+        assert!(eroded.get(&Index::new(0, 0, 0)));
+
+        let opened = bitmap.opening(radius, Neighborhood::Six);
+        assert!(!opened.get(&Index::new(28, 3, 5)));
+        assert!(opened.get(&Index::new(12, 12, 9)));
+        let closed = bitmap.closing(radius, Neighborhood::TwentySix);
+        assert!(closed.get(&Index::new(12, 12, 12)));
+    }
+}
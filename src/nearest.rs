@@ -0,0 +1,194 @@
+//! Nearest-neighbor queries by best-first search of the tree.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::iter::FusedIterator;
+
+use crate::{BranchIndex, Index, OctreeBitmap, View};
+
+/// A node waiting to be visited, ordered by its distance from the query
+/// point.
+struct Candidate {
+    /// The squared distance from the query point to the nearest voxel of the
+    /// node.
+    dist: u64,
+    node: BranchIndex,
+    view: View,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.dist, self.node).cmp(&(other.dist, other.node))
+    }
+}
+
+/// An iterator over the set voxels of a bitmap in order of nondecreasing
+/// distance from a point, as returned by [`OctreeBitmap::iter_by_distance`].
+pub struct NearestIter<'a> {
+    bitmap: &'a OctreeBitmap,
+    point: [i64; 3],
+    queue: BinaryHeap<Reverse<Candidate>>,
+}
+
+impl<'a> NearestIter<'a> {
+    pub(crate) fn new(bitmap: &'a OctreeBitmap, point: Index) -> Self {
+        let mut nearest = Self {
+            bitmap,
+            point: [point.x, point.y, point.z].map(i64::from),
+            queue: BinaryHeap::new(),
+        };
+        nearest.push(BranchIndex::root(bitmap.height), View::root(bitmap));
+        nearest
+    }
+
+    fn push(&mut self, node: BranchIndex, view: View) {
+        if let View::Uniform(false) = view {
+            return;
+        }
+        let low = [node.base.x, node.base.y, node.base.z].map(i64::from);
+        let side = 1 << node.height;
+        let dist = (0..3)
+            .map(|i| {
+                let nearest = self.point[i].clamp(low[i], low[i] + side - 1);
+                self.point[i].abs_diff(nearest).pow(2)
+            })
+            .sum();
+        self.queue.push(Reverse(Candidate { dist, node, view }));
+    }
+}
+
+impl Iterator for NearestIter<'_> {
+    type Item = (Index, u64);
+
+    /// The next nearest set voxel, and its squared distance from the point.
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(Reverse(Candidate { dist, node, view })) = self.queue.pop() {
+            if node.height == 0 {
+                return Some((node.base, dist));
+            }
+            for i in 0..8 {
+                let child = node.child(i & 1, (i >> 1) & 1, i >> 2);
+                self.push(child, view.child(self.bitmap, node, i));
+            }
+        }
+        None
+    }
+}
+
+impl FusedIterator for NearestIter<'_> {}
+
+impl OctreeBitmap {
+    /// An iterator over the set voxels in order of nondecreasing Euclidean
+    /// distance from `from`, each with its squared distance, such as to
+    /// expand outward from a point until enough voxels are found. Voxels at
+    /// the same distance are yielded in no particular order.
+    ///
+    /// Nodes are kept in a heap by the distance to their bounds and only split
+    /// when they are the nearest, so each voxel costs a few heap operations,
+    /// and nodes beyond the last voxel taken are never descended into.
+    /// `from` may be outside the bitmap.
+    pub fn iter_by_distance(&self, from: Index) -> NearestIter<'_> {
+        NearestIter::new(self, from)
+    }
+
+    /// The set voxel nearest to `point`, by Euclidean distance between
+    /// indexes, or `None` if no voxels are set. Ties are broken arbitrarily.
+    ///
+    /// Nodes are searched nearest first by the distance to their bounds, so
+    /// empty regions and nodes farther away than the answer are never
+    /// descended into. `point` may be outside the bitmap.
+    pub fn nearest_set(&self, point: Index) -> Option<Index> {
+        NearestIter::new(self, point).next().map(|(idx, _)| idx)
+    }
+
+    /// The `k` set voxels nearest to `point`, nearest first, as for
+    /// [`nearest_set`](Self::nearest_set). Fewer are returned if fewer than
+    /// `k` voxels are set.
+    ///
+    /// Full nodes are only split as far as needed to find the nearest of their
+    /// voxels, so this is cheap even when `point` is beside a large solid
+    /// region.
+    pub fn k_nearest(&self, point: Index, k: usize) -> Vec<Index> {
+        NearestIter::new(self, point)
+            .take(k)
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearest_set() {
+        let mut bitmap = OctreeBitmap::new(16);
+        assert_eq!(bitmap.nearest_set(Index::new(3, 4, 5)), None);
+
+        bitmap.fill_sphere([20.0, 20.0, 20.0], 4.0, true);
+        bitmap.set_many([Index::new(2, 9, 4), Index::new(7, 1, 30)], true);
+        let set: Vec<Index> = bitmap.iter().collect();
+        let squared = |a: Index, b: Index| {
+            [(a.x, b.x), (a.y, b.y), (a.z, b.z)]
+                .iter()
+                .map(|&(a, b)| u64::from(a.abs_diff(b)).pow(2))
+                .sum::<u64>()
+        };
+        for point in [
+            Index::new(0, 0, 0),
+            Index::new(20, 20, 20),
+            Index::new(4, 6, 25),
+            Index::new(40, 2, 1),
+        ] {
+            let nearest = bitmap.nearest_set(point).unwrap();
+            let best = set.iter().map(|&idx| squared(idx, point)).min().unwrap();
+            assert_eq!(squared(nearest, point), best, "{:?}", point);
+            assert!(bitmap.get(&nearest));
+        }
+    }
+
+    #[test]
+    fn k_nearest() {
+        let mut bitmap = OctreeBitmap::new(16);
+        bitmap.fill_sphere([10.0, 12.0, 9.0], 3.0, true);
+        bitmap.set_many([Index::new(0, 0, 0), Index::new(31, 2, 17)], true);
+        let point = Index::new(4, 20, 3);
+        let squared = |a: &Index| {
+            [(a.x, point.x), (a.y, point.y), (a.z, point.z)]
+                .iter()
+                .map(|&(a, b)| u64::from(a.abs_diff(b)).pow(2))
+                .sum::<u64>()
+        };
+        let mut distances: Vec<u64> = bitmap.iter().map(|idx| squared(&idx)).collect();
+        distances.sort_unstable();
+
+        let nearest = bitmap.k_nearest(point, 10);
+        assert_eq!(
+            nearest.iter().map(squared).collect::<Vec<_>>(),
+            distances[..10]
+        );
+        assert_eq!(bitmap.k_nearest(point, 1000).len(), distances.len());
+        assert!(bitmap.k_nearest(point, 0).is_empty());
+
+        let all: Vec<(Index, u64)> = bitmap.iter_by_distance(point).collect();
+        assert_eq!(
+            all.iter().map(|&(_, dist)| dist).collect::<Vec<_>>(),
+            distances
+        );
+        assert!(all.iter().all(|(idx, dist)| squared(idx) == *dist));
+    }
+}
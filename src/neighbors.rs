@@ -0,0 +1,96 @@
+//! Queries of the voxels around a voxel.
+
+use crate::{BranchIndex, Index, Neighborhood, OctreeBitmap, View};
+
+impl OctreeBitmap {
+    /// Get the values of the neighbors of the given index, in the order of
+    /// [`Neighborhood::offsets`]. Neighbors outside the bitmap are `false`.
+    ///
+    /// The tree is descended once to the smallest node containing all of the
+    /// neighbors, and each neighbor is looked up from there, so this is much
+    /// cheaper than calling [`get`](Self::get) for each of them.
+    pub fn neighbors(&self, idx: &Index, connectivity: Neighborhood) -> Vec<bool> {
+        let width = i64::from(self.width());
+        let center = [idx.x, idx.y, idx.z].map(i64::from);
+        let low = center.map(|c| (c - 1).max(0));
+        let high = center.map(|c| (c + 1).min(width - 1));
+
+        let mut node = BranchIndex::root(self.height);
+        let mut view = View::root(self);
+        while node.height > 0 && !matches!(view, View::Uniform(_)) {
+            let bits = low.map(|c| (c >> (node.height - 1)) as usize & 1);
+            if high.map(|c| (c >> (node.height - 1)) as usize & 1) != bits {
+                break;
+            }
+            view = view.child(self, node, bits[0] | bits[1] << 1 | bits[2] << 2);
+            node = node.child(bits[0], bits[1], bits[2]);
+        }
+
+        connectivity
+            .offsets()
+            .map(|offset| {
+                let neighbor = [0, 1, 2].map(|i| center[i] + i64::from(offset[i]));
+                if neighbor.iter().any(|c| !(0..width).contains(c)) {
+                    return false;
+                }
+                let (mut node, mut view) = (node, view);
+                loop {
+                    match view {
+                        View::Uniform(value) => return value,
+                        _ => {
+                            let bits = neighbor.map(|c| (c >> (node.height - 1)) as usize & 1);
+                            view = view.child(self, node, bits[0] | bits[1] << 1 | bits[2] << 2);
+                            node = node.child(bits[0], bits[1], bits[2]);
+                        }
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn neighbors_match_get() {
+        let mut bitmap = OctreeBitmap::new(16);
+        let width = bitmap.width();
+        bitmap.fill_sphere([14.0, 13.0, 16.0], 6.0, true);
+        bitmap.set_many([Index::new(0, 1, 1), Index::new(1, 0, 0)], true);
+
+        for idx in [
+            Index::new(0, 0, 0),
+            Index::new(15, 15, 15),
+            Index::new(16, 16, 16),
+            Index::new(9, 13, 16),
+            Index::new(width - 1, 4, 7),
+        ] {
+            for connectivity in [
+                Neighborhood::Six,
+                Neighborhood::Eighteen,
+                Neighborhood::TwentySix,
+            ] {
+                let expected: Vec<bool> = connectivity
+                    .offsets()
+                    .map(|offset| {
+                        let neighbor = [idx.x, idx.y, idx.z]
+                            .iter()
+                            .zip(offset)
+                            .map(|(&c, d)| c as i64 + d as i64)
+                            .collect::<Vec<_>>();
+                        neighbor.iter().all(|&c| (0..width as i64).contains(&c))
+                            && bitmap.get(&Index::new(
+                                neighbor[0] as u32,
+                                neighbor[1] as u32,
+                                neighbor[2] as u32,
+                            ))
+                    })
+                    .collect();
+                assert_eq!(bitmap.neighbors(&idx, connectivity), expected);
+            }
+        }
+        assert_eq!(Neighborhood::Eighteen.offsets().count(), 18);
+    }
+}
@@ -0,0 +1,79 @@
+//! Estimation of surface normals from the occupancy around each voxel.
+
+use std::collections::HashMap;
+
+use crate::{Index, OctreeBitmap};
+
+impl OctreeBitmap {
+    /// An approximate outward unit normal for each voxel of the
+    /// [`surface`](Self::surface), such as for shading or for orienting
+    /// decals placed on it.
+    ///
+    /// The normal points against the gradient of occupancy: away from the
+    /// average position of the set voxels within `radius` of the voxel. A
+    /// larger radius gives smoother normals across the steps of a voxelized
+    /// slope. The set voxels are found with
+    /// [`iter_in_box`](Self::iter_in_box), so empty space around the voxel is
+    /// skipped whole. The outside of the bitmap counts as unset. Voxels whose
+    /// surroundings are symmetric, such as a lone voxel, get a zero vector.
+    pub fn estimate_normals(&self, radius: u32) -> HashMap<Index, [f32; 3]> {
+        let radius_squared = u64::from(radius).pow(2);
+        let mut normals = HashMap::new();
+        for idx in self.surface().iter() {
+            let min = Index::new(
+                idx.x.saturating_sub(radius),
+                idx.y.saturating_sub(radius),
+                idx.z.saturating_sub(radius),
+            );
+            let max = Index::new(
+                idx.x.saturating_add(radius + 1),
+                idx.y.saturating_add(radius + 1),
+                idx.z.saturating_add(radius + 1),
+            );
+            let mut sum = [0i64; 3];
+            for other in self.iter_in_box(min, max) {
+                let offset = [(idx.x, other.x), (idx.y, other.y), (idx.z, other.z)]
+                    .map(|(a, b)| i64::from(b) - i64::from(a));
+                let distance: u64 = offset.iter().map(|d| d.unsigned_abs().pow(2)).sum();
+                if distance <= radius_squared {
+                    for i in 0..3 {
+                        sum[i] -= offset[i];
+                    }
+                }
+            }
+            let length = sum.iter().map(|&c| (c * c) as f64).sum::<f64>().sqrt();
+            let normal = match length > 0.0 {
+                true => sum.map(|c| (c as f64 / length) as f32),
+                false => [0.0; 3],
+            };
+            normals.insert(idx, normal);
+        }
+        normals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sphere_normals_point_outward() {
+        let mut bitmap = OctreeBitmap::new(32);
+        let center = [20.0, 20.0, 20.0];
+        bitmap.fill_sphere(center, 12.0, true);
+        bitmap.set(&Index::new(2, 2, 2), true);
+        let normals = bitmap.estimate_normals(3);
+        assert_eq!(normals.len(), bitmap.surface().iter().count());
+        assert_eq!(normals[&Index::new(2, 2, 2)], [0.0; 3]);
+        for (idx, normal) in &normals {
+            if *idx == Index::new(2, 2, 2) {
+                continue;
+            }
+            let radial = [idx.x, idx.y, idx.z].map(|c| c as f32 + 0.5);
+            let radial = [0, 1, 2].map(|i| radial[i] - center[i]);
+            let length = radial.iter().map(|c| c * c).sum::<f32>().sqrt();
+            let cos: f32 = (0..3).map(|i| normal[i] * radial[i] / length).sum();
+            assert!(cos > 0.9, "{:?} {:?} {}", idx, normal, cos);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! Export to the Wavefront OBJ format.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::{Face, OctreeBitmap};
+
+/// The faces in the order their normals are written. OBJ numbers them from 1,
+/// so the normal of a face is its position here plus one.
+const NORMALS: [Face; 6] = [
+    Face::NegX,
+    Face::PosX,
+    Face::NegY,
+    Face::PosY,
+    Face::NegZ,
+    Face::PosZ,
+];
+
+impl OctreeBitmap {
+    /// Writes the boundary between set and unset voxels as a Wavefront OBJ
+    /// model, such as to inspect the bitmap in any 3D viewer.
+    ///
+    /// The faces are the rectangles of [`extract_quads`](Self::extract_quads),
+    /// each written as one quadrilateral with its normal, in model space where
+    /// the voxel at index `(x, y, z)` spans `x..x + 1` (and so on) along each
+    /// axis. Corners shared between rectangles are written once.
+    pub fn export_obj<W: Write>(&self, writer: W) -> io::Result<()> {
+        trace_span!("export_obj");
+        let quads = self.extract_quads();
+        let mut writer = io::BufWriter::new(writer);
+        writeln!(writer, "# october")?;
+        for face in NORMALS {
+            let [x, y, z] = face.normal();
+            writeln!(writer, "vn {} {} {}", x, y, z)?;
+        }
+
+        let mut vertices: HashMap<[u32; 3], usize> = HashMap::new();
+        for quad in &quads {
+            let normal = NORMALS.iter().position(|&f| f == quad.face).unwrap() + 1;
+            let mut face = [0; 4];
+            for (corner, vertex) in quad.corners().into_iter().zip(&mut face) {
+                let next = vertices.len() + 1;
+                *vertex = *vertices.entry(corner).or_insert_with(|| next);
+                if *vertex == next {
+                    let [x, y, z] = corner;
+                    writeln!(writer, "v {} {} {}", x, y, z)?;
+                }
+            }
+            write!(writer, "f")?;
+            for vertex in face {
+                write!(writer, " {}//{}", vertex, normal)?;
+            }
+            writeln!(writer)?;
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Index;
+
+    #[test]
+    fn cube_export() {
+        let mut octree = OctreeBitmap::new(8);
+        octree.fill_box([2, 2, 2], [4, 5, 6], true);
+        let mut obj = Vec::new();
+        octree.export_obj(&mut obj).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        let count = |prefix: &str| obj.lines().filter(|l| l.starts_with(prefix)).count();
+        assert_eq!((count("v "), count("vn "), count("f ")), (8, 6, 6));
+        assert!(obj.lines().any(|l| l == "v 4 5 6"));
+
+        octree.set(&Index::new(7, 7, 7), true);
+        let mut obj = Vec::new();
+        octree.export_obj(&mut obj).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        let faces: Vec<&str> = obj.lines().filter(|l| l.starts_with("f ")).collect();
+        assert_eq!(faces.len(), 12);
+        let vertices = obj.lines().filter(|l| l.starts_with("v ")).count();
+        for face in faces {
+            for vertex in face.split(' ').skip(1) {
+                let index: usize = vertex.split("//").next().unwrap().parse().unwrap();
+                assert!((1..=vertices).contains(&index));
+            }
+        }
+    }
+}
@@ -0,0 +1,203 @@
+//! Splitting a bitmap into its top-level octants, and merging them back.
+
+use crate::{Branch, BranchIndex, Index, OctreeBitmap, RawNode};
+
+/// The offset of the octant at the given Morton position within a root of the
+/// given height.
+fn octant_base(octant: usize, height: u32) -> Index {
+    let half = height - 1;
+    Index::new(
+        ((octant & 1) as u32) << half,
+        (((octant >> 1) & 1) as u32) << half,
+        ((octant >> 2) as u32) << half,
+    )
+}
+
+fn octant_position(octant: usize) -> (usize, usize, usize) {
+    (octant & 1, (octant >> 1) & 1, octant >> 2)
+}
+
+impl OctreeBitmap {
+    /// Splits the bitmap into its eight top-level octants, in Morton order,
+    /// each as a bitmap of half the width with indexes relative to the
+    /// octant.
+    ///
+    /// This is useful for distributing work on a large bitmap across threads
+    /// or machines. Branches are moved into the octant that contains them
+    /// rather than rebuilt, except in small trees where the octants have
+    /// different brick sizes. The undo history and changed regions are
+    /// discarded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bitmap is narrower than 4, the smallest width that can
+    /// be split.
+    pub fn split(mut self) -> [OctreeBitmap; 8] {
+        assert!(self.height >= 2, "bitmap is too small to split");
+        let half = self.height - 1;
+        let root = self
+            .branches
+            .remove(&BranchIndex::root(self.height))
+            .unwrap();
+        let mut octants: [OctreeBitmap; 8] = std::array::from_fn(|_| self.with_height_like(half));
+
+        if half > self.leaf_height {
+            for (index, branch) in self.branches.drain() {
+                let (x, y, z) = index.base.bit(half);
+                let octant = x | y << 1 | z << 2;
+                let mask = (1 << half) - 1;
+                let index = BranchIndex {
+                    base: Index::new(
+                        index.base.x & mask,
+                        index.base.y & mask,
+                        index.base.z & mask,
+                    ),
+                    height: index.height,
+                };
+                octants[octant].branches.insert(index, branch);
+            }
+            for (octant, bitmap) in octants.iter_mut().enumerate() {
+                let (x, y, z) = octant_position(octant);
+                if let node @ (RawNode::False | RawNode::True) = root.children[z][y][x] {
+                    bitmap
+                        .branches
+                        .get_mut(&BranchIndex::root(half))
+                        .unwrap()
+                        .children = [[[node; 2]; 2]; 2];
+                }
+            }
+        } else {
+            // The octants have smaller bricks than this tree, so rebuild them.
+            let children = root.children;
+            self.branches.insert(BranchIndex::root(self.height), root);
+            let mask = (1 << half) - 1;
+            for (octant, bitmap) in octants.iter_mut().enumerate() {
+                let (x, y, z) = octant_position(octant);
+                let node = BranchIndex::root(self.height).child(x, y, z);
+                bitmap.set_many(
+                    crate::Iter::node(&self, node, children[z][y][x])
+                        .map(|idx| Index::new(idx.x & mask, idx.y & mask, idx.z & mask)),
+                    true,
+                );
+            }
+        }
+        octants
+    }
+
+    /// Assembles eight bitmaps of equal width, in Morton order, into the
+    /// octants of a bitmap of twice the width. The inverse of
+    /// [`split`](Self::split), such as to recombine the results of work
+    /// distributed across threads or machines.
+    ///
+    /// As with splitting, branches are moved rather than rebuilt, except in
+    /// small trees. The undo histories and changed regions of the octants are
+    /// discarded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the octants differ in width or [leaf size](Self::leaf_size),
+    /// or if twice their width is greater than that of a bitmap created with
+    /// [`MAX_WIDTH`](Self::MAX_WIDTH), which is rounded up to `1 << 31`.
+    pub fn from_octants(octants: [OctreeBitmap; 8]) -> Self {
+        let half = octants[0].height;
+        assert!(
+            octants.iter().all(|octant| octant.height == half),
+            "octants must all have the same width"
+        );
+        let leaf_height = octants[0].leaf_height;
+        assert!(
+            octants
+                .iter()
+                .all(|octant| octant.leaf_height == leaf_height),
+            "octants must all have the same leaf size"
+        );
+        let height = half + 1;
+        assert!(
+            height < u32::BITS,
+            "merged width {} is greater than the maximum of {}",
+            1u64 << height,
+            1u64 << (u32::BITS - 1)
+        );
+        let mut bitmap = octants[0].with_height_like(height);
+
+        if half > leaf_height {
+            let mut children = [[[RawNode::False; 2]; 2]; 2];
+            for (octant, mut source) in octants.into_iter().enumerate() {
+                let base = octant_base(octant, height);
+                let (x, y, z) = octant_position(octant);
+                let root = BranchIndex::root(half);
+                let root_children = source.branches[&root].children;
+                children[z][y][x] = if root_children == [[[RawNode::False; 2]; 2]; 2] {
+                    RawNode::False
+                } else if root_children == [[[RawNode::True; 2]; 2]; 2] {
+                    RawNode::True
+                } else {
+                    RawNode::Branch
+                };
+                if children[z][y][x] != RawNode::Branch {
+                    continue;
+                }
+                for (index, branch) in source.branches.drain() {
+                    let index = BranchIndex {
+                        base: Index::new(
+                            index.base.x | base.x,
+                            index.base.y | base.y,
+                            index.base.z | base.z,
+                        ),
+                        height: index.height,
+                    };
+                    bitmap.branches.insert(index, branch);
+                }
+            }
+            bitmap
+                .branches
+                .insert(BranchIndex::root(height), Branch { children });
+        } else {
+            for (octant, source) in octants.iter().enumerate() {
+                let base = octant_base(octant, height);
+                bitmap.set_many(
+                    source
+                        .iter()
+                        .map(|idx| Index::new(idx.x | base.x, idx.y | base.y, idx.z | base.z)),
+                    true,
+                );
+            }
+        }
+        bitmap
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_and_merge() {
+        for width in [4, 8, 64] {
+            let mut bitmap = OctreeBitmap::new(width);
+            let width = bitmap.width();
+            bitmap.set_many(
+                (0..width).flat_map(|x| (0..width).map(move |y| Index::new(x, y, (x + y) % width))),
+                true,
+            );
+            let expected: Vec<Index> = bitmap.iter().collect();
+            let branches = bitmap.branches.len();
+
+            let octants = bitmap.split();
+            let mut merged = OctreeBitmap::from_octants(octants);
+            assert_eq!(merged.width(), width);
+            assert_eq!(merged.iter().collect::<Vec<_>>(), expected);
+            assert_eq!(merged.branches.len(), branches);
+
+            merged.set_many(expected.iter().copied(), false);
+            assert_eq!(merged.branches.len(), 1);
+        }
+
+        let mut widest = OctreeBitmap::new(OctreeBitmap::MAX_WIDTH);
+        let far = Index::new((1 << 31) - 1, 5, 1 << 30);
+        widest.set(&far, true);
+        let merged = OctreeBitmap::from_octants(widest.split());
+        assert_eq!(merged.width(), 1 << 31);
+        assert_eq!(merged.iter().collect::<Vec<_>>(), [far]);
+    }
+}
@@ -0,0 +1,84 @@
+//! Restoring the minimal form of a tree.
+
+use crate::{BranchIndex, OctreeBitmap, RawNode};
+
+impl OctreeBitmap {
+    /// Merges every uniform subtree of the tree into a single node, returning
+    /// the number of branches removed.
+    ///
+    /// Edits keep the tree minimal as they go, so this only finds work to do
+    /// after operations that build parts of the tree directly. Afterwards,
+    /// the tree has the fewest branches that can hold its contents.
+    pub fn optimize(&mut self) -> usize {
+        let before = self.branches.len();
+        self.optimize_branch(BranchIndex::root(self.height));
+        before - self.branches.len()
+    }
+
+    /// Optimizes the subtree of the branch at the index, returning the node
+    /// that should replace it.
+    fn optimize_branch(&mut self, index: BranchIndex) -> RawNode {
+        let mut children = self.branches[&index].children;
+        for i in 0..8 {
+            let (x, y, z) = (i & 1, (i >> 1) & 1, i >> 2);
+            let child = &mut children[z][y][x];
+            match *child {
+                RawNode::Branch => *child = self.optimize_branch(index.child(x, y, z)),
+                RawNode::Brick(bits) => *child = RawNode::from_brick(bits, index.height - 1),
+                RawNode::False | RawNode::True => {}
+            }
+        }
+        self.branches.get_mut(&index).unwrap().children = children;
+        let first = children[0][0][0];
+        if index.height < self.height
+            && first != RawNode::Branch
+            && !matches!(first, RawNode::Brick(_))
+            && children
+                .iter()
+                .flatten()
+                .flatten()
+                .all(|&child| child == first)
+        {
+            self.branches.remove(&index);
+            first
+        } else {
+            RawNode::Branch
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Branch, Index};
+
+    #[test]
+    fn merge_uniform_subtrees() {
+        let mut bitmap = OctreeBitmap::new(64);
+        bitmap.set(&Index::new(100, 3, 9), true);
+        let expected: Vec<Index> = bitmap.iter().collect();
+        let branches = bitmap.branches.len();
+        assert_eq!(bitmap.optimize(), 0);
+
+        // Split an empty octant into a chain of uniform branches.
+        let root = BranchIndex::root(bitmap.height);
+        let mut node = root.child(0, 0, 0);
+        bitmap.branches.get_mut(&root).unwrap().children[0][0][0] = RawNode::Branch;
+        while node.height > 3 {
+            let mut children = [[[RawNode::False; 2]; 2]; 2];
+            children[0][0][0] = RawNode::Branch;
+            bitmap.branches.insert(node, Branch { children });
+            node = node.child(0, 0, 0);
+        }
+        bitmap.branches.insert(
+            node,
+            Branch {
+                children: [[[RawNode::False; 2]; 2]; 2],
+            },
+        );
+
+        assert_eq!(bitmap.optimize(), bitmap.height as usize - 3);
+        assert_eq!(bitmap.branches.len(), branches);
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), expected);
+    }
+}
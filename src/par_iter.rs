@@ -0,0 +1,66 @@
+//! Parallel iteration with rayon.
+
+use rayon::prelude::*;
+
+use crate::{BranchIndex, Index, Iter, OctreeBitmap, RawNode};
+
+/// The number of pieces of work to split iteration into per thread, when the
+/// tree is deep enough. More pieces balance better between threads when the
+/// voxels are unevenly distributed.
+const TASKS_PER_THREAD: usize = 4;
+
+impl OctreeBitmap {
+    /// A parallel iterator over the indexes of all set voxels.
+    ///
+    /// Work is split between threads by octant, starting from the top-level
+    /// octants and subdividing further until there are enough pieces to keep
+    /// every thread busy. Within each octant, voxels are visited sequentially,
+    /// and collecting the iterator yields the same Morton order as
+    /// [`iter`](Self::iter).
+    pub fn par_iter(&self) -> impl ParallelIterator<Item = Index> + '_ {
+        let target = rayon::current_num_threads() * TASKS_PER_THREAD;
+        let mut nodes = vec![(BranchIndex::root(self.height), RawNode::Branch)];
+        while nodes.len() < target && nodes.iter().any(|&(_, node)| node == RawNode::Branch) {
+            nodes = nodes
+                .into_iter()
+                .flat_map(|(index, node)| {
+                    let children = match node {
+                        RawNode::Branch => {
+                            let branch = &self.branches[&index];
+                            (0..8)
+                                .map(|i| {
+                                    let (x, y, z) = (i & 1, (i >> 1) & 1, i >> 2);
+                                    (index.child(x, y, z), branch.children[z][y][x])
+                                })
+                                .collect()
+                        }
+                        node => vec![(index, node)],
+                    };
+                    children
+                        .into_iter()
+                        .filter(|&(_, node)| node != RawNode::False)
+                })
+                .collect();
+        }
+        nodes
+            .into_par_iter()
+            .flat_map_iter(move |(index, node)| Iter::node(self, index, node))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn par_iter_matches_iter() {
+        let mut octree = OctreeBitmap::new(64);
+        octree.set_many(
+            (0..64u32).flat_map(|x| (0..64).map(move |y| Index::new(x, y, (x * y) % 64))),
+            true,
+        );
+        let expected: Vec<Index> = octree.iter().collect();
+        assert_eq!(octree.par_iter().collect::<Vec<_>>(), expected);
+        assert_eq!(OctreeBitmap::new(64).par_iter().count(), 0);
+    }
+}
@@ -0,0 +1,275 @@
+//! Differences between bitmaps.
+
+use crate::{BranchHashes, BranchIndex, Index, OctreeBitmap, View};
+
+/// An octant of a bitmap that a [`Patch`] sets to a single value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PatchRegion {
+    /// The lowest index in the region.
+    pub base: Index,
+    /// The region is a cube with a side length of `1 << height`.
+    pub height: u32,
+    pub value: bool,
+}
+
+/// The changes that turn one bitmap into another, as produced by
+/// [`OctreeBitmap::diff`] and applied by [`OctreeBitmap::apply_patch`].
+///
+/// A patch is a list of aligned cubic regions that are each set to a single
+/// value, so a change to a large uniform area takes one region rather than
+/// one entry per voxel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+    regions: Vec<PatchRegion>,
+    width: u32,
+}
+
+impl Patch {
+    /// Creates a patch from its regions, such as ones received from
+    /// [`regions`](Self::regions) of a patch sent over the network.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `width` is not a power of two, or if any region is not
+    /// aligned to its size or does not fit within the width.
+    pub fn from_regions(width: u32, regions: Vec<PatchRegion>) -> Self {
+        assert!(
+            width.is_power_of_two(),
+            "width {} is not a power of two",
+            width
+        );
+        for region in &regions {
+            let side = 1u64 << region.height;
+            let base = region.base;
+            assert!(
+                [base.x, base.y, base.z]
+                    .iter()
+                    .all(|&x| (x as u64).is_multiple_of(side) && x as u64 + side <= width as u64),
+                "region {:?} is out of bounds or unaligned",
+                region
+            );
+        }
+        Self { regions, width }
+    }
+
+    /// The regions to set, in Morton order. They never overlap.
+    pub fn regions(&self) -> &[PatchRegion] {
+        &self.regions
+    }
+
+    /// The width of the bitmaps that this patch applies to.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Whether the patch changes nothing.
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+}
+
+impl OctreeBitmap {
+    /// The changes that turn this bitmap into `other`.
+    ///
+    /// Both trees are walked together, and subtrees that are uniform and
+    /// equal in both, or equal bricks, are skipped without visiting their
+    /// voxels. Wherever `other` is uniform over an octant that differs, the
+    /// whole octant becomes one region of the patch.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bitmaps have different widths.
+    pub fn diff(&self, other: &OctreeBitmap) -> Patch {
+        assert_eq!(
+            self.height, other.height,
+            "cannot diff bitmaps of different widths"
+        );
+        let root = BranchIndex::root(self.height);
+        let mut regions = Vec::new();
+        self.diff_node(
+            other,
+            root,
+            View::root(self),
+            View::root(other),
+            None,
+            &mut regions,
+        );
+        Patch {
+            regions,
+            width: self.width(),
+        }
+    }
+
+    /// The changes that turn this bitmap into `other`, as with
+    /// [`diff`](Self::diff), given the [`branch_hashes`](Self::branch_hashes)
+    /// of both.
+    ///
+    /// Subtrees with the same hash in both are skipped without being walked,
+    /// so when the hashes of a snapshot are kept, the changes since then are
+    /// found in time proportional to how much has changed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bitmaps or the hashes have different widths. The result
+    /// is meaningless, and may panic, if either bitmap has changed since its
+    /// hashes were computed.
+    pub fn diff_hashed(
+        &self,
+        hashes: &BranchHashes,
+        other: &OctreeBitmap,
+        other_hashes: &BranchHashes,
+    ) -> Patch {
+        assert!(
+            self.height == other.height
+                && hashes.height() == self.height
+                && other_hashes.height() == self.height,
+            "cannot diff bitmaps of different widths"
+        );
+        let root = BranchIndex::root(self.height);
+        let mut regions = Vec::new();
+        self.diff_node(
+            other,
+            root,
+            View::root(self),
+            View::root(other),
+            Some((hashes, other_hashes)),
+            &mut regions,
+        );
+        Patch {
+            regions,
+            width: self.width(),
+        }
+    }
+
+    /// Applies the changes of a patch, such as one produced by
+    /// [`diff`](Self::diff), by filling each of its regions.
+    ///
+    /// Applying `a.diff(&b)` to `a` makes it equal to `b`. The patch is
+    /// recorded as a single edit in recording mode.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the patch is for bitmaps of a different width.
+    pub fn apply_patch(&mut self, patch: &Patch) {
+        assert_eq!(
+            patch.width,
+            self.width(),
+            "cannot apply a patch for a different width"
+        );
+        self.record_group(|bitmap| {
+            for region in &patch.regions {
+                bitmap.fill_node(
+                    BranchIndex {
+                        base: region.base,
+                        height: region.height,
+                    },
+                    region.value,
+                );
+            }
+        });
+    }
+
+    fn diff_node(
+        &self,
+        other: &OctreeBitmap,
+        index: BranchIndex,
+        a: View,
+        b: View,
+        hashes: Option<(&BranchHashes, &BranchHashes)>,
+        regions: &mut Vec<PatchRegion>,
+    ) {
+        if let Some((hashes, other_hashes)) = hashes {
+            if hashes.node(index, a) == other_hashes.node(index, b) {
+                return;
+            }
+        }
+        match (a, b) {
+            (View::Uniform(a), View::Uniform(b)) if a == b => {}
+            (View::Brick(a), View::Brick(b)) if a == b => {}
+            (_, View::Uniform(value)) => regions.push(PatchRegion {
+                base: index.base,
+                height: index.height,
+                value,
+            }),
+            _ => {
+                for i in 0..8 {
+                    let child = index.child(i & 1, (i >> 1) & 1, i >> 2);
+                    self.diff_node(
+                        other,
+                        child,
+                        a.child(self, index, i),
+                        b.child(other, index, i),
+                        hashes,
+                        regions,
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::pseudo_random_indexes;
+
+    #[test]
+    fn diff_and_apply() {
+        let mut a = OctreeBitmap::new(32);
+        a.set_many((0..32).map(|x| Index::new(x, x, x)), true);
+        let mut b = OctreeBitmap::new(32);
+        b.set_many((0..32).map(|x| Index::new(x, x, x)), true);
+        b.fill_node(Index::new(0, 0, 16).branch_at(4), true);
+        b.set(&Index::new(1, 1, 1), false);
+        b.set(&Index::new(3, 0, 0), true);
+
+        let patch = a.diff(&b);
+        assert_eq!(patch.width(), a.width());
+        assert!(a.diff(&a).is_empty());
+        assert!(patch.regions().contains(&PatchRegion {
+            base: Index::new(0, 0, 16),
+            height: 4,
+            value: true,
+        }));
+        assert!(patch.regions().contains(&PatchRegion {
+            base: Index::new(1, 1, 1),
+            height: 0,
+            value: false,
+        }));
+
+        let original: Vec<Index> = a.iter().collect();
+        a.start_recording();
+        a.apply_patch(&patch);
+        assert!(a.iter().eq(b.iter()));
+        assert!(a.diff(&b).is_empty());
+
+        assert!(a.undo());
+        assert_eq!(a.iter().collect::<Vec<_>>(), original);
+        assert!(!a.undo());
+        assert!(a.redo());
+        assert!(a.iter().eq(b.iter()));
+    }
+
+    #[test]
+    fn diff_hashed_matches_diff() {
+        let mut snapshot = OctreeBitmap::new(64);
+        snapshot.fill_sphere([30.0, 30.0, 30.0], 20.0, true);
+        snapshot.set_many(pseudo_random_indexes(128, 500), true);
+        let hashes = snapshot.branch_hashes();
+        let mut world = snapshot.clone();
+        assert_eq!(world.branch_hashes().root(), hashes.root());
+        world.set(&Index::new(100, 3, 70), true);
+        world.fill_node(Index::new(64, 64, 0).branch_at(4), true);
+        world.set(&Index::new(30, 30, 30), false);
+        let world_hashes = world.branch_hashes();
+        assert_ne!(world_hashes.root(), hashes.root());
+
+        let patch = snapshot.diff_hashed(&hashes, &world, &world_hashes);
+        assert_eq!(patch, snapshot.diff(&world));
+        assert!(world
+            .diff_hashed(&world_hashes, &world, &world_hashes)
+            .is_empty());
+        snapshot.apply_patch(&patch);
+        assert!(snapshot == world);
+    }
+}
@@ -0,0 +1,185 @@
+//! Pathfinding through unset voxels.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::{BranchIndex, Index, Neighborhood, OctreeBitmap};
+
+/// The number of steps between two voxels with no obstacles between them,
+/// where each step moves to a voxel in the neighborhood.
+fn steps(a: Index, b: Index, neighborhood: Neighborhood) -> u64 {
+    let d = [a.x.abs_diff(b.x), a.y.abs_diff(b.y), a.z.abs_diff(b.z)].map(u64::from);
+    let (sum, max) = (d.iter().sum::<u64>(), *d.iter().max().unwrap());
+    match neighborhood {
+        Neighborhood::Six => sum,
+        Neighborhood::Eighteen => max.max(sum.div_ceil(2)),
+        Neighborhood::TwentySix => max,
+    }
+}
+
+/// The voxel of the octant at the node nearest to `idx`.
+fn clamp_to(idx: Index, node: BranchIndex) -> Index {
+    let high = (1 << node.height) - 1;
+    Index::new(
+        idx.x.clamp(node.base.x, node.base.x + high),
+        idx.y.clamp(node.base.y, node.base.y + high),
+        idx.z.clamp(node.base.z, node.base.z + high),
+    )
+}
+
+/// Adds the voxels after `from` along a path to `to` of the fewest steps,
+/// moving along the axes with the farthest to go first. Every voxel is within
+/// the box spanned by the two.
+fn walk(from: Index, to: Index, neighborhood: Neighborhood, path: &mut Vec<Index>) {
+    let max_axes = match neighborhood {
+        Neighborhood::Six => 1,
+        Neighborhood::Eighteen => 2,
+        Neighborhood::TwentySix => 3,
+    };
+    let target = [to.x, to.y, to.z];
+    let mut current = [from.x, from.y, from.z];
+    while current != target {
+        let mut axes = [0, 1, 2];
+        axes.sort_by_key(|&i| Reverse(current[i].abs_diff(target[i])));
+        for &i in &axes[..max_axes] {
+            if current[i] < target[i] {
+                current[i] += 1;
+            } else if current[i] > target[i] {
+                current[i] -= 1;
+            }
+        }
+        path.push(Index::from(current));
+    }
+}
+
+impl OctreeBitmap {
+    /// A path of unset voxels from `start` to `goal`, both included, where
+    /// each voxel is in the neighborhood of the one before it, or `None` if
+    /// there is none, such as for navigating an occupancy grid.
+    ///
+    /// The path is found by an A* search over the largest empty octants of
+    /// the tree rather than over single voxels, crossing each octant in a
+    /// straight line, so large open spaces are crossed in a single step of
+    /// the search. Octants are entered through their faces only. The path is
+    /// short, but not always the shortest possible. Returns `None` if either
+    /// end is set or outside the bitmap.
+    pub fn find_path(
+        &self,
+        start: Index,
+        goal: Index,
+        neighborhood: Neighborhood,
+    ) -> Option<Vec<Index>> {
+        if !self.in_bounds(&start) || !self.in_bounds(&goal) || self.get(&start) || self.get(&goal)
+        {
+            return None;
+        }
+        let (first, _) = self.uniform_octant(start.branch_at(0)).unwrap();
+        let (last, _) = self.uniform_octant(goal.branch_at(0)).unwrap();
+        // The cost of the best path found to each octant, the voxel it enters
+        // the octant at, and the octant before it.
+        let mut best: HashMap<BranchIndex, (u64, Index, Option<BranchIndex>)> =
+            HashMap::from([(first, (0, start, None))]);
+        let mut queue = BinaryHeap::from([Reverse((steps(start, goal, neighborhood), 0, first))]);
+        let mut found = Vec::new();
+        while let Some(Reverse((_, cost, octant))) = queue.pop() {
+            let (best_cost, entry, _) = best[&octant];
+            if cost > best_cost {
+                continue;
+            }
+            if octant == last {
+                return Some(self.trace_path(&best, octant, goal, neighborhood));
+            }
+            self.neighbor_octants(octant, false, &mut found);
+            for next in found.drain(..) {
+                let next_entry = clamp_to(entry, next);
+                // Cross this octant to the voxel beside the next one's entry,
+                // and step across the face.
+                let exit = clamp_to(next_entry, octant);
+                let next_cost = cost + steps(entry, exit, neighborhood) + 1;
+                if best.get(&next).is_none_or(|&(cost, ..)| next_cost < cost) {
+                    best.insert(next, (next_cost, next_entry, Some(octant)));
+                    let estimate = next_cost + steps(next_entry, goal, neighborhood);
+                    queue.push(Reverse((estimate, next_cost, next)));
+                }
+            }
+        }
+        None
+    }
+
+    /// The voxels of the path found by [`find_path`](Self::find_path) ending
+    /// at `goal` in the octant at `last`.
+    fn trace_path(
+        &self,
+        best: &HashMap<BranchIndex, (u64, Index, Option<BranchIndex>)>,
+        last: BranchIndex,
+        goal: Index,
+        neighborhood: Neighborhood,
+    ) -> Vec<Index> {
+        let mut octants = vec![last];
+        while let (_, _, Some(previous)) = best[octants.last().unwrap()] {
+            octants.push(previous);
+        }
+        octants.reverse();
+        let mut current = best[&octants[0]].1;
+        let mut path = vec![current];
+        for pair in octants.windows(2) {
+            let entry = best[&pair[1]].1;
+            let exit = clamp_to(entry, pair[0]);
+            walk(current, exit, neighborhood, &mut path);
+            path.push(entry);
+            current = entry;
+        }
+        walk(current, goal, neighborhood, &mut path);
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_around_wall() {
+        let mut bitmap = OctreeBitmap::new(16);
+        // A wall across the middle with a hole near one corner.
+        bitmap.fill_box([16, 0, 0], [17, 32, 32], true);
+        bitmap.set(&Index::new(16, 29, 3), false);
+        let (start, goal) = (Index::new(2, 5, 7), Index::new(30, 6, 8));
+
+        for neighborhood in [
+            Neighborhood::Six,
+            Neighborhood::Eighteen,
+            Neighborhood::TwentySix,
+        ] {
+            let path = bitmap.find_path(start, goal, neighborhood).unwrap();
+            assert_eq!(path.first(), Some(&start));
+            assert_eq!(path.last(), Some(&goal));
+            assert!(path.contains(&Index::new(16, 29, 3)));
+            assert!(path.iter().all(|idx| !bitmap.get(idx)));
+            for pair in path.windows(2) {
+                let offset = [
+                    pair[1].x as i32 - pair[0].x as i32,
+                    pair[1].y as i32 - pair[0].y as i32,
+                    pair[1].z as i32 - pair[0].z as i32,
+                ];
+                assert!(
+                    neighborhood.offsets().any(|o| o == offset),
+                    "{:?} {:?}",
+                    neighborhood,
+                    pair
+                );
+            }
+        }
+
+        assert_eq!(
+            bitmap.find_path(start, start, Neighborhood::Six),
+            Some(vec![start])
+        );
+        assert_eq!(
+            bitmap.find_path(start, Index::new(16, 0, 0), Neighborhood::Six),
+            None
+        );
+        bitmap.set(&Index::new(16, 29, 3), true);
+        assert_eq!(bitmap.find_path(start, goal, Neighborhood::TwentySix), None);
+    }
+}
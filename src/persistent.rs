@@ -0,0 +1,328 @@
+//! An immutable bitmap whose versions share unchanged subtrees.
+
+use std::sync::Arc;
+
+use crate::iter::LeafVoxels;
+use crate::{
+    brick_mask, Branch, BranchIndex, Index, LeafSize, OctreeBitmap, RawNode, BRICK_HEIGHT,
+};
+
+#[derive(Clone)]
+enum Node {
+    False,
+    True,
+    /// A dense leaf brick, as in [`RawNode::Brick`].
+    Brick(u64),
+    Branch(Arc<[[[Node; 2]; 2]; 2]>),
+}
+
+impl Node {
+    fn uniform(&self) -> Option<bool> {
+        match self {
+            Self::False => Some(false),
+            Self::True => Some(true),
+            _ => None,
+        }
+    }
+
+    fn from_brick(bits: u64, height: u32) -> Self {
+        match RawNode::from_brick(bits, height) {
+            RawNode::False => Self::False,
+            RawNode::True => Self::True,
+            _ => Self::Brick(bits),
+        }
+    }
+
+    /// The node with the given children, compressing it if they are all
+    /// equally uniform.
+    fn from_children(children: [[[Node; 2]; 2]; 2]) -> Self {
+        match children[0][0][0].uniform() {
+            Some(value)
+                if children
+                    .iter()
+                    .flatten()
+                    .flatten()
+                    .all(|c| c.uniform() == Some(value)) =>
+            {
+                value.into()
+            }
+            _ => Self::Branch(Arc::new(children)),
+        }
+    }
+
+    fn get(&self, height: u32, idx: &Index) -> bool {
+        match self {
+            Self::False => false,
+            Self::True => true,
+            Self::Brick(bits) => (bits >> idx.brick_offset(height)) & 1 != 0,
+            Self::Branch(children) => {
+                let (x, y, z) = idx.bit(height - 1);
+                children[z][y][x].get(height - 1, idx)
+            }
+        }
+    }
+
+    /// The node of the given height with the voxel at the index set to `value`,
+    /// or `None` if it is unchanged.
+    fn set(&self, height: u32, brick_height: u32, idx: &Index, value: bool) -> Option<Self> {
+        if self.uniform() == Some(value) {
+            return None;
+        }
+        if height <= brick_height {
+            let bits = match self {
+                Self::False => 0,
+                Self::True => brick_mask(height),
+                Self::Brick(bits) => *bits,
+                Self::Branch(_) => unreachable!("branch node at brick height"),
+            };
+            let bit = 1 << idx.brick_offset(height);
+            let new_bits = if value { bits | bit } else { bits & !bit };
+            return (new_bits != bits).then(|| Self::from_brick(new_bits, height));
+        }
+        let mut children = match self {
+            Self::Branch(children) => (**children).clone(),
+            uniform => {
+                std::array::from_fn(|_| std::array::from_fn(|_| [uniform.clone(), uniform.clone()]))
+            }
+        };
+        let (x, y, z) = idx.bit(height - 1);
+        children[z][y][x] = children[z][y][x].set(height - 1, brick_height, idx, value)?;
+        Some(Self::from_children(children))
+    }
+}
+
+impl From<bool> for Node {
+    fn from(x: bool) -> Self {
+        match x {
+            false => Self::False,
+            true => Self::True,
+        }
+    }
+}
+
+/// An immutable three-dimensional bitmap with cheap snapshots.
+///
+/// [`set`](Self::set) returns a new bitmap instead of modifying this one, and
+/// the two share every subtree that the change did not touch, so each
+/// version only costs the nodes along the path to the changed voxel. Cloning
+/// is a reference count increment. This suits undo stacks and speculative
+/// edits, where many versions of a large bitmap are kept alive at once.
+/// Versions can be shared between threads.
+#[derive(Clone)]
+pub struct PersistentOctreeBitmap {
+    root: Node,
+    height: u32,
+}
+
+impl PersistentOctreeBitmap {
+    /// Creates a new, empty bitmap.
+    ///
+    /// The width is chosen as in [`OctreeBitmap::new`].
+    pub fn new(width: u32) -> Self {
+        Self {
+            root: Node::False,
+            height: OctreeBitmap::new(width).height,
+        }
+    }
+
+    /// The width of the map. Index values in each dimension must be within
+    /// the range `0..map.width()`.
+    pub fn width(&self) -> u32 {
+        1 << self.height
+    }
+
+    fn brick_height(&self) -> u32 {
+        BRICK_HEIGHT.min(self.height - 1)
+    }
+
+    /// Get the current value of the bit at the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn get(&self, idx: &Index) -> bool {
+        let width = self.width();
+        assert!(
+            idx.x < width && idx.y < width && idx.z < width,
+            "index {:?} out of bounds for width {}",
+            idx,
+            width
+        );
+        self.root.get(self.height, idx)
+    }
+
+    /// Returns a copy of this bitmap with the value at the given index set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn set(&self, idx: &Index, value: bool) -> Self {
+        let width = self.width();
+        assert!(
+            idx.x < width && idx.y < width && idx.z < width,
+            "index {:?} out of bounds for width {}",
+            idx,
+            width
+        );
+        match self.root.set(self.height, self.brick_height(), idx, value) {
+            Some(root) => Self {
+                root,
+                height: self.height,
+            },
+            None => self.clone(),
+        }
+    }
+
+    /// Whether the two bitmaps are known to be the same version, without
+    /// comparing their contents.
+    ///
+    /// This is `true` for clones and for bitmaps returned by a
+    /// [`set`](Self::set) that changed nothing. It may be `false` for
+    /// bitmaps with equal contents that were built separately.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.height == other.height
+            && match (&self.root, &other.root) {
+                (Node::Branch(a), Node::Branch(b)) => Arc::ptr_eq(a, b),
+                (Node::False, Node::False) | (Node::True, Node::True) => true,
+                _ => false,
+            }
+    }
+
+    /// An iterator over the indexes of all set voxels, in Morton order, as
+    /// for [`OctreeBitmap::iter`].
+    pub fn iter(&self) -> impl Iterator<Item = Index> + '_ {
+        let mut stack = vec![(&self.root, BranchIndex::root(self.height))];
+        let mut leaf: Option<LeafVoxels> = None;
+        std::iter::from_fn(move || loop {
+            if let Some(idx) = leaf.as_mut().and_then(Iterator::next) {
+                return Some(idx);
+            }
+            let (node, index) = stack.pop()?;
+            leaf = match node {
+                Node::False => None,
+                Node::True => LeafVoxels::new(index, RawNode::True, true),
+                Node::Brick(bits) => LeafVoxels::new(index, RawNode::Brick(*bits), true),
+                Node::Branch(children) => {
+                    for i in (0..8).rev() {
+                        let (x, y, z) = (i & 1, (i >> 1) & 1, i >> 2);
+                        stack.push((&children[z][y][x], index.child(x, y, z)));
+                    }
+                    None
+                }
+            };
+        })
+    }
+
+    /// Copies the contents into a mutable bitmap.
+    pub fn to_bitmap(&self) -> OctreeBitmap {
+        fn insert(bitmap: &mut OctreeBitmap, index: BranchIndex, children: &[[[Node; 2]; 2]; 2]) {
+            let mut raw = [[[RawNode::False; 2]; 2]; 2];
+            for z in 0..2 {
+                for y in 0..2 {
+                    for x in 0..2 {
+                        raw[z][y][x] = match &children[z][y][x] {
+                            Node::False => RawNode::False,
+                            Node::True => RawNode::True,
+                            Node::Brick(bits) => RawNode::Brick(*bits),
+                            Node::Branch(grandchildren) => {
+                                insert(bitmap, index.child(x, y, z), grandchildren);
+                                RawNode::Branch
+                            }
+                        };
+                    }
+                }
+            }
+            bitmap.branches.insert(index, Branch { children: raw });
+        }
+
+        let mut bitmap = OctreeBitmap::with_height(self.height);
+        match &self.root {
+            Node::Branch(children) => insert(&mut bitmap, BranchIndex::root(self.height), children),
+            Node::True => bitmap.fill_node(BranchIndex::root(self.height), true),
+            _ => {}
+        }
+        bitmap
+    }
+}
+
+impl From<&OctreeBitmap> for PersistentOctreeBitmap {
+    fn from(bitmap: &OctreeBitmap) -> Self {
+        fn node(bitmap: &OctreeBitmap, index: BranchIndex) -> Node {
+            let children = &bitmap.branches[&index].children;
+            Node::from_children(std::array::from_fn(|z| {
+                std::array::from_fn(|y| {
+                    std::array::from_fn(|x| match children[z][y][x] {
+                        RawNode::False => Node::False,
+                        RawNode::True => Node::True,
+                        RawNode::Brick(bits) => Node::Brick(bits),
+                        RawNode::Branch => node(bitmap, index.child(x, y, z)),
+                    })
+                })
+            }))
+        }
+
+        // Persistent bitmaps always have leaves of the default size.
+        let bitmap = bitmap.leaves_of(LeafSize::default());
+        Self {
+            root: node(&bitmap, BranchIndex::root(bitmap.height)),
+            height: bitmap.height,
+        }
+    }
+}
+
+impl From<&PersistentOctreeBitmap> for OctreeBitmap {
+    fn from(bitmap: &PersistentOctreeBitmap) -> Self {
+        bitmap.to_bitmap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::pseudo_random_indexes;
+
+    #[test]
+    fn versions_share_subtrees() {
+        let empty = PersistentOctreeBitmap::new(128);
+        let width = empty.width();
+        let far = &Index::new(width - 8, width - 7, width - 6);
+        let a = empty.set(&Index::new(1, 2, 3), true);
+        let b = a.set(far, true);
+        assert!(!empty.get(&Index::new(1, 2, 3)));
+        assert!(a.get(&Index::new(1, 2, 3)));
+        assert!(!a.get(far));
+        assert!(b.get(&Index::new(1, 2, 3)) && b.get(far));
+
+        let (Node::Branch(a_root), Node::Branch(b_root)) = (&a.root, &b.root) else {
+            panic!("expected branches at the root");
+        };
+        let (Node::Branch(a_child), Node::Branch(b_child)) = (&a_root[0][0][0], &b_root[0][0][0])
+        else {
+            panic!("expected branches below the root");
+        };
+        assert!(Arc::ptr_eq(a_child, b_child));
+        assert!(b.set(&Index::new(1, 2, 3), true).ptr_eq(&b));
+
+        let cleared = b.set(&Index::new(1, 2, 3), false).set(far, false);
+        assert!(matches!(cleared.root, Node::False));
+
+        let bitmap = b.to_bitmap();
+        assert_eq!(
+            bitmap.iter().collect::<Vec<_>>(),
+            [Index::new(1, 2, 3), *far]
+        );
+        let round_trip = PersistentOctreeBitmap::from(&bitmap);
+        assert!(round_trip.get(far));
+        assert_eq!(round_trip.to_bitmap().branches.len(), bitmap.branches.len());
+    }
+
+    #[test]
+    fn iter_in_morton_order() {
+        let mut bitmap = OctreeBitmap::new(32);
+        bitmap.fill_sphere([10.0, 12.0, 14.0], 6.0, true);
+        bitmap.set_many(pseudo_random_indexes(64, 200), true);
+        let persistent = PersistentOctreeBitmap::from(&bitmap);
+        assert!(persistent.iter().eq(bitmap.iter()));
+        assert_eq!(PersistentOctreeBitmap::new(8).iter().next(), None);
+    }
+}
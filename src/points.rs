@@ -0,0 +1,215 @@
+//! Voxelization of world-space points.
+
+#[cfg(feature = "las")]
+mod las;
+#[cfg(feature = "ply")]
+mod ply;
+
+use crate::{Index, OctreeBitmap};
+
+/// Maps world-space points onto the voxel grid of a bitmap.
+///
+/// Voxel `(x, y, z)` covers the half-open box from `origin + voxel_size * (x,
+/// y, z)` to `origin + voxel_size * (x + 1, y + 1, z + 1)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoxelTransform {
+    /// The world-space position of the minimum corner of voxel `(0, 0, 0)`.
+    pub origin: [f64; 3],
+    /// The side length of a voxel, in world units.
+    pub voxel_size: f64,
+}
+
+impl VoxelTransform {
+    pub fn new(origin: [f64; 3], voxel_size: f64) -> Self {
+        Self { origin, voxel_size }
+    }
+
+    /// The index of the voxel containing the given world-space point, or
+    /// `None` if that voxel is outside of the range `0..width`.
+    pub fn index_of(&self, point: [f64; 3], width: u32) -> Option<Index> {
+        let mut coords = [0; 3];
+        for i in 0..3 {
+            let coord = ((point[i] - self.origin[i]) / self.voxel_size).floor();
+            // Also rejects NaN.
+            if !(coord >= 0.0 && coord < width as f64) {
+                return None;
+            }
+            coords[i] = coord as u32;
+        }
+        Some(Index::from(coords))
+    }
+}
+
+impl Default for VoxelTransform {
+    /// Maps each unit cube of world space to one voxel.
+    fn default() -> Self {
+        Self::new([0.0; 3], 1.0)
+    }
+}
+
+/// What [`OctreeBitmap::from_points`] does with points outside of the bitmap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutOfRange {
+    /// Skips the point.
+    #[default]
+    Ignore,
+    /// Sets the voxel on the border of the bitmap nearest to the point. Points
+    /// with a NaN coordinate are still skipped.
+    Clamp,
+    /// Stops with a [`PointOutOfRange`] error.
+    Fail,
+}
+
+/// A point passed to [`OctreeBitmap::from_points`] was outside of the bitmap,
+/// with the [`OutOfRange::Fail`] policy.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointOutOfRange {
+    /// The position of the point in the input.
+    pub position: usize,
+    pub point: [f32; 3],
+}
+
+impl std::fmt::Display for PointOutOfRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "point {} at {:?} is outside of the bitmap",
+            self.position, self.point
+        )
+    }
+}
+
+impl std::error::Error for PointOutOfRange {}
+
+impl OctreeBitmap {
+    /// Voxelizes world-space points, such as from a LiDAR scan or
+    /// photogrammetry, into a new bitmap with the given width.
+    ///
+    /// Every voxel containing at least one point is set, and points outside
+    /// of the bitmap are handled according to the policy.
+    pub fn from_points(
+        points: impl Iterator<Item = [f32; 3]>,
+        transform: &VoxelTransform,
+        width: u32,
+        policy: OutOfRange,
+    ) -> Result<Self, PointOutOfRange> {
+        let mut bitmap = Self::new(width);
+        let width = bitmap.width();
+        let mut indices = Vec::new();
+        for (position, point) in points.enumerate() {
+            let world = point.map(f64::from);
+            if let Some(idx) = transform.index_of(world, width) {
+                indices.push(idx);
+                continue;
+            }
+            match policy {
+                OutOfRange::Ignore => {}
+                OutOfRange::Clamp => {
+                    if world.iter().any(|c| c.is_nan()) {
+                        continue;
+                    }
+                    let coords = [0, 1, 2].map(|i| {
+                        let coord =
+                            ((world[i] - transform.origin[i]) / transform.voxel_size).floor();
+                        coord.clamp(0.0, (width - 1) as f64) as u32
+                    });
+                    indices.push(Index::from(coords));
+                }
+                OutOfRange::Fail => return Err(PointOutOfRange { position, point }),
+            }
+        }
+        bitmap.set_many(indices, true);
+        Ok(bitmap)
+    }
+}
+
+/// An error encountered while reading a point cloud file.
+#[cfg(any(feature = "ply", feature = "las"))]
+#[derive(Debug)]
+pub enum PointCloudError {
+    Io(std::io::Error),
+    #[cfg(feature = "las")]
+    Las(::las::Error),
+    /// The file does not contain a numeric `x`, `y` or `z` vertex property.
+    MissingCoordinate(&'static str),
+}
+
+#[cfg(any(feature = "ply", feature = "las"))]
+impl std::fmt::Display for PointCloudError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "failed to read point cloud: {}", err),
+            #[cfg(feature = "las")]
+            Self::Las(err) => write!(f, "failed to read LAS file: {}", err),
+            Self::MissingCoordinate(axis) => {
+                write!(
+                    f,
+                    "point cloud vertices have no numeric `{}` property",
+                    axis
+                )
+            }
+        }
+    }
+}
+
+#[cfg(any(feature = "ply", feature = "las"))]
+impl std::error::Error for PointCloudError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            #[cfg(feature = "las")]
+            Self::Las(err) => Some(err),
+            Self::MissingCoordinate(_) => None,
+        }
+    }
+}
+
+#[cfg(any(feature = "ply", feature = "las"))]
+impl From<std::io::Error> for PointCloudError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_of() {
+        let transform = VoxelTransform::new([-1.0, 0.0, 10.0], 0.5);
+        assert_eq!(
+            transform.index_of([-1.0, 0.25, 11.9], 8),
+            Some(Index::new(0, 0, 3))
+        );
+        assert_eq!(transform.index_of([-1.1, 0.0, 10.0], 8), None);
+        assert_eq!(transform.index_of([0.0, 4.0, 10.0], 8), None);
+        assert_eq!(transform.index_of([0.0, f64::NAN, 10.0], 8), None);
+    }
+
+    #[test]
+    fn from_points_policies() {
+        let transform = VoxelTransform::new([-10.0, 0.0, 0.0], 0.5);
+        let points = [[-9.9, 0.2, 1.1], [0.0, 100.0, 0.0], [-20.0, 1.0, f32::NAN]];
+        let bitmap =
+            OctreeBitmap::from_points(points.into_iter(), &transform, 16, OutOfRange::Ignore)
+                .unwrap();
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), [Index::new(0, 0, 2)]);
+
+        let bitmap =
+            OctreeBitmap::from_points(points.into_iter(), &transform, 16, OutOfRange::Clamp)
+                .unwrap();
+        let top = bitmap.width() - 1;
+        assert_eq!(
+            bitmap.iter().collect::<Vec<_>>(),
+            [Index::new(0, 0, 2), Index::new(20, top, 0)]
+        );
+
+        assert_eq!(
+            OctreeBitmap::from_points(points.into_iter(), &transform, 16, OutOfRange::Fail)
+                .err()
+                .map(|err| err.position),
+            Some(1)
+        );
+    }
+}
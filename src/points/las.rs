@@ -0,0 +1,80 @@
+use std::io::{Read, Seek};
+
+use las::Reader;
+
+use crate::{OctreeBitmap, PointCloudError, VoxelTransform};
+
+/// The number of points read from the file at a time.
+const BATCH_SIZE: u64 = 1 << 16;
+
+impl From<las::Error> for PointCloudError {
+    fn from(err: las::Error) -> Self {
+        Self::Las(err)
+    }
+}
+
+impl OctreeBitmap {
+    /// Voxelizes the points of a LAS file into a new bitmap with the given
+    /// width. With the `laz` feature, compressed LAZ files are also supported.
+    ///
+    /// Point coordinates are read with the scale and offset from the file
+    /// header applied. Every voxel containing at least one point is set.
+    /// Points that fall outside of the bitmap are ignored.
+    pub fn from_las<R>(
+        reader: R,
+        width: u32,
+        transform: &VoxelTransform,
+    ) -> Result<Self, PointCloudError>
+    where
+        R: Read + Seek + Send + Sync + 'static,
+    {
+        let mut reader = Reader::new(reader)?;
+        let mut bitmap = Self::new(width);
+        let width = bitmap.width();
+        loop {
+            let points = reader.read_points(BATCH_SIZE)?;
+            if points.is_empty() {
+                break;
+            }
+            let mut indices = Vec::with_capacity(points.len());
+            for point in points.points() {
+                let point = point?;
+                indices.extend(transform.index_of([point.x, point.y, point.z], width));
+            }
+            bitmap.set_many(indices, true);
+        }
+        Ok(bitmap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use las::{Point, Writer};
+
+    use super::*;
+    use crate::Index;
+
+    #[test]
+    fn las_points() {
+        let mut writer = Writer::new(Cursor::new(Vec::new()), Default::default()).unwrap();
+        for (x, y, z) in [(0.5, 0.5, 0.5), (9.0, 2.0, 2.0), (100.0, 0.0, 0.0)] {
+            writer
+                .write_point(Point {
+                    x,
+                    y,
+                    z,
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        let file = writer.into_inner().unwrap();
+
+        let transform = VoxelTransform::new([0.0; 3], 2.0);
+        let bitmap = OctreeBitmap::from_las(Cursor::new(file.into_inner()), 8, &transform).unwrap();
+        assert!(bitmap.get(&Index::new(0, 0, 0)));
+        assert!(bitmap.get(&Index::new(4, 1, 1)));
+        assert!(!bitmap.get(&Index::new(1, 1, 1)));
+    }
+}
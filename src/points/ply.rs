@@ -0,0 +1,75 @@
+use std::io::{BufReader, Read};
+
+use ply_rs::parser::Parser;
+use ply_rs::ply::{DefaultElement, Property};
+
+use crate::{OctreeBitmap, PointCloudError, VoxelTransform};
+
+fn scalar(property: &Property) -> Option<f64> {
+    Some(match *property {
+        Property::Char(x) => x as f64,
+        Property::UChar(x) => x as f64,
+        Property::Short(x) => x as f64,
+        Property::UShort(x) => x as f64,
+        Property::Int(x) => x as f64,
+        Property::UInt(x) => x as f64,
+        Property::Float(x) => x as f64,
+        Property::Double(x) => x,
+        _ => return None,
+    })
+}
+
+impl OctreeBitmap {
+    /// Voxelizes the vertices of a PLY file (ASCII or binary) into a new
+    /// bitmap with the given width.
+    ///
+    /// Every voxel containing at least one vertex is set. Vertices that fall
+    /// outside of the bitmap are ignored.
+    pub fn from_ply<R: Read>(
+        reader: R,
+        width: u32,
+        transform: &VoxelTransform,
+    ) -> Result<Self, PointCloudError> {
+        let ply = Parser::<DefaultElement>::new().read_ply(&mut BufReader::new(reader))?;
+        let mut bitmap = Self::new(width);
+        let width = bitmap.width();
+        let mut indices = Vec::new();
+        for vertex in ply.payload.get("vertex").into_iter().flatten() {
+            let mut point = [0.0; 3];
+            for (coord, axis) in point.iter_mut().zip(["x", "y", "z"]) {
+                *coord = vertex
+                    .get(axis)
+                    .and_then(scalar)
+                    .ok_or(PointCloudError::MissingCoordinate(axis))?;
+            }
+            indices.extend(transform.index_of(point, width));
+        }
+        bitmap.set_many(indices, true);
+        Ok(bitmap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Index;
+
+    #[test]
+    fn ascii_vertices() {
+        let ply = "ply
+format ascii 1.0
+element vertex 3
+property float x
+property float y
+property float z
+end_header
+0.5 0.5 0.5
+2.25 1.0 3.75
+-4.0 0.0 0.0
+";
+        let bitmap = OctreeBitmap::from_ply(ply.as_bytes(), 8, &VoxelTransform::default()).unwrap();
+        assert!(bitmap.get(&Index::new(0, 0, 0)));
+        assert!(bitmap.get(&Index::new(2, 1, 3)));
+        assert!(!bitmap.get(&Index::new(1, 1, 1)));
+    }
+}
@@ -0,0 +1,149 @@
+//! Python bindings through `pyo3`, with NumPy array import and export.
+//!
+//! The crate builds as a Python extension module named `october`, exporting
+//! the `OctreeBitmap` class. NumPy arrays are indexed as `array[x, y, z]`.
+//! To build a wheel with `maturin`, enable this feature along with
+//! `pyo3/extension-module`.
+
+use numpy::ndarray::Array3;
+use numpy::{IntoPyArray, PyArray3, PyReadonlyArray3};
+use pyo3::exceptions::{PyIndexError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::{Index, OctreeBitmap};
+
+/// A bitmap, as seen from Python.
+#[pyclass(name = "OctreeBitmap", module = "october")]
+pub struct PyOctreeBitmap(OctreeBitmap);
+
+#[pymethods]
+impl PyOctreeBitmap {
+    /// Creates a new, empty bitmap. Raises `ValueError` if the width is zero
+    /// or too large.
+    #[new]
+    fn new(width: u32) -> PyResult<Self> {
+        OctreeBitmap::try_new(width)
+            .map(Self)
+            .map_err(|err| PyValueError::new_err(err.to_string()))
+    }
+
+    #[getter]
+    fn width(&self) -> u32 {
+        self.0.width()
+    }
+
+    /// Get the value of the voxel at the given index. Raises `IndexError` if
+    /// the index is out of bounds.
+    fn get(&self, x: u32, y: u32, z: u32) -> PyResult<bool> {
+        self.0
+            .try_get(&Index::new(x, y, z))
+            .map_err(|err| PyIndexError::new_err(err.to_string()))
+    }
+
+    /// Set the value of the voxel at the given index. Raises `IndexError` if
+    /// the index is out of bounds.
+    fn set(&mut self, x: u32, y: u32, z: u32, value: bool) -> PyResult<()> {
+        self.0
+            .try_set(&Index::new(x, y, z), value)
+            .map_err(|err| PyIndexError::new_err(err.to_string()))
+    }
+
+    /// Clears every voxel.
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    /// The number of set voxels.
+    fn __len__(&self) -> usize {
+        self.0.iter().count()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+
+    /// Creates a bitmap from a three-dimensional boolean array, just wide
+    /// enough to fit it, with the voxels set where the array is true.
+    #[staticmethod]
+    fn from_numpy(array: PyReadonlyArray3<'_, bool>) -> PyResult<Self> {
+        let array = array.as_array();
+        let width = array.shape().iter().copied().max().unwrap_or(0).max(1);
+        let width =
+            u32::try_from(width).map_err(|_| PyValueError::new_err("array is too large"))?;
+        let mut bitmap =
+            OctreeBitmap::try_new(width).map_err(|err| PyValueError::new_err(err.to_string()))?;
+        bitmap.set_many(
+            array
+                .indexed_iter()
+                .filter(|&(_, &value)| value)
+                .map(|((x, y, z), _)| Index::new(x as u32, y as u32, z as u32)),
+            true,
+        );
+        Ok(Self(bitmap))
+    }
+
+    /// The bitmap as a dense boolean array of shape `(width, width, width)`.
+    fn to_numpy<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray3<bool>> {
+        let width = self.0.width() as usize;
+        let mut array = Array3::from_elem((width, width, width), false);
+        for idx in self.0.iter() {
+            array[[idx.x as usize, idx.y as usize, idx.z as usize]] = true;
+        }
+        array.into_pyarray(py)
+    }
+
+    /// The bitmap encoded in the binary format.
+    fn to_binary<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.0.to_binary())
+    }
+
+    /// Decodes a bitmap in the binary format. Raises `ValueError` if the data
+    /// is invalid.
+    #[staticmethod]
+    fn from_binary(bytes: &[u8]) -> PyResult<Self> {
+        OctreeBitmap::from_binary(bytes)
+            .map(Self)
+            .map_err(|err| PyValueError::new_err(err.to_string()))
+    }
+}
+
+impl From<OctreeBitmap> for PyOctreeBitmap {
+    fn from(bitmap: OctreeBitmap) -> Self {
+        Self(bitmap)
+    }
+}
+
+impl From<PyOctreeBitmap> for OctreeBitmap {
+    fn from(bitmap: PyOctreeBitmap) -> Self {
+        bitmap.0
+    }
+}
+
+#[pymodule]
+fn october(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyOctreeBitmap>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn python_methods() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut bitmap = PyOctreeBitmap::new(16).unwrap();
+            assert!(PyOctreeBitmap::new(0).is_err());
+            bitmap.set(1, 2, 3, true).unwrap();
+            assert!(bitmap.get(1, 2, 3).unwrap());
+            let err = bitmap.get(bitmap.width(), 0, 0).unwrap_err();
+            assert!(err.is_instance_of::<PyIndexError>(py));
+
+            let bytes = bitmap.to_binary(py);
+            let copy = PyOctreeBitmap::from_binary(bytes.as_bytes()).unwrap();
+            assert!(copy.__eq__(&bitmap));
+            assert_eq!(copy.__len__(), 1);
+        });
+    }
+}
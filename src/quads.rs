@@ -0,0 +1,264 @@
+//! Surface extraction as merged rectangular faces.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{BranchIndex, Face, Index, Occupancy, OctreeBitmap};
+
+/// A rectangle on the boundary between set and unset voxels, covering the
+/// faces of one or more set voxels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Quad {
+    /// The corner of the rectangle with the lowest coordinates, in the space
+    /// where the voxel at index `(x, y, z)` spans `x..x + 1` (and so on)
+    /// along each axis.
+    pub position: [u32; 3],
+    /// The side lengths of the rectangle along the two axes other than the
+    /// face's normal, in cyclic order after it: `[y, z]` for `x` faces,
+    /// `[z, x]` for `y` faces and `[x, y]` for `z` faces.
+    pub size: [u32; 2],
+    /// The side of the set voxels that the rectangle faces, and so the
+    /// direction of its normal.
+    pub face: Face,
+}
+
+impl Quad {
+    /// The outward unit normal of the rectangle.
+    pub fn normal(&self) -> [i32; 3] {
+        self.face.normal()
+    }
+
+    /// The four corners, counterclockwise when viewed from outside.
+    pub fn corners(&self) -> [[u32; 3]; 4] {
+        let axis = axis_of(self.face);
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        let corner = |du: u32, dv: u32| {
+            let mut corner = self.position;
+            corner[u] += du;
+            corner[v] += dv;
+            corner
+        };
+        let [su, sv] = self.size;
+        if positive(self.face) {
+            [corner(0, 0), corner(su, 0), corner(su, sv), corner(0, sv)]
+        } else {
+            [corner(0, 0), corner(0, sv), corner(su, sv), corner(su, 0)]
+        }
+    }
+}
+
+pub(crate) fn axis_of(face: Face) -> usize {
+    match face {
+        Face::NegX | Face::PosX => 0,
+        Face::NegY | Face::PosY => 1,
+        Face::NegZ | Face::PosZ => 2,
+    }
+}
+
+pub(crate) fn positive(face: Face) -> bool {
+    matches!(face, Face::PosX | Face::PosY | Face::PosZ)
+}
+
+const FACES: [Face; 6] = [
+    Face::NegX,
+    Face::PosX,
+    Face::NegY,
+    Face::PosY,
+    Face::NegZ,
+    Face::PosZ,
+];
+
+/// The exposed unit faces on each plane, keyed by face and plane coordinate,
+/// as coordinates along the plane's two axes.
+pub(crate) type Planes = HashMap<(Face, u32), Vec<[u32; 2]>>;
+
+impl OctreeBitmap {
+    /// Extracts the boundary between set and unset voxels as rectangles,
+    /// merging adjacent coplanar faces greedily.
+    ///
+    /// The outside of the bitmap counts as unset. Each uniformly-set node of
+    /// the tree is only compared against the nodes beside it, which are
+    /// split only where they are mixed, so the interiors of solid regions and
+    /// large empty regions are never visited voxel by voxel. Rectangles are
+    /// sorted by face, then by plane.
+    pub fn extract_quads(&self) -> Vec<Quad> {
+        let planes = self.exposed_planes();
+        let mut keys: Vec<(Face, u32)> = planes.keys().copied().collect();
+        keys.sort_by_key(|&(face, plane)| (FACES.iter().position(|&f| f == face), plane));
+        let mut quads = Vec::new();
+        for key in keys {
+            merge_plane(key.0, key.1, &planes[&key], &mut quads);
+        }
+        quads
+    }
+
+    /// Every face between a set voxel and an unset voxel, or the outside of the
+    /// bitmap, grouped by plane.
+    pub(crate) fn exposed_planes(&self) -> Planes {
+        let width = self.width();
+        let mut planes = Planes::new();
+        self.visit_nodes(|base, height, occupancy| {
+            if occupancy != Occupancy::Full {
+                return occupancy == Occupancy::Mixed;
+            }
+            let side = 1 << height;
+            let base = [base.x, base.y, base.z];
+            for face in FACES {
+                let axis = axis_of(face);
+                let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+                let plane = base[axis] + if positive(face) { side } else { 0 };
+                let neighbor = if positive(face) {
+                    (plane < width).then_some(plane)
+                } else {
+                    base[axis].checked_sub(side)
+                };
+                let cells = planes.entry((face, plane)).or_default();
+                let neighbor = neighbor.map(|coordinate| {
+                    let mut neighbor = base;
+                    neighbor[axis] = coordinate;
+                    BranchIndex {
+                        base: Index::from(neighbor),
+                        height,
+                    }
+                });
+                self.exposed_faces(face, neighbor, [base[u], base[v]], height, cells);
+            }
+            false
+        });
+        planes
+    }
+
+    /// Adds the cells of the square face of a set node, of the given height
+    /// and corner on its plane, that are not covered by the neighboring node
+    /// of the same size on the other side of the face.
+    fn exposed_faces(
+        &self,
+        face: Face,
+        neighbor: Option<BranchIndex>,
+        corner: [u32; 2],
+        height: u32,
+        cells: &mut Vec<[u32; 2]>,
+    ) {
+        let occupancy = neighbor.map_or(Occupancy::Empty, |node| self.node_occupancy(node));
+        let side = 1 << height;
+        match occupancy {
+            Occupancy::Full => {}
+            Occupancy::Empty => {
+                for dv in 0..side {
+                    cells.extend((0..side).map(|du| [corner[0] + du, corner[1] + dv]));
+                }
+            }
+            Occupancy::Mixed => {
+                let neighbor = neighbor.unwrap();
+                let axis = axis_of(face);
+                let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+                let half = side / 2;
+                for (du, dv) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    // The octant of the neighbor touching this quarter of the
+                    // face.
+                    let mut bits = [0; 3];
+                    bits[axis] = usize::from(!positive(face));
+                    bits[u] = du;
+                    bits[v] = dv;
+                    let child = neighbor.child(bits[0], bits[1], bits[2]);
+                    let corner = [corner[0] + du as u32 * half, corner[1] + dv as u32 * half];
+                    self.exposed_faces(face, Some(child), corner, height - 1, cells);
+                }
+            }
+        }
+    }
+}
+
+/// Greedily merges the exposed cells of a plane into rectangles.
+fn merge_plane(face: Face, plane: u32, cells: &[[u32; 2]], quads: &mut Vec<Quad>) {
+    let mut remaining: HashSet<[u32; 2]> = cells.iter().copied().collect();
+    let mut order = cells.to_vec();
+    order.sort_unstable_by_key(|&[u, v]| (v, u));
+    let axis = axis_of(face);
+    for [u, v] in order {
+        if !remaining.contains(&[u, v]) {
+            continue;
+        }
+        let mut su = 1;
+        while remaining.contains(&[u + su, v]) {
+            su += 1;
+        }
+        let mut sv = 1;
+        while (0..su).all(|du| remaining.contains(&[u + du, v + sv])) {
+            sv += 1;
+        }
+        for dv in 0..sv {
+            for du in 0..su {
+                remaining.remove(&[u + du, v + dv]);
+            }
+        }
+        let mut position = [0; 3];
+        position[axis] = plane;
+        position[(axis + 1) % 3] = u;
+        position[(axis + 2) % 3] = v;
+        quads.push(Quad {
+            position,
+            size: [su, sv],
+            face,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merged_faces() {
+        let mut bitmap = OctreeBitmap::new(16);
+        bitmap.set(&Index::new(3, 3, 3), true);
+        assert_eq!(bitmap.extract_quads().len(), 6);
+
+        // A 3x1x1 bar: each side is a single rectangle.
+        bitmap.set_many([Index::new(4, 3, 3), Index::new(5, 3, 3)], true);
+        let quads = bitmap.extract_quads();
+        assert_eq!(quads.len(), 6);
+        assert!(quads.contains(&Quad {
+            position: [3, 4, 3],
+            size: [1, 3],
+            face: Face::PosY,
+        }));
+
+        // A solid block against the edge of the bitmap and a voxel touching
+        // it, compared against counting exposed voxel faces one by one.
+        bitmap.fill_node(Index::new(8, 0, 0).branch_at(3), true);
+        bitmap.set(&Index::new(7, 2, 2), true);
+        bitmap.set(&Index::new(12, 8, 3), true);
+        let quads = bitmap.extract_quads();
+        let area: u32 = quads.iter().map(|quad| quad.size[0] * quad.size[1]).sum();
+        let width = bitmap.width() as i64;
+        let mut expected = 0;
+        for idx in bitmap.iter() {
+            for face in FACES {
+                let n = face.normal();
+                let neighbor = [
+                    idx.x as i64 + n[0] as i64,
+                    idx.y as i64 + n[1] as i64,
+                    idx.z as i64 + n[2] as i64,
+                ];
+                let outside = neighbor.iter().any(|&c| c < 0 || c >= width);
+                if outside || !bitmap.get(&Index::from(neighbor.map(|c| c as u32))) {
+                    expected += 1;
+                }
+            }
+        }
+        assert_eq!(area, expected);
+        assert!(
+            quads.contains(&Quad {
+                position: [8, 0, 0],
+                size: [8, 8],
+                face: Face::NegZ,
+            }) || quads
+                .iter()
+                .any(|quad| quad.face == Face::NegZ && quad.size == [8, 8])
+        );
+        assert_eq!(
+            quads[0].corners(),
+            [[3, 3, 3], [3, 3, 4], [3, 4, 4], [3, 4, 3]]
+        );
+    }
+}
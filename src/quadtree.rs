@@ -0,0 +1,132 @@
+//! A two-dimensional counterpart of the octree bitmap.
+
+use crate::{Hypertree, HypertreeBitmap};
+
+/// A two-dimensional bitmap, implemented as a quadtree, for masks such as
+/// terrain coverage and collision layers.
+///
+/// Like [`OctreeBitmap`](crate::OctreeBitmap), uniform quadrants are stored
+/// as single nodes and merged as soon as they become uniform. This is a
+/// [`HypertreeBitmap`] of two dimensions, with coordinates passed
+/// separately.
+pub struct QuadtreeBitmap(HypertreeBitmap<2>);
+
+impl QuadtreeBitmap {
+    /// Creates a new, empty bitmap, with coordinates in the range `0..width`
+    /// on both axes.
+    ///
+    /// As with [`OctreeBitmap::new`](crate::OctreeBitmap::new), the width is
+    /// rounded up to a power of two.
+    pub fn new(width: u32) -> Self {
+        Self(HypertreeBitmap::new(width))
+    }
+
+    pub fn width(&self) -> u32 {
+        self.0.width()
+    }
+
+    /// Clears the map.
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    /// Whether no bits are set.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Get the current value of the bit at the given position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is outside the bitmap.
+    pub fn get(&self, x: u32, y: u32) -> bool {
+        self.0.get(&[x, y])
+    }
+
+    /// Set the value at the given position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is outside the bitmap.
+    pub fn set(&mut self, x: u32, y: u32, value: bool) {
+        self.0.set(&[x, y], value);
+    }
+
+    /// An iterator over the positions of all set bits, in Z order.
+    pub fn iter(&self) -> impl Iterator<Item = [u32; 2]> + '_ {
+        self.0.iter()
+    }
+}
+
+impl Hypertree<2> for QuadtreeBitmap {
+    fn width(&self) -> u32 {
+        self.0.width()
+    }
+
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn get(&self, position: &[u32; 2]) -> bool {
+        self.0.get(position)
+    }
+
+    fn set(&mut self, position: &[u32; 2], value: bool) {
+        self.0.set(position, value);
+    }
+
+    fn iter(&self) -> impl Iterator<Item = [u32; 2]> + '_ {
+        self.0.iter()
+    }
+}
+
+impl From<HypertreeBitmap<2>> for QuadtreeBitmap {
+    fn from(bitmap: HypertreeBitmap<2>) -> Self {
+        Self(bitmap)
+    }
+}
+
+impl From<QuadtreeBitmap> for HypertreeBitmap<2> {
+    fn from(bitmap: QuadtreeBitmap) -> Self {
+        bitmap.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quadtree_set_and_get() {
+        let mut bitmap = QuadtreeBitmap::new(16);
+        let width = bitmap.width();
+        let mut expected = Vec::new();
+        for y in 0..width {
+            for x in 0..width {
+                let value = (x < 8 && y < 8) || (x * 3 + y) % 5 == 0;
+                bitmap.set(x, y, value);
+                if value {
+                    expected.push([x, y]);
+                }
+            }
+        }
+        for y in 0..width {
+            for x in 0..width {
+                assert_eq!(bitmap.get(x, y), expected.contains(&[x, y]));
+            }
+        }
+        let mut iterated: Vec<[u32; 2]> = bitmap.iter().collect();
+        iterated.sort_by_key(|&[x, y]| (y, x));
+        assert_eq!(iterated, expected);
+
+        for &[x, y] in &expected {
+            bitmap.set(x, y, false);
+        }
+        assert!(bitmap.is_empty());
+    }
+}
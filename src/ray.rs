@@ -0,0 +1,421 @@
+//! Ray queries.
+
+use crate::{BranchIndex, Index, OctreeBitmap, View};
+
+/// A face of a voxel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Face {
+    NegX,
+    PosX,
+    NegY,
+    PosY,
+    NegZ,
+    PosZ,
+}
+
+impl Face {
+    /// The face perpendicular to the given axis (0 for `x`, 1 for `y`, 2 for
+    /// `z`) on its positive or negative side.
+    fn from_axis(axis: usize, positive: bool) -> Self {
+        match (axis, positive) {
+            (0, false) => Self::NegX,
+            (0, true) => Self::PosX,
+            (1, false) => Self::NegY,
+            (1, true) => Self::PosY,
+            (2, false) => Self::NegZ,
+            (2, true) => Self::PosZ,
+            _ => unreachable!("invalid axis {}", axis),
+        }
+    }
+
+    /// The outward unit normal of the face.
+    pub fn normal(self) -> [i32; 3] {
+        match self {
+            Self::NegX => [-1, 0, 0],
+            Self::PosX => [1, 0, 0],
+            Self::NegY => [0, -1, 0],
+            Self::PosY => [0, 1, 0],
+            Self::NegZ => [0, 0, -1],
+            Self::PosZ => [0, 0, 1],
+        }
+    }
+}
+
+/// A ray in the space of the bitmap, where the voxel at index `(x, y, z)`
+/// spans `x..x + 1` (and so on) along each axis.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Ray {
+    origin: [f64; 3],
+    dir: [f64; 3],
+}
+
+impl Ray {
+    /// The ray, if its direction is finite and not zero.
+    pub(crate) fn new(origin: [f32; 3], dir: [f32; 3]) -> Option<Self> {
+        let valid = origin.iter().chain(&dir).all(|c| c.is_finite()) && dir != [0.0; 3];
+        valid.then(|| Self {
+            origin: origin.map(f64::from),
+            dir: dir.map(f64::from),
+        })
+    }
+
+    pub(crate) fn at(&self, t: f64) -> [f64; 3] {
+        [0, 1, 2].map(|i| self.origin[i] + self.dir[i] * t)
+    }
+
+    /// The interval of the ray parameter, from `t = 0`, where the ray is
+    /// inside the octant at the given node, and the axis through which it
+    /// enters the octant if the origin is outside it.
+    pub(crate) fn intersect(&self, node: BranchIndex) -> Option<(f64, f64, Option<usize>)> {
+        let base = [node.base.x, node.base.y, node.base.z];
+        let side = (1u64 << node.height) as f64;
+        let mut enter = (f64::NEG_INFINITY, None);
+        let mut exit = f64::INFINITY;
+        for (axis, &low) in base.iter().enumerate() {
+            let low = low as f64;
+            let high = low + side;
+            if self.dir[axis] == 0.0 {
+                if !(low..high).contains(&self.origin[axis]) {
+                    return None;
+                }
+                continue;
+            }
+            let t0 = (low - self.origin[axis]) / self.dir[axis];
+            let t1 = (high - self.origin[axis]) / self.dir[axis];
+            let (near, far) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+            if near > enter.0 {
+                enter = (near, Some(axis));
+            }
+            exit = exit.min(far);
+        }
+        if enter.0 < 0.0 {
+            enter = (0.0, None);
+        }
+        (enter.0 < exit).then_some((enter.0, exit, enter.1))
+    }
+
+    /// The face through which the ray enters a voxel along the given axis.
+    pub(crate) fn entry_face(&self, axis: usize) -> Face {
+        Face::from_axis(axis, self.dir[axis] < 0.0)
+    }
+
+    /// The axis along which the ray travels fastest.
+    pub(crate) fn major_axis(&self) -> usize {
+        (0..3)
+            .max_by(|&a, &b| self.dir[a].abs().total_cmp(&self.dir[b].abs()))
+            .unwrap()
+    }
+}
+
+/// The uniformly-set nodes that a ray passes through, in order along the ray,
+/// as the node, the interval of the ray parameter inside it, and the axis
+/// through which the ray enters it.
+///
+/// Empty nodes are skipped without descending into them.
+pub(crate) struct RayNodes<'a> {
+    bitmap: &'a OctreeBitmap,
+    ray: Ray,
+    stack: Vec<(BranchIndex, View, f64, f64, Option<usize>)>,
+}
+
+impl<'a> RayNodes<'a> {
+    pub(crate) fn new(bitmap: &'a OctreeBitmap, ray: Ray) -> Self {
+        let root = BranchIndex::root(bitmap.height);
+        let view = View::root(bitmap);
+        let stack = match (view, ray.intersect(root)) {
+            (View::Uniform(false), _) | (_, None) => Vec::new(),
+            (view, Some((enter, exit, axis))) => vec![(root, view, enter, exit, axis)],
+        };
+        Self { bitmap, ray, stack }
+    }
+}
+
+impl Iterator for RayNodes<'_> {
+    type Item = (BranchIndex, f64, f64, Option<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (index, view, enter, exit, axis) = self.stack.pop()?;
+            if let View::Uniform(_) = view {
+                return Some((index, enter, exit, axis));
+            }
+            let start = self.stack.len();
+            for i in 0..8 {
+                let child_view = view.child(self.bitmap, index, i);
+                if let View::Uniform(false) = child_view {
+                    continue;
+                }
+                let child = index.child(i & 1, (i >> 1) & 1, i >> 2);
+                if let Some((enter, exit, axis)) = self.ray.intersect(child) {
+                    self.stack.push((child, child_view, enter, exit, axis));
+                }
+            }
+            // The nearest child is popped first.
+            self.stack[start..].sort_unstable_by(|a, b| b.2.total_cmp(&a.2));
+        }
+    }
+}
+
+/// A set voxel that a ray passes through.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    pub index: Index,
+    /// The face through which the ray enters the voxel.
+    ///
+    /// If the ray starts inside the voxel, this is the face facing against
+    /// the ray's fastest-changing axis.
+    pub face: Face,
+    /// The ray parameter where the ray enters the voxel, or zero if it starts
+    /// inside it.
+    pub enter: f32,
+    /// The ray parameter where the ray leaves the voxel.
+    pub exit: f32,
+}
+
+/// A step-by-step walk through the voxels of a uniformly-set node.
+pub(crate) struct VoxelWalk {
+    voxel: [i64; 3],
+    low: [i64; 3],
+    high: [i64; 3],
+    step: [i64; 3],
+    /// The ray parameter at the next boundary crossing along each axis.
+    next: [f64; 3],
+    /// The distance in the ray parameter between crossings along each axis.
+    delta: [f64; 3],
+    enter: f64,
+    exit: f64,
+    axis: usize,
+}
+
+impl VoxelWalk {
+    pub(crate) fn new(
+        ray: &Ray,
+        node: BranchIndex,
+        enter: f64,
+        exit: f64,
+        axis: Option<usize>,
+    ) -> Self {
+        let point = ray.at(enter);
+        let low = [node.base.x, node.base.y, node.base.z].map(i64::from);
+        let high = low.map(|c| c + (1 << node.height));
+        let voxel = [0, 1, 2].map(|i| (point[i].floor() as i64).clamp(low[i], high[i] - 1));
+        let step = ray.dir.map(|d| if d > 0.0 { 1 } else { -1 });
+        let next = [0, 1, 2].map(|i| {
+            let boundary = voxel[i] + i64::from(step[i] > 0);
+            let t = (boundary as f64 - ray.origin[i]) / ray.dir[i];
+            if ray.dir[i] == 0.0 {
+                f64::INFINITY
+            } else {
+                t.max(enter)
+            }
+        });
+        Self {
+            voxel,
+            low,
+            high,
+            step,
+            next,
+            delta: ray.dir.map(|d| 1.0 / d.abs()),
+            enter,
+            exit,
+            axis: axis.unwrap_or_else(|| ray.major_axis()),
+        }
+    }
+
+    /// The next voxel, with the ray parameters where the ray enters and
+    /// leaves it and the axis through which it enters.
+    pub(crate) fn next(&mut self) -> Option<([i64; 3], f64, f64, usize)> {
+        let inside = (0..3).all(|i| (self.low[i]..self.high[i]).contains(&self.voxel[i]));
+        if !inside || self.enter >= self.exit {
+            return None;
+        }
+        let axis = (0..3)
+            .min_by(|&a, &b| self.next[a].total_cmp(&self.next[b]))
+            .unwrap();
+        let exit = self.next[axis].min(self.exit);
+        let hit = (self.voxel, self.enter, exit, self.axis);
+        self.voxel[axis] += self.step[axis];
+        self.next[axis] += self.delta[axis];
+        self.enter = exit;
+        self.axis = axis;
+        Some(hit)
+    }
+}
+
+/// An iterator over the set voxels that a ray passes through, in order along
+/// the ray.
+///
+/// This struct is created by [`OctreeBitmap::ray_iter`].
+pub struct RayIter<'a> {
+    ray: Option<Ray>,
+    nodes: Option<RayNodes<'a>>,
+    walk: Option<VoxelWalk>,
+}
+
+impl Iterator for RayIter<'_> {
+    type Item = RayHit;
+
+    fn next(&mut self) -> Option<RayHit> {
+        let ray = self.ray.as_ref()?;
+        loop {
+            if let Some((voxel, enter, exit, axis)) = self.walk.as_mut().and_then(VoxelWalk::next) {
+                return Some(RayHit {
+                    index: Index::from(voxel.map(|c| c as u32)),
+                    face: ray.entry_face(axis),
+                    enter: enter as f32,
+                    exit: exit as f32,
+                });
+            }
+            let (node, enter, exit, axis) = self.nodes.as_mut()?.next()?;
+            self.walk = Some(VoxelWalk::new(ray, node, enter, exit, axis));
+        }
+    }
+}
+
+impl std::iter::FusedIterator for RayIter<'_> {}
+
+impl OctreeBitmap {
+    /// Iterate over every set voxel that a ray passes through, in order along
+    /// the ray, starting from `origin`.
+    ///
+    /// The ray is given as for [`raycast`](Self::raycast). Each voxel comes
+    /// with the range of the ray parameter `t` inside it, where the ray is at
+    /// `origin + t * dir`, so callers can walk past the first hit, such as to
+    /// accumulate the thickness of material along the ray. Empty nodes are
+    /// skipped whole, as for `raycast`. Yields nothing if `dir` is zero or any
+    /// component is not finite.
+    pub fn ray_iter(&self, origin: [f32; 3], dir: [f32; 3]) -> RayIter<'_> {
+        let ray = Ray::new(origin, dir);
+        RayIter {
+            ray,
+            nodes: ray.map(|ray| RayNodes::new(self, ray)),
+            walk: None,
+        }
+    }
+
+    /// The first set voxel hit by a ray, and the face of the voxel through
+    /// which the ray enters it.
+    ///
+    /// The ray starts at `origin` and travels along `dir`, in the space where
+    /// the voxel at index `(x, y, z)` spans `x..x + 1` (and so on) along each
+    /// axis; `dir` does not need to be normalized. The tree is traversed from
+    /// the root, visiting only the nodes the ray passes through in order and
+    /// skipping empty ones whole, so long rays through sparse space are
+    /// cheap.
+    ///
+    /// If the origin is inside a set voxel, that voxel is returned, along
+    /// with the face facing against the ray's fastest-changing axis. Returns
+    /// `None` if the ray hits nothing, or if `dir` is zero or any component
+    /// is not finite.
+    pub fn raycast(&self, origin: [f32; 3], dir: [f32; 3]) -> Option<(Index, Face)> {
+        self.ray_iter(origin, dir)
+            .next()
+            .map(|hit| (hit.index, hit.face))
+    }
+
+    /// Whether the line segment between the centers of the voxels at `a` and
+    /// `b` passes through no set voxels other than `a` and `b` themselves,
+    /// such as for visibility checks between agents standing in the voxels.
+    ///
+    /// The segment is marched through the tree as for
+    /// [`raycast`](Self::raycast), skipping empty nodes whole. Parts of the
+    /// segment outside the bitmap count as unblocked. A voxel can always see
+    /// itself.
+    pub fn line_of_sight(&self, a: Index, b: Index) -> bool {
+        let origin = [a.x, a.y, a.z].map(|c| c as f32 + 0.5);
+        let dir = [(a.x, b.x), (a.y, b.y), (a.z, b.z)].map(|(a, b)| b as f32 - a as f32);
+        self.ray_iter(origin, dir)
+            .take_while(|hit| hit.enter < 1.0)
+            .all(|hit| hit.index == a || hit.index == b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_hit() {
+        let mut bitmap = OctreeBitmap::new(16);
+        bitmap.fill_node(Index::new(8, 8, 8).branch_at(3), true);
+        bitmap.set(&Index::new(3, 2, 3), true);
+
+        assert_eq!(
+            bitmap.raycast([-5.0, 2.5, 3.5], [1.0, 0.0, 0.0]),
+            Some((Index::new(3, 2, 3), Face::NegX))
+        );
+        assert_eq!(
+            bitmap.raycast([20.0, 9.5, 12.25], [-2.0, 0.0, 0.0]),
+            Some((Index::new(15, 9, 12), Face::PosX))
+        );
+        assert_eq!(
+            bitmap.raycast([9.5, 30.0, 10.5], [0.1, -1.0, 0.2]),
+            Some((Index::new(10, 15, 13), Face::PosY))
+        );
+        assert_eq!(
+            bitmap.raycast([9.5, 9.5, 9.5], [0.0, 0.0, 1.0]),
+            Some((Index::new(9, 9, 9), Face::NegZ))
+        );
+        assert_eq!(bitmap.raycast([-5.0, 2.5, 3.5], [-1.0, 0.0, 0.0]), None);
+        assert_eq!(bitmap.raycast([0.5, 0.5, 0.5], [0.0, 0.0, 0.0]), None);
+
+        // Compare against small steps along diagonal rays.
+        let origin = [-1.3f32, 0.7, -2.1];
+        for i in 0..50 {
+            let target = [7.0 + i as f32 * 0.21, 4.0 + i as f32 * 0.17, 3.1];
+            let dir = [0, 1, 2].map(|j| target[j] - origin[j]);
+            let expected = (0..40_000).find_map(|step| {
+                let t = step as f32 * 0.0005;
+                let p = [0, 1, 2].map(|j| origin[j] + dir[j] * t);
+                if p.iter().any(|&c| !(0.0..16.0).contains(&c)) {
+                    return None;
+                }
+                let idx = Index::from(p.map(|c| c as u32));
+                bitmap.get(&idx).then_some(idx)
+            });
+            assert_eq!(bitmap.raycast(origin, dir).map(|(idx, _)| idx), expected);
+        }
+    }
+
+    #[test]
+    fn every_hit() {
+        let mut bitmap = OctreeBitmap::new(16);
+        bitmap.fill_node(Index::new(8, 0, 0).branch_at(2), true);
+        bitmap.set(&Index::new(2, 1, 1), true);
+        bitmap.set(&Index::new(14, 1, 1), true);
+
+        let hits: Vec<RayHit> = bitmap.ray_iter([0.0, 1.5, 1.5], [2.0, 0.0, 0.0]).collect();
+        let indices: Vec<Index> = hits.iter().map(|hit| hit.index).collect();
+        assert_eq!(indices, [2, 8, 9, 10, 11, 14].map(|x| Index::new(x, 1, 1)));
+        assert_eq!(hits[0].enter, 1.0);
+        assert_eq!(hits[0].exit, 1.5);
+        assert!(hits.iter().all(|hit| hit.face == Face::NegX));
+        assert!(hits.windows(2).all(|w| w[0].exit <= w[1].enter));
+
+        // A diagonal ray through a solid block crosses a voxel at every step.
+        let mut solid = OctreeBitmap::new(16);
+        solid.fill_node(BranchIndex::root(solid.height), true);
+        let hits: Vec<RayHit> = solid.ray_iter([0.5, 0.25, 0.0], [1.0, 1.0, 0.0]).collect();
+        assert_eq!(hits.len() as u32, 2 * solid.width() - 1);
+        assert!(hits.windows(2).all(|w| w[0].exit == w[1].enter));
+        assert_eq!(hits[1].face, Face::NegX);
+        assert_eq!(hits[2].face, Face::NegY);
+    }
+
+    #[test]
+    fn line_of_sight() {
+        let mut bitmap = OctreeBitmap::new(16);
+        bitmap.fill_box([8, 0, 0], [9, 16, 16], true);
+        bitmap.set(&Index::new(2, 2, 2), true);
+        let (a, b) = (Index::new(2, 2, 2), Index::new(5, 9, 3));
+        assert!(bitmap.line_of_sight(a, b));
+        assert!(bitmap.line_of_sight(b, a));
+        assert!(bitmap.line_of_sight(a, a));
+        assert!(!bitmap.line_of_sight(b, Index::new(12, 9, 3)));
+        // The ends themselves may be set.
+        assert!(bitmap.line_of_sight(Index::new(8, 3, 3), Index::new(12, 3, 3)));
+        assert!(bitmap.line_of_sight(Index::new(0, 5, 5), Index::new(3, 5, 5)));
+        bitmap.set(&Index::new(1, 5, 5), true);
+        assert!(!bitmap.line_of_sight(Index::new(0, 5, 5), Index::new(3, 5, 5)));
+    }
+}
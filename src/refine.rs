@@ -0,0 +1,114 @@
+//! Resumable coarse-to-fine traversal.
+
+use std::collections::VecDeque;
+
+use crate::{BranchIndex, Index, Occupancy, OctreeBitmap};
+
+/// A node of the tree whose contents have been classified by
+/// [`OctreeBitmap::refine_next`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RefinedRegion {
+    /// The lowest index in the region.
+    pub base: Index,
+    /// The region is a cube with a side length of `1 << height`.
+    pub height: u32,
+    pub occupancy: Occupancy,
+}
+
+/// The state of a progressive, coarse-to-fine pass over a bitmap.
+///
+/// The state does not borrow the bitmap, so a pass can be spread out over
+/// many calls to [`OctreeBitmap::refine_next`], interleaved with other work
+/// and even with changes to the bitmap. Regions that are yet to be refined
+/// are classified against the bitmap as it is when they are reached.
+#[derive(Debug, Clone, Default)]
+pub struct Refinement {
+    pending: VecDeque<BranchIndex>,
+    started: bool,
+}
+
+impl Refinement {
+    /// The state of a pass that has not started yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the pass has reached every voxel, so that
+    /// [`OctreeBitmap::refine_next`] will return `None`.
+    pub fn is_done(&self) -> bool {
+        self.started && self.pending.is_empty()
+    }
+}
+
+impl OctreeBitmap {
+    /// Advance a progressive pass by one region.
+    ///
+    /// Regions are returned breadth-first, starting from the whole bitmap, so
+    /// every region of a given height is returned before any smaller one.
+    /// Only the octants of [mixed](Occupancy::Mixed) regions are refined
+    /// further, so a caller can stop at any point with a complete, if coarse,
+    /// view of the bitmap: every voxel is within a returned region, and
+    /// uniform regions are exact. Returns `None` once every region has been
+    /// refined down to uniform nodes.
+    pub fn refine_next(&self, state: &mut Refinement) -> Option<RefinedRegion> {
+        if !state.started {
+            state.started = true;
+            state.pending.push_back(BranchIndex::root(self.height));
+        }
+        let node = state.pending.pop_front()?;
+        let occupancy = self.node_occupancy(node);
+        if occupancy == Occupancy::Mixed {
+            for i in 0..8 {
+                state
+                    .pending
+                    .push_back(node.child(i & 1, (i >> 1) & 1, i >> 2));
+            }
+        }
+        Some(RefinedRegion {
+            base: node.base,
+            height: node.height,
+            occupancy,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coarse_to_fine() {
+        let mut bitmap = OctreeBitmap::new(16);
+        bitmap.set(&Index::new(5, 6, 7), true);
+        let mut state = Refinement::new();
+        let mut regions = Vec::new();
+        while let Some(region) = bitmap.refine_next(&mut state) {
+            regions.push(region);
+        }
+        assert!(state.is_done());
+        assert!(regions.windows(2).all(|w| w[0].height >= w[1].height));
+        let mixed = regions
+            .iter()
+            .filter(|region| region.occupancy == Occupancy::Mixed)
+            .count();
+        assert_eq!(regions.len(), 1 + 8 * mixed);
+        assert_eq!(
+            regions
+                .iter()
+                .filter(|region| region.occupancy == Occupancy::Full)
+                .collect::<Vec<_>>(),
+            [&RefinedRegion {
+                base: Index::new(5, 6, 7),
+                height: 0,
+                occupancy: Occupancy::Full,
+            }]
+        );
+
+        // Changes made partway through are picked up by later steps.
+        let mut state = Refinement::new();
+        bitmap.refine_next(&mut state);
+        bitmap.set(&Index::new(5, 6, 7), false);
+        assert!(std::iter::from_fn(|| bitmap.refine_next(&mut state))
+            .all(|region| region.occupancy == Occupancy::Empty));
+    }
+}
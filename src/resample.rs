@@ -0,0 +1,209 @@
+//! Conversion between bitmaps of different widths.
+
+use std::collections::HashMap;
+
+use crate::{BranchIndex, Index, Occupancy, OctreeBitmap};
+
+/// How each voxel of a coarser bitmap is decided from the voxels it covers
+/// when resampling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResamplePolicy {
+    /// Set if any covered voxel is set.
+    Any,
+    /// Set if every covered voxel is set.
+    All,
+    /// Set if more than half of the covered voxels are set.
+    Majority,
+}
+
+impl OctreeBitmap {
+    /// Converts the bitmap to one of a different width, so that each voxel
+    /// covers the same fraction of the domain.
+    ///
+    /// Widths are rounded up to a power of two as in [`new`](Self::new), so
+    /// every voxel of the coarser bitmap covers a cube of voxels of the finer
+    /// one. When the new width is larger, every voxel is scaled up and
+    /// `policy` has no effect; when it is smaller, `policy` decides which of
+    /// the coarser voxels are set. The conversion works on whole nodes of the
+    /// tree, so it takes time proportional to the size of the tree rather
+    /// than the number of voxels.
+    pub fn resample_to_width(&self, new_width: u32, policy: ResamplePolicy) -> OctreeBitmap {
+        self.resample_to_height(OctreeBitmap::new(new_width).height, policy)
+    }
+
+    /// Resamples the bitmap to one with the given root height, as for
+    /// [`resample_to_width`](Self::resample_to_width).
+    fn resample_to_height(&self, height: u32, policy: ResamplePolicy) -> OctreeBitmap {
+        let mut resampled = self.with_height_like(height);
+        if resampled.height >= self.height {
+            let scale = resampled.height - self.height;
+            self.visit_nodes(|base, height, occupancy| {
+                if occupancy == Occupancy::Full {
+                    let base = Index::new(base.x << scale, base.y << scale, base.z << scale);
+                    resampled.fill_node(base.branch_at(height + scale), true);
+                }
+                occupancy == Occupancy::Mixed
+            });
+            return resampled;
+        }
+
+        let scale = self.height - resampled.height;
+        let target = |base: Index| Index::new(base.x >> scale, base.y >> scale, base.z >> scale);
+        // The number of set voxels covered by each partially set voxel, when
+        // counting them is needed.
+        let mut counts: HashMap<Index, u64> = HashMap::new();
+        self.visit_nodes(|base, height, occupancy| match occupancy {
+            Occupancy::Empty => false,
+            Occupancy::Full if height >= scale => {
+                resampled.fill_node(target(base).branch_at(height - scale), true);
+                false
+            }
+            Occupancy::Full => {
+                *counts.entry(target(base)).or_default() += 1 << (3 * height);
+                false
+            }
+            Occupancy::Mixed if height > scale || policy == ResamplePolicy::Majority => true,
+            Occupancy::Mixed => {
+                if policy == ResamplePolicy::Any {
+                    resampled.fill_node(
+                        BranchIndex {
+                            base: target(base),
+                            height: 0,
+                        },
+                        true,
+                    );
+                }
+                false
+            }
+        });
+        let covered = 1u64 << (3 * scale);
+        resampled.set_many(
+            counts
+                .into_iter()
+                .filter(|&(_, count)| 2 * count > covered)
+                .map(|(idx, _)| idx),
+            true,
+        );
+        resampled
+    }
+
+    /// Scales the bitmap up by `factor`, which must be a power of two, so
+    /// that each voxel becomes a uniform cube of `factor` voxels on a side.
+    ///
+    /// The counterpart to downsampling with
+    /// [`resample_to_width`](Self::resample_to_width). Whole nodes are
+    /// copied, so the result has about as many branches as this bitmap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `factor` is not a power of two, or if the scaled width does
+    /// not fit in a `u32`.
+    pub fn upsample(&self, factor: u32) -> OctreeBitmap {
+        assert!(factor.is_power_of_two(), "factor must be a power of two");
+        let height = self.height + factor.trailing_zeros();
+        assert!(height < u32::BITS, "upsampled width is too large");
+        self.resample_to_height(height, ResamplePolicy::Any)
+    }
+
+    /// Builds a pyramid of up to `levels` progressively coarser copies of the
+    /// bitmap, each half the width of the one before, starting with half the
+    /// width of this bitmap. `policy` decides which voxels of each level are
+    /// set from the voxels of this bitmap they cover.
+    ///
+    /// The pyramid stops early at a width of 2, so renderers can pick the
+    /// coarsest occupancy that is detailed enough at a given distance.
+    pub fn build_lods(&self, levels: usize, policy: ResamplePolicy) -> Vec<OctreeBitmap> {
+        (1..self.height)
+            .rev()
+            .take(levels)
+            .map(|height| self.resample_to_height(height, policy))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resample_down_and_up() {
+        let mut bitmap = OctreeBitmap::new(16);
+        // A full 2x2x2 block, one with 5 of 8 set, and one with 1 of 8 set.
+        bitmap.set_many(
+            (0..8).map(|i| Index::new(i & 1, (i >> 1) & 1, i >> 2)),
+            true,
+        );
+        bitmap.set_many(
+            (0..5).map(|i| Index::new(2 + (i & 1), (i >> 1) & 1, i >> 2)),
+            true,
+        );
+        bitmap.set(&Index::new(15, 15, 15), true);
+
+        let coarse = |policy| {
+            bitmap
+                .resample_to_width(8, policy)
+                .iter()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(coarse(ResamplePolicy::All), [Index::new(0, 0, 0)]);
+        assert_eq!(
+            coarse(ResamplePolicy::Majority),
+            [Index::new(0, 0, 0), Index::new(1, 0, 0)]
+        );
+        assert_eq!(
+            coarse(ResamplePolicy::Any),
+            [
+                Index::new(0, 0, 0),
+                Index::new(1, 0, 0),
+                Index::new(7, 7, 7)
+            ]
+        );
+
+        let fine = bitmap.resample_to_width(32, ResamplePolicy::Any);
+        assert_eq!(fine.iter().count(), 8 * (8 + 5 + 1));
+        assert!(fine.get(&Index::new(31, 30, 31)));
+        assert!(!fine.get(&Index::new(29, 30, 31)));
+        assert_eq!(
+            fine.resample_to_width(16, ResamplePolicy::All)
+                .iter()
+                .collect::<Vec<_>>(),
+            bitmap.iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn lods() {
+        let mut bitmap = OctreeBitmap::new(16);
+        let width = bitmap.width();
+        bitmap.fill_sphere([8.0, 8.0, 8.0], 6.0, true);
+        bitmap.set(&Index::new(width - 1, 0, 0), true);
+
+        let lods = bitmap.build_lods(10, ResamplePolicy::Any);
+        assert_eq!(lods.len() as u32, bitmap.height - 1);
+        for (level, lod) in lods.iter().enumerate() {
+            assert_eq!(lod.width(), width >> (level + 1));
+            assert!(lod.get(&Index::new(lod.width() - 1, 0, 0)));
+        }
+        assert_eq!(lods.last().unwrap().iter().count(), 2);
+        assert_eq!(bitmap.build_lods(2, ResamplePolicy::All).len(), 2);
+    }
+
+    #[test]
+    fn upsample() {
+        let mut bitmap = OctreeBitmap::new(4);
+        bitmap.set_many([Index::new(0, 0, 0), Index::new(3, 1, 2)], true);
+        let upsampled = bitmap.upsample(4);
+        assert_eq!(upsampled.width(), bitmap.width() * 4);
+        assert_eq!(upsampled.iter().count(), 2 * 64);
+        assert!(upsampled.get(&Index::new(15, 7, 11)));
+        assert!(!upsampled.get(&Index::new(15, 8, 11)));
+        assert_eq!(
+            upsampled
+                .resample_to_height(bitmap.height, ResamplePolicy::All)
+                .iter()
+                .collect::<Vec<_>>(),
+            bitmap.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(bitmap.upsample(1).iter().count(), 2);
+    }
+}
@@ -0,0 +1,104 @@
+//! Clearing of set voxels by predicate.
+
+use crate::{BranchIndex, Index, OctreeBitmap, RawNode};
+
+impl OctreeBitmap {
+    /// Retains only the set voxels for which `f` returns `true`, clearing the
+    /// others, as for [`HashMap::retain`](std::collections::HashMap::retain).
+    ///
+    /// `f` is called once for each set voxel, in a single traversal of the
+    /// tree. Empty octants are skipped, and full octants where `f` returns
+    /// `false` for every voxel are cleared whole. In recording mode, this is
+    /// recorded as a single edit.
+    pub fn retain(&mut self, f: impl Fn(Index) -> bool) {
+        let mut nodes = Vec::new();
+        let mut voxels = Vec::new();
+        let mut stack = vec![BranchIndex::root(self.height)];
+        while let Some(index) = stack.pop() {
+            for i in 0..8 {
+                let (x, y, z) = (i & 1, (i >> 1) & 1, i >> 2);
+                let child = index.child(x, y, z);
+                match self.branches[&index].children[z][y][x] {
+                    RawNode::False => {}
+                    RawNode::Branch => stack.push(child),
+                    RawNode::True => {
+                        if !retain_full(child, &f, &mut nodes, &mut voxels) {
+                            nodes.push(child);
+                        }
+                    }
+                    RawNode::Brick(mut bits) => {
+                        let base = child.base.morton();
+                        while bits != 0 {
+                            let idx = Index::from_morton(base + u128::from(bits.trailing_zeros()));
+                            bits &= bits - 1;
+                            if !f(idx) {
+                                voxels.push(idx);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        self.record_group(|bitmap| {
+            for node in nodes {
+                bitmap.fill_node(node, false);
+            }
+            bitmap.set_many(voxels, false);
+        });
+    }
+}
+
+/// Calls `f` for each voxel of the full octant at the node, adding the
+/// octants and voxels to clear. Returns whether any voxel is retained; if
+/// none are, clearing the whole octant is left to the caller.
+fn retain_full(
+    node: BranchIndex,
+    f: &impl Fn(Index) -> bool,
+    nodes: &mut Vec<BranchIndex>,
+    voxels: &mut Vec<Index>,
+) -> bool {
+    if node.height == 0 {
+        return f(node.base);
+    }
+    let children = [0, 1, 2, 3, 4, 5, 6, 7].map(|i| {
+        let child = node.child(i & 1, (i >> 1) & 1, i >> 2);
+        (child, retain_full(child, f, nodes, voxels))
+    });
+    if children.iter().all(|&(_, kept)| !kept) {
+        return false;
+    }
+    for (child, kept) in children {
+        match kept {
+            true => {}
+            false if child.height == 0 => voxels.push(child.base),
+            false => nodes.push(child),
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::pseudo_random_indexes;
+
+    #[test]
+    fn retain_matches_filter() {
+        let mut bitmap = OctreeBitmap::new(32);
+        bitmap.fill_box([0, 0, 0], [16, 16, 16], true);
+        bitmap.set_many(pseudo_random_indexes(32, 500), true);
+        let keep = |idx: Index| idx.x >= 8 || (idx.y + idx.z).is_multiple_of(3);
+        let expected: Vec<Index> = bitmap.iter().filter(|&idx| keep(idx)).collect();
+        let before: Vec<Index> = bitmap.iter().collect();
+
+        bitmap.start_recording();
+        bitmap.retain(keep);
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), expected);
+        assert!(bitmap.validate().is_ok());
+        assert!(bitmap.undo());
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), before);
+
+        bitmap.retain(|_| false);
+        assert!(bitmap.is_empty());
+    }
+}
@@ -0,0 +1,129 @@
+//! Runs of set voxels along scanlines.
+
+use crate::{Axis, BranchIndex, Index, OctreeBitmap, RawNode};
+
+impl OctreeBitmap {
+    /// The maximal runs of set voxels along each line parallel to the axis,
+    /// each as the index of its first voxel and its length, such as for
+    /// slicing for 3D printing or run-length encoded export.
+    ///
+    /// Runs are in order of their lines, by the coordinates on the other two
+    /// axes with the last axis most significant, and then in order along
+    /// each line. Each line through a full octant becomes a single run, which
+    /// is joined with the runs of the octants next to it along the axis, so
+    /// the voxels are never visited one at a time.
+    pub fn runs(&self, axis: Axis) -> impl Iterator<Item = (Index, u32)> {
+        let axis = axis.index();
+        // The other axes, most significant first.
+        let (high, low) = match axis {
+            0 => (2, 1),
+            1 => (2, 0),
+            _ => (1, 0),
+        };
+        let mut segments: Vec<([u32; 2], u32, u32)> = Vec::new();
+        let mut push = |coords: [u32; 3], len: u32| {
+            segments.push(([coords[high], coords[low]], coords[axis], len));
+        };
+        let mut stack = vec![BranchIndex::root(self.height)];
+        while let Some(index) = stack.pop() {
+            for i in 0..8 {
+                let (x, y, z) = (i & 1, (i >> 1) & 1, i >> 2);
+                let child = index.child(x, y, z);
+                let base = [child.base.x, child.base.y, child.base.z];
+                match self.branches[&index].children[z][y][x] {
+                    RawNode::False => {}
+                    RawNode::Branch => stack.push(child),
+                    RawNode::True => {
+                        let side = 1 << child.height;
+                        for a in 0..side {
+                            for b in 0..side {
+                                let mut coords = base;
+                                coords[high] += a;
+                                coords[low] += b;
+                                push(coords, side);
+                            }
+                        }
+                    }
+                    RawNode::Brick(mut bits) => {
+                        while bits != 0 {
+                            let offset = Index::from_morton(u128::from(bits.trailing_zeros()));
+                            bits &= bits - 1;
+                            push(
+                                [base[0] | offset.x, base[1] | offset.y, base[2] | offset.z],
+                                1,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        segments.sort_unstable();
+
+        let mut runs: Vec<([u32; 2], u32, u32)> = Vec::new();
+        for (line, start, len) in segments {
+            match runs.last_mut() {
+                Some((last, last_start, last_len))
+                    if *last == line && *last_start + *last_len == start =>
+                {
+                    *last_len += len;
+                }
+                _ => runs.push((line, start, len)),
+            }
+        }
+        runs.into_iter().map(move |(line, start, len)| {
+            let mut coords = [0; 3];
+            coords[high] = line[0];
+            coords[low] = line[1];
+            coords[axis] = start;
+            (Index::from(coords), len)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::pseudo_random_indexes;
+
+    #[test]
+    fn runs_match_scan() {
+        let mut bitmap = OctreeBitmap::new(16);
+        bitmap.fill_box([0, 0, 0], [8, 8, 8], true);
+        bitmap.fill_box([8, 2, 3], [13, 3, 4], true);
+        bitmap.set_many(pseudo_random_indexes(32, 300), true);
+        let width = bitmap.width();
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            let a = axis.index();
+            let (high, low) = [(2, 1), (2, 0), (1, 0)][a];
+            let mut expected = Vec::new();
+            for u in 0..width {
+                for v in 0..width {
+                    let mut run: Option<(Index, u32)> = None;
+                    for t in 0..=width {
+                        let mut coords = [0; 3];
+                        coords[high] = u;
+                        coords[low] = v;
+                        coords[a] = t;
+                        if t < width && bitmap.get(&Index::from(coords)) {
+                            match &mut run {
+                                Some((_, len)) => *len += 1,
+                                None => run = Some((Index::from(coords), 1)),
+                            }
+                        } else {
+                            expected.extend(run.take());
+                        }
+                    }
+                }
+            }
+            assert_eq!(
+                bitmap.runs(axis).collect::<Vec<_>>(),
+                expected,
+                "{:?}",
+                axis
+            );
+        }
+        assert!(bitmap
+            .runs(Axis::X)
+            .any(|run| run == (Index::new(0, 2, 3), 13)));
+    }
+}
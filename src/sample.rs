@@ -0,0 +1,115 @@
+//! Random sampling of set voxels.
+
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use crate::{BranchIndex, Index, OctreeBitmap, RawNode};
+
+impl OctreeBitmap {
+    /// `n` voxels chosen uniformly at random from the set voxels, such as to
+    /// scatter particles or props over occupied space.
+    ///
+    /// Voxels are chosen independently, so the same voxel may be chosen more
+    /// than once. The number of set voxels in each branch is counted once,
+    /// and each sample then descends from the root into each child with a
+    /// probability proportional to its count, so uniform nodes are never
+    /// visited voxel by voxel. Returns nothing if the bitmap is empty.
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R, n: usize) -> Vec<Index> {
+        let root = BranchIndex::root(self.height);
+        let mut counts = HashMap::new();
+        let total = self.count_branch(root, &mut counts);
+        if total == 0 {
+            return Vec::new();
+        }
+        (0..n)
+            .map(|_| self.nth_set(root, rng.random_range(0..total), &counts))
+            .collect()
+    }
+
+    /// Counts the set voxels in the branch and each branch below it.
+    fn count_branch(&self, index: BranchIndex, counts: &mut HashMap<BranchIndex, u128>) -> u128 {
+        let mut count = 0;
+        for i in 0..8 {
+            let (x, y, z) = (i & 1, (i >> 1) & 1, i >> 2);
+            count += match self.branches[&index].children[z][y][x] {
+                RawNode::False => 0,
+                RawNode::True => 1 << (3 * (index.height - 1)),
+                RawNode::Brick(bits) => u128::from(bits.count_ones()),
+                RawNode::Branch => self.count_branch(index.child(x, y, z), counts),
+            };
+        }
+        counts.insert(index, count);
+        count
+    }
+
+    /// The `n`th set voxel in Morton order in the branch.
+    fn nth_set(
+        &self,
+        index: BranchIndex,
+        mut n: u128,
+        counts: &HashMap<BranchIndex, u128>,
+    ) -> Index {
+        let child_height = index.height - 1;
+        for i in 0..8 {
+            let (x, y, z) = (i & 1, (i >> 1) & 1, i >> 2);
+            let child = index.child(x, y, z);
+            let count = match self.branches[&index].children[z][y][x] {
+                RawNode::False => 0,
+                RawNode::True => 1 << (3 * child_height),
+                RawNode::Brick(bits) => u128::from(bits.count_ones()),
+                RawNode::Branch => counts[&child],
+            };
+            if n >= count {
+                n -= count;
+                continue;
+            }
+            return match self.branches[&index].children[z][y][x] {
+                RawNode::True => Index::from_morton(child.base.morton() + n),
+                RawNode::Brick(mut bits) => {
+                    // Clear the lower set bits to find the `n`th.
+                    for _ in 0..n {
+                        bits &= bits - 1;
+                    }
+                    Index::from_morton(child.base.morton() + u128::from(bits.trailing_zeros()))
+                }
+                _ => self.nth_set(child, n, counts),
+            };
+        }
+        unreachable!("fewer set voxels than counted")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::SmallRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn samples_are_set_and_spread() {
+        let mut rng = SmallRng::seed_from_u64(1);
+        let mut bitmap = OctreeBitmap::new(64);
+        assert!(bitmap.sample(&mut rng, 10).is_empty());
+
+        bitmap.fill_node(Index::new(0, 0, 0).branch_at(4), true);
+        for i in 0..4096 {
+            bitmap.set(
+                &Index::new(100 + i % 16, 100 + i / 16 % 16, 100 + i / 256),
+                true,
+            );
+        }
+        bitmap.set(&Index::new(33, 2, 7), true);
+        let samples = bitmap.sample(&mut rng, 2000);
+        assert_eq!(samples.len(), 2000);
+        assert!(samples.iter().all(|idx| bitmap.get(idx)));
+        // Half the set voxels are in each block.
+        let low = samples.iter().filter(|idx| idx.x < 16).count();
+        assert!((800..1200).contains(&low), "{}", low);
+
+        let mut single = OctreeBitmap::new(64);
+        single.set(&Index::new(5, 6, 7), true);
+        assert_eq!(single.sample(&mut rng, 3), [Index::new(5, 6, 7); 3]);
+    }
+}
@@ -0,0 +1,542 @@
+//! Import and export of Minecraft schematic files.
+//!
+//! Both the Sponge (`.schem`, versions 2 and 3) and Litematica (`.litematic`)
+//! formats are supported. Minecraft coordinates map directly onto indexes, so
+//! the Minecraft `y` axis (up) is the `y` axis of the bitmap. Non-air blocks
+//! become set voxels; block states are not preserved.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use fastnbt::{ByteArray, IntArray, LongArray, SerOpts, Value};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::{Index, OctreeBitmap};
+
+/// The block written for set voxels when exporting.
+const SOLID_BLOCK: &str = "minecraft:stone";
+const AIR_BLOCK: &str = "minecraft:air";
+
+/// The Minecraft data version recorded in exported files (1.20.1).
+const DATA_VERSION: i32 = 3465;
+
+/// An error encountered while reading or writing a schematic.
+#[derive(Debug)]
+pub enum SchematicError {
+    Io(io::Error),
+    Nbt(fastnbt::error::Error),
+    /// The file is valid NBT, but not a schematic of the expected format.
+    Invalid(&'static str),
+    /// The bitmap contents are too large to be stored in the format.
+    TooLarge,
+}
+
+impl fmt::Display for SchematicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "failed to access schematic: {}", err),
+            Self::Nbt(err) => write!(f, "invalid NBT data: {}", err),
+            Self::Invalid(reason) => write!(f, "invalid schematic: {}", reason),
+            Self::TooLarge => write!(f, "bitmap is too large for the schematic format"),
+        }
+    }
+}
+
+impl std::error::Error for SchematicError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Nbt(err) => Some(err),
+            Self::Invalid(_) | Self::TooLarge => None,
+        }
+    }
+}
+
+impl From<io::Error> for SchematicError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<fastnbt::error::Error> for SchematicError {
+    fn from(err: fastnbt::error::Error) -> Self {
+        Self::Nbt(err)
+    }
+}
+
+/// Whether the block state with the given name is an air block.
+fn is_air(state: &str) -> bool {
+    let name = state.split('[').next().unwrap_or(state);
+    let name = name.strip_prefix("minecraft:").unwrap_or(name);
+    matches!(name, "air" | "cave_air" | "void_air")
+}
+
+fn field<'a>(compound: &'a Value, name: &'static str) -> Result<&'a Value, SchematicError> {
+    match compound {
+        Value::Compound(fields) => fields.get(name).ok_or(SchematicError::Invalid(name)),
+        _ => Err(SchematicError::Invalid(name)),
+    }
+}
+
+fn int(compound: &Value, name: &'static str) -> Result<i64, SchematicError> {
+    field(compound, name)?
+        .as_i64()
+        .ok_or(SchematicError::Invalid(name))
+}
+
+/// Reads a `{x, y, z}` compound of integers.
+fn vector(compound: &Value, name: &'static str) -> Result<[i64; 3], SchematicError> {
+    let vector = field(compound, name)?;
+    Ok([int(vector, "x")?, int(vector, "y")?, int(vector, "z")?])
+}
+
+fn write_vector(v: [i64; 3]) -> Value {
+    Value::Compound(HashMap::from([
+        ("x".to_owned(), Value::Int(v[0] as i32)),
+        ("y".to_owned(), Value::Int(v[1] as i32)),
+        ("z".to_owned(), Value::Int(v[2] as i32)),
+    ]))
+}
+
+/// The smallest extent, starting at the origin, that contains every set
+/// voxel.
+fn extent(bitmap: &OctreeBitmap) -> [u64; 3] {
+    bitmap.iter().fold([0; 3], |acc, idx| {
+        [
+            acc[0].max(idx.x as u64 + 1),
+            acc[1].max(idx.y as u64 + 1),
+            acc[2].max(idx.z as u64 + 1),
+        ]
+    })
+}
+
+/// The number of blocks in a volume of the given size, or an error if it does
+/// not fit in memory.
+fn volume(size: [u64; 3]) -> Result<u64, SchematicError> {
+    size[0]
+        .checked_mul(size[1])
+        .and_then(|area| area.checked_mul(size[2]))
+        .filter(|&volume| usize::try_from(volume).is_ok())
+        .ok_or(SchematicError::TooLarge)
+}
+
+/// The width of a bitmap that fits the given extent, or an error if it is
+/// wider than a bitmap can be.
+fn bitmap_width(extent: u64) -> Result<u32, SchematicError> {
+    u32::try_from(extent.max(1))
+        .ok()
+        .filter(|&width| width <= OctreeBitmap::MAX_WIDTH)
+        .ok_or(SchematicError::TooLarge)
+}
+
+/// Builds a bitmap from the set voxels of a dense volume with the given size,
+/// in YZX order (`x` varying fastest).
+fn from_dense(
+    size: [u64; 3],
+    set: impl IntoIterator<Item = bool>,
+) -> Result<OctreeBitmap, SchematicError> {
+    let width = bitmap_width(size.iter().copied().max().unwrap_or(0))?;
+    let mut bitmap = OctreeBitmap::new(width);
+    let indices = set.into_iter().enumerate().filter_map(|(i, set)| {
+        let i = i as u64;
+        set.then(|| {
+            Index::new(
+                (i % size[0]) as u32,
+                (i / (size[0] * size[2])) as u32,
+                ((i / size[0]) % size[2]) as u32,
+            )
+        })
+    });
+    bitmap.set_many(indices, true);
+    Ok(bitmap)
+}
+
+/// Reads the set voxels of a bitmap into a dense volume of the given size, in
+/// the same order as [`from_dense`].
+fn to_dense(bitmap: &OctreeBitmap, size: [u64; 3]) -> Vec<bool> {
+    let mut dense = vec![false; (size[0] * size[1] * size[2]) as usize];
+    for idx in bitmap {
+        let i = (idx.y as u64 * size[2] + idx.z as u64) * size[0] + idx.x as u64;
+        dense[i as usize] = true;
+    }
+    dense
+}
+
+fn read_nbt<R: Read>(reader: R) -> Result<Value, SchematicError> {
+    let mut data = Vec::new();
+    GzDecoder::new(reader).read_to_end(&mut data)?;
+    Ok(fastnbt::from_bytes(&data)?)
+}
+
+impl OctreeBitmap {
+    /// Reads a gzip-compressed Sponge schematic (`.schem`, version 2 or 3),
+    /// setting the voxels of all non-air blocks.
+    ///
+    /// The minimum corner of the schematic is placed at index `(0, 0, 0)`,
+    /// and the bitmap is made just wide enough to fit it.
+    pub fn from_sponge_schematic<R: Read>(reader: R) -> Result<Self, SchematicError> {
+        let root = read_nbt(reader)?;
+        // Version 3 nests everything into a `Schematic` compound, and moves the
+        // block data into a `Blocks` container.
+        let (schematic, blocks, data_name) = match field(&root, "Schematic") {
+            Ok(schematic) => (schematic, field(schematic, "Blocks")?, "Data"),
+            Err(_) => (&root, &root, "BlockData"),
+        };
+        // Sizes are unsigned shorts, stored as signed.
+        let size = [
+            int(schematic, "Width")? as u16 as u64,
+            int(schematic, "Height")? as u16 as u64,
+            int(schematic, "Length")? as u16 as u64,
+        ];
+
+        let mut air = Vec::new();
+        match field(blocks, "Palette")? {
+            Value::Compound(palette) => {
+                for (state, id) in palette {
+                    let id = id.as_i64().ok_or(SchematicError::Invalid("Palette"))?;
+                    let id = usize::try_from(id).map_err(|_| SchematicError::Invalid("Palette"))?;
+                    if air.len() <= id {
+                        air.resize(id + 1, false);
+                    }
+                    air[id] = is_air(state);
+                }
+            }
+            _ => return Err(SchematicError::Invalid("Palette")),
+        }
+
+        let data = match field(blocks, data_name)? {
+            Value::ByteArray(data) => data,
+            _ => return Err(SchematicError::Invalid(data_name)),
+        };
+        // Every block takes at least one byte, so the size in the header can
+        // be checked against the data before allocating for it.
+        let volume = volume(size)?;
+        if volume > data.len() as u64 {
+            return Err(SchematicError::Invalid(data_name));
+        }
+        let mut set = Vec::with_capacity(volume as usize);
+        let mut id = 0;
+        let mut shift = 0;
+        for &byte in data.iter() {
+            id |= ((byte as u8 & 0x7f) as usize) << shift;
+            if byte as u8 & 0x80 != 0 {
+                shift += 7;
+                if shift >= 32 {
+                    return Err(SchematicError::Invalid(data_name));
+                }
+                continue;
+            }
+            set.push(!air.get(id).copied().unwrap_or(false));
+            id = 0;
+            shift = 0;
+        }
+        if set.len() as u64 != volume {
+            return Err(SchematicError::Invalid(data_name));
+        }
+        from_dense(size, set)
+    }
+
+    /// Writes the bitmap as a gzip-compressed Sponge schematic (`.schem`,
+    /// version 2).
+    ///
+    /// The schematic spans from index `(0, 0, 0)` to the furthest set voxel
+    /// on each axis. Set voxels are written as stone, and all others as air.
+    pub fn write_sponge_schematic<W: Write>(&self, writer: W) -> Result<(), SchematicError> {
+        let size = extent(self);
+        if size.iter().any(|&len| len > u16::MAX as u64) {
+            return Err(SchematicError::TooLarge);
+        }
+        let block_data = to_dense(self, size)
+            .into_iter()
+            .map(|set| set as i8)
+            .collect();
+        let schematic = Value::Compound(HashMap::from([
+            ("Version".to_owned(), Value::Int(2)),
+            ("DataVersion".to_owned(), Value::Int(DATA_VERSION)),
+            ("Width".to_owned(), Value::Short(size[0] as u16 as i16)),
+            ("Height".to_owned(), Value::Short(size[1] as u16 as i16)),
+            ("Length".to_owned(), Value::Short(size[2] as u16 as i16)),
+            (
+                "Offset".to_owned(),
+                Value::IntArray(IntArray::new(vec![0; 3])),
+            ),
+            ("PaletteMax".to_owned(), Value::Int(2)),
+            (
+                "Palette".to_owned(),
+                Value::Compound(HashMap::from([
+                    (AIR_BLOCK.to_owned(), Value::Int(0)),
+                    (SOLID_BLOCK.to_owned(), Value::Int(1)),
+                ])),
+            ),
+            (
+                "BlockData".to_owned(),
+                Value::ByteArray(ByteArray::new(block_data)),
+            ),
+        ]));
+        let mut encoder = GzEncoder::new(writer, Compression::default());
+        fastnbt::to_writer_with_opts(
+            &mut encoder,
+            &schematic,
+            SerOpts::new().root_name("Schematic"),
+        )?;
+        encoder.finish()?;
+        Ok(())
+    }
+
+    /// Reads a gzip-compressed Litematica schematic (`.litematic`), setting
+    /// the voxels of all non-air blocks in every region.
+    ///
+    /// The minimum corner of the box enclosing all regions is placed at index
+    /// `(0, 0, 0)`, and the bitmap is made just wide enough to fit it.
+    pub fn from_litematic<R: Read>(reader: R) -> Result<Self, SchematicError> {
+        let root = read_nbt(reader)?;
+        let regions = match field(&root, "Regions")? {
+            Value::Compound(regions) => regions,
+            _ => return Err(SchematicError::Invalid("Regions")),
+        };
+
+        // Each region as its minimum corner, size and set voxels.
+        let mut boxes = Vec::new();
+        for region in regions.values() {
+            let position = vector(region, "Position")?;
+            let size = vector(region, "Size")?;
+            // Negative sizes extend from the position in the negative
+            // direction.
+            let min: [i64; 3] =
+                std::array::from_fn(|i| position[i] + if size[i] < 0 { size[i] + 1 } else { 0 });
+            let size: [u64; 3] = size.map(|len| len.unsigned_abs());
+
+            let palette = match field(region, "BlockStatePalette")? {
+                Value::List(palette) => palette,
+                _ => return Err(SchematicError::Invalid("BlockStatePalette")),
+            };
+            let air = palette
+                .iter()
+                .map(|state| match field(state, "Name")? {
+                    Value::String(name) => Ok(is_air(name)),
+                    _ => Err(SchematicError::Invalid("Name")),
+                })
+                .collect::<Result<Vec<bool>, _>>()?;
+            let states = match field(region, "BlockStates")? {
+                Value::LongArray(states) => states,
+                _ => return Err(SchematicError::Invalid("BlockStates")),
+            };
+
+            let bits = (usize::BITS - air.len().saturating_sub(1).leading_zeros()).max(2) as u64;
+            let mask = (1 << bits) - 1;
+            let volume = volume(size)?;
+            let state_bits = volume.checked_mul(bits).ok_or(SchematicError::TooLarge)?;
+            if (states.len() as u64) * 64 < state_bits {
+                return Err(SchematicError::Invalid("BlockStates"));
+            }
+            // Entries are packed tightly, and may span two longs.
+            let set: Vec<bool> = (0..volume)
+                .map(|i| {
+                    let start = i * bits;
+                    let (word, offset) = ((start / 64) as usize, start % 64);
+                    let mut id = (states[word] as u64) >> offset;
+                    if offset + bits > 64 {
+                        id |= (states[word + 1] as u64) << (64 - offset);
+                    }
+                    !air.get((id & mask) as usize).copied().unwrap_or(false)
+                })
+                .collect();
+            boxes.push((min, size, set));
+        }
+
+        let origin: [i64; 3] =
+            std::array::from_fn(|i| boxes.iter().map(|(min, _, _)| min[i]).min().unwrap_or(0));
+        let end: [i64; 3] = std::array::from_fn(|i| {
+            boxes
+                .iter()
+                .map(|(min, size, _)| min[i] + size[i] as i64)
+                .max()
+                .unwrap_or(0)
+        });
+        let width = (0..3).map(|i| end[i] - origin[i]).max().unwrap_or(0);
+        let mut bitmap = Self::new(bitmap_width(width as u64)?);
+        for (min, size, set) in boxes {
+            let base = Index::new(
+                (min[0] - origin[0]) as u32,
+                (min[1] - origin[1]) as u32,
+                (min[2] - origin[2]) as u32,
+            );
+            let region = from_dense(size, set)?;
+            bitmap.set_many(
+                region
+                    .iter()
+                    .map(|idx| Index::new(base.x + idx.x, base.y + idx.y, base.z + idx.z)),
+                true,
+            );
+        }
+        Ok(bitmap)
+    }
+
+    /// Writes the bitmap as a gzip-compressed Litematica schematic
+    /// (`.litematic`) with a single region.
+    ///
+    /// The region spans from index `(0, 0, 0)` to the furthest set voxel on
+    /// each axis. Set voxels are written as stone, and all others as air.
+    pub fn write_litematic<W: Write>(&self, writer: W) -> Result<(), SchematicError> {
+        let size = extent(self);
+        if size.iter().any(|&len| len > i32::MAX as u64) {
+            return Err(SchematicError::TooLarge);
+        }
+        let dense = to_dense(self, size);
+        let total_blocks = dense.iter().filter(|&&set| set).count();
+        // Two palette entries still use the minimum of two bits each.
+        let mut states = vec![0i64; (dense.len() * 2).div_ceil(64)];
+        for (i, set) in dense.into_iter().enumerate() {
+            if set {
+                states[i * 2 / 64] |= 1 << (i * 2 % 64);
+            }
+        }
+
+        let size = size.map(|len| len as i64);
+        let block_state = |name: &str| {
+            Value::Compound(HashMap::from([(
+                "Name".to_owned(),
+                Value::String(name.to_owned()),
+            )]))
+        };
+        let region = Value::Compound(HashMap::from([
+            ("Position".to_owned(), write_vector([0; 3])),
+            ("Size".to_owned(), write_vector(size)),
+            (
+                "BlockStatePalette".to_owned(),
+                Value::List(vec![block_state(AIR_BLOCK), block_state(SOLID_BLOCK)]),
+            ),
+            (
+                "BlockStates".to_owned(),
+                Value::LongArray(LongArray::new(states)),
+            ),
+            ("Entities".to_owned(), Value::List(Vec::new())),
+            ("TileEntities".to_owned(), Value::List(Vec::new())),
+            ("PendingBlockTicks".to_owned(), Value::List(Vec::new())),
+            ("PendingFluidTicks".to_owned(), Value::List(Vec::new())),
+        ]));
+        let metadata = Value::Compound(HashMap::from([
+            ("Name".to_owned(), Value::String("october".to_owned())),
+            ("Author".to_owned(), Value::String(String::new())),
+            ("Description".to_owned(), Value::String(String::new())),
+            ("RegionCount".to_owned(), Value::Int(1)),
+            ("TotalBlocks".to_owned(), Value::Int(total_blocks as i32)),
+            (
+                "TotalVolume".to_owned(),
+                Value::Int((size[0] * size[1] * size[2]) as i32),
+            ),
+            ("EnclosingSize".to_owned(), write_vector(size)),
+            ("TimeCreated".to_owned(), Value::Long(0)),
+            ("TimeModified".to_owned(), Value::Long(0)),
+        ]));
+        let root = Value::Compound(HashMap::from([
+            ("Version".to_owned(), Value::Int(6)),
+            ("SubVersion".to_owned(), Value::Int(1)),
+            ("MinecraftDataVersion".to_owned(), Value::Int(DATA_VERSION)),
+            ("Metadata".to_owned(), metadata),
+            (
+                "Regions".to_owned(),
+                Value::Compound(HashMap::from([("october".to_owned(), region)])),
+            ),
+        ]));
+        let mut encoder = GzEncoder::new(writer, Compression::default());
+        fastnbt::to_writer(&mut encoder, &root)?;
+        encoder.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> OctreeBitmap {
+        let mut bitmap = OctreeBitmap::new(16);
+        for idx in [(0, 0, 0), (3, 1, 4), (1, 5, 9), (2, 6, 5), (5, 3, 5)] {
+            bitmap.set(&Index::from(idx), true);
+        }
+        bitmap
+    }
+
+    #[test]
+    fn sponge_round_trip() {
+        let bitmap = sample();
+        let mut file = Vec::new();
+        bitmap.write_sponge_schematic(&mut file).unwrap();
+        let read = OctreeBitmap::from_sponge_schematic(file.as_slice()).unwrap();
+        assert_eq!(
+            read.iter().collect::<Vec<_>>(),
+            bitmap.iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn litematic_round_trip() {
+        let bitmap = sample();
+        let mut file = Vec::new();
+        bitmap.write_litematic(&mut file).unwrap();
+        let read = OctreeBitmap::from_litematic(file.as_slice()).unwrap();
+        assert_eq!(
+            read.iter().collect::<Vec<_>>(),
+            bitmap.iter().collect::<Vec<_>>()
+        );
+    }
+
+    /// Rewrites the root compound of a schematic written by this module.
+    fn edit_nbt(
+        file: &[u8],
+        root_name: &str,
+        edit: impl FnOnce(&mut HashMap<String, Value>),
+    ) -> Vec<u8> {
+        let Value::Compound(mut root) = read_nbt(file).unwrap() else {
+            panic!("root is not a compound");
+        };
+        edit(&mut root);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        fastnbt::to_writer_with_opts(
+            &mut encoder,
+            &Value::Compound(root),
+            SerOpts::new().root_name(root_name),
+        )
+        .unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn oversized_headers() {
+        let mut file = Vec::new();
+        sample().write_sponge_schematic(&mut file).unwrap();
+        let huge = edit_nbt(&file, "Schematic", |root| {
+            for name in ["Width", "Height", "Length"] {
+                root.insert(name.to_owned(), Value::Short(-1));
+            }
+        });
+        assert!(matches!(
+            OctreeBitmap::from_sponge_schematic(huge.as_slice()),
+            Err(SchematicError::Invalid("BlockData"))
+        ));
+
+        // Two single blocks further apart than the widest bitmap.
+        let mut file = Vec::new();
+        let mut block = OctreeBitmap::new(1);
+        block.set(&Index::new(0, 0, 0), true);
+        block.write_litematic(&mut file).unwrap();
+        let far = edit_nbt(&file, "", |root| {
+            let Some(Value::Compound(regions)) = root.get_mut("Regions") else {
+                panic!("missing regions");
+            };
+            let mut region = regions["october"].clone();
+            if let Value::Compound(fields) = &mut region {
+                fields.insert("Position".to_owned(), write_vector([(1 << 31) - 2, 0, 0]));
+            }
+            regions.insert("far".to_owned(), region);
+        });
+        assert!(matches!(
+            OctreeBitmap::from_litematic(far.as_slice()),
+            Err(SchematicError::TooLarge)
+        ));
+    }
+}
@@ -0,0 +1,117 @@
+//! Measurements of the size of a tree.
+
+use std::mem;
+
+use crate::{Branch, BranchIndex, OctreeBitmap, RawNode};
+
+/// The size of a bitmap's tree, as returned by [`OctreeBitmap::stats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of branches, including the root.
+    pub branches: usize,
+    /// The number of branches at each height, indexed by height.
+    pub branches_per_level: Vec<usize>,
+    /// The number of dense leaf bricks.
+    pub bricks: usize,
+    /// An estimate of the heap memory used by the tree, in bytes, not
+    /// counting the undo history.
+    pub heap_bytes: usize,
+    /// The memory a dense bitmap of the same width would use, at one bit per
+    /// voxel, in bytes.
+    pub dense_bytes: u128,
+}
+
+impl Stats {
+    /// How many times smaller the tree is than a dense bitmap.
+    pub fn compression_ratio(&self) -> f64 {
+        self.dense_bytes as f64 / self.heap_bytes.max(1) as f64
+    }
+}
+
+impl OctreeBitmap {
+    /// Measures the size of the tree, to help reason about the memory used
+    /// by large bitmaps.
+    pub fn stats(&self) -> Stats {
+        let mut branches_per_level = vec![0; self.height as usize + 1];
+        let mut bricks = 0;
+        for (index, branch) in &self.branches {
+            branches_per_level[index.height as usize] += 1;
+            bricks += branch
+                .children
+                .iter()
+                .flatten()
+                .flatten()
+                .filter(|child| matches!(child, RawNode::Brick(_)))
+                .count();
+        }
+        // Each bucket of the map holds a key and value, and a control byte.
+        let bucket = mem::size_of::<(BranchIndex, Branch)>() + 1;
+        Stats {
+            branches: self.branches.len(),
+            branches_per_level,
+            bricks,
+            heap_bytes: self.branches.capacity() * bucket,
+            dense_bytes: (1u128 << (3 * self.height)).div_ceil(8),
+        }
+    }
+
+    /// The fraction of the voxels of the bitmap that are set, from `0.0` for
+    /// an empty bitmap to `1.0` for a full one.
+    ///
+    /// Uniform nodes and bricks are counted whole, so this takes time
+    /// proportional to the number of branches rather than of voxels.
+    pub fn density(&self) -> f64 {
+        self.count_set() as f64 / (1u128 << (3 * self.height)) as f64
+    }
+
+    /// The number of voxels that are set.
+    pub(crate) fn count_set(&self) -> u128 {
+        let mut count = 0;
+        for (index, branch) in &self.branches {
+            let child_height = index.height - 1;
+            for child in branch.children.iter().flatten().flatten() {
+                count += match *child {
+                    RawNode::True => 1u128 << (3 * child_height),
+                    RawNode::Brick(bits) => u128::from(bits.count_ones()),
+                    RawNode::False | RawNode::Branch => 0,
+                };
+            }
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Index;
+
+    #[test]
+    fn stats() {
+        let mut bitmap = OctreeBitmap::new(64);
+        let empty = bitmap.stats();
+        assert_eq!(empty.branches, 1);
+        assert_eq!(empty.bricks, 0);
+        assert_eq!(empty.dense_bytes, 128 * 128 * 128 / 8);
+
+        bitmap.set(&Index::new(1, 2, 3), true);
+        let stats = bitmap.stats();
+        assert_eq!(stats.branches, bitmap.height as usize - 2);
+        assert_eq!(stats.branches_per_level[..4], [0, 0, 0, 1]);
+        assert_eq!(stats.bricks, 1);
+        assert!(stats.heap_bytes >= stats.branches * mem::size_of::<Branch>());
+        assert!(stats.compression_ratio() > 1.0);
+    }
+
+    #[test]
+    fn density() {
+        let mut bitmap = OctreeBitmap::new(16);
+        assert_eq!(bitmap.density(), 0.0);
+        bitmap.fill_sphere([10.0, 12.0, 14.0], 9.0, true);
+        bitmap.set(&Index::new(31, 0, 31), true);
+        let volume = f64::from(bitmap.width()).powi(3);
+        assert_eq!(bitmap.density(), bitmap.iter().count() as f64 / volume);
+        bitmap.fill_node(BranchIndex::root(bitmap.height), true);
+        assert_eq!(bitmap.density(), 1.0);
+    }
+}
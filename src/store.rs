@@ -0,0 +1,278 @@
+//! Pluggable storage for the branches of a bitmap.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::BuildHasher;
+use std::sync::Arc;
+
+use crate::{Branch, BranchIndex, BranchMap, Index};
+
+/// Storage for the branches of an [`OctreeBitmap`](crate::OctreeBitmap),
+/// a map from the index of each branch to its children.
+///
+/// The default store is a [`HashMap`], and a [`BTreeMap`] or a
+/// [`SharedStore`] can be used as well. Implementing this for other maps,
+/// such as an arena or a memory-mapped table, allows comparing their
+/// performance without changing the bitmap itself. Only the basic
+/// operations of the bitmap, such as [`get`](crate::OctreeBitmap::get),
+/// [`set`](crate::OctreeBitmap::set),
+/// [`set_many`](crate::OctreeBitmap::set_many) and undo, are available with
+/// other stores; [`into_store`](crate::OctreeBitmap::into_store) converts a
+/// bitmap to the default store for the rest.
+///
+/// Indexing must panic if there is no branch at the index.
+pub trait NodeStore:
+    Default
+    + for<'a> std::ops::Index<&'a BranchIndex, Output = Branch>
+    + Extend<(BranchIndex, Branch)>
+    + IntoIterator<Item = (BranchIndex, Branch)>
+{
+    fn get(&self, index: &BranchIndex) -> Option<&Branch>;
+
+    fn get_mut(&mut self, index: &BranchIndex) -> Option<&mut Branch>;
+
+    /// Stores the branch at the index, returning the branch it replaced.
+    fn insert(&mut self, index: BranchIndex, branch: Branch) -> Option<Branch>;
+
+    fn remove(&mut self, index: &BranchIndex) -> Option<Branch>;
+
+    /// The number of branches stored.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn clear(&mut self);
+
+    /// Makes room for at least `additional` more branches, if the store can
+    /// be pre-sized. The default does nothing.
+    fn reserve(&mut self, additional: usize) {
+        let _ = additional;
+    }
+}
+
+impl<H: BuildHasher + Default> NodeStore for HashMap<BranchIndex, Branch, H> {
+    fn get(&self, index: &BranchIndex) -> Option<&Branch> {
+        HashMap::get(self, index)
+    }
+
+    fn get_mut(&mut self, index: &BranchIndex) -> Option<&mut Branch> {
+        HashMap::get_mut(self, index)
+    }
+
+    fn insert(&mut self, index: BranchIndex, branch: Branch) -> Option<Branch> {
+        HashMap::insert(self, index, branch)
+    }
+
+    fn remove(&mut self, index: &BranchIndex) -> Option<Branch> {
+        HashMap::remove(self, index)
+    }
+
+    fn len(&self) -> usize {
+        HashMap::len(self)
+    }
+
+    fn clear(&mut self) {
+        HashMap::clear(self);
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        HashMap::reserve(self, additional);
+    }
+}
+
+impl NodeStore for BTreeMap<BranchIndex, Branch> {
+    fn get(&self, index: &BranchIndex) -> Option<&Branch> {
+        BTreeMap::get(self, index)
+    }
+
+    fn get_mut(&mut self, index: &BranchIndex) -> Option<&mut Branch> {
+        BTreeMap::get_mut(self, index)
+    }
+
+    fn insert(&mut self, index: BranchIndex, branch: Branch) -> Option<Branch> {
+        BTreeMap::insert(self, index, branch)
+    }
+
+    fn remove(&mut self, index: &BranchIndex) -> Option<Branch> {
+        BTreeMap::remove(self, index)
+    }
+
+    fn len(&self) -> usize {
+        BTreeMap::len(self)
+    }
+
+    fn clear(&mut self) {
+        BTreeMap::clear(self);
+    }
+}
+
+/// The height of the subtrees that a [`SharedStore`] shares between clones.
+const SHARD_HEIGHT: u32 = 6;
+
+/// A [`NodeStore`] whose clones share their branches, copying them only when
+/// they are changed, so that cloning a bitmap is cheap.
+///
+/// The branches are grouped by the octant of a fixed size that they are in,
+/// each group behind an [`Arc`]. Cloning the store only clones the pointers,
+/// and the first change to a group in either copy copies that group alone.
+/// This makes it affordable to keep a snapshot of a large bitmap every frame
+/// while editing a small part of it.
+#[derive(Default, Clone)]
+pub struct SharedStore {
+    shards: HashMap<Index, Arc<BranchMap>>,
+    len: usize,
+}
+
+impl SharedStore {
+    /// The key of the group that holds the branch.
+    fn shard(index: &BranchIndex) -> Index {
+        let mask = !((1 << SHARD_HEIGHT) - 1);
+        Index::new(
+            index.base.x & mask,
+            index.base.y & mask,
+            index.base.z & mask,
+        )
+    }
+}
+
+impl NodeStore for SharedStore {
+    fn get(&self, index: &BranchIndex) -> Option<&Branch> {
+        self.shards.get(&Self::shard(index))?.get(index)
+    }
+
+    fn get_mut(&mut self, index: &BranchIndex) -> Option<&mut Branch> {
+        let shard = self.shards.get_mut(&Self::shard(index))?;
+        if !shard.contains_key(index) {
+            return None;
+        }
+        Arc::make_mut(shard).get_mut(index)
+    }
+
+    fn insert(&mut self, index: BranchIndex, branch: Branch) -> Option<Branch> {
+        let shard = self.shards.entry(Self::shard(&index)).or_default();
+        let old = Arc::make_mut(shard).insert(index, branch);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    fn remove(&mut self, index: &BranchIndex) -> Option<Branch> {
+        let key = Self::shard(index);
+        let shard = self.shards.get_mut(&key)?;
+        if !shard.contains_key(index) {
+            return None;
+        }
+        let old = Arc::make_mut(shard).remove(index);
+        if shard.is_empty() {
+            self.shards.remove(&key);
+        }
+        self.len -= 1;
+        old
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn clear(&mut self) {
+        self.shards.clear();
+        self.len = 0;
+    }
+}
+
+impl std::ops::Index<&BranchIndex> for SharedStore {
+    type Output = Branch;
+
+    fn index(&self, index: &BranchIndex) -> &Branch {
+        self.get(index).expect("no branch at index")
+    }
+}
+
+impl Extend<(BranchIndex, Branch)> for SharedStore {
+    fn extend<T: IntoIterator<Item = (BranchIndex, Branch)>>(&mut self, iter: T) {
+        for (index, branch) in iter {
+            self.insert(index, branch);
+        }
+    }
+}
+
+impl IntoIterator for SharedStore {
+    type Item = (BranchIndex, Branch);
+    type IntoIter = std::iter::FlatMap<
+        std::collections::hash_map::IntoValues<Index, Arc<BranchMap>>,
+        BranchMap,
+        fn(Arc<BranchMap>) -> BranchMap,
+    >;
+
+    /// The branches, copying those still shared with a clone.
+    fn into_iter(self) -> Self::IntoIter {
+        self.shards
+            .into_values()
+            .flat_map(|shard| Arc::try_unwrap(shard).unwrap_or_else(|shard| (*shard).clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Index, OctreeBitmap};
+
+    use super::*;
+
+    #[test]
+    fn btree_store() {
+        let mut bitmap =
+            OctreeBitmap::<BTreeMap<BranchIndex, Branch>>::with_store(32, BTreeMap::new());
+        bitmap.start_recording();
+        bitmap.set(&Index::new(1, 2, 3), true);
+        bitmap.set_many(
+            (0..64).map(|i| Index::new(i % 4, i / 4 % 4, 16 + i / 16)),
+            true,
+        );
+        assert!(bitmap.get(&Index::new(1, 2, 3)));
+        assert!(bitmap.get(&Index::new(3, 3, 19)));
+        assert!(!bitmap.get(&Index::new(4, 3, 19)));
+        assert!(bitmap.undo());
+        assert!(!bitmap.get(&Index::new(3, 3, 19)));
+
+        let bitmap: OctreeBitmap = bitmap.into_store();
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), [Index::new(1, 2, 3)]);
+    }
+
+    #[test]
+    fn shared_clones() {
+        let mut bitmap = OctreeBitmap::with_store(256, SharedStore::default());
+        bitmap.set_many((0..200).map(|i| Index::new(i, i * 7 % 256, 3)), true);
+        let snapshot = bitmap.clone();
+        let shared = |a: &OctreeBitmap<SharedStore>, b: &OctreeBitmap<SharedStore>| {
+            a.branches
+                .shards
+                .iter()
+                .filter(|(key, shard)| Arc::ptr_eq(shard, &b.branches.shards[key]))
+                .count()
+        };
+        let shards = bitmap.branches.shards.len();
+        assert!(shards > 1);
+        assert_eq!(shared(&bitmap, &snapshot), shards);
+
+        // Only the group that changed is copied.
+        bitmap.set(&Index::new(1, 1, 3), true);
+        assert_eq!(shared(&bitmap, &snapshot), shards - 1);
+        assert!(bitmap.get(&Index::new(1, 1, 3)));
+        assert!(!snapshot.get(&Index::new(1, 1, 3)));
+        assert_eq!(
+            bitmap.branches.len(),
+            bitmap
+                .branches
+                .shards
+                .values()
+                .map(|s| s.len())
+                .sum::<usize>()
+        );
+
+        let bitmap: OctreeBitmap = bitmap.into_store();
+        let snapshot: OctreeBitmap = snapshot.into_store();
+        assert_eq!(bitmap.iter().count(), snapshot.iter().count() + 1);
+    }
+}
@@ -0,0 +1,155 @@
+//! Smooth surface extraction with surface nets.
+
+use std::collections::HashMap;
+
+use crate::quads::{axis_of, positive};
+use crate::{Index, OctreeBitmap};
+
+/// An indexed triangle mesh.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TriangleMesh {
+    /// Vertex positions, in the space where the voxel at index `(x, y, z)`
+    /// spans `x..x + 1` (and so on) along each axis.
+    pub positions: Vec<[f32; 3]>,
+    /// Unit vertex normals, pointing out of the set voxels.
+    pub normals: Vec<[f32; 3]>,
+    /// The vertex indices of each triangle, counterclockwise when viewed
+    /// from outside.
+    pub indices: Vec<[u32; 3]>,
+}
+
+impl OctreeBitmap {
+    /// Whether the voxel at the given signed position is set, where positions
+    /// outside of the bitmap are unset.
+    fn get_signed(&self, position: [i64; 3]) -> bool {
+        let width = self.width() as i64;
+        position.iter().all(|&c| (0..width).contains(&c))
+            && self.get(&Index::from(position.map(|c| c as u32)))
+    }
+
+    /// Extracts a smooth, closed triangle mesh of the boundary between set and
+    /// unset voxels with the surface nets algorithm.
+    ///
+    /// The voxel centers form a grid of cells. Every cell whose corners are
+    /// not all equal gets one vertex, at the average of the midpoints of its
+    /// edges that cross the boundary, and every pair of neighboring voxels
+    /// where one is set and the other is not contributes a quad between the
+    /// four cells around them. The result follows the shape of the voxels
+    /// without their stair steps. The outside of the bitmap counts as unset,
+    /// and only the cells along the boundary are visited, found from the
+    /// tree's uniform nodes as for [`extract_quads`](Self::extract_quads).
+    pub fn surface_nets(&self) -> TriangleMesh {
+        let mut mesh = TriangleMesh::default();
+        // The vertex index of each cell, keyed by its lowest corner.
+        let mut vertices: HashMap<[i64; 3], u32> = HashMap::new();
+        let mut planes: Vec<_> = self.exposed_planes().into_iter().collect();
+        planes.sort_unstable_by_key(|&((face, plane), _)| (face as u8, plane));
+
+        for ((face, plane), cells) in planes {
+            let axis = axis_of(face);
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            for [cu, cv] in cells {
+                // The four cells around the edge between the two voxels
+                // across this face, counterclockwise around the axis.
+                let quad = [(-1, -1), (0, -1), (0, 0), (-1, 0)].map(|(du, dv)| {
+                    let mut cell = [0; 3];
+                    cell[axis] = plane as i64 - 1;
+                    cell[u] = cu as i64 + du;
+                    cell[v] = cv as i64 + dv;
+                    *vertices
+                        .entry(cell)
+                        .or_insert_with(|| self.cell_vertex(cell, &mut mesh))
+                });
+                if positive(face) {
+                    mesh.indices.push([quad[0], quad[1], quad[2]]);
+                    mesh.indices.push([quad[0], quad[2], quad[3]]);
+                } else {
+                    mesh.indices.push([quad[0], quad[2], quad[1]]);
+                    mesh.indices.push([quad[0], quad[3], quad[2]]);
+                }
+            }
+        }
+        mesh
+    }
+
+    /// Adds the vertex of the cell with the given lowest corner to the mesh,
+    /// returning its index.
+    fn cell_vertex(&self, cell: [i64; 3], mesh: &mut TriangleMesh) -> u32 {
+        let corners: [bool; 8] = std::array::from_fn(|i| {
+            self.get_signed([
+                cell[0] + (i & 1) as i64,
+                cell[1] + ((i >> 1) & 1) as i64,
+                cell[2] + (i >> 2) as i64,
+            ])
+        });
+        let offset = |i: usize| [i & 1, (i >> 1) & 1, i >> 2].map(|b| b as f64);
+        let mut sum = [0.0; 3];
+        let mut crossings = 0;
+        let mut normal = [0.0; 3];
+        for i in 0..8 {
+            let corner = offset(i);
+            let sign = if corners[i] { -1.0 } else { 1.0 };
+            for axis in 0..3 {
+                normal[axis] += sign * (corner[axis] - 0.5);
+            }
+            for axis in 0..3 {
+                let j = i | 1 << axis;
+                if j != i && corners[i] != corners[j] {
+                    let other = offset(j);
+                    for k in 0..3 {
+                        sum[k] += (corner[k] + other[k]) / 2.0;
+                    }
+                    crossings += 1;
+                }
+            }
+        }
+        let position = [0, 1, 2].map(|k| (cell[k] as f64 + 0.5 + sum[k] / crossings as f64) as f32);
+        let length = normal.iter().map(|c| c * c).sum::<f64>().sqrt();
+        let normal = if length > 0.0 {
+            normal.map(|c| (c / length) as f32)
+        } else {
+            [0.0; 3]
+        };
+        mesh.positions.push(position);
+        mesh.normals.push(normal);
+        mesh.positions.len() as u32 - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closed_surface() {
+        let mut bitmap = OctreeBitmap::new(16);
+        bitmap.set(&Index::new(3, 3, 3), true);
+        let mesh = bitmap.surface_nets();
+        // A single voxel becomes a smaller cube inside it, with a vertex for
+        // each cell that it is a corner of.
+        assert_eq!(mesh.positions.len(), 8);
+        assert_eq!(mesh.indices.len(), 6 * 2);
+        assert!(mesh
+            .positions
+            .iter()
+            .flatten()
+            .all(|&c| (3.3..3.7).contains(&c) && (c - 3.5).abs() > 0.1));
+
+        bitmap.fill_sphere([8.0, 8.0, 8.0], 5.0, true);
+        let mesh = bitmap.surface_nets();
+        // Every edge of a closed mesh is shared by exactly two triangles, in
+        // opposite directions.
+        let mut edges = HashMap::new();
+        for triangle in &mesh.indices {
+            for i in 0..3 {
+                *edges
+                    .entry((triangle[i], triangle[(i + 1) % 3]))
+                    .or_insert(0) += 1;
+            }
+        }
+        assert!(edges
+            .iter()
+            .all(|(&(a, b), &count)| count == 1 && edges.get(&(b, a)) == Some(&1)));
+        assert_eq!(mesh.normals.len(), mesh.positions.len());
+    }
+}
@@ -0,0 +1,199 @@
+//! Sparse voxel DAGs, which store identical subtrees only once.
+
+use std::collections::HashMap;
+
+use crate::{Branch, BranchIndex, Index, OctreeBitmap, RawNode, View};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum DagChild {
+    Uniform(bool),
+    /// The position of a node in [`SvdagBitmap::nodes`].
+    Node(u32),
+    /// The bits of a brick, as in the octree.
+    Brick(u64),
+}
+
+/// A bitmap as a directed acyclic graph of nodes, in which every distinct
+/// subtree is stored once and shared by all positions it appears at.
+///
+/// Repetitive content, such as walls, floors and tiled structures, takes up
+/// far less memory than in an [`OctreeBitmap`], at the cost of being
+/// read-only. Created by [`OctreeBitmap::to_dag`].
+#[derive(Debug, Clone)]
+pub struct SvdagBitmap {
+    /// The children of each node in Morton order. Every node comes after
+    /// all of its descendants.
+    nodes: Vec<[DagChild; 8]>,
+    root: DagChild,
+    height: u32,
+    /// The leaf height of the bitmap, which the bricks were stored at.
+    leaf_height: u32,
+}
+
+impl SvdagBitmap {
+    pub fn width(&self) -> u32 {
+        1 << self.height
+    }
+
+    /// The number of distinct nodes stored, each of which would take up a
+    /// branch for every position it appears at in an [`OctreeBitmap`].
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Get the current value of the bit at the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is outside the bitmap.
+    pub fn get(&self, idx: &Index) -> bool {
+        let width = self.width();
+        assert!(
+            idx.x < width && idx.y < width && idx.z < width,
+            "index {:?} is out of bounds for width {}",
+            idx,
+            width
+        );
+        let mut node = self.root;
+        let mut height = self.height;
+        loop {
+            match node {
+                DagChild::Uniform(value) => return value,
+                DagChild::Brick(bits) => return (bits >> idx.brick_offset(height)) & 1 != 0,
+                DagChild::Node(n) => {
+                    let (x, y, z) = idx.bit(height - 1);
+                    node = self.nodes[n as usize][x | y << 1 | z << 2];
+                    height -= 1;
+                }
+            }
+        }
+    }
+
+    /// Expands the graph back into a tree, in which every subtree has its
+    /// own branches.
+    pub fn to_octree(&self) -> OctreeBitmap {
+        let mut bitmap = OctreeBitmap::with_height(self.height);
+        bitmap.leaf_height = self.leaf_height;
+        let root = BranchIndex::root(self.height);
+        let children = match self.root {
+            DagChild::Node(n) => self.expand(&mut bitmap, n, root),
+            DagChild::Uniform(value) => [[[RawNode::from(value); 2]; 2]; 2],
+            DagChild::Brick(_) => unreachable!("brick at the root"),
+        };
+        bitmap.branches.insert(root, Branch { children });
+        bitmap
+    }
+
+    /// The children of the branch at the given index for a node, inserting
+    /// the branches below it.
+    fn expand(
+        &self,
+        bitmap: &mut OctreeBitmap,
+        node: u32,
+        index: BranchIndex,
+    ) -> [[[RawNode; 2]; 2]; 2] {
+        let mut children = [[[RawNode::False; 2]; 2]; 2];
+        for (i, &child) in self.nodes[node as usize].iter().enumerate() {
+            let (x, y, z) = (i & 1, (i >> 1) & 1, i >> 2);
+            children[z][y][x] = match child {
+                DagChild::Uniform(value) => RawNode::from(value),
+                DagChild::Brick(bits) => RawNode::Brick(bits),
+                DagChild::Node(n) => {
+                    let child_index = index.child(x, y, z);
+                    let grandchildren = self.expand(bitmap, n, child_index);
+                    bitmap.branches.insert(
+                        child_index,
+                        Branch {
+                            children: grandchildren,
+                        },
+                    );
+                    RawNode::Branch
+                }
+            };
+        }
+        children
+    }
+}
+
+impl OctreeBitmap {
+    /// Converts the bitmap into a [`SvdagBitmap`], merging identical
+    /// subtrees into shared nodes.
+    pub fn to_dag(&self) -> SvdagBitmap {
+        let mut dag = SvdagBitmap {
+            nodes: Vec::new(),
+            root: DagChild::Uniform(false),
+            height: self.height,
+            leaf_height: self.leaf_height,
+        };
+        let mut unique = HashMap::new();
+        let root = BranchIndex::root(self.height);
+        dag.root = match View::root(self) {
+            View::Uniform(value) => DagChild::Uniform(value),
+            view => self.dag_node(root, view, &mut dag, &mut unique),
+        };
+        dag
+    }
+
+    /// The node for the subtree at the given index, shared with any identical
+    /// subtree already added.
+    fn dag_node(
+        &self,
+        index: BranchIndex,
+        view: View,
+        dag: &mut SvdagBitmap,
+        unique: &mut HashMap<[DagChild; 8], u32>,
+    ) -> DagChild {
+        let mut children = [DagChild::Uniform(false); 8];
+        for (i, child) in children.iter_mut().enumerate() {
+            let child_index = index.child(i & 1, (i >> 1) & 1, i >> 2);
+            *child = match view.child(self, index, i) {
+                View::Uniform(value) => DagChild::Uniform(value),
+                View::Brick(bits) => DagChild::Brick(bits),
+                child_view @ View::Branch(_) => self.dag_node(child_index, child_view, dag, unique),
+            };
+        }
+        let n = *unique.entry(children).or_insert_with(|| {
+            dag.nodes.push(children);
+            (dag.nodes.len() - 1) as u32
+        });
+        DagChild::Node(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dag_shares_subtrees() {
+        // A repeating pattern of pillars.
+        let mut bitmap = OctreeBitmap::new(32);
+        let width = bitmap.width();
+        bitmap.set_many(
+            (0..width)
+                .flat_map(|z| (0..width).flat_map(move |y| (0..width).map(move |x| (x, y, z))))
+                .filter(|&(x, y, _)| x % 8 < 2 && y % 8 < 3)
+                .map(|(x, y, z)| Index::new(x, y, z)),
+            true,
+        );
+        bitmap.set(&Index::new(5, 40, 61), true);
+        let dag = bitmap.to_dag();
+        assert!(dag.node_count() * 8 < bitmap.branches.len());
+        for z in 0..width {
+            for y in 0..width {
+                for x in 0..width {
+                    let idx = Index::new(x, y, z);
+                    assert_eq!(dag.get(&idx), bitmap.get(&idx));
+                }
+            }
+        }
+        let tree = dag.to_octree();
+        assert_eq!(tree.validate(), Ok(()));
+        assert!(tree == bitmap);
+        assert_eq!(tree.branches.len(), bitmap.branches.len());
+
+        let empty = OctreeBitmap::new(4).to_dag();
+        assert_eq!(empty.node_count(), 0);
+        assert!(!empty.get(&Index::new(1, 2, 3)));
+    }
+}
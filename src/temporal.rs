@@ -0,0 +1,167 @@
+//! A bitmap that keeps its history, for replaying and rolling back edits.
+
+use std::collections::VecDeque;
+
+use crate::{Index, PersistentOctreeBitmap};
+
+/// A bitmap whose edits are tagged with a version, such as a simulation
+/// tick, and which can be queried as it was at any retained version.
+///
+/// Each version with edits is stored as a [`PersistentOctreeBitmap`]
+/// sharing every unchanged subtree with the version before it, so the
+/// history costs only the nodes along the paths to the changed voxels. A
+/// networked game can keep the last few seconds of ticks, look up the world
+/// as a client saw it, [`rollback`](Self::rollback) to resimulate from a
+/// corrected tick, and [`prune_before`](Self::prune_before) ticks that can no
+/// longer change.
+#[derive(Clone)]
+pub struct TemporalOctree {
+    /// The state after the edits of each version that has any, in ascending
+    /// order of version. Never empty: the first is the oldest state kept.
+    versions: VecDeque<(u64, PersistentOctreeBitmap)>,
+}
+
+impl TemporalOctree {
+    /// Creates a new, empty bitmap, with no edits before version 0.
+    ///
+    /// The width is chosen as in [`OctreeBitmap::new`](crate::OctreeBitmap::new).
+    pub fn new(width: u32) -> Self {
+        Self {
+            versions: VecDeque::from([(0, PersistentOctreeBitmap::new(width))]),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.latest().width()
+    }
+
+    /// The version of the most recent edit.
+    pub fn latest_version(&self) -> u64 {
+        self.versions.back().unwrap().0
+    }
+
+    /// The earliest version that can still be queried.
+    pub fn oldest_version(&self) -> u64 {
+        self.versions.front().unwrap().0
+    }
+
+    /// The bitmap after all edits so far.
+    pub fn latest(&self) -> &PersistentOctreeBitmap {
+        &self.versions.back().unwrap().1
+    }
+
+    /// The bitmap after the edits of the given version and those before it,
+    /// or `None` if that version has been pruned.
+    pub fn at(&self, version: u64) -> Option<&PersistentOctreeBitmap> {
+        // The number of states at or before the version.
+        let count = self.versions.partition_point(|&(v, _)| v <= version);
+        let (_, bitmap) = self.versions.get(count.checked_sub(1)?)?;
+        Some(bitmap)
+    }
+
+    /// Get the current value of the bit at the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn get(&self, idx: &Index) -> bool {
+        self.latest().get(idx)
+    }
+
+    /// Get the value of the bit at the given index as of the given version,
+    /// or `None` if that version has been pruned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn get_at(&self, idx: &Index, version: u64) -> Option<bool> {
+        Some(self.at(version)?.get(idx))
+    }
+
+    /// Sets the value at the given index as an edit of the given version.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the version is before the [latest](Self::latest_version),
+    /// since edits must be made in order; [`rollback`](Self::rollback) first
+    /// to change the past. Panics if the index is out of bounds.
+    pub fn set(&mut self, idx: &Index, value: bool, version: u64) {
+        let latest = self.latest_version();
+        assert!(
+            version >= latest,
+            "edit for version {} is before the latest version {}",
+            version,
+            latest
+        );
+        let bitmap = self.latest().set(idx, value);
+        if bitmap.ptr_eq(self.latest()) {
+            return;
+        }
+        if version == latest {
+            *self.versions.back_mut().unwrap() = (version, bitmap);
+        } else {
+            self.versions.push_back((version, bitmap));
+        }
+    }
+
+    /// Discards the edits made after the given version, returning `false`
+    /// without changing anything if that version has been pruned.
+    pub fn rollback(&mut self, version: u64) -> bool {
+        if version < self.oldest_version() {
+            return false;
+        }
+        let count = self.versions.partition_point(|&(v, _)| v <= version);
+        self.versions.truncate(count);
+        true
+    }
+
+    /// Forgets the history before the given version, which can still be
+    /// queried along with every version after it. Earlier versions may
+    /// remain queryable if nothing was edited between them and this one.
+    pub fn prune_before(&mut self, version: u64) {
+        let count = self.versions.partition_point(|&(v, _)| v <= version);
+        self.versions.drain(..count.saturating_sub(1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_rollback_and_pruning() {
+        let mut bitmap = TemporalOctree::new(16);
+        let (a, b) = (Index::new(1, 2, 3), Index::new(30, 0, 7));
+        bitmap.set(&a, true, 2);
+        bitmap.set(&b, true, 2);
+        bitmap.set(&a, false, 5);
+        bitmap.set(&a, false, 6);
+        bitmap.set(&b, false, 9);
+        assert_eq!(bitmap.latest_version(), 9);
+        assert!(!bitmap.get(&a) && !bitmap.get(&b));
+        let states = |bitmap: &TemporalOctree, idx: &Index| -> Vec<Option<bool>> {
+            (0..11).map(|v| bitmap.get_at(idx, v)).collect()
+        };
+        let (t, f) = (Some(true), Some(false));
+        assert_eq!(states(&bitmap, &a), [f, f, t, t, t, f, f, f, f, f, f]);
+        assert_eq!(states(&bitmap, &b), [f, f, t, t, t, t, t, t, t, f, f]);
+
+        assert!(bitmap.rollback(7));
+        assert_eq!(bitmap.latest_version(), 5);
+        assert!(bitmap.get(&b));
+        bitmap.set(&a, true, 8);
+        assert_eq!(bitmap.get_at(&a, 7), f);
+        assert_eq!(bitmap.get_at(&a, 8), t);
+
+        bitmap.prune_before(4);
+        assert_eq!(bitmap.oldest_version(), 2);
+        assert_eq!(bitmap.get_at(&b, 3), t);
+        assert_eq!(bitmap.get_at(&b, 1), None);
+        assert!(!bitmap.rollback(1));
+        bitmap.prune_before(100);
+        assert_eq!(bitmap.oldest_version(), 8);
+        assert_eq!(states(&bitmap, &a)[8..], [t, t, t]);
+        assert!(bitmap.rollback(8));
+        assert!(bitmap.latest().to_bitmap().get(&a));
+    }
+}
@@ -0,0 +1,126 @@
+//! A reference model of a bitmap, for model-based testing of operations.
+//!
+//! [`DenseModel`] keeps one `bool` per voxel in a flat array, so every
+//! operation on it is obviously correct, if slow. A test can apply the same
+//! edits to a model and to an [`OctreeBitmap`] and check that they agree.
+
+use crate::{Index, OctreeBitmap};
+
+/// A bitmap stored densely, one `bool` per voxel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DenseModel {
+    width: u32,
+    voxels: Vec<bool>,
+}
+
+impl DenseModel {
+    /// An empty model with the given width, which should be the
+    /// [`width`](OctreeBitmap::width) of the bitmap it is compared with.
+    pub fn new(width: u32) -> Self {
+        Self {
+            width,
+            voxels: vec![false; (width as usize).pow(3)],
+        }
+    }
+
+    /// A model with the same voxels set as the bitmap.
+    pub fn from_bitmap(bitmap: &OctreeBitmap) -> Self {
+        let mut model = Self::new(bitmap.width());
+        for idx in bitmap.iter() {
+            model.set(&idx, true);
+        }
+        model
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn offset(&self, idx: &Index) -> usize {
+        assert!(
+            idx.x < self.width && idx.y < self.width && idx.z < self.width,
+            "index {:?} is outside the model of width {}",
+            idx,
+            self.width
+        );
+        let width = self.width as usize;
+        (idx.z as usize * width + idx.y as usize) * width + idx.x as usize
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the index is outside the model.
+    pub fn get(&self, idx: &Index) -> bool {
+        self.voxels[self.offset(idx)]
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the index is outside the model.
+    pub fn set(&mut self, idx: &Index, value: bool) {
+        let offset = self.offset(idx);
+        self.voxels[offset] = value;
+    }
+
+    /// Sets every voxel from `min` to `max` (exclusive) on each axis to
+    /// `value`. The part of the box outside the model is ignored.
+    pub fn fill_box(&mut self, min: Index, max: Index, value: bool) {
+        let width = self.width;
+        let clamp = |c: u32| c.min(width);
+        for z in min.z..clamp(max.z) {
+            for y in min.y..clamp(max.y) {
+                for x in min.x..clamp(max.x) {
+                    self.set(&Index::new(x, y, z), value);
+                }
+            }
+        }
+    }
+
+    /// The set voxels, in Morton order like [`OctreeBitmap::iter`].
+    pub fn iter(&self) -> impl Iterator<Item = Index> + '_ {
+        let mut set: Vec<Index> = (0..self.voxels.len())
+            .filter(|&i| self.voxels[i])
+            .map(|i| {
+                let width = self.width as usize;
+                Index::new(
+                    (i % width) as u32,
+                    (i / width % width) as u32,
+                    (i / (width * width)) as u32,
+                )
+            })
+            .collect();
+        set.sort_by_key(Index::morton);
+        set.into_iter()
+    }
+
+    /// Whether the bitmap has the same width and the same voxels set.
+    pub fn matches(&self, bitmap: &OctreeBitmap) -> bool {
+        bitmap.width() == self.width && bitmap.iter().eq(self.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::pseudo_random_indexes;
+
+    #[test]
+    fn model_matches_bitmap() {
+        let mut bitmap = OctreeBitmap::new(8);
+        let mut model = DenseModel::new(bitmap.width());
+        assert!(model.matches(&bitmap));
+
+        for (i, idx) in pseudo_random_indexes(16, 200).into_iter().enumerate() {
+            let value = i % 3 != 0;
+            bitmap.set(&idx, value);
+            model.set(&idx, value);
+        }
+        bitmap.fill_with(Index::new(2, 3, 4), Index::new(9, 20, 7), |_| true);
+        model.fill_box(Index::new(2, 3, 4), Index::new(9, 20, 7), true);
+        assert!(model.matches(&bitmap));
+        assert_eq!(DenseModel::from_bitmap(&bitmap), model);
+
+        model.set(&Index::new(15, 15, 15), !model.get(&Index::new(15, 15, 15)));
+        assert!(!model.matches(&bitmap));
+    }
+}
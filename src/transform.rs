@@ -0,0 +1,222 @@
+//! Rotations and reflections of the whole bitmap, done by reordering the
+//! children of every branch.
+
+use crate::{Branch, BranchIndex, BranchMap, Index, OctreeBitmap, RawNode};
+
+/// One of the three coordinate axes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    pub(crate) fn index(self) -> usize {
+        match self {
+            Self::X => 0,
+            Self::Y => 1,
+            Self::Z => 2,
+        }
+    }
+}
+
+/// A mapping of coordinates that permutes the axes and mirrors some of
+/// them: coordinate `i` of the result is coordinate `axes[i]` of the input,
+/// reversed if `mirror[i]`.
+#[derive(Clone, Copy)]
+struct AxisMap {
+    axes: [usize; 3],
+    mirror: [bool; 3],
+}
+
+impl AxisMap {
+    const IDENTITY: Self = Self {
+        axes: [0, 1, 2],
+        mirror: [false; 3],
+    };
+
+    /// A quarter turn counterclockwise about the axis, when looking from its
+    /// positive end.
+    fn quarter_turn(axis: Axis) -> Self {
+        let axis = axis.index();
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        let mut map = Self::IDENTITY;
+        map.axes[u] = v;
+        map.mirror[u] = true;
+        map.axes[v] = u;
+        map
+    }
+
+    /// A reflection across the plane through the center perpendicular to
+    /// the axis.
+    fn reflection(axis: Axis) -> Self {
+        let mut map = Self::IDENTITY;
+        map.mirror[axis.index()] = true;
+        map
+    }
+
+    /// The mapping that applies `self` and then `next`.
+    fn then(self, next: Self) -> Self {
+        Self {
+            axes: next.axes.map(|axis| self.axes[axis]),
+            mirror: [0, 1, 2].map(|i| next.mirror[i] ^ self.mirror[next.axes[i]]),
+        }
+    }
+
+    /// Maps a position within a cube of the given side.
+    fn apply(self, position: [u32; 3], side: u32) -> [u32; 3] {
+        [0, 1, 2].map(|i| {
+            let c = position[self.axes[i]];
+            if self.mirror[i] {
+                side - 1 - c
+            } else {
+                c
+            }
+        })
+    }
+
+    /// Maps the node within a tree of the given height.
+    fn apply_node(self, node: BranchIndex, height: u32) -> BranchIndex {
+        let side = 1 << node.height;
+        let base = [node.base.x, node.base.y, node.base.z];
+        // Map the node's voxels as if each were one voxel of a coarser tree.
+        let scaled = self.apply(base.map(|c| c >> node.height), 1 << (height - node.height));
+        BranchIndex {
+            base: Index::from(scaled.map(|c| c * side)),
+            height: node.height,
+        }
+    }
+
+    /// Maps the bits of a brick of the given height.
+    fn apply_brick(self, bits: u64, height: u32) -> u64 {
+        let mut mapped = 0;
+        for offset in 0..1u32 << (3 * height) {
+            if (bits >> offset) & 1 != 0 {
+                let position = Index::from_morton(offset.into());
+                let position = self.apply([position.x, position.y, position.z], 1 << height);
+                mapped |= 1 << Index::from(position).brick_offset(height);
+            }
+        }
+        mapped
+    }
+
+    fn apply_raw(self, raw: RawNode, height: u32) -> RawNode {
+        match raw {
+            RawNode::Brick(bits) => RawNode::Brick(self.apply_brick(bits, height)),
+            raw => raw,
+        }
+    }
+}
+
+impl OctreeBitmap {
+    /// Applies the mapping to every voxel, moving each branch to its new
+    /// place and reordering its children.
+    fn transform(&mut self, map: AxisMap) {
+        self.record_subtree(BranchIndex::root(self.height));
+        let height = self.height;
+        let branches: BranchMap = self
+            .branches
+            .drain()
+            .map(|(index, branch)| {
+                let mut children = [[[RawNode::False; 2]; 2]; 2];
+                for z in 0..2 {
+                    for y in 0..2 {
+                        for x in 0..2 {
+                            let [mx, my, mz] = map.apply([x, y, z], 2).map(|c| c as usize);
+                            children[mz][my][mx] = map.apply_raw(
+                                branch.children[z as usize][y as usize][x as usize],
+                                index.height - 1,
+                            );
+                        }
+                    }
+                }
+                (map.apply_node(index, height), Branch { children })
+            })
+            .collect();
+        self.branches = branches;
+    }
+
+    /// Rotates the bitmap by `turns` quarter turns about an axis through its
+    /// center, counterclockwise when looking from the positive end of the
+    /// axis. Negative turns rotate clockwise.
+    ///
+    /// The branches are moved and their children reordered in place of
+    /// moving every voxel, so this takes time proportional to the size of
+    /// the tree. In recording mode, this is recorded as a single edit.
+    pub fn rotate90(&mut self, axis: Axis, turns: i32) {
+        let turn = AxisMap::quarter_turn(axis);
+        let map = (0..turns.rem_euclid(4)).fold(AxisMap::IDENTITY, |map, _| map.then(turn));
+        self.transform(map);
+    }
+
+    /// Mirrors the bitmap along an axis, so that the voxel at coordinate `c`
+    /// on that axis moves to `width - 1 - c`.
+    ///
+    /// This swaps the children of every branch rather than moving every
+    /// voxel, as for [`rotate90`](Self::rotate90). In recording mode, this
+    /// is recorded as a single edit.
+    pub fn flip(&mut self, axis: Axis) {
+        self.transform(AxisMap::reflection(axis));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::pseudo_random_indexes;
+
+    #[test]
+    fn rotate_matches_voxels() {
+        let mut bitmap = OctreeBitmap::new(16);
+        let width = bitmap.width();
+        bitmap.fill_sphere([10.0, 12.0, 20.0], 6.0, true);
+        bitmap.set_many(pseudo_random_indexes(32, 100), true);
+        let set: Vec<Index> = bitmap.iter().collect();
+
+        bitmap.rotate90(Axis::Z, 1);
+        let mut expected: Vec<Index> = set
+            .iter()
+            .map(|idx| Index::new(width - 1 - idx.y, idx.x, idx.z))
+            .collect();
+        expected.sort();
+        let mut actual: Vec<Index> = bitmap.iter().collect();
+        actual.sort();
+        assert_eq!(actual, expected);
+
+        bitmap.rotate90(Axis::X, 2);
+        bitmap.rotate90(Axis::X, -2);
+        bitmap.rotate90(Axis::Y, 4);
+        bitmap.rotate90(Axis::Z, 3);
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), set);
+    }
+
+    #[test]
+    fn flip_matches_voxels() {
+        let mut bitmap = OctreeBitmap::new(8);
+        let width = bitmap.width();
+        bitmap.fill_sphere([3.0, 4.0, 5.0], 2.5, true);
+        bitmap.set_many([Index::new(0, 1, 2), Index::new(15, 0, 9)], true);
+        let set: Vec<Index> = bitmap.iter().collect();
+
+        bitmap.flip(Axis::Y);
+        let mut expected: Vec<Index> = set
+            .iter()
+            .map(|idx| Index::new(idx.x, width - 1 - idx.y, idx.z))
+            .collect();
+        expected.sort();
+        let mut actual: Vec<Index> = bitmap.iter().collect();
+        actual.sort();
+        assert_eq!(actual, expected);
+
+        bitmap.start_recording();
+        bitmap.flip(Axis::X);
+        bitmap.flip(Axis::X);
+        bitmap.flip(Axis::Y);
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), set);
+        assert!(bitmap.undo());
+        let mut actual: Vec<Index> = bitmap.iter().collect();
+        actual.sort();
+        assert_eq!(actual, expected);
+    }
+}
@@ -0,0 +1,271 @@
+//! Moving the contents of a bitmap by an offset.
+
+use std::collections::HashSet;
+
+use crate::{BlitMode, Branch, BranchIndex, Index, Occupancy, OctreeBitmap, Offset, RawNode};
+
+/// What happens to voxels moved past the bounds of a bitmap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EdgeMode {
+    /// Voxels moved outside the bitmap are discarded.
+    Clip,
+    /// Voxels moved outside the bitmap wrap around to the opposite side.
+    Wrap,
+}
+
+impl OctreeBitmap {
+    /// A copy of the bitmap with every set voxel moved by `offset`.
+    ///
+    /// Voxels that move past the bounds are handled according to `mode`.
+    /// When every component of the offset is a multiple of some power of two
+    /// larger than a brick, the mixed octants of that size have the same
+    /// shape after moving, and their subtrees are re-keyed whole instead of
+    /// being rebuilt. Uniform octants are filled as boxes at their new
+    /// position, so only the voxels of unaligned mixed octants are moved one
+    /// at a time.
+    pub fn translated(&self, offset: [i32; 3], mode: EdgeMode) -> OctreeBitmap {
+        let width = i64::from(self.width());
+        let offset = offset.map(|d| match mode {
+            EdgeMode::Clip => i64::from(d),
+            EdgeMode::Wrap => i64::from(d).rem_euclid(width),
+        });
+        // The height of the largest octants that end up aligned to octants.
+        let aligned = offset
+            .iter()
+            .map(|&d| {
+                if d == 0 {
+                    self.height
+                } else {
+                    d.trailing_zeros()
+                }
+            })
+            .min()
+            .unwrap()
+            .min(self.height - 1);
+
+        let mut translated = self.with_height_like(self.height);
+        let mut voxels = Vec::new();
+        self.visit_nodes(|base, height, occupancy| {
+            let low = [base.x, base.y, base.z].map(i64::from);
+            let low = [0, 1, 2].map(|i| low[i] + offset[i]);
+            let side = 1 << height;
+            match occupancy {
+                Occupancy::Empty => false,
+                Occupancy::Full => {
+                    if height == 0 {
+                        let low = match mode {
+                            EdgeMode::Clip => low,
+                            EdgeMode::Wrap => low.map(|c| c % width),
+                        };
+                        if low.iter().all(|c| (0..width).contains(c)) {
+                            voxels.push(Index::new(low[0] as u32, low[1] as u32, low[2] as u32));
+                        }
+                        return false;
+                    }
+                    // The parts of the moved box, split where it wraps.
+                    let ranges = low.map(|low| match mode {
+                        EdgeMode::Clip => vec![(low, low + side)],
+                        EdgeMode::Wrap if low + side <= width => vec![(low, low + side)],
+                        EdgeMode::Wrap if low >= width => vec![(low - width, low + side - width)],
+                        EdgeMode::Wrap => vec![(low, width), (0, low + side - width)],
+                    });
+                    for &(x0, x1) in &ranges[0] {
+                        for &(y0, y1) in &ranges[1] {
+                            for &(z0, z1) in &ranges[2] {
+                                translated.fill_box([x0, y0, z0], [x1, y1, z1], true);
+                            }
+                        }
+                    }
+                    false
+                }
+                Occupancy::Mixed if height <= aligned && height > self.brick_height() => {
+                    let low = match mode {
+                        EdgeMode::Clip => low,
+                        EdgeMode::Wrap => low.map(|c| c % width),
+                    };
+                    if low.iter().all(|c| (0..width).contains(c)) {
+                        let target = Index::new(low[0] as u32, low[1] as u32, low[2] as u32);
+                        translated.graft(self, base.branch_at(height), target.branch_at(height));
+                    }
+                    false
+                }
+                Occupancy::Mixed => true,
+            }
+        });
+        translated.set_many(voxels, true);
+        translated
+    }
+
+    /// Moves every set voxel by `offset`, as for
+    /// [`translated`](Self::translated).
+    ///
+    /// In recording mode, this is recorded as a single edit.
+    pub fn translate(&mut self, offset: [i32; 3], mode: EdgeMode) {
+        let translated = self.translated(offset, mode);
+        self.record_subtree(BranchIndex::root(self.height));
+        self.branches = translated.branches;
+    }
+
+    /// The union of copies of the bitmap moved by each offset along `path`,
+    /// such as the volume swept by a moving object for motion-planning
+    /// collision checks. Voxels moved outside the bitmap are discarded, and
+    /// an empty path gives an empty bitmap.
+    ///
+    /// Each copy is moved as for [`translated`](Self::translated), re-keying
+    /// aligned subtrees whole, and merged as for [`blit`](Self::blit), which
+    /// copies whole subtrees into the empty space they land on. Repeated
+    /// offsets are only applied once, and the rest of the path is skipped
+    /// once every voxel is set.
+    pub fn swept(&self, path: impl IntoIterator<Item = Offset>) -> OctreeBitmap {
+        let mut swept = self.with_height_like(self.height);
+        let mut seen = HashSet::new();
+        for offset in path {
+            if swept.is_full() {
+                break;
+            }
+            if seen.insert(offset) {
+                let moved = self.translated([offset.x, offset.y, offset.z], EdgeMode::Clip);
+                swept.blit(&moved, Index::new(0, 0, 0), BlitMode::Union);
+            }
+        }
+        swept
+    }
+
+    /// Copies the subtree of the branch at `source` in another bitmap into
+    /// the empty octant at `target`. Both bitmaps must have the same leaf
+    /// size, and the branch must be above the bricks of both.
+    pub(crate) fn graft(&mut self, other: &OctreeBitmap, source: BranchIndex, target: BranchIndex) {
+        // Split the uniform nodes above the target.
+        let mut current_height = self.height;
+        while current_height > target.height + 1 {
+            let current_index = target.base.branch_at(current_height);
+            let child_height = current_height - 1;
+            let (x, y, z) = target.base.bit(child_height);
+            let child = &mut self.branches.get_mut(&current_index).unwrap().children[z][y][x];
+            if *child != RawNode::Branch {
+                let current = std::mem::replace(child, RawNode::Branch);
+                self.branches.insert(
+                    target.base.branch_at(child_height),
+                    Branch {
+                        children: [[[current; 2]; 2]; 2],
+                    },
+                );
+            }
+            current_height = child_height;
+        }
+        let (x, y, z) = target.base.bit(target.height);
+        self.branches
+            .get_mut(&target.base.branch_at(target.height + 1))
+            .unwrap()
+            .children[z][y][x] = RawNode::Branch;
+
+        let mut stack = vec![source];
+        while let Some(index) = stack.pop() {
+            let branch = other.branches[&index].clone();
+            for z in 0..2 {
+                for y in 0..2 {
+                    for x in 0..2 {
+                        if branch.children[z][y][x] == RawNode::Branch {
+                            stack.push(index.child(x, y, z));
+                        }
+                    }
+                }
+            }
+            let rekeyed = BranchIndex {
+                base: Index::new(
+                    index.base.x - source.base.x + target.base.x,
+                    index.base.y - source.base.y + target.base.y,
+                    index.base.z - source.base.z + target.base.z,
+                ),
+                height: index.height,
+            };
+            self.branches.insert(rekeyed, branch);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::pseudo_random_indexes;
+
+    #[test]
+    fn translate_matches_voxels() {
+        let mut bitmap = OctreeBitmap::new(32);
+        let width = bitmap.width() as i64;
+        bitmap.fill_sphere([40.0, 20.0, 24.0], 14.0, true);
+        bitmap.set_many(pseudo_random_indexes(64, 200), true);
+        let set: Vec<Index> = bitmap.iter().collect();
+
+        for offset in [[0, 0, 0], [16, -32, 8], [-5, 3, 60], [64, 0, -8]] {
+            for mode in [EdgeMode::Clip, EdgeMode::Wrap] {
+                let mut expected: Vec<Index> =
+                    set.iter()
+                        .filter_map(|idx| {
+                            let moved = [idx.x, idx.y, idx.z]
+                                .iter()
+                                .zip(offset)
+                                .map(|(&c, d)| match mode {
+                                    EdgeMode::Clip => c as i64 + d as i64,
+                                    EdgeMode::Wrap => (c as i64 + d as i64).rem_euclid(width),
+                                })
+                                .collect::<Vec<_>>();
+                            moved.iter().all(|c| (0..width).contains(c)).then(|| {
+                                Index::new(moved[0] as u32, moved[1] as u32, moved[2] as u32)
+                            })
+                        })
+                        .collect();
+                expected.sort();
+                let mut actual: Vec<Index> = bitmap.translated(offset, mode).iter().collect();
+                actual.sort();
+                assert_eq!(actual, expected, "{:?} {:?}", offset, mode);
+            }
+        }
+
+        // Subtrees up to the alignment of the offset keep their shape.
+        let wrapped = bitmap.translated([16, -32, 8], EdgeMode::Wrap);
+        let low_branches = |bitmap: &OctreeBitmap| {
+            bitmap
+                .branches
+                .keys()
+                .filter(|index| index.height <= 3)
+                .count()
+        };
+        assert_eq!(low_branches(&wrapped), low_branches(&bitmap));
+
+        let moved: Vec<Index> = bitmap
+            .translated([8, 8, -3], EdgeMode::Clip)
+            .iter()
+            .collect();
+        bitmap.start_recording();
+        bitmap.translate([8, 8, -3], EdgeMode::Clip);
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), moved);
+        assert!(bitmap.undo());
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), set);
+    }
+
+    #[test]
+    fn swept_along_path() {
+        let mut bitmap = OctreeBitmap::new(16);
+        bitmap.fill_box([4, 4, 4], [8, 6, 5], true);
+        bitmap.set(&Index::new(0, 1, 2), true);
+        assert!(bitmap.swept([]).is_empty());
+
+        let path: Vec<Offset> = (0..20)
+            .map(|i| Offset::new(i, i / 2, -(i / 5)))
+            .chain([Offset::new(-3, 0, 0), Offset::new(0, 0, 0)])
+            .collect();
+        let swept = bitmap.swept(path.iter().copied());
+        let mut expected: Vec<Index> = bitmap
+            .iter()
+            .flat_map(|idx| {
+                path.iter()
+                    .filter_map(move |&offset| idx.checked_add(offset))
+            })
+            .filter(|idx| bitmap.in_bounds(idx))
+            .collect();
+        expected.sort_by_key(Index::morton);
+        expected.dedup();
+        assert_eq!(swept.iter().collect::<Vec<_>>(), expected);
+    }
+}
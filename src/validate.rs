@@ -0,0 +1,203 @@
+//! Checking the invariants of a tree.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::{brick_mask, BranchIndex, Index, OctreeBitmap, RawNode};
+
+/// A broken invariant of a tree, found by [`OctreeBitmap::validate`].
+///
+/// Each variant gives the base index and height of the branch or node where
+/// the problem was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// There is no branch for the root of the tree.
+    MissingRoot,
+    /// A branch is stored at height zero, where only voxels can be.
+    BranchAtHeightZero { base: Index },
+    /// A branch is stored for a node that is not inside the bitmap, or
+    /// whose base is not aligned to its size.
+    OutOfBounds { base: Index, height: u32 },
+    /// A branch is stored that is not reachable from the root.
+    Orphan { base: Index, height: u32 },
+    /// A node is marked as a branch, but no branch is stored for it.
+    MissingBranch { base: Index, height: u32 },
+    /// A node at or below brick height is a branch instead of a brick, or a
+    /// node above brick height is a brick.
+    WrongLeafKind { base: Index, height: u32 },
+    /// A brick has bits set outside its size, or is uniform and should have
+    /// been compressed.
+    InvalidBrick { base: Index, height: u32, bits: u64 },
+    /// The children of a branch other than the root are all empty or all
+    /// full, so it should have been compressed.
+    Uncompressed { base: Index, height: u32 },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingRoot => write!(f, "no root branch"),
+            Self::BranchAtHeightZero { base } => write!(f, "branch at height zero at {:?}", base),
+            Self::OutOfBounds { base, height } => {
+                write!(f, "branch out of bounds at {:?}, height {}", base, height)
+            }
+            Self::Orphan { base, height } => {
+                write!(f, "unreachable branch at {:?}, height {}", base, height)
+            }
+            Self::MissingBranch { base, height } => {
+                write!(f, "missing branch at {:?}, height {}", base, height)
+            }
+            Self::WrongLeafKind { base, height } => {
+                write!(f, "wrong kind of node at {:?}, height {}", base, height)
+            }
+            Self::InvalidBrick { base, height, bits } => write!(
+                f,
+                "invalid brick {:#x} at {:?}, height {}",
+                bits, base, height
+            ),
+            Self::Uncompressed { base, height } => {
+                write!(f, "uniform branch at {:?}, height {}", base, height)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl OctreeBitmap {
+    /// Checks that the internal structure of the tree is consistent and
+    /// minimal, returning the first problem found.
+    ///
+    /// Every operation keeps the tree valid, so an error here means a bug.
+    /// This walks the whole tree, so it is intended for debug builds, tests
+    /// and fuzzing.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let root = BranchIndex::root(self.height);
+        if !self.branches.contains_key(&root) {
+            return Err(ValidationError::MissingRoot);
+        }
+        let mut keys: Vec<BranchIndex> = self.branches.keys().copied().collect();
+        keys.sort();
+        for index in &keys {
+            let (base, height) = (index.base, index.height);
+            if height == 0 {
+                return Err(ValidationError::BranchAtHeightZero { base });
+            }
+            if height > self.height || base.branch_at(height) != *index || !self.in_bounds(&base) {
+                return Err(ValidationError::OutOfBounds { base, height });
+            }
+        }
+
+        let brick_height = self.brick_height();
+        let mut reachable = HashSet::new();
+        let mut stack = vec![root];
+        while let Some(index) = stack.pop() {
+            reachable.insert(index);
+            let children = self.branches[&index].children;
+            for i in 0..8 {
+                let (x, y, z) = (i & 1, (i >> 1) & 1, i >> 2);
+                let child = index.child(x, y, z);
+                let (base, height) = (child.base, child.height);
+                match children[z][y][x] {
+                    RawNode::Branch if height <= brick_height => {
+                        return Err(ValidationError::WrongLeafKind { base, height });
+                    }
+                    RawNode::Branch if !self.branches.contains_key(&child) => {
+                        return Err(ValidationError::MissingBranch { base, height });
+                    }
+                    RawNode::Branch => stack.push(child),
+                    RawNode::Brick(_) if height > brick_height => {
+                        return Err(ValidationError::WrongLeafKind { base, height });
+                    }
+                    RawNode::Brick(bits) if bits & !brick_mask(height) != 0 || bits == 0 => {
+                        return Err(ValidationError::InvalidBrick { base, height, bits });
+                    }
+                    RawNode::Brick(bits) if bits == brick_mask(height) => {
+                        return Err(ValidationError::InvalidBrick { base, height, bits });
+                    }
+                    RawNode::Brick(_) | RawNode::False | RawNode::True => {}
+                }
+            }
+            let first = children[0][0][0];
+            if index != root
+                && matches!(first, RawNode::False | RawNode::True)
+                && children
+                    .iter()
+                    .flatten()
+                    .flatten()
+                    .all(|&child| child == first)
+            {
+                return Err(ValidationError::Uncompressed {
+                    base: index.base,
+                    height: index.height,
+                });
+            }
+        }
+        if let Some(orphan) = keys.iter().find(|index| !reachable.contains(index)) {
+            return Err(ValidationError::Orphan {
+                base: orphan.base,
+                height: orphan.height,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Branch;
+
+    #[test]
+    fn detect_broken_trees() {
+        let mut bitmap = OctreeBitmap::new(32);
+        bitmap.fill_sphere([20.0, 20.0, 20.0], 12.0, true);
+        bitmap.set(&Index::new(1, 2, 3), true);
+        assert_eq!(bitmap.validate(), Ok(()));
+
+        let root = BranchIndex::root(bitmap.height);
+        let corner = root.child(1, 1, 1);
+        let empty = Branch {
+            children: [[[RawNode::False; 2]; 2]; 2],
+        };
+        let mut broken = bitmap.clone();
+        broken.branches.insert(corner.child(0, 0, 0), empty.clone());
+        assert_eq!(
+            broken.validate(),
+            Err(ValidationError::Orphan {
+                base: corner.base,
+                height: corner.height - 1
+            })
+        );
+
+        let mut broken = bitmap.clone();
+        let outside = Index::new(bitmap.width(), 0, 0).branch_at(3);
+        broken.branches.insert(outside, empty.clone());
+        assert_eq!(
+            broken.validate(),
+            Err(ValidationError::OutOfBounds {
+                base: outside.base,
+                height: 3
+            })
+        );
+
+        let mut broken = bitmap.clone();
+        broken.branches.get_mut(&root).unwrap().children[1][1][1] = RawNode::Branch;
+        broken.branches.insert(corner, empty);
+        assert_eq!(
+            broken.validate(),
+            Err(ValidationError::Uncompressed {
+                base: corner.base,
+                height: corner.height
+            })
+        );
+
+        let mut broken = bitmap.clone();
+        let leaf = Index::new(1, 2, 3).branch_at(3);
+        broken.branches.get_mut(&leaf).unwrap().children[0][0][0] = RawNode::Brick(u64::MAX);
+        assert!(matches!(
+            broken.validate(),
+            Err(ValidationError::InvalidBrick { .. })
+        ));
+    }
+}
@@ -0,0 +1,368 @@
+//! Import of dense scalar volumes by thresholding.
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read};
+
+use crate::{Index, OctreeBitmap};
+
+/// The type of each sample of a raw volume.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScalarType {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    I64,
+    F32,
+    F64,
+}
+
+impl ScalarType {
+    /// The size of one sample, in bytes.
+    pub fn size(self) -> usize {
+        match self {
+            Self::U8 | Self::I8 => 1,
+            Self::U16 | Self::I16 => 2,
+            Self::U32 | Self::I32 | Self::F32 => 4,
+            Self::U64 | Self::I64 | Self::F64 => 8,
+        }
+    }
+
+    /// The type for the given NRRD `type` field value.
+    fn from_nrrd(name: &str) -> Option<Self> {
+        Some(match name {
+            "signed char" | "int8" | "int8_t" => Self::I8,
+            "uchar" | "unsigned char" | "uint8" | "uint8_t" => Self::U8,
+            "short" | "short int" | "signed short" | "signed short int" | "int16" | "int16_t" => {
+                Self::I16
+            }
+            "ushort" | "unsigned short" | "unsigned short int" | "uint16" | "uint16_t" => Self::U16,
+            "int" | "signed int" | "int32" | "int32_t" => Self::I32,
+            "uint" | "unsigned int" | "uint32" | "uint32_t" => Self::U32,
+            "longlong"
+            | "long long"
+            | "long long int"
+            | "signed long long"
+            | "signed long long int"
+            | "int64"
+            | "int64_t" => Self::I64,
+            "ulonglong"
+            | "unsigned long long"
+            | "unsigned long long int"
+            | "uint64"
+            | "uint64_t" => Self::U64,
+            "float" => Self::F32,
+            "double" => Self::F64,
+            _ => return None,
+        })
+    }
+}
+
+/// The byte order of multi-byte samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Endian {
+    #[default]
+    Little,
+    Big,
+}
+
+/// The binary layout of each sample of a raw volume.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataType {
+    pub scalar: ScalarType,
+    pub endian: Endian,
+}
+
+impl DataType {
+    pub fn new(scalar: ScalarType, endian: Endian) -> Self {
+        Self { scalar, endian }
+    }
+
+    /// Decodes one sample from exactly [`ScalarType::size`] bytes.
+    pub(crate) fn decode(self, bytes: &[u8]) -> f64 {
+        macro_rules! decode {
+            ($t:ty) => {{
+                let bytes = bytes.try_into().unwrap();
+                match self.endian {
+                    Endian::Little => <$t>::from_le_bytes(bytes) as f64,
+                    Endian::Big => <$t>::from_be_bytes(bytes) as f64,
+                }
+            }};
+        }
+        match self.scalar {
+            ScalarType::U8 => bytes[0] as f64,
+            ScalarType::I8 => bytes[0] as i8 as f64,
+            ScalarType::U16 => decode!(u16),
+            ScalarType::I16 => decode!(i16),
+            ScalarType::U32 => decode!(u32),
+            ScalarType::I32 => decode!(i32),
+            ScalarType::U64 => decode!(u64),
+            ScalarType::I64 => decode!(i64),
+            ScalarType::F32 => decode!(f32),
+            ScalarType::F64 => decode!(f64),
+        }
+    }
+}
+
+/// An error encountered while reading a volume.
+#[derive(Debug)]
+pub enum VolumeError {
+    Io(io::Error),
+    /// The NRRD header is malformed or missing a required field.
+    InvalidHeader(String),
+    /// The volume uses a feature of the format that is not supported.
+    Unsupported(String),
+    /// The volume is wider than [`OctreeBitmap::MAX_WIDTH`].
+    TooLarge,
+}
+
+impl fmt::Display for VolumeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "failed to read volume: {}", err),
+            Self::InvalidHeader(reason) => write!(f, "invalid NRRD header: {}", reason),
+            Self::Unsupported(what) => write!(f, "unsupported volume: {}", what),
+            Self::TooLarge => write!(f, "volume is too large for a bitmap"),
+        }
+    }
+}
+
+impl std::error::Error for VolumeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::InvalidHeader(_) | Self::Unsupported(_) | Self::TooLarge => None,
+        }
+    }
+}
+
+impl From<io::Error> for VolumeError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// The width of a bitmap containing a volume with the given dimensions.
+pub(crate) fn width_for(dims: [u32; 3]) -> u32 {
+    dims.iter().copied().max().unwrap_or(0).max(1)
+}
+
+impl OctreeBitmap {
+    /// Reads a dense volume of scalar samples, setting each voxel whose
+    /// sample is greater than or equal to `threshold`.
+    ///
+    /// `dims` is the size of the volume along each axis. The samples are
+    /// stored contiguously with `x` varying fastest, then `y`, then `z`, as is
+    /// conventional for `.raw` files. The volume is read one `z` slice at a
+    /// time, so it never needs to fit in memory. The bitmap is made just wide
+    /// enough to fit the volume, or [`VolumeError::TooLarge`] is returned if
+    /// no bitmap is wide enough.
+    pub fn from_raw_volume<R: Read>(
+        mut reader: R,
+        dims: [u32; 3],
+        dtype: DataType,
+        threshold: f64,
+    ) -> Result<Self, VolumeError> {
+        let mut bitmap = Self::try_new(width_for(dims)).map_err(|_| VolumeError::TooLarge)?;
+        let sample_size = dtype.scalar.size();
+        let slice_len = (dims[0] as usize)
+            .checked_mul(dims[1] as usize)
+            .and_then(|area| area.checked_mul(sample_size))
+            .ok_or(VolumeError::TooLarge)?;
+        // The slice buffer only grows as data arrives, so a header claiming a
+        // huge volume cannot allocate more than the input holds.
+        let mut slice = Vec::new();
+        for z in 0..dims[2] {
+            slice.clear();
+            (&mut reader)
+                .take(slice_len as u64)
+                .read_to_end(&mut slice)?;
+            if slice.len() != slice_len {
+                return Err(VolumeError::Io(io::ErrorKind::UnexpectedEof.into()));
+            }
+            let row = dims[0] as usize;
+            let indices = slice
+                .chunks_exact(sample_size)
+                .enumerate()
+                .filter(|(_, sample)| dtype.decode(sample) >= threshold)
+                .map(|(i, _)| Index::new((i % row) as u32, (i / row) as u32, z));
+            bitmap.set_many(indices, true);
+        }
+        Ok(bitmap)
+    }
+
+    /// Reads a 3D NRRD (`.nrrd`) volume with an attached header, setting each
+    /// voxel whose sample is greater than or equal to `threshold`.
+    ///
+    /// The `raw` and `text` encodings are supported, as is `gzip` with the
+    /// `gzip` feature. Detached data files are not supported.
+    pub fn from_nrrd<R: Read>(reader: R, threshold: f64) -> Result<Self, VolumeError> {
+        let mut reader = BufReader::new(reader);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        if !line.starts_with("NRRD000") {
+            return Err(VolumeError::InvalidHeader("missing magic".to_owned()));
+        }
+
+        let mut scalar = None;
+        let mut dims = None;
+        let mut endian = Endian::Little;
+        let mut encoding = "raw".to_owned();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(VolumeError::InvalidHeader("no data".to_owned()));
+            }
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.is_empty() {
+                break;
+            }
+            // Comments, and `key:=value` pairs, are not needed.
+            if line.starts_with('#') || line.contains(":=") {
+                continue;
+            }
+            let (field, value) = line
+                .split_once(": ")
+                .ok_or_else(|| VolumeError::InvalidHeader(format!("malformed line {:?}", line)))?;
+            match field {
+                "type" => {
+                    scalar = Some(ScalarType::from_nrrd(value).ok_or_else(|| {
+                        VolumeError::Unsupported(format!("sample type {:?}", value))
+                    })?);
+                }
+                "dimension" if value != "3" => {
+                    return Err(VolumeError::Unsupported(format!("dimension {}", value)));
+                }
+                "sizes" => {
+                    let sizes = value
+                        .split_whitespace()
+                        .map(str::parse)
+                        .collect::<Result<Vec<u32>, _>>()
+                        .map_err(|_| VolumeError::InvalidHeader(format!("sizes {:?}", value)))?;
+                    dims = Some(
+                        <[u32; 3]>::try_from(sizes)
+                            .map_err(|_| VolumeError::Unsupported(format!("sizes {}", value)))?,
+                    );
+                }
+                "endian" => {
+                    endian = match value {
+                        "little" => Endian::Little,
+                        "big" => Endian::Big,
+                        _ => return Err(VolumeError::InvalidHeader(format!("endian {}", value))),
+                    };
+                }
+                "encoding" => encoding = value.to_owned(),
+                "data file" | "datafile" => {
+                    return Err(VolumeError::Unsupported("detached data file".to_owned()));
+                }
+                _ => {}
+            }
+        }
+
+        let scalar = scalar.ok_or_else(|| VolumeError::InvalidHeader("no type".to_owned()))?;
+        let dims = dims.ok_or_else(|| VolumeError::InvalidHeader("no sizes".to_owned()))?;
+        let dtype = DataType::new(scalar, endian);
+        match encoding.as_str() {
+            "raw" => Self::from_raw_volume(reader, dims, dtype, threshold),
+            #[cfg(feature = "gzip")]
+            "gzip" | "gz" => {
+                Self::from_raw_volume(flate2::read::GzDecoder::new(reader), dims, dtype, threshold)
+            }
+            "text" | "txt" | "ascii" => {
+                let mut text = String::new();
+                reader.read_to_string(&mut text)?;
+                let mut samples = text.split_whitespace();
+                let mut bitmap =
+                    Self::try_new(width_for(dims)).map_err(|_| VolumeError::TooLarge)?;
+                let mut indices = Vec::new();
+                for z in 0..dims[2] {
+                    for y in 0..dims[1] {
+                        for x in 0..dims[0] {
+                            let sample = samples
+                                .next()
+                                .ok_or_else(|| {
+                                    VolumeError::Io(io::ErrorKind::UnexpectedEof.into())
+                                })?
+                                .parse::<f64>()
+                                .map_err(|err| {
+                                    VolumeError::Io(io::Error::new(io::ErrorKind::InvalidData, err))
+                                })?;
+                            if sample >= threshold {
+                                indices.push(Index::new(x, y, z));
+                            }
+                        }
+                    }
+                }
+                bitmap.set_many(indices, true);
+                Ok(bitmap)
+            }
+            _ => Err(VolumeError::Unsupported(format!("encoding {}", encoding))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_u16_big_endian() {
+        let samples: [u16; 8] = [0, 500, 1000, 1500, 2000, 2500, 3000, 3500];
+        let data: Vec<u8> = samples.iter().flat_map(|s| s.to_be_bytes()).collect();
+        let dtype = DataType::new(ScalarType::U16, Endian::Big);
+        let bitmap = OctreeBitmap::from_raw_volume(&data[..], [2, 2, 2], dtype, 1500.0).unwrap();
+        assert_eq!(
+            bitmap.iter().collect::<Vec<_>>(),
+            [
+                Index::new(1, 1, 0),
+                Index::new(0, 0, 1),
+                Index::new(1, 0, 1),
+                Index::new(0, 1, 1),
+                Index::new(1, 1, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn nrrd() {
+        let mut file = b"NRRD0004\n# comment\ntype: float\ndimension: 3\nsizes: 3 1 1\nendian: little\nencoding: raw\n\n".to_vec();
+        for sample in [0.25f32, 0.75, 0.5] {
+            file.extend(sample.to_le_bytes());
+        }
+        let bitmap = OctreeBitmap::from_nrrd(&file[..], 0.5).unwrap();
+        assert_eq!(
+            bitmap.iter().collect::<Vec<_>>(),
+            [Index::new(1, 0, 0), Index::new(2, 0, 0)]
+        );
+
+        let file = "NRRD0004\ntype: uchar\ndimension: 3\nsizes: 2 1 1\nencoding: ascii\n\n3 9\n";
+        let bitmap = OctreeBitmap::from_nrrd(file.as_bytes(), 5.0).unwrap();
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), [Index::new(1, 0, 0)]);
+    }
+
+    #[test]
+    fn oversized_headers() {
+        for encoding in ["raw", "ascii"] {
+            let file = format!(
+                "NRRD0004\ntype: uchar\ndimension: 3\nsizes: 2000000000 1 1\nencoding: {}\n\n",
+                encoding
+            );
+            assert!(matches!(
+                OctreeBitmap::from_nrrd(file.as_bytes(), 1.0),
+                Err(VolumeError::TooLarge)
+            ));
+        }
+
+        // A volume as wide as a bitmap can be, but with no data behind it.
+        let width = OctreeBitmap::MAX_WIDTH;
+        let dtype = DataType::new(ScalarType::F64, Endian::Little);
+        let data = [0; 64];
+        assert!(matches!(
+            OctreeBitmap::from_raw_volume(&data[..], [width, width, width], dtype, 1.0),
+            Err(VolumeError::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof
+        ));
+    }
+}
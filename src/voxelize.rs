@@ -0,0 +1,209 @@
+//! Voxelization of triangle meshes.
+
+use std::collections::HashMap;
+
+use crate::{BranchIndex, Index, OctreeBitmap};
+
+/// Which voxels of a mesh are set by [`OctreeBitmap::from_mesh`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoxelizeMode {
+    /// Only the voxels that the surface passes through.
+    Surface,
+    /// The surface voxels and every voxel enclosed by the surface. The mesh
+    /// should be closed; the interior is found by parity, counting how many
+    /// times a line through each voxel crosses the surface.
+    Solid,
+}
+
+type Triangle = [[f64; 3]; 3];
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// Whether the triangle touches the axis-aligned box with the given center
+/// and half side length, by the separating axis test.
+fn overlaps(triangle: &Triangle, center: [f64; 3], half: f64) -> bool {
+    let v = triangle.map(|vertex| sub(vertex, center));
+    let edges = [sub(v[1], v[0]), sub(v[2], v[1]), sub(v[0], v[2])];
+    let units = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    let mut axes = vec![cross(edges[0], edges[1])];
+    axes.extend(units);
+    for edge in edges {
+        axes.extend(units.map(|unit| cross(edge, unit)));
+    }
+    axes.iter().all(|&axis| {
+        let projections = v.map(|vertex| dot(vertex, axis));
+        let radius = half * (axis[0].abs() + axis[1].abs() + axis[2].abs());
+        let min = projections.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = projections
+            .iter()
+            .copied()
+            .fold(f64::NEG_INFINITY, f64::max);
+        min <= radius && max >= -radius
+    })
+}
+
+impl OctreeBitmap {
+    /// Voxelizes a triangle mesh.
+    ///
+    /// `vertices` are positions in the space where the voxel at index
+    /// `(x, y, z)` spans `x..x + 1` (and so on) along each axis, and each
+    /// element of `indices` is the three vertex indices of one triangle.
+    /// Parts of the mesh outside `0..width` are clipped.
+    ///
+    /// The surface is found by descending the tree from the root with each
+    /// triangle, keeping only the octants it touches, so the cost depends on
+    /// the area of the surface rather than the size of the volume.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a vertex index is out of range for `vertices`.
+    pub fn from_mesh(
+        vertices: &[[f32; 3]],
+        indices: &[[u32; 3]],
+        width: u32,
+        mode: VoxelizeMode,
+    ) -> Self {
+        let mut bitmap = Self::new(width);
+        let triangles: Vec<Triangle> = indices
+            .iter()
+            .map(|triangle| triangle.map(|i| vertices[i as usize].map(f64::from)))
+            .collect();
+
+        let mut voxels = Vec::new();
+        let mut stack = vec![(
+            BranchIndex::root(bitmap.height),
+            (0..triangles.len()).collect::<Vec<_>>(),
+        )];
+        while let Some((node, candidates)) = stack.pop() {
+            let half = (1u64 << node.height) as f64 / 2.0;
+            let center = [node.base.x, node.base.y, node.base.z].map(|c| c as f64 + half);
+            let touching: Vec<usize> = candidates
+                .into_iter()
+                .filter(|&i| overlaps(&triangles[i], center, half))
+                .collect();
+            if touching.is_empty() {
+                continue;
+            }
+            if node.height == 0 {
+                voxels.push(node.base);
+                continue;
+            }
+            for i in 0..8 {
+                stack.push((node.child(i & 1, (i >> 1) & 1, i >> 2), touching.clone()));
+            }
+        }
+
+        if mode == VoxelizeMode::Solid {
+            bitmap.fill_interior(&triangles, &mut voxels);
+        }
+        bitmap.set_many(voxels, true);
+        bitmap
+    }
+
+    /// Adds the voxels whose centers are enclosed by the triangles, by
+    /// crossing parity along `z`.
+    fn fill_interior(&self, triangles: &[Triangle], voxels: &mut Vec<Index>) {
+        let width = self.width();
+        // Sample slightly off the voxel centers, so that lines rarely pass
+        // exactly through an edge or vertex of the mesh.
+        const NUDGE: [f64; 2] = [1.3e-6, 0.7e-6];
+        let mut crossings: HashMap<(u32, u32), Vec<f64>> = HashMap::new();
+        for triangle in triangles {
+            let [a, b, c] = *triangle;
+            let area = (b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1]);
+            if area == 0.0 {
+                continue;
+            }
+            let range = |axis: usize| {
+                let min = a[axis].min(b[axis]).min(c[axis]);
+                let max = a[axis].max(b[axis]).max(c[axis]);
+                let low = (min - 0.5).ceil().max(0.0);
+                let high = (max - 0.5).floor().min(width as f64 - 1.0);
+                low as u32..(high + 1.0).max(low) as u32
+            };
+            for y in range(1) {
+                for x in range(0) {
+                    let p = [x as f64 + 0.5 + NUDGE[0], y as f64 + 0.5 + NUDGE[1]];
+                    // Barycentric coordinates of the point in the projected
+                    // triangle.
+                    let u = ((b[0] - p[0]) * (c[1] - p[1]) - (c[0] - p[0]) * (b[1] - p[1])) / area;
+                    let v = ((c[0] - p[0]) * (a[1] - p[1]) - (a[0] - p[0]) * (c[1] - p[1])) / area;
+                    let w = 1.0 - u - v;
+                    if u >= 0.0 && v >= 0.0 && w >= 0.0 {
+                        let z = u * a[2] + v * b[2] + w * c[2];
+                        crossings.entry((x, y)).or_default().push(z);
+                    }
+                }
+            }
+        }
+        for ((x, y), mut zs) in crossings {
+            zs.sort_unstable_by(f64::total_cmp);
+            for pair in zs.chunks_exact(2) {
+                let low = (pair[0] - 0.5).ceil().max(0.0);
+                let high = (pair[1] - 0.5).floor().min(width as f64 - 1.0);
+                if low <= high {
+                    voxels.extend((low as u32..=high as u32).map(|z| Index::new(x, y, z)));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An axis-aligned box from `low` to `high`, with outward-facing
+    /// triangles.
+    fn cube(low: f32, high: f32) -> (Vec<[f32; 3]>, Vec<[u32; 3]>) {
+        let vertices = (0..8)
+            .map(|i| [i & 1, (i >> 1) & 1, i >> 2].map(|b| if b == 0 { low } else { high }))
+            .collect();
+        let faces = [
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+        ];
+        let indices = faces
+            .iter()
+            .flat_map(|&[a, b, c, d]| [[a, b, c], [a, c, d]])
+            .collect();
+        (vertices, indices)
+    }
+
+    #[test]
+    fn voxelize_cube() {
+        let (vertices, indices) = cube(2.2, 9.8);
+        let surface = OctreeBitmap::from_mesh(&vertices, &indices, 16, VoxelizeMode::Surface);
+        let solid = OctreeBitmap::from_mesh(&vertices, &indices, 16, VoxelizeMode::Solid);
+        // The surface voxels are the shell of the 8x8x8 block from 2 to 9.
+        assert_eq!(surface.iter().count(), 8 * 8 * 8 - 6 * 6 * 6);
+        assert!(surface.get(&Index::new(2, 5, 9)));
+        assert!(!surface.get(&Index::new(5, 5, 5)));
+        assert_eq!(solid.iter().count(), 8 * 8 * 8);
+        assert!(solid.get(&Index::new(5, 5, 5)));
+        assert!(!solid.get(&Index::new(1, 5, 5)));
+
+        // Clipped by the bounds of the bitmap.
+        let (vertices, indices) = cube(-4.0, 40.0);
+        let solid = OctreeBitmap::from_mesh(&vertices, &indices, 16, VoxelizeMode::Solid);
+        assert_eq!(solid.iter().count() as u32, solid.width().pow(3));
+    }
+}
@@ -0,0 +1,110 @@
+//! JavaScript bindings for use in the browser through `wasm-bindgen`.
+//!
+//! The bitmap is exported to JavaScript as the `OctreeBitmap` class, with
+//! methods named in camel case. Errors are thrown as JavaScript `Error`s.
+
+use wasm_bindgen::prelude::*;
+
+use crate::{Index, OctreeBitmap};
+
+/// A bitmap, as seen from JavaScript.
+#[wasm_bindgen(js_name = OctreeBitmap)]
+pub struct WasmOctreeBitmap(OctreeBitmap);
+
+#[wasm_bindgen(js_class = OctreeBitmap)]
+impl WasmOctreeBitmap {
+    /// Creates a new, empty bitmap. Throws if the width is zero or too large.
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: u32) -> Result<WasmOctreeBitmap, JsError> {
+        Ok(Self(OctreeBitmap::try_new(width)?))
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.0.width()
+    }
+
+    /// Get the value of the voxel at the given index. Throws if the index is
+    /// out of bounds.
+    pub fn get(&self, x: u32, y: u32, z: u32) -> Result<bool, JsError> {
+        Ok(self.0.try_get(&Index::new(x, y, z))?)
+    }
+
+    /// Set the value of the voxel at the given index. Throws if the index is
+    /// out of bounds.
+    pub fn set(&mut self, x: u32, y: u32, z: u32, value: bool) -> Result<(), JsError> {
+        Ok(self.0.try_set(&Index::new(x, y, z), value)?)
+    }
+
+    /// Sets the value of every voxel in the box from the minimum corner up to,
+    /// but not including, the maximum corner. Parts of the box outside the
+    /// bitmap are ignored.
+    #[wasm_bindgen(js_name = fillBox)]
+    #[allow(clippy::too_many_arguments)]
+    pub fn fill_box(
+        &mut self,
+        min_x: u32,
+        min_y: u32,
+        min_z: u32,
+        max_x: u32,
+        max_y: u32,
+        max_z: u32,
+        value: bool,
+    ) {
+        let low = [min_x, min_y, min_z].map(i64::from);
+        let high = [max_x, max_y, max_z].map(i64::from);
+        self.0.fill_box(low, high, value);
+    }
+
+    /// Clears every voxel.
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    /// The bitmap encoded in the binary format.
+    #[wasm_bindgen(js_name = toBinary)]
+    pub fn to_binary(&self) -> Vec<u8> {
+        self.0.to_binary()
+    }
+
+    /// Decodes a bitmap in the binary format. Throws if the data is invalid.
+    #[wasm_bindgen(js_name = fromBinary)]
+    pub fn from_binary(bytes: &[u8]) -> Result<WasmOctreeBitmap, JsError> {
+        Ok(Self(OctreeBitmap::from_binary(bytes)?))
+    }
+}
+
+impl From<OctreeBitmap> for WasmOctreeBitmap {
+    fn from(bitmap: OctreeBitmap) -> Self {
+        Self(bitmap)
+    }
+}
+
+impl From<WasmOctreeBitmap> for OctreeBitmap {
+    fn from(bitmap: WasmOctreeBitmap) -> Self {
+        bitmap.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Errors can only be created on a wasm target, so only the paths that
+    // succeed are tested natively.
+    #[test]
+    fn wrapper_round_trip() {
+        let mut bitmap = WasmOctreeBitmap::new(16).unwrap();
+        bitmap.fill_box(2, 3, 4, 10, 9, 8, true);
+        bitmap.set(2, 3, 4, false).unwrap();
+        assert!(!bitmap.get(2, 3, 4).unwrap());
+        assert!(bitmap.get(9, 8, 7).unwrap());
+        assert!(!bitmap.get(10, 8, 7).unwrap());
+
+        let copy = WasmOctreeBitmap::from_binary(&bitmap.to_binary()).unwrap();
+        assert_eq!(copy.width(), bitmap.width());
+        let copy = OctreeBitmap::from(copy);
+        assert_eq!(copy.iter().count(), 8 * 6 * 4 - 1);
+        assert!(copy == bitmap.into());
+    }
+}
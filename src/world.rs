@@ -0,0 +1,168 @@
+//! Bitmaps placed in world space.
+
+use crate::{BoxIter, Index, OctreeBitmap, RayHit, VoxelTransform};
+
+/// A bitmap placed in world space by a [`VoxelTransform`], accessed with
+/// world-space coordinates.
+///
+/// World-space positions outside of the bitmap read as unset and are ignored
+/// when written.
+pub struct WorldGrid {
+    bitmap: OctreeBitmap,
+    transform: VoxelTransform,
+}
+
+impl WorldGrid {
+    pub fn new(bitmap: OctreeBitmap, transform: VoxelTransform) -> Self {
+        Self { bitmap, transform }
+    }
+
+    pub fn bitmap(&self) -> &OctreeBitmap {
+        &self.bitmap
+    }
+
+    pub fn bitmap_mut(&mut self) -> &mut OctreeBitmap {
+        &mut self.bitmap
+    }
+
+    pub fn into_bitmap(self) -> OctreeBitmap {
+        self.bitmap
+    }
+
+    pub fn transform(&self) -> &VoxelTransform {
+        &self.transform
+    }
+
+    /// The index of the voxel containing the world-space point, or `None` if
+    /// it is outside of the bitmap.
+    pub fn index_of(&self, point: [f32; 3]) -> Option<Index> {
+        self.transform
+            .index_of(point.map(f64::from), self.bitmap.width())
+    }
+
+    /// The world-space minimum and maximum corners of the voxel at the given
+    /// index.
+    pub fn voxel_bounds(&self, idx: &Index) -> ([f32; 3], [f32; 3]) {
+        let corner = |offset: u32| {
+            let coords = [idx.x, idx.y, idx.z];
+            [0, 1, 2].map(|i| {
+                let voxel = f64::from(coords[i]) + f64::from(offset);
+                (self.transform.origin[i] + voxel * self.transform.voxel_size) as f32
+            })
+        };
+        (corner(0), corner(1))
+    }
+
+    /// Get the value of the voxel containing the world-space point.
+    pub fn get_world(&self, point: [f32; 3]) -> bool {
+        self.index_of(point)
+            .is_some_and(|idx| self.bitmap.get(&idx))
+    }
+
+    /// Set the value of the voxel containing the world-space point, returning
+    /// whether it is inside the bitmap.
+    pub fn set_world(&mut self, point: [f32; 3], value: bool) -> bool {
+        match self.index_of(point) {
+            Some(idx) => {
+                self.bitmap.set(&idx, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Converts a world-space point to the space of the bitmap, where each
+    /// voxel spans one unit.
+    fn to_grid(&self, point: [f32; 3]) -> [f32; 3] {
+        [0, 1, 2].map(|i| {
+            ((f64::from(point[i]) - self.transform.origin[i]) / self.transform.voxel_size) as f32
+        })
+    }
+
+    /// The first set voxel hit by a ray starting at the world-space `origin`
+    /// and travelling along `dir`, as for [`OctreeBitmap::raycast`].
+    ///
+    /// The ray parameters of the hit are those of the world-space ray, so the
+    /// ray enters the voxel at `origin + enter * dir`.
+    pub fn raycast_world(&self, origin: [f32; 3], dir: [f32; 3]) -> Option<RayHit> {
+        let origin = self.to_grid(origin);
+        let dir = dir.map(|d| (f64::from(d) / self.transform.voxel_size) as f32);
+        self.bitmap.ray_iter(origin, dir).next()
+    }
+
+    /// The range of voxels overlapping the world-space box from `min` to
+    /// `max`, clamped to the bitmap, or `None` if there are none.
+    fn voxel_range(&self, min: [f32; 3], max: [f32; 3]) -> Option<([i64; 3], [i64; 3])> {
+        let width = i64::from(self.bitmap.width());
+        let (min, max) = (self.to_grid(min), self.to_grid(max));
+        let low = min.map(|c| (c.floor() as i64).clamp(0, width));
+        let high = max.map(|c| (c.ceil() as i64).clamp(0, width));
+        // Also rejects NaN, which saturates to zero.
+        let valid = (0..3).all(|i| min[i] <= max[i] && low[i] < high[i]);
+        valid.then_some((low, high))
+    }
+
+    /// An iterator over the indexes of the set voxels overlapping the
+    /// world-space box from `min` to `max`, in Morton order.
+    pub fn iter_in_aabb(&self, min: [f32; 3], max: [f32; 3]) -> BoxIter<'_> {
+        let (low, high) = self.voxel_range(min, max).unwrap_or(([0; 3], [0; 3]));
+        let [low, high] = [low, high].map(|c| Index::from(c.map(|c| c as u32)));
+        self.bitmap.iter_in_box(low, high)
+    }
+
+    /// Whether any voxel overlapping the world-space box from `min` to `max`
+    /// is set, such as to test whether an object's bounding box collides
+    /// with the bitmap.
+    pub fn any_in_aabb(&self, min: [f32; 3], max: [f32; 3]) -> bool {
+        self.iter_in_aabb(min, max).next().is_some()
+    }
+
+    /// Set every voxel overlapping the world-space box from `min` to `max`
+    /// to `value`.
+    pub fn fill_aabb(&mut self, min: [f32; 3], max: [f32; 3], value: bool) {
+        if let Some((low, high)) = self.voxel_range(min, max) {
+            self.bitmap.fill_box(low, high, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Face;
+
+    #[test]
+    fn world_space_access() {
+        let transform = VoxelTransform::new([-8.0, 0.0, 2.0], 0.25);
+        let mut grid = WorldGrid::new(OctreeBitmap::new(16), transform);
+        assert!(grid.set_world([-7.9, 1.1, 2.3], true));
+        assert!(!grid.set_world([-9.0, 0.0, 2.0], true));
+        assert!(grid.bitmap().get(&Index::new(0, 4, 1)));
+        assert!(grid.get_world([-7.8, 1.2, 2.4]));
+        assert!(!grid.get_world([-7.7, 1.2, 2.4]));
+        assert_eq!(
+            grid.voxel_bounds(&Index::new(0, 4, 1)),
+            ([-8.0, 1.0, 2.25], [-7.75, 1.25, 2.5])
+        );
+
+        let hit = grid
+            .raycast_world([-7.9, 1.1, 0.0], [0.0, 0.0, 2.0])
+            .unwrap();
+        assert_eq!(hit.index, Index::new(0, 4, 1));
+        assert_eq!(hit.face, Face::NegZ);
+        assert!((hit.enter - 1.125).abs() < 1e-5);
+
+        assert!(grid.any_in_aabb([-8.5, 0.9, 2.2], [-7.99, 1.01, 2.26]));
+        assert!(!grid.any_in_aabb([-7.7, 0.9, 2.2], [-7.0, 1.01, 2.26]));
+        assert!(!grid.any_in_aabb([-7.9, 1.1, 2.3], [-8.0, 1.2, 2.4]));
+
+        grid.fill_aabb([-100.0, 1.9, 2.6], [-7.1, 2.1, 2.9], true);
+        let filled: Vec<Index> = grid
+            .iter_in_aabb([-100.0, 1.76, 2.6], [100.0, 2.24, 2.9])
+            .collect();
+        assert_eq!(filled.len(), 4 * 2 * 2);
+        assert!(filled
+            .iter()
+            .all(|idx| idx.x < 4 && (7..9).contains(&idx.y) && (2..4).contains(&idx.z)));
+    }
+}
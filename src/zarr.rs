@@ -0,0 +1,465 @@
+//! Import of chunked Zarr arrays by thresholding.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::volume::width_for;
+use crate::{DataType, Endian, Index, OctreeBitmap, ScalarType};
+
+/// An error encountered while reading a Zarr array.
+#[derive(Debug)]
+pub enum ZarrError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// The array metadata is malformed or missing a required field.
+    InvalidMetadata(String),
+    /// The array uses a feature of the format that is not supported, such as
+    /// an unsupported codec.
+    Unsupported(String),
+}
+
+impl fmt::Display for ZarrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "failed to read Zarr array: {}", err),
+            Self::Json(err) => write!(f, "failed to parse Zarr metadata: {}", err),
+            Self::InvalidMetadata(reason) => write!(f, "invalid Zarr metadata: {}", reason),
+            Self::Unsupported(what) => write!(f, "unsupported Zarr array: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for ZarrError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Json(err) => Some(err),
+            Self::InvalidMetadata(_) | Self::Unsupported(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for ZarrError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for ZarrError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compressor {
+    None,
+    Gzip,
+    Zlib,
+}
+
+/// The parts of the array metadata needed to read it, with axes in storage
+/// order (slowest-varying first).
+struct Metadata {
+    shape: [u32; 3],
+    chunks: [u32; 3],
+    dtype: DataType,
+    fill_value: f64,
+    fortran_order: bool,
+    compressor: Compressor,
+    /// Prepended to the chunk coordinates to form a chunk key, if any.
+    key_prefix: Option<&'static str>,
+    separator: String,
+}
+
+fn field<'a>(meta: &'a Value, name: &str) -> Result<&'a Value, ZarrError> {
+    meta.get(name)
+        .ok_or_else(|| ZarrError::InvalidMetadata(format!("missing {}", name)))
+}
+
+fn dims(value: &Value, name: &str) -> Result<[u32; 3], ZarrError> {
+    let dims = value
+        .as_array()
+        .ok_or_else(|| ZarrError::InvalidMetadata(format!("{} is not an array", name)))?
+        .iter()
+        .map(|dim| dim.as_u64().and_then(|dim| u32::try_from(dim).ok()))
+        .collect::<Option<Vec<u32>>>()
+        .ok_or_else(|| ZarrError::InvalidMetadata(format!("invalid {}", name)))?;
+    <[u32; 3]>::try_from(dims)
+        .map_err(|dims| ZarrError::Unsupported(format!("{}-dimensional array", dims.len())))
+}
+
+fn fill_value(value: &Value) -> Result<f64, ZarrError> {
+    Ok(match value {
+        // An undefined fill value; missing chunks are treated as zero.
+        Value::Null => 0.0,
+        Value::Bool(value) => *value as u8 as f64,
+        Value::Number(value) => value.as_f64().unwrap(),
+        Value::String(value) if value == "NaN" => f64::NAN,
+        Value::String(value) if value == "Infinity" => f64::INFINITY,
+        Value::String(value) if value == "-Infinity" => f64::NEG_INFINITY,
+        _ => return Err(ZarrError::Unsupported(format!("fill value {}", value))),
+    })
+}
+
+/// Parses Zarr v2 `.zarray` metadata.
+fn parse_v2(meta: &Value) -> Result<Metadata, ZarrError> {
+    let dtype = field(meta, "dtype")?
+        .as_str()
+        .ok_or_else(|| ZarrError::InvalidMetadata("dtype is not a string".to_owned()))?;
+    let (endian, kind) = dtype.split_at(dtype.len().min(1));
+    let endian = match endian {
+        "<" | "|" => Endian::Little,
+        ">" => Endian::Big,
+        _ => return Err(ZarrError::Unsupported(format!("dtype {}", dtype))),
+    };
+    let scalar = match kind {
+        "u1" | "b1" => ScalarType::U8,
+        "i1" => ScalarType::I8,
+        "u2" => ScalarType::U16,
+        "i2" => ScalarType::I16,
+        "u4" => ScalarType::U32,
+        "i4" => ScalarType::I32,
+        "u8" => ScalarType::U64,
+        "i8" => ScalarType::I64,
+        "f4" => ScalarType::F32,
+        "f8" => ScalarType::F64,
+        _ => return Err(ZarrError::Unsupported(format!("dtype {}", dtype))),
+    };
+    let compressor = match meta.get("compressor") {
+        None | Some(Value::Null) => Compressor::None,
+        Some(compressor) => match compressor.get("id").and_then(Value::as_str) {
+            Some("gzip") => Compressor::Gzip,
+            Some("zlib") => Compressor::Zlib,
+            _ => return Err(ZarrError::Unsupported(format!("compressor {}", compressor))),
+        },
+    };
+    if !matches!(meta.get("filters"), None | Some(Value::Null)) {
+        return Err(ZarrError::Unsupported("filters".to_owned()));
+    }
+    Ok(Metadata {
+        shape: dims(field(meta, "shape")?, "shape")?,
+        chunks: dims(field(meta, "chunks")?, "chunks")?,
+        dtype: DataType::new(scalar, endian),
+        fill_value: fill_value(meta.get("fill_value").unwrap_or(&Value::Null))?,
+        fortran_order: meta.get("order").and_then(Value::as_str) == Some("F"),
+        compressor,
+        key_prefix: None,
+        separator: meta
+            .get("dimension_separator")
+            .and_then(Value::as_str)
+            .unwrap_or(".")
+            .to_owned(),
+    })
+}
+
+/// Parses Zarr v3 `zarr.json` array metadata.
+fn parse_v3(meta: &Value) -> Result<Metadata, ZarrError> {
+    if field(meta, "node_type")? != "array" {
+        return Err(ZarrError::InvalidMetadata("not an array".to_owned()));
+    }
+    let data_type = field(meta, "data_type")?;
+    let scalar = match data_type.as_str() {
+        Some("bool" | "uint8") => ScalarType::U8,
+        Some("int8") => ScalarType::I8,
+        Some("uint16") => ScalarType::U16,
+        Some("int16") => ScalarType::I16,
+        Some("uint32") => ScalarType::U32,
+        Some("int32") => ScalarType::I32,
+        Some("uint64") => ScalarType::U64,
+        Some("int64") => ScalarType::I64,
+        Some("float32") => ScalarType::F32,
+        Some("float64") => ScalarType::F64,
+        _ => return Err(ZarrError::Unsupported(format!("data type {}", data_type))),
+    };
+
+    let grid = field(meta, "chunk_grid")?;
+    if field(grid, "name")? != "regular" {
+        return Err(ZarrError::Unsupported(format!("chunk grid {}", grid)));
+    }
+    let chunks = dims(
+        field(field(grid, "configuration")?, "chunk_shape")?,
+        "chunk_shape",
+    )?;
+
+    let encoding = meta.get("chunk_key_encoding");
+    let separator = encoding
+        .and_then(|encoding| encoding.get("configuration"))
+        .and_then(|config| config.get("separator"))
+        .and_then(Value::as_str);
+    let (key_prefix, separator) = match encoding.and_then(|encoding| encoding.get("name")) {
+        None => (Some("c"), "/"),
+        Some(name) if name == "default" => (Some("c"), separator.unwrap_or("/")),
+        Some(name) if name == "v2" => (None, separator.unwrap_or(".")),
+        Some(name) => {
+            return Err(ZarrError::Unsupported(format!(
+                "chunk key encoding {}",
+                name
+            )))
+        }
+    };
+
+    // Only the `bytes` codec, optionally followed by `gzip`, is supported.
+    let mut endian = Endian::Little;
+    let mut compressor = Compressor::None;
+    let codecs = field(meta, "codecs")?
+        .as_array()
+        .ok_or_else(|| ZarrError::InvalidMetadata("codecs is not an array".to_owned()))?;
+    for (i, codec) in codecs.iter().enumerate() {
+        match (i, codec.get("name").and_then(Value::as_str)) {
+            (0, Some("bytes")) => {
+                let config = codec.get("configuration");
+                if config.and_then(|config| config.get("endian")) == Some(&"big".into()) {
+                    endian = Endian::Big;
+                }
+            }
+            (1, Some("gzip")) => compressor = Compressor::Gzip,
+            _ => return Err(ZarrError::Unsupported(format!("codec {}", codec))),
+        }
+    }
+
+    Ok(Metadata {
+        shape: dims(field(meta, "shape")?, "shape")?,
+        chunks,
+        dtype: DataType::new(scalar, endian),
+        fill_value: fill_value(field(meta, "fill_value")?)?,
+        fortran_order: false,
+        compressor,
+        key_prefix,
+        separator: separator.to_owned(),
+    })
+}
+
+impl Metadata {
+    fn chunk_key(&self, coords: [u32; 3]) -> String {
+        let coords = coords.map(|c| c.to_string());
+        let key = coords.join(&self.separator);
+        match self.key_prefix {
+            Some(prefix) => format!("{}{}{}", prefix, self.separator, key),
+            None => key,
+        }
+    }
+
+    /// The in-bounds element positions of the chunk at the given grid
+    /// coordinates, in the order they are stored, as bitmap indexes.
+    fn chunk_indices(&self, coords: [u32; 3]) -> impl Iterator<Item = Option<Index>> + '_ {
+        let [k0, k1, k2] = self.chunks.map(|k| k as u64);
+        (0..k0 * k1 * k2).map(move |i| {
+            let local = if self.fortran_order {
+                [i % k0, i / k0 % k1, i / (k0 * k1)]
+            } else {
+                [i / (k1 * k2), i / k2 % k1, i % k2]
+            };
+            let mut global = [0; 3];
+            for axis in 0..3 {
+                let pos = coords[axis] as u64 * self.chunks[axis] as u64 + local[axis];
+                if pos >= self.shape[axis] as u64 {
+                    return None;
+                }
+                global[axis] = pos as u32;
+            }
+            // The last storage axis is the fastest-varying, so it becomes `x`.
+            Some(Index::new(global[2], global[1], global[0]))
+        })
+    }
+}
+
+impl OctreeBitmap {
+    /// Reads a 3D Zarr v2 or v3 array from a directory store, setting each
+    /// voxel whose element is greater than or equal to `threshold`.
+    ///
+    /// The last axis of the array becomes `x`, so that the element at
+    /// `[z, y, x]` is stored at index `(x, y, z)`. Chunks are read and
+    /// decompressed one at a time, so the array never needs to fit in memory.
+    /// Missing chunks take the array's fill value. Chunks may be
+    /// uncompressed or compressed with `gzip` or `zlib`; other codecs, such
+    /// as Blosc and sharding, are not supported.
+    pub fn from_zarr<P: AsRef<Path>>(path: P, threshold: f64) -> Result<Self, ZarrError> {
+        let path = path.as_ref();
+        let meta = match fs::read(path.join("zarr.json")) {
+            Ok(json) => {
+                let meta: Value = serde_json::from_slice(&json)?;
+                match field(&meta, "zarr_format")?.as_u64() {
+                    Some(3) => parse_v3(&meta)?,
+                    _ => return Err(ZarrError::Unsupported(format!("format {}", meta))),
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let meta: Value = serde_json::from_slice(&fs::read(path.join(".zarray"))?)?;
+                match field(&meta, "zarr_format")?.as_u64() {
+                    Some(2) => parse_v2(&meta)?,
+                    _ => return Err(ZarrError::Unsupported(format!("format {}", meta))),
+                }
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if meta.chunks.contains(&0) {
+            return Err(ZarrError::InvalidMetadata("empty chunk shape".to_owned()));
+        }
+
+        let mut bitmap = Self::try_new(width_for(meta.shape))
+            .map_err(|err| ZarrError::InvalidMetadata(format!("shape: {}", err)))?;
+        let grid = [0, 1, 2].map(|axis| meta.shape[axis].div_ceil(meta.chunks[axis]));
+        let sample_size = meta.dtype.scalar.size();
+        let chunk_len = meta
+            .chunks
+            .iter()
+            .try_fold(sample_size, |len, &k| len.checked_mul(k as usize))
+            .ok_or_else(|| ZarrError::InvalidMetadata("chunk shape is too large".to_owned()))?;
+        // Chunks are read no further than their expected length, and the
+        // buffer only grows as data arrives, so the chunk shape alone cannot
+        // allocate more than the store holds.
+        let mut chunk = Vec::new();
+        for c0 in 0..grid[0] {
+            for c1 in 0..grid[1] {
+                for c2 in 0..grid[2] {
+                    let coords = [c0, c1, c2];
+                    let key = meta.chunk_key(coords);
+                    let file = match fs::File::open(path.join(&key)) {
+                        Ok(file) => file,
+                        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                            if meta.fill_value >= threshold {
+                                bitmap.set_many(meta.chunk_indices(coords).flatten(), true);
+                            }
+                            continue;
+                        }
+                        Err(err) => return Err(err.into()),
+                    };
+                    chunk.clear();
+                    // One byte past the end is enough to tell that a chunk is
+                    // too long.
+                    let limit = chunk_len as u64 + 1;
+                    match meta.compressor {
+                        Compressor::None => io::BufReader::new(file)
+                            .take(limit)
+                            .read_to_end(&mut chunk)?,
+                        Compressor::Gzip => flate2::read::GzDecoder::new(file)
+                            .take(limit)
+                            .read_to_end(&mut chunk)?,
+                        Compressor::Zlib => flate2::read::ZlibDecoder::new(file)
+                            .take(limit)
+                            .read_to_end(&mut chunk)?,
+                    };
+                    if chunk.len() != chunk_len {
+                        return Err(ZarrError::InvalidMetadata(format!(
+                            "chunk {} has {} bytes, expected {}",
+                            key,
+                            chunk.len(),
+                            chunk_len
+                        )));
+                    }
+                    let indices = meta
+                        .chunk_indices(coords)
+                        .zip(chunk.chunks_exact(sample_size))
+                        .filter(|(_, sample)| meta.dtype.decode(sample) >= threshold)
+                        .filter_map(|(index, _)| index);
+                    bitmap.set_many(indices, true);
+                }
+            }
+        }
+        Ok(bitmap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn read_v2_and_v3() {
+        let dir = std::env::temp_dir().join(format!("october-zarr-{}", std::process::id()));
+
+        // A 1x2x3 array in 1x2x2 chunks; the second chunk is cut off by the
+        // edge of the array.
+        let v2 = dir.join("v2");
+        fs::create_dir_all(&v2).unwrap();
+        fs::write(
+            v2.join(".zarray"),
+            r#"{"zarr_format": 2, "shape": [1, 2, 3], "chunks": [1, 2, 2], "dtype": ">u2",
+                "compressor": {"id": "gzip", "level": 1}, "fill_value": 0, "order": "C",
+                "filters": null}"#,
+        )
+        .unwrap();
+        for (key, samples) in [("0.0.0", [0u16, 700, 900, 0]), ("0.0.1", [500, 0, 800, 0])] {
+            let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+            for sample in samples {
+                gzip.write_all(&sample.to_be_bytes()).unwrap();
+            }
+            fs::write(v2.join(key), gzip.finish().unwrap()).unwrap();
+        }
+
+        // A 2x2x2 array where only the first of two chunks is stored, and
+        // the rest takes the fill value.
+        let v3 = dir.join("v3");
+        fs::create_dir_all(v3.join("c/0/0")).unwrap();
+        fs::write(
+            v3.join("zarr.json"),
+            r#"{"zarr_format": 3, "node_type": "array", "shape": [2, 2, 2],
+                "data_type": "float32", "fill_value": "Infinity",
+                "chunk_grid": {"name": "regular", "configuration": {"chunk_shape": [1, 2, 2]}},
+                "chunk_key_encoding": {"name": "default"},
+                "codecs": [{"name": "bytes", "configuration": {"endian": "little"}}]}"#,
+        )
+        .unwrap();
+        let chunk: Vec<u8> = [0.25f32, 0.75, 0.5, f32::NAN]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        fs::write(v3.join("c/0/0/0"), chunk).unwrap();
+
+        let v2 = OctreeBitmap::from_zarr(&v2, 600.0);
+        let v3 = OctreeBitmap::from_zarr(&v3, 0.5);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            v2.unwrap().iter().collect::<Vec<_>>(),
+            [
+                Index::new(1, 0, 0),
+                Index::new(0, 1, 0),
+                Index::new(2, 1, 0)
+            ]
+        );
+        let v3 = v3.unwrap();
+        assert_eq!(
+            v3.iter().collect::<Vec<_>>(),
+            [
+                Index::new(1, 0, 0),
+                Index::new(0, 1, 0),
+                Index::new(0, 0, 1),
+                Index::new(1, 0, 1),
+                Index::new(0, 1, 1),
+                Index::new(1, 1, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn oversized_metadata() {
+        let dir = std::env::temp_dir().join(format!("october-zarr-large-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let read = |shape: &str, chunks: &str| {
+            fs::write(
+                dir.join(".zarray"),
+                format!(
+                    r#"{{"zarr_format": 2, "shape": {}, "chunks": {}, "dtype": "|u1",
+                        "compressor": null, "fill_value": 0, "order": "C", "filters": null}}"#,
+                    shape, chunks
+                ),
+            )
+            .unwrap();
+            OctreeBitmap::from_zarr(&dir, 1.0)
+        };
+        let small = read("[1, 1, 1]", "[1, 1, 1]");
+        let wide = read("[1, 1, 2000000000]", "[1, 1, 1]");
+        let huge_chunks = read("[1, 1, 1]", "[4000000000, 4000000000, 4000000000]");
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(small.is_ok());
+        assert!(matches!(wide, Err(ZarrError::InvalidMetadata(_))));
+        assert!(matches!(huge_chunks, Err(ZarrError::InvalidMetadata(_))));
+    }
+}